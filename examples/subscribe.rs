@@ -0,0 +1,17 @@
+//! Prints what the engine makes of each button toggle, as an overlay or a
+//! trainer embedding it would observe it, from lines such as `A 1` and `A 0`
+//! on stdin, e.g.
+//!
+//!     printf 'A 1\nRight 1\nA 0\n' | cargo run --example subscribe
+
+// Compiled in as a module, most of the engine goes unused, and its
+// `pub(crate)` items are visible beyond the private types they use.
+#![allow(dead_code, private_bounds, private_interfaces)]
+
+// tuxb0xx is a binary crate, so the engine is compiled in from source.
+#[path = "../src/main.rs"]
+mod tuxb0xx;
+
+fn main() -> anyhow::Result<()> {
+    tuxb0xx::remap::subscribe_example()
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+// tuxb0xx is a binary crate, so the engine is compiled in from source, without
+// the linux-input feature.
+#[path = "../../src/main.rs"]
+mod tuxb0xx;
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| tuxb0xx::fuzz::run(data));
@@ -0,0 +1,476 @@
+//! A report for tournament organizers of what a profile can and can't do.
+//!
+//! `certify` checks a profile as `run` would use it: the engine, how each
+//! axis resolves opposite directions, whether every coordinate either stick
+//! can output per `coordinates::outputs`, under the coordinates of the profile
+//! and of each of its characters, is within range, each feature that
+//! changes the output, and how fast inputs can come after the limiters.
+//! Features are registered in `flags`, which lists every field of
+//! `config::Profile` so that a new one doesn't build until it is classified
+//! there. The report ends with a hash of the profile and the results, to
+//! match a report to the profile it came from; anyone can compute it, so it
+//! is no signature.
+//!
+//! `lockout` turns every restricted feature off for `--tournament`. It is
+//! applied to a copy of the profile wherever one takes effect, at startup and
+//! on every switch, so that the config stays as written and the report can
+//! list what was turned off.
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Outcome {
+    Pass,
+    Fail,
+    /// Reported for the organizer to judge, e.g. the input rate.
+    Info,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Fail => "FAIL",
+            Self::Info => "INFO",
+        })
+    }
+}
+
+/// How a feature bears on what tournament rules allow.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Class {
+    /// Only holds the output back, e.g. the SDI limit, as some rulesets
+    /// require.
+    Limiter,
+    /// Changes what is held without timing anything of its own.
+    Allowed,
+    /// Outputs inputs nobody pressed, or times them, e.g. a macro.
+    Restricted,
+}
+
+/// A feature of a profile that changes the output while enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Flag {
+    /// The profile's key for it.
+    pub(crate) name: &'static str,
+    pub(crate) class: Class,
+    pub(crate) description: &'static str,
+}
+
+/// Each feature of `profile` under `engine`, with whether it is enabled.
+pub(crate) fn flags(profile: &config::Profile, engine: Engine) -> Vec<(Flag, bool)> {
+    let config::Profile {
+        // Remapping is allowed, and what the buttons then output is covered
+        // by the coordinate and SOCD checks.
+        bindings: _,
+        coordinates: _,
+        characters: _,
+        c_stick_mode: _,
+        shield_tiers: _,
+        invert: _,
+        a_stick_scale: _,
+        crouch_walk: _,
+        dpad: _,
+        socd: _,
+        stick_coupling: _,
+        handedness: _,
+        // Only logs feedback.
+        techniques: _,
+        b_reverse,
+        turbo,
+        short_hop,
+        sdi_limit,
+        ledgedash_nerf,
+        neutral_b,
+        start_reset,
+        z_composite,
+        trigger_ramp,
+        negative_edge,
+        light_press,
+        double_tap,
+        shield_behavior,
+        macros,
+        tap_hold,
+        autorepeat,
+        // Only runs internal commands, e.g. pausing with the controller
+        // neutral.
+        combos: _,
+    } = profile;
+    let flag = |name, class, description, enabled| {
+        (
+            Flag {
+                name,
+                class,
+                description,
+            },
+            enabled,
+        )
+    };
+    vec![
+        flag(
+            "b_reverse",
+            Class::Restricted,
+            "outputs a timed flick of the opposite direction after B",
+            b_reverse.is_some(),
+        ),
+        flag(
+            "turbo",
+            Class::Restricted,
+            "presses a button repeatedly while TB is held",
+            turbo.is_some(),
+        ),
+        flag(
+            "short_hop",
+            Class::Restricted,
+            "releases X a fixed time after SH presses it",
+            short_hop.is_some(),
+        ),
+        flag(
+            "sdi_limit",
+            Class::Limiter,
+            "delays A-stick changes of direction",
+            sdi_limit.is_some(),
+        ),
+        flag(
+            "ledgedash_nerf",
+            Class::Limiter,
+            "outputs neutral between rapid left/right alternations",
+            ledgedash_nerf.is_some(),
+        ),
+        flag(
+            "neutral_b",
+            Class::Restricted,
+            "centres the A-stick X axis for B pressed shortly after a direction",
+            neutral_b.is_some(),
+        ),
+        flag(
+            "start_reset",
+            Class::Allowed,
+            "resets the output to neutral while Start is held",
+            start_reset.is_some(),
+        ),
+        flag(
+            "z_composite",
+            Class::Restricted,
+            "presses A and the analog trigger for Z",
+            z_composite.is_some(),
+        ),
+        flag(
+            "trigger_ramp",
+            Class::Restricted,
+            "ramps the analog trigger while TR is held",
+            trigger_ramp.is_some(),
+        ),
+        flag(
+            "negative_edge",
+            Class::Restricted,
+            "presses a shield button for a fixed time on its release",
+            negative_edge.is_some(),
+        ),
+        flag(
+            "light_press",
+            Class::Restricted,
+            "sets the analog trigger a fixed time before pressing L or R",
+            light_press.is_some(),
+        ),
+        flag(
+            "double_tap",
+            Class::Restricted,
+            "holds an analog value for L or R pressed twice within a window",
+            double_tap.is_some(),
+        ),
+        flag(
+            "shield_behavior",
+            Class::Restricted,
+            "presses L or R once a shield button is held long enough",
+            shield_behavior.values().any(|behavior| {
+                matches!(behavior, config::ShieldBehavior::AnalogThenDigital { .. })
+            }),
+        ),
+        flag(
+            "macros",
+            Class::Restricted,
+            "plays a sequence of inputs from one key",
+            !macros.is_empty(),
+        ),
+        flag(
+            "tap_hold",
+            Class::Restricted,
+            "makes a button act as another once held long enough",
+            !tap_hold.is_empty(),
+        ),
+        flag(
+            "autorepeat",
+            Class::Restricted,
+            "presses a button again on the keyboard's autorepeat",
+            autorepeat.values().any(|autorepeat| match autorepeat {
+                config::Autorepeat::Drop => false,
+                config::Autorepeat::Press => true,
+                config::Autorepeat::Raw => engine == Engine::Raw,
+            }),
+        ),
+    ]
+}
+
+/// Returns `profile` with every restricted feature off, as `--tournament`
+/// runs it, and the names of those that were on under `engine`.
+pub(crate) fn lockout(
+    profile: &config::Profile,
+    engine: Engine,
+) -> (config::Profile, Vec<&'static str>) {
+    let locked = config::Profile {
+        b_reverse: None,
+        turbo: None,
+        short_hop: None,
+        neutral_b: None,
+        z_composite: None,
+        trigger_ramp: None,
+        negative_edge: None,
+        light_press: None,
+        double_tap: None,
+        shield_behavior: profile.shield_behavior.untimed(),
+        macros: Default::default(),
+        tap_hold: Default::default(),
+        autorepeat: Default::default(),
+        ..profile.clone()
+    };
+    let suppressed = flags(profile, engine)
+        .into_iter()
+        .zip(flags(&locked, engine))
+        .filter(|&((_, was), (_, is))| was && !is)
+        .map(|((flag, _), _)| flag.name)
+        .collect();
+    (locked, suppressed)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Check {
+    pub(crate) name: String,
+    pub(crate) outcome: Outcome,
+    pub(crate) detail: String,
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.outcome, self.name, self.detail)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Report {
+    pub(crate) profile: String,
+    pub(crate) checks: Vec<Check>,
+    /// FNV-1a of the profile, the options it is run with and the checks.
+    pub(crate) hash: u64,
+}
+
+impl Report {
+    pub(crate) fn failed(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.outcome == Outcome::Fail)
+            .count()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn check(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "certification of profile {:?}", self.profile)?;
+        for check in &self.checks {
+            writeln!(f, "{}", check)?;
+        }
+        match self.failed() {
+            0 => writeln!(f, "result: PASS")?,
+            failed => writeln!(
+                f,
+                "result: FAIL, {} of {} checks failed",
+                failed,
+                self.checks.len()
+            )?,
+        }
+        write!(f, "hash: {:016x}", self.hash)
+    }
+}
+
+/// Checks `profile` as run under `engine`, in Melee, with `lockout` applied
+/// under `tournament`.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn certify(
+    name: &str,
+    profile: &config::Profile,
+    engine: Engine,
+    crouch_walk_option_select: bool,
+    tournament: bool,
+) -> Report {
+    let check = |name: &str, outcome, detail: String| Check {
+        name: name.to_owned(),
+        outcome,
+        detail,
+    };
+    let mut checks = Vec::new();
+    let raw = engine == Engine::Raw;
+    checks.push(if raw {
+        check(
+            "engine",
+            Outcome::Fail,
+            "raw, a generic gamepad with modifiers doing nothing".to_owned(),
+        )
+    } else {
+        check("engine", Outcome::Pass, "b0xx".to_owned())
+    });
+    let locked_out = tournament.then(|| lockout(profile, engine));
+    let profile = locked_out.as_ref().map_or(profile, |(locked, _)| locked);
+    if let Some((_, suppressed)) = &locked_out {
+        checks.push(check(
+            "tournament",
+            Outcome::Info,
+            if suppressed.is_empty() {
+                "on, nothing to turn off".to_owned()
+            } else {
+                format!("on, turned off {}", suppressed.join(", "))
+            },
+        ));
+    }
+
+    // The raw engine outputs the last direction pressed on every axis.
+    let socd = |second_input: bool, other: &str| {
+        if second_input || raw {
+            "second input".to_owned()
+        } else {
+            other.to_owned()
+        }
+    };
+    checks.push(check("socd.a_stick.x", Outcome::Pass, socd(true, "")));
+    checks.push(check(
+        "socd.a_stick.y",
+        Outcome::Pass,
+        socd(profile.socd.y == config::YSocd::SecondInput, "up priority"),
+    ));
+    checks.push(check(
+        "socd.c_stick",
+        Outcome::Pass,
+        socd(
+            profile.socd.c_stick == config::CStickSocd::SecondInput,
+            "neutral",
+        ),
+    ));
+
+    let outputs = if raw {
+        [Stick::A, Stick::C]
+            .into_iter()
+            .flat_map(|stick| {
+                [Analog::MIN, P0000, Analog::MAX]
+                    .into_iter()
+                    .flat_map(move |x| {
+                        [Analog::MIN, P0000, Analog::MAX]
+                            .into_iter()
+                            .map(move |y| (stick, (x, y)))
+                    })
+            })
+            .collect::<Vec<_>>()
+    } else {
+        // Each character's coordinates can be selected at runtime.
+        std::iter::once(None)
+            .chain(profile.characters.0.keys().map(|name| Some(name.as_str())))
+            .flat_map(|character| {
+                let profile = config::Profile {
+                    coordinates: profile
+                        .coordinates_for(character)
+                        .expect("the character is the profile's")
+                        .into(),
+                    ..profile.clone()
+                };
+                coordinates::outputs(&Main::new(&profile), crouch_walk_option_select)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    };
+    let mut outside = outputs
+        .iter()
+        .filter(|&&(_, input)| config::polar(input).1 > 1.)
+        .map(|&(stick, (x, y))| format!("{:?}-stick [{}, {}]", stick, x.get(), y.get()))
+        .collect::<Vec<_>>();
+    outside.sort();
+    outside.dedup();
+    checks.push(if outside.is_empty() {
+        check(
+            "coordinates",
+            Outcome::Pass,
+            "every coordinate is within the unit circle".to_owned(),
+        )
+    } else {
+        check(
+            "coordinates",
+            Outcome::Fail,
+            format!("outside the unit circle: {}", outside.join(", ")),
+        )
+    });
+
+    let flags = flags(profile, engine);
+    for &(flag, enabled) in &flags {
+        let (outcome, state) = match (enabled, flag.class) {
+            (false, _) => (Outcome::Pass, "off"),
+            (true, Class::Restricted) => (Outcome::Fail, "on"),
+            (true, Class::Limiter | Class::Allowed) => (Outcome::Pass, "on"),
+        };
+        checks.push(check(
+            &format!("feature.{}", flag.name),
+            outcome,
+            format!("{}, {}", state, flag.description),
+        ));
+    }
+
+    let autorepeat = flags
+        .iter()
+        .any(|&(flag, enabled)| flag.name == "autorepeat" && enabled);
+    let buttons = match profile.turbo {
+        Some(turbo) => format!("up to {} presses per second with turbo", turbo.rate_hz),
+        None if autorepeat => "up to the keyboard's autorepeat rate".to_owned(),
+        None => "a press per key press".to_owned(),
+    };
+    checks.push(check("rate.buttons", Outcome::Info, buttons));
+    let a_stick = match profile.sdi_limit {
+        Some(sdi_limit) => format!(
+            "a change of direction per {}ms at most, by the SDI limit",
+            sdi_limit.interval_ms
+        ),
+        None => "a change of direction per key press".to_owned(),
+    };
+    checks.push(check("rate.a_stick", Outcome::Info, a_stick));
+
+    let mut hash = Fnv::default();
+    hash.write(&format!(
+        "{}\n{:?}\n{}\n{}\n",
+        name, engine, crouch_walk_option_select, profile
+    ));
+    for check in &checks {
+        hash.write(&format!("{}\n", check));
+    }
+    Report {
+        profile: name.to_owned(),
+        checks,
+        hash: hash.0,
+    }
+}
+
+/// FNV-1a, which unlike `std`'s hashers is the same on every build.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, s: &str) {
+        for &byte in s.as_bytes() {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Combo triggers: internal commands run by holding buttons together.
+//!
+//! `Detector` follows the buttons held as the events go by, apart from `Main`,
+//! so that the buttons still act as usual. Of the combos fully held, only the
+//! first in order of precedence is armed, and it fires once held for its
+//! `hold_ms`. Every combo held when one fires is then spent until one of its
+//! buttons is released, so holding on, or releasing the extra buttons of a
+//! bigger combo, fires nothing more.
+
+use super::*;
+
+#[derive(Default)]
+pub(crate) struct Detector {
+    combos: Vec<config::Combo>,
+    /// When each button held was pressed.
+    held: std::collections::BTreeMap<B0xxRaw, Timestamp>,
+    /// The combos held when one last fired, by index.
+    spent: std::collections::BTreeSet<usize>,
+}
+
+impl Detector {
+    pub(crate) fn new(combos: config::Combos) -> Self {
+        Self {
+            combos: combos.0,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn process(&mut self, &B0xxEvent { time, btn, pressed }: &B0xxEvent) {
+        if pressed {
+            let _ = self.held.entry(btn).or_insert(time);
+        } else {
+            let _ = self.held.remove(&btn);
+            let Self {
+                combos,
+                held,
+                spent,
+            } = self;
+            spent.retain(|&i| holds(held, &combos[i]));
+        }
+    }
+
+    /// Returns the combo armed, by index, and when it fires.
+    fn armed(&self) -> Option<(usize, Timestamp)> {
+        let (i, combo) = self
+            .combos
+            .iter()
+            .enumerate()
+            .find(|(_, combo)| holds(&self.held, combo))?;
+        if self.spent.contains(&i) {
+            return None;
+        }
+        let since = combo.buttons.iter().map(|btn| self.held[btn]).max()?;
+        Some((i, since + combo.hold()))
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Timestamp> {
+        self.armed().map(|(_, at)| at)
+    }
+
+    /// Returns the action of the combo armed, if it is due.
+    pub(crate) fn process_deadline(&mut self, now: Timestamp) -> Option<config::ComboAction> {
+        let (i, at) = self.armed()?;
+        if now < at {
+            return None;
+        }
+        let config::Combo {
+            buttons, action, ..
+        } = &self.combos[i];
+        info!("combo {:?} fired: {:?}", buttons, action);
+        self.spent = (0..self.combos.len())
+            .filter(|&i| holds(&self.held, &self.combos[i]))
+            .collect();
+        Some(action.clone())
+    }
+}
+
+fn holds(held: &std::collections::BTreeMap<B0xxRaw, Timestamp>, combo: &config::Combo) -> bool {
+    combo.buttons.iter().all(|btn| held.contains_key(btn))
+}
@@ -0,0 +1,141 @@
+//! A log of the exact commands written to Dolphin's pipe, for lining up
+//! against a replay afterwards.
+//!
+//! Each line is `<nanos> <frame> <command>`, where `nanos` is the monotonic
+//! time of the write and `frame` the frame it falls into, or `-` before frames
+//! are counted. Lines are written by a thread of their own through a buffer, so
+//! that the disk never holds up the pipe.
+
+use super::*;
+
+/// How often buffered lines are flushed while commands keep coming.
+#[cfg(feature = "linux-input")]
+const FLUSH_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Writes the commands it is given to a file in the background.
+#[cfg(feature = "linux-input")]
+pub(crate) struct CommandLog {
+    sender: Option<std::sync::mpsc::Sender<(Timestamp, Option<u64>, String)>>,
+    thread: Option<std::thread::JoinHandle<std::io::Result<()>>>,
+    frames: frame::FrameClock,
+}
+
+#[cfg(feature = "linux-input")]
+impl CommandLog {
+    pub(crate) fn create(
+        path: &std::path::Path,
+        frames: frame::FrameClock,
+    ) -> anyhow::Result<Self> {
+        use std::io::Write as _;
+        use std::sync::mpsc::RecvTimeoutError;
+
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("failed to create command log {}", path.display()))?;
+        let (sender, receiver) = std::sync::mpsc::channel::<(Timestamp, Option<u64>, String)>();
+        let thread = std::thread::spawn(move || {
+            let mut writer = std::io::BufWriter::new(file);
+            let mut next_flush = std::time::Instant::now() + FLUSH_PERIOD;
+            loop {
+                let timeout = next_flush.saturating_duration_since(std::time::Instant::now());
+                match receiver.recv_timeout(timeout) {
+                    Ok((time, frame, command)) => {
+                        writer.write_all(line(time, frame, &command).as_bytes())?
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return writer.flush(),
+                }
+                if std::time::Instant::now() >= next_flush {
+                    writer.flush()?;
+                    next_flush = std::time::Instant::now() + FLUSH_PERIOD;
+                }
+            }
+        });
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+            frames,
+        })
+    }
+
+    /// Queues `command`, as written to the pipe at `time`.
+    pub(crate) fn record(&self, time: Timestamp, command: &str) {
+        if let Some(sender) = &self.sender {
+            // The thread only stops early on a write error, which `drop`
+            // reports.
+            let _ = sender.send((time, self.frames.frame(time), command.to_string()));
+        }
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl Drop for CommandLog {
+    /// Writes out everything queued before returning.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        match self.thread.take().map(std::thread::JoinHandle::join) {
+            Some(Ok(Err(e))) => warn!("failed to write command log: {}", e),
+            Some(Err(_)) => warn!("command log thread panicked"),
+            Some(Ok(Ok(()))) | None => {}
+        }
+    }
+}
+
+/// Formats a line of the log. Commands written to the pipe may or may not end
+/// in a newline.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn line(time: Timestamp, frame: Option<u64>, command: &str) -> String {
+    let frame = frame.map_or_else(|| String::from("-"), |frame| frame.to_string());
+    format!("{} {} {}\n", time.0.as_nanos(), frame, command.trim_end())
+}
+
+/// Counts and timing of the commands in a log.
+#[derive(Debug, Default)]
+pub(crate) struct Analysis {
+    /// Commands by type, e.g. `PRESS A` or `SET MAIN`, without their values.
+    pub(crate) counts: std::collections::BTreeMap<String, u64>,
+    /// Time between consecutive commands.
+    pub(crate) gaps: LatencyHistogram,
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (command, count) in &self.counts {
+            writeln!(f, "{}: {}", command, count)?;
+        }
+        write!(f, "gaps between commands: {}", self.gaps)
+    }
+}
+
+/// Parses a command log as written by `CommandLog`, or before it wrote frames.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn analyze(log: &str) -> anyhow::Result<Analysis> {
+    let mut analysis = Analysis::default();
+    let mut previous = None;
+    for (number, line) in log.lines().enumerate() {
+        let parse = || {
+            let (nanos, command) = line.split_once(' ')?;
+            let nanos = nanos.parse::<u64>().ok()?;
+            let mut words = command.split(' ').peekable();
+            let _: Option<&str> =
+                words.next_if(|&frame| frame == "-" || frame.parse::<u64>().is_ok());
+            let kind = match (words.next()?, words.next()?) {
+                (verb @ ("PRESS" | "RELEASE"), button) => format!("{} {}", verb, button),
+                ("SET", target @ ("MAIN" | "C" | "L" | "R")) => format!("SET {}", target),
+                _ => return None,
+            };
+            Some((std::time::Duration::from_nanos(nanos), kind))
+        };
+        let (time, kind) = parse().ok_or_else(|| {
+            anyhow::anyhow!(
+                "line {}: expected \"<nanos> <frame> <command>\", got {:?}",
+                number + 1,
+                line
+            )
+        })?;
+        *analysis.counts.entry(kind).or_default() += 1;
+        if let Some(previous) = previous.replace(time) {
+            analysis.gaps.record(time.saturating_sub(previous));
+        }
+    }
+    Ok(analysis)
+}
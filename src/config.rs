@@ -0,0 +1,2418 @@
+//! Configuration file support.
+//!
+//! A configuration file holds any number of named profiles, each of which
+//! specifies a key layout and coordinate overrides. Anything left unspecified
+//! falls back to the built-in defaults, so an empty file behaves exactly like
+//! running without one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context as _;
+#[cfg(feature = "linux-input")]
+use evdev_rs::enums::{EventCode, EventType};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::consts::*;
+#[cfg(feature = "linux-input")]
+use super::NEGATIVE;
+use super::{
+    coord, Analog, B0xxRaw, B0xxState, Direction, GCButton, GCStickInput, Shield, Trigger, LS, MS,
+    POSITIVE,
+};
+
+pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) profile: HashMap<String, Profile>,
+    /// Limits the devices considered when identifying the keyboard.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    pub(crate) devices: DeviceFilter,
+    /// What the pipe of the Dolphin build in use accepts.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    pub(crate) pipe_capability: PipeCapability,
+    /// Keys that pause or resume the output when pressed.
+    #[serde(default)]
+    pub(crate) focus_keys: FocusKeys,
+}
+
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+impl Config {
+    pub(crate) fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid config {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Checks what refers across profiles, e.g. the profile a combo switches
+    /// to, or the focus keys, which no profile may bind.
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, profile) in &self.profile {
+            if let Some(key) = self
+                .focus_keys
+                .0
+                .iter()
+                .find(|key| profile.bindings.binds(key))
+            {
+                anyhow::bail!(
+                    "profile {:?} binds focus key {:?}, which would never press its button",
+                    name,
+                    key
+                );
+            }
+            for combo in &profile.combos.0 {
+                match &combo.action {
+                    ComboAction::SetProfile(to)
+                        if to != DEFAULT_PROFILE && !self.profile.contains_key(to) =>
+                    {
+                        anyhow::bail!(
+                            "a combo of profile {:?} switches to no profile named {:?}",
+                            name,
+                            to
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the named profile. The default profile is always available,
+    /// even if the config file doesn't define it.
+    pub(crate) fn profile(&self, name: &str) -> anyhow::Result<Profile> {
+        match self.profile.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None if name == DEFAULT_PROFILE => Ok(Profile::default()),
+            None => Err(anyhow::anyhow!("no profile named {:?}", name)),
+        }
+    }
+}
+
+/// Globs matched against each input device's name, physical path and unique
+/// identifier.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DeviceFilter {
+    /// Devices matching any of these are skipped.
+    pub(crate) ignore: Vec<DevicePattern>,
+    /// Unless empty, only devices matching one of these are considered.
+    pub(crate) allow: Vec<DevicePattern>,
+}
+
+impl DeviceFilter {
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ignore.is_empty() && self.allow.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DevicePattern(pub(crate) glob::Pattern);
+
+impl std::str::FromStr for DevicePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        glob::Pattern::new(s)
+            .map(Self)
+            .map_err(|e| format!("invalid device pattern {:?}: {}", s, e))
+    }
+}
+
+impl<'de> Deserialize<'de> for DevicePattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Profile {
+    /// Replaces the default key layout entirely when present.
+    pub(crate) bindings: Bindings,
+    /// Overrides individual coordinates.
+    pub(crate) coordinates: CoordinateOverrides,
+    /// Overrides individual coordinates further for the character selected
+    /// at runtime, if any. None unless present.
+    pub(crate) characters: Characters,
+    pub(crate) c_stick_mode: CStickMode,
+    /// Disabled unless present.
+    pub(crate) b_reverse: Option<BReverseHelper>,
+    /// Replaces the default shield tiers entirely when present.
+    pub(crate) shield_tiers: ShieldTiers,
+    pub(crate) invert: Inversion,
+    pub(crate) a_stick_scale: Scale,
+    pub(crate) crouch_walk: CrouchWalk,
+    pub(crate) dpad: DPadActivation,
+    pub(crate) socd: Socd,
+    pub(crate) stick_coupling: StickCoupling,
+    pub(crate) handedness: Handedness,
+    /// Disabled unless present.
+    pub(crate) techniques: Option<Techniques>,
+    /// Disabled unless present.
+    pub(crate) turbo: Option<Turbo>,
+    /// Disabled unless present.
+    pub(crate) short_hop: Option<ShortHop>,
+    /// Disabled unless present.
+    pub(crate) sdi_limit: Option<SdiLimit>,
+    /// Disabled unless present.
+    pub(crate) ledgedash_nerf: Option<LedgedashNerf>,
+    /// Disabled unless present.
+    pub(crate) neutral_b: Option<NeutralBHelper>,
+    /// Disabled unless present.
+    pub(crate) start_reset: Option<StartReset>,
+    /// Disabled unless present.
+    pub(crate) z_composite: Option<ZComposite>,
+    /// Disabled unless present.
+    pub(crate) trigger_ramp: Option<TriggerRamp>,
+    /// Disabled unless present.
+    pub(crate) negative_edge: Option<NegativeEdge>,
+    /// Disabled unless present.
+    pub(crate) light_press: Option<LightPress>,
+    /// Disabled unless present.
+    pub(crate) double_tap: Option<DoubleTap>,
+    /// Standard for every button unless present.
+    pub(crate) shield_behavior: ShieldBehaviors,
+    /// None unless present.
+    pub(crate) macros: Macros,
+    /// None unless present.
+    pub(crate) tap_hold: TapHolds,
+    /// Drops every key's autorepeat unless present.
+    pub(crate) autorepeat: Autorepeats,
+    /// None unless present.
+    pub(crate) combos: Combos,
+}
+
+/// Range of the C-stick's output.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CStickMode {
+    /// Full deflection, for smash attacks.
+    #[default]
+    Smash,
+    /// Tilt-range deflection, for tilt attacks. The Mod X pivot-tilt
+    /// coordinates still apply.
+    Tilt,
+}
+
+/// Maps evdev key names, e.g. `KEY_SPACE`, to b0xx buttons, as well as switch
+/// names, e.g. `SW_LID`, and hat directions, e.g. `ABS_HAT0X-`. Several keys may
+/// map to the same button, either each on its own or listed under the button,
+/// e.g. `Start = ["KEY_Y", "KEY_F"]`. Names are kept as strings so that the
+/// config can be handled without evdev, and are only resolved to event codes at
+/// the device boundary.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "HashMap<String, Binding>")]
+pub(crate) struct Bindings(HashMap<String, B0xxRaw>);
+
+/// An entry of `Bindings` as written: the button of the key named, or the keys
+/// of the button named.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Binding {
+    Button(B0xxRaw),
+    Keys(Vec<String>),
+}
+
+impl Bindings {
+    /// Resolves names to what they name on the device. Names are validated
+    /// when the config is loaded, so this cannot fail.
+    #[cfg(feature = "linux-input")]
+    pub(crate) fn sources(&self) -> HashMap<Source, B0xxRaw> {
+        self.0
+            .iter()
+            .map(|(key, &btn)| (source(key).expect("validated key name"), btn))
+            .collect()
+    }
+
+    /// Returns whether the key of this name is bound.
+    pub(crate) fn binds(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Returns the names of the keys bound to `btn`, sorted.
+    pub(crate) fn keys(&self, btn: B0xxRaw) -> Vec<&str> {
+        let mut keys = self
+            .0
+            .iter()
+            .filter(|&(_, &bound)| bound == btn)
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+}
+
+/// What a key's autorepeat, which evdev sends while it is held, does to its
+/// button.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Autorepeat {
+    /// Nothing, as the button is held already.
+    #[default]
+    Drop,
+    /// Releases the button and presses it again, e.g. to mash it.
+    Press,
+    /// As `Press` under the raw engine, which passes buttons straight to the
+    /// controller, and as `Drop` otherwise.
+    Raw,
+}
+
+/// The autorepeat handling of keys by name, e.g. `KEY_SPACE`, for those not
+/// dropping it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<String, Autorepeat>")]
+pub(crate) struct Autorepeats(BTreeMap<String, Autorepeat>);
+
+impl Autorepeats {
+    /// Resolves names to key codes, as `Bindings::sources` does.
+    #[cfg(feature = "linux-input")]
+    pub(crate) fn sources(&self) -> HashMap<Source, Autorepeat> {
+        self.0
+            .iter()
+            .map(|(key, &autorepeat)| {
+                (
+                    Source::Code(key_code(key).expect("validated key name")),
+                    autorepeat,
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = Autorepeat> + '_ {
+        self.0.values().copied()
+    }
+}
+
+impl TryFrom<BTreeMap<String, Autorepeat>> for Autorepeats {
+    type Error = String;
+
+    fn try_from(autorepeats: BTreeMap<String, Autorepeat>) -> Result<Self, Self::Error> {
+        // Only keys repeat, not switches or hats.
+        #[cfg(feature = "linux-input")]
+        if let Some(key) = autorepeats.keys().find(|key| key_code(key).is_none()) {
+            return Err(format!("autorepeat of {:?}, which is not a key", key));
+        }
+        Ok(Self(autorepeats))
+    }
+}
+
+/// Keys by name, e.g. `KEY_LEFTMETA`, pressed before switching to another
+/// window, which pause the output rather than being remapped, leaving the
+/// controller neutral, and resume it when pressed again.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub(crate) struct FocusKeys(Vec<String>);
+
+impl FocusKeys {
+    /// Resolves names to key codes, as `Bindings::sources` does.
+    #[cfg(feature = "linux-input")]
+    pub(crate) fn codes(&self) -> std::collections::HashSet<EventCode> {
+        self.0
+            .iter()
+            .map(|key| key_code(key).expect("validated key name"))
+            .collect()
+    }
+}
+
+impl Default for FocusKeys {
+    fn default() -> Self {
+        // Super, which opens the launcher on most desktops.
+        Self(vec!["KEY_LEFTMETA".to_owned()])
+    }
+}
+
+impl TryFrom<Vec<String>> for FocusKeys {
+    type Error = String;
+
+    fn try_from(keys: Vec<String>) -> Result<Self, Self::Error> {
+        #[cfg(feature = "linux-input")]
+        if let Some(key) = keys.iter().find(|key| key_code(key).is_none()) {
+            return Err(format!("focus key {:?} is not a key", key));
+        }
+        Ok(Self(keys))
+    }
+}
+
+/// Resolves an evdev key name, e.g. `KEY_SPACE`.
+#[cfg(feature = "linux-input")]
+pub(crate) fn key_code(key: &str) -> Option<EventCode> {
+    EventCode::from_str(&EventType::EV_KEY, key)
+}
+
+/// Something on an input device that a button can be bound to.
+#[cfg(feature = "linux-input")]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Source {
+    /// A key or switch, held while its value is nonzero.
+    Code(EventCode),
+    /// One direction of a hat axis, held while its value points that way.
+    Hat(EventCode, Direction),
+}
+
+/// Resolves a key or switch name, e.g. `SW_LID`, or a hat axis name followed
+/// by the direction, e.g. `ABS_HAT0X-`.
+#[cfg(feature = "linux-input")]
+pub(crate) fn source(name: &str) -> Option<Source> {
+    if let Some(code) = key_code(name) {
+        return Some(Source::Code(code));
+    }
+    if name.starts_with("SW_") {
+        return EventCode::from_str(&EventType::EV_SW, name).map(Source::Code);
+    }
+    let (axis, dir) = match name.strip_suffix('+') {
+        Some(axis) => (axis, POSITIVE),
+        None => (name.strip_suffix('-')?, NEGATIVE),
+    };
+    if !axis.starts_with("ABS_HAT") {
+        return None;
+    }
+    EventCode::from_str(&EventType::EV_ABS, axis).map(|code| Source::Hat(code, dir))
+}
+
+impl TryFrom<HashMap<String, Binding>> for Bindings {
+    type Error = String;
+
+    /// Only a key bound to two different buttons is a conflict, not one bound
+    /// to the same button twice, e.g. on its own and in the button's list.
+    fn try_from(bindings: HashMap<String, Binding>) -> Result<Self, Self::Error> {
+        use serde::de::IntoDeserializer as _;
+
+        // Sorted, so that the conflict reported doesn't vary between runs.
+        let mut bindings = bindings.into_iter().collect::<Vec<_>>();
+        bindings.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let mut resolved = HashMap::new();
+        for (name, binding) in bindings {
+            let pairs = match binding {
+                Binding::Button(btn) => vec![(name, btn)],
+                Binding::Keys(keys) => {
+                    let btn = B0xxRaw::deserialize(name.as_str().into_deserializer()).map_err(
+                        |_: serde::de::value::Error| {
+                            format!("keys listed under {:?}, which is not a button", name)
+                        },
+                    )?;
+                    if keys.is_empty() {
+                        return Err(format!("no keys listed under {:?}", btn));
+                    }
+                    keys.into_iter().map(|key| (key, btn)).collect()
+                }
+            };
+            for (key, btn) in pairs {
+                match resolved.insert(key.clone(), btn) {
+                    Some(bound) if bound != btn => {
+                        return Err(format!(
+                            "key {:?} is bound to both {:?} and {:?}",
+                            key,
+                            bound.min(btn),
+                            bound.max(btn)
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self::try_from(resolved)
+    }
+}
+
+impl TryFrom<HashMap<String, B0xxRaw>> for Bindings {
+    type Error = String;
+
+    fn try_from(bindings: HashMap<String, B0xxRaw>) -> Result<Self, Self::Error> {
+        #[cfg(feature = "linux-input")]
+        if let Some(key) = bindings.keys().find(|key| source(key).is_none()) {
+            return Err(format!("unknown key {:?}", key));
+        }
+        Ok(Self(bindings))
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self(
+            [
+                ("KEY_SEMICOLON", B0xxRaw::L),
+                ("KEY_O", B0xxRaw::Left),
+                ("KEY_E", B0xxRaw::Down),
+                ("KEY_U", B0xxRaw::Right),
+                ("KEY_LEFTSHIFT", B0xxRaw::MX),
+                ("KEY_LEFTCTRL", B0xxRaw::MY),
+                ("KEY_Y", B0xxRaw::Start),
+                ("KEY_F", B0xxRaw::Start),
+                ("KEY_G", B0xxRaw::R),
+                ("KEY_C", B0xxRaw::Y),
+                ("KEY_R", B0xxRaw::LS),
+                ("KEY_S", B0xxRaw::MS),
+                ("KEY_H", B0xxRaw::B),
+                ("KEY_T", B0xxRaw::X),
+                ("KEY_N", B0xxRaw::Z),
+                ("KEY_Z", B0xxRaw::Up),
+                ("KEY_ESC", B0xxRaw::CD),
+                ("KEY_BACKSPACE", B0xxRaw::CL),
+                ("KEY_DOWN", B0xxRaw::CU),
+                ("KEY_ENTER", B0xxRaw::CR),
+                ("KEY_SPACE", B0xxRaw::A),
+            ]
+            .into_iter()
+            .map(|(key, btn)| (key.to_owned(), btn))
+            .collect(),
+        )
+    }
+}
+
+/// What turns the C-stick buttons into D-pad buttons.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DPadActivation {
+    /// Mod X and Mod Y.
+    #[default]
+    BothMods,
+    /// A dedicated D-pad layer button.
+    Layer,
+    /// Either modifier and Start.
+    ModAndStart,
+}
+
+impl DPadActivation {
+    pub(crate) fn enabled(self, state: B0xxState) -> bool {
+        match self {
+            Self::BothMods => state.contains(B0xxState::MODS),
+            Self::Layer => state.contains(B0xxState::DPAD_LAYER),
+            Self::ModAndStart => {
+                state.intersects(B0xxState::MODS) && state.contains(B0xxState::START)
+            }
+        }
+    }
+}
+
+/// Which of the controller's triggers digital L/R and the analog shield are
+/// written to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Handedness {
+    /// L to L, R to R and the analog shield to L.
+    #[default]
+    Standard,
+    /// L to R, R to L and the analog shield to R.
+    Swapped,
+}
+
+impl Handedness {
+    pub(crate) fn button(self, button: GCButton) -> GCButton {
+        match (self, button) {
+            (Self::Swapped, GCButton::L) => GCButton::R,
+            (Self::Swapped, GCButton::R) => GCButton::L,
+            _ => button,
+        }
+    }
+
+    /// Returns the pipe's name for the analog trigger.
+    pub(crate) fn analog_trigger(self) -> &'static str {
+        match self {
+            Self::Standard => "L",
+            Self::Swapped => "R",
+        }
+    }
+}
+
+/// The commands written to the pipe beyond those every Dolphin build accepts.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PipeCapability {
+    /// Presses, releases, and `SET` of the sticks and the analog shield's
+    /// trigger.
+    #[default]
+    Standard,
+    /// Also `SET` of the other trigger, fully with the press of its digital
+    /// button and back with the release.
+    Extended,
+}
+
+impl std::str::FromStr for PipeCapability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Self::Standard),
+            "extended" => Ok(Self::Extended),
+            _ => Err(format!("unknown pipe capability {:?}", s)),
+        }
+    }
+}
+
+/// The horizontal directions in which the crouch/walk option-select applies to
+/// down diagonals.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CrouchWalk {
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+}
+
+impl CrouchWalk {
+    pub(crate) fn enabled(self, x_dir: Direction) -> bool {
+        if x_dir {
+            self.right
+        } else {
+            self.left
+        }
+    }
+}
+
+/// How the A-stick resolves opposite directions held together, per axis,
+/// and how the C-stick does outside of the D-pad layer. The A-stick's
+/// horizontal axis always gives priority to the second input.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Socd {
+    pub(crate) y: YSocd,
+    pub(crate) c_stick: CStickSocd,
+}
+
+/// How each stick's coordinates depend on the other's directions in Melee.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct StickCoupling {
+    /// A C-stick direction refines the A-stick's Mod X and Mod Y diagonals,
+    /// e.g. to angle up-B.
+    pub(crate) c_stick_refines_a_stick: bool,
+    /// A vertical A-stick direction angles the C-stick with Mod X, e.g. for
+    /// an angled forward smash, and a horizontal one angles C-up and C-down
+    /// with either mod.
+    pub(crate) a_stick_angles_c_stick: bool,
+}
+
+impl Default for StickCoupling {
+    fn default() -> Self {
+        Self {
+            c_stick_refines_a_stick: true,
+            a_stick_angles_c_stick: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum YSocd {
+    /// The last direction pressed wins.
+    #[default]
+    SecondInput,
+    /// Up wins while held, whatever the press order.
+    UpPriority,
+}
+
+impl YSocd {
+    /// Returns the direction that wins while both are held, if fixed.
+    pub(crate) fn priority(self) -> Option<Direction> {
+        match self {
+            Self::SecondInput => None,
+            Self::UpPriority => Some(POSITIVE),
+        }
+    }
+}
+
+/// How the C-stick resolves opposite directions held together, on both axes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CStickSocd {
+    /// The last direction pressed wins.
+    #[default]
+    SecondInput,
+    /// Neither wins while both are held, and releasing either leaves the
+    /// other active.
+    Neutral,
+}
+
+/// Mirrors stick axes in the final output, leaving the D-pad alone.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Inversion {
+    pub(crate) a_stick: AxisInversion,
+    pub(crate) c_stick: AxisInversion,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AxisInversion {
+    pub(crate) x: bool,
+    pub(crate) y: bool,
+}
+
+impl AxisInversion {
+    pub(crate) fn apply(self, (x, y): GCStickInput) -> GCStickInput {
+        (if self.x { -x } else { x }, if self.y { -y } else { y })
+    }
+}
+
+/// Multiplies the A-stick's magnitude once its coordinates are chosen,
+/// rounding to the closest angle on the analog grid. Magnitudes beyond a full
+/// deflection are clamped to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Scale(f64);
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl Scale {
+    pub(crate) fn new(factor: f64) -> Result<Self, String> {
+        if factor.is_finite() && factor > 0.0 {
+            Ok(Self(factor))
+        } else {
+            Err(format!("scale {} is not positive", factor))
+        }
+    }
+
+    pub(crate) fn apply(self, input: GCStickInput) -> GCStickInput {
+        // Leaves the output exactly as it was by default.
+        if self == Self::default() {
+            return input;
+        }
+        let (angle, magnitude) = polar(input);
+        from_polar(angle, (magnitude * self.0).min(1.0)).expect("magnitude is within [0, 1]")
+    }
+}
+
+impl<'de> Deserialize<'de> for Scale {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Scale::new(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A modifier that can select a shield tier.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Modifier {
+    ModX,
+    ModY,
+}
+
+/// The trigger value output by pressing a shield button while holding
+/// `modifier`, or neither modifier if absent.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ShieldTier {
+    pub(crate) button: Shield,
+    #[serde(default)]
+    pub(crate) modifier: Option<Modifier>,
+    pub(crate) value: Trigger,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct ShieldTiers(Vec<ShieldTier>);
+
+impl ShieldTiers {
+    /// Returns the value of the tier matching `modifier`, falling back to the
+    /// unmodified tier of `button`.
+    pub(crate) fn select(&self, button: Shield, modifier: Option<Modifier>) -> Option<Trigger> {
+        let find = |modifier| {
+            self.0
+                .iter()
+                .find(|tier| tier.button == button && tier.modifier == modifier)
+                .map(|tier| tier.value)
+        };
+        modifier
+            .and_then(|modifier| find(Some(modifier)))
+            .or_else(|| find(None))
+    }
+}
+
+impl Default for ShieldTiers {
+    fn default() -> Self {
+        Self(vec![
+            ShieldTier {
+                button: Shield::Light,
+                modifier: None,
+                value: LS,
+            },
+            ShieldTier {
+                button: Shield::Medium,
+                modifier: None,
+                value: MS,
+            },
+            ShieldTier {
+                button: Shield::Light,
+                modifier: Some(Modifier::ModX),
+                value: Trigger::P72,
+            },
+            ShieldTier {
+                button: Shield::Light,
+                modifier: Some(Modifier::ModY),
+                value: Trigger::P110,
+            },
+        ])
+    }
+}
+
+/// Turns an opposite-direction press shortly after B into the flick needed
+/// for a B-reverse: the opposite cardinal is output briefly, after which the
+/// X axis stays neutral until a horizontal direction is next pressed or
+/// released.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BReverseHelper {
+    /// How long after B, which must be pressed while holding a horizontal
+    /// direction, the opposite direction may be pressed.
+    pub(crate) window_ms: u64,
+    /// How long the opposite direction is output for.
+    #[serde(default = "BReverseHelper::default_flick_ms")]
+    pub(crate) flick_ms: u64,
+}
+
+impl BReverseHelper {
+    fn default_flick_ms() -> u64 {
+        // Two frames, so that the flick can't fall between polls.
+        34
+    }
+
+    pub(crate) fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.window_ms)
+    }
+
+    pub(crate) fn flick(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.flick_ms)
+    }
+}
+
+/// Windows for the technique detectors, which log feedback on attempts and
+/// never change the output.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Techniques {
+    /// From jump to airdodge, i.e. the character's jumpsquat.
+    pub(crate) wavedash: FrameWindow,
+    /// How long the opposite direction is held before returning to neutral.
+    pub(crate) pivot: FrameWindow,
+    /// From leaving crouch to a horizontal dash.
+    pub(crate) dashback_out_of_crouch: FrameWindow,
+}
+
+impl Default for Techniques {
+    fn default() -> Self {
+        Self {
+            // Fox's and Falco's jumpsquat.
+            wavedash: FrameWindow { min: 3, max: 3 },
+            pivot: FrameWindow { min: 1, max: 1 },
+            dashback_out_of_crouch: FrameWindow { min: 0, max: 1 },
+        }
+    }
+}
+
+/// Repeatedly presses and releases a button while the turbo key (`TB`) is
+/// held, starting with a press and ending with a release.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "TurboSpec")]
+pub(crate) struct Turbo {
+    /// A b0xx button that outputs a controller button as is, e.g. `A` or
+    /// `DU`.
+    pub(crate) button: B0xxRaw,
+    /// Presses per second.
+    pub(crate) rate_hz: f64,
+    /// The fraction of each period the button is held for.
+    pub(crate) duty: f64,
+}
+
+impl Turbo {
+    /// Time from one press to the next.
+    pub(crate) fn period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.rate_hz.recip())
+    }
+
+    /// Time from a press to its release.
+    pub(crate) fn held(&self) -> std::time::Duration {
+        self.period().mul_f64(self.duty)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TurboSpec {
+    button: B0xxRaw,
+    #[serde(default = "TurboSpec::default_rate_hz")]
+    rate_hz: f64,
+    #[serde(default = "TurboSpec::default_duty")]
+    duty: f64,
+}
+
+impl TurboSpec {
+    fn default_rate_hz() -> f64 {
+        30.
+    }
+
+    fn default_duty() -> f64 {
+        0.5
+    }
+}
+
+impl TryFrom<TurboSpec> for Turbo {
+    type Error = String;
+
+    fn try_from(spec: TurboSpec) -> Result<Self, Self::Error> {
+        let TurboSpec {
+            button,
+            rate_hz,
+            duty,
+        } = spec;
+        if button.button().is_none() {
+            return Err(format!(
+                "turbo button {:?} is not a controller button",
+                button
+            ));
+        }
+        // Anything faster couldn't be both pressed and released within a
+        // frame.
+        if !(rate_hz > 0. && rate_hz <= 30.) {
+            return Err(format!("turbo rate_hz {} is outside (0, 30]", rate_hz));
+        }
+        if !(duty > 0. && duty < 1.) {
+            return Err(format!("turbo duty {} is outside (0, 1)", duty));
+        }
+        Ok(Self {
+            button,
+            rate_hz,
+            duty,
+        })
+    }
+}
+
+/// Makes the short-hop key (`SH`) press X and release it after a fixed time,
+/// however long the key is held.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ShortHop {
+    #[serde(default = "ShortHop::default_release_ms")]
+    pub(crate) release_ms: u64,
+}
+
+impl ShortHop {
+    fn default_release_ms() -> u64 {
+        // Within the three frames of the shortest jumpsquat, yet long enough
+        // to span a poll.
+        25
+    }
+
+    pub(crate) fn release(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.release_ms)
+    }
+}
+
+/// Caps how often the A-stick may change direction along an axis, as some
+/// rulesets require to limit SDI. Presses that come too soon are delayed, not
+/// dropped.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SdiLimit {
+    /// The least time between a press and the next press of the opposite
+    /// direction taking effect.
+    pub(crate) interval_ms: u64,
+}
+
+impl SdiLimit {
+    pub(crate) fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.interval_ms)
+    }
+}
+
+/// Turns B pressed shortly after a horizontal direction into neutral-B: the
+/// A-stick X axis outputs neutral until B is released. Mod X and Mod Y, or a
+/// vertical direction, leave B alone, since they already say which B is meant.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NeutralBHelper {
+    /// How long after the horizontal direction was pressed B may be pressed.
+    pub(crate) window_ms: u64,
+}
+
+impl NeutralBHelper {
+    pub(crate) fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.window_ms)
+    }
+}
+
+/// Resets the output to neutral when Start is held, as a way out of a desync
+/// without reaching for the keyboard. A shorter press of Start is unaffected.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StartReset {
+    #[serde(default = "StartReset::default_hold_ms")]
+    pub(crate) hold_ms: u64,
+}
+
+impl StartReset {
+    fn default_hold_ms() -> u64 {
+        2000
+    }
+
+    pub(crate) fn hold(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.hold_ms)
+    }
+}
+
+/// Makes Z press A and lightly press the analog trigger instead of pressing Z,
+/// for the timing of Z's grab out of a jump as on a controller.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ZComposite {
+    /// The analog trigger value while Z is held and no shield is.
+    #[serde(default = "ZComposite::default_trigger")]
+    pub(crate) trigger: Trigger,
+}
+
+impl ZComposite {
+    fn default_trigger() -> Trigger {
+        LS
+    }
+}
+
+/// Ramps the analog trigger from zero to `max` over `ramp_ms` while the
+/// trigger-ramp key (`TR`) is held, overriding the shield, for practicing
+/// shield pressure.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TriggerRamp {
+    #[serde(default = "TriggerRamp::default_max")]
+    pub(crate) max: Trigger,
+    #[serde(default = "TriggerRamp::default_ramp_ms")]
+    pub(crate) ramp_ms: u64,
+    /// The least time between values written, at least 1.
+    #[serde(default = "TriggerRamp::default_interval_ms")]
+    pub(crate) interval_ms: u64,
+}
+
+impl TriggerRamp {
+    fn default_max() -> Trigger {
+        Trigger::MAX
+    }
+
+    fn default_ramp_ms() -> u64 {
+        1000
+    }
+
+    fn default_interval_ms() -> u64 {
+        // A frame at 60Hz.
+        17
+    }
+
+    pub(crate) fn ramp(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.ramp_ms)
+    }
+
+    pub(crate) fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.interval_ms.max(1))
+    }
+
+    /// Returns the value `elapsed` after the key was pressed, rounded to the
+    /// nearest.
+    pub(crate) fn value_at(&self, elapsed: std::time::Duration) -> Trigger {
+        if elapsed >= self.ramp() {
+            return self.max;
+        }
+        let fraction = elapsed.as_secs_f64() / self.ramp().as_secs_f64();
+        Trigger::from_unit_f64(f64::from(self.max.get()) / coord::TRIGGER_UNIT * fraction)
+    }
+}
+
+/// Makes shield buttons take effect on release rather than press, for
+/// negative-edge powershield timing: releasing one within `max_hold_ms` of
+/// pressing it presses it for `pulse_ms`, while a longer hold does nothing.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "NegativeEdgeSpec")]
+pub(crate) struct NegativeEdge {
+    /// Any of `L`, `R`, `LS` and `MS`.
+    pub(crate) buttons: Vec<B0xxRaw>,
+    pub(crate) max_hold_ms: u64,
+    pub(crate) pulse_ms: u64,
+}
+
+impl NegativeEdge {
+    pub(crate) fn max_hold(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_hold_ms)
+    }
+
+    pub(crate) fn pulse(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.pulse_ms)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NegativeEdgeSpec {
+    buttons: Vec<B0xxRaw>,
+    #[serde(default = "NegativeEdgeSpec::default_max_hold_ms")]
+    max_hold_ms: u64,
+    #[serde(default = "NegativeEdgeSpec::default_pulse_ms")]
+    pulse_ms: u64,
+}
+
+impl NegativeEdgeSpec {
+    fn default_max_hold_ms() -> u64 {
+        200
+    }
+
+    fn default_pulse_ms() -> u64 {
+        // A frame at 60Hz.
+        17
+    }
+}
+
+impl TryFrom<NegativeEdgeSpec> for NegativeEdge {
+    type Error = String;
+
+    fn try_from(spec: NegativeEdgeSpec) -> Result<Self, Self::Error> {
+        let NegativeEdgeSpec {
+            buttons,
+            max_hold_ms,
+            pulse_ms,
+        } = spec;
+        if let Some(button) = buttons
+            .iter()
+            .find(|button| !matches!(button, B0xxRaw::L | B0xxRaw::R | B0xxRaw::LS | B0xxRaw::MS))
+        {
+            return Err(format!(
+                "negative-edge button {:?} is not L, R, LS or MS",
+                button
+            ));
+        }
+        if pulse_ms == 0 {
+            return Err("negative-edge pulse_ms must be positive".to_owned());
+        }
+        Ok(Self {
+            buttons,
+            max_hold_ms,
+            pulse_ms,
+        })
+    }
+}
+
+/// Makes digital L and R first set the analog trigger to `value` and press
+/// the button `delay_ms` later, and on release release the button and then
+/// the analog trigger, for shield interactions that want a light press ahead
+/// of the digital one.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "LightPressSpec")]
+pub(crate) struct LightPress {
+    /// Any of `L` and `R`.
+    pub(crate) buttons: Vec<B0xxRaw>,
+    pub(crate) value: Trigger,
+    pub(crate) delay_ms: u64,
+}
+
+impl LightPress {
+    pub(crate) fn delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.delay_ms)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightPressSpec {
+    buttons: Vec<B0xxRaw>,
+    #[serde(default = "LightPressSpec::default_value")]
+    value: Trigger,
+    #[serde(default = "LightPressSpec::default_delay_ms")]
+    delay_ms: u64,
+}
+
+impl LightPressSpec {
+    fn default_value() -> Trigger {
+        LS
+    }
+
+    fn default_delay_ms() -> u64 {
+        // A frame at 60Hz.
+        17
+    }
+}
+
+impl TryFrom<LightPressSpec> for LightPress {
+    type Error = String;
+
+    fn try_from(spec: LightPressSpec) -> Result<Self, Self::Error> {
+        let LightPressSpec {
+            buttons,
+            value,
+            delay_ms,
+        } = spec;
+        if let Some(button) = buttons
+            .iter()
+            .find(|button| !matches!(button, B0xxRaw::L | B0xxRaw::R))
+        {
+            return Err(format!("light-press button {:?} is not L or R", button));
+        }
+        if value == Trigger::Z {
+            return Err("light-press value must be positive".to_owned());
+        }
+        if delay_ms == 0 {
+            return Err("light-press delay_ms must be positive".to_owned());
+        }
+        Ok(Self {
+            buttons,
+            value,
+            delay_ms,
+        })
+    }
+}
+
+/// Makes digital L and R hold the analog trigger at `value` instead when
+/// pressed twice within `window_ms`, until released, for an analog shield
+/// from a key. A single press takes effect once the window passes.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "DoubleTapSpec")]
+pub(crate) struct DoubleTap {
+    /// Any of `L` and `R`.
+    pub(crate) buttons: Vec<B0xxRaw>,
+    pub(crate) value: Trigger,
+    pub(crate) window_ms: u64,
+}
+
+impl DoubleTap {
+    pub(crate) fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.window_ms)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DoubleTapSpec {
+    buttons: Vec<B0xxRaw>,
+    #[serde(default = "DoubleTapSpec::default_value")]
+    value: Trigger,
+    #[serde(default = "DoubleTapSpec::default_window_ms")]
+    window_ms: u64,
+}
+
+impl DoubleTapSpec {
+    fn default_value() -> Trigger {
+        // 0.5 in the pipe's terms.
+        Trigger::P64
+    }
+
+    fn default_window_ms() -> u64 {
+        150
+    }
+}
+
+impl TryFrom<DoubleTapSpec> for DoubleTap {
+    type Error = String;
+
+    fn try_from(spec: DoubleTapSpec) -> Result<Self, Self::Error> {
+        let DoubleTapSpec {
+            buttons,
+            value,
+            window_ms,
+        } = spec;
+        if let Some(button) = buttons
+            .iter()
+            .find(|button| !matches!(button, B0xxRaw::L | B0xxRaw::R))
+        {
+            return Err(format!("double-tap button {:?} is not L or R", button));
+        }
+        if value == Trigger::Z {
+            return Err("double-tap value must be positive".to_owned());
+        }
+        if window_ms == 0 {
+            return Err("double-tap window_ms must be positive".to_owned());
+        }
+        Ok(Self {
+            buttons,
+            value,
+            window_ms,
+        })
+    }
+}
+
+/// What a shield button does in place of its usual handling, so that one key
+/// can hold an analog value alone and another press digital L or R alone.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum ShieldBehavior {
+    /// L and R press their button and drop the analog trigger while held, and
+    /// LS and MS set their shield tier.
+    #[default]
+    Standard,
+    /// Presses `L` or `R` alone, leaving the analog trigger as it is.
+    Digital(B0xxRaw),
+    /// Sets the analog trigger like a shield tier, and never presses L or R.
+    Analog(Trigger),
+    /// Sets the analog trigger like `Analog`, and presses `button` as well
+    /// once held for `hold_ms`.
+    AnalogThenDigital {
+        value: Trigger,
+        button: B0xxRaw,
+        hold_ms: u64,
+    },
+}
+
+/// The shield behavior of each of L, R, LS and MS with one.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<B0xxRaw, ShieldBehavior>")]
+pub(crate) struct ShieldBehaviors(BTreeMap<B0xxRaw, ShieldBehavior>);
+
+impl ShieldBehaviors {
+    pub(crate) fn get(&self, btn: B0xxRaw) -> ShieldBehavior {
+        self.0.get(&btn).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = ShieldBehavior> + '_ {
+        self.0.values().copied()
+    }
+
+    /// Returns the behaviors with `AnalogThenDigital` holding its analog value
+    /// alone, as `Analog` does, so that nothing is pressed on a timer.
+    pub(crate) fn untimed(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(&btn, &behavior)| {
+                    let behavior = match behavior {
+                        ShieldBehavior::AnalogThenDigital { value, .. } => {
+                            ShieldBehavior::Analog(value)
+                        }
+                        behavior => behavior,
+                    };
+                    (btn, behavior)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl TryFrom<BTreeMap<B0xxRaw, ShieldBehavior>> for ShieldBehaviors {
+    type Error = String;
+
+    fn try_from(behaviors: BTreeMap<B0xxRaw, ShieldBehavior>) -> Result<Self, Self::Error> {
+        for (&btn, &behavior) in &behaviors {
+            if !matches!(btn, B0xxRaw::L | B0xxRaw::R | B0xxRaw::LS | B0xxRaw::MS) {
+                return Err(format!(
+                    "shield behavior of {:?}, which is not L, R, LS or MS",
+                    btn
+                ));
+            }
+            match behavior {
+                ShieldBehavior::Standard => {}
+                ShieldBehavior::Digital(button)
+                | ShieldBehavior::AnalogThenDigital { button, .. }
+                    if !matches!(button, B0xxRaw::L | B0xxRaw::R) =>
+                {
+                    return Err(format!(
+                        "shield behavior of {:?} presses {:?}, which is not L or R",
+                        btn, button
+                    ));
+                }
+                ShieldBehavior::Digital(_) => {}
+                ShieldBehavior::Analog(value) | ShieldBehavior::AnalogThenDigital { value, .. }
+                    if value == Trigger::Z =>
+                {
+                    return Err(format!("shield behavior of {:?} has no analog value", btn));
+                }
+                ShieldBehavior::Analog(_) => {}
+                ShieldBehavior::AnalogThenDigital { hold_ms, .. } => {
+                    if hold_ms == 0 {
+                        return Err(format!("shield behavior of {:?} has no hold_ms", btn));
+                    }
+                }
+            }
+        }
+        Ok(Self(behaviors))
+    }
+}
+
+/// Makes a button act as `hold` once held for `hold_ms`, and as itself,
+/// pressed and released at once, when released sooner.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TapHold {
+    pub(crate) hold: B0xxRaw,
+    pub(crate) hold_ms: u64,
+}
+
+impl TapHold {
+    pub(crate) fn threshold(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.hold_ms)
+    }
+}
+
+/// The tap-hold behaviour of each button with one.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<B0xxRaw, TapHold>")]
+pub(crate) struct TapHolds(BTreeMap<B0xxRaw, TapHold>);
+
+impl TapHolds {
+    pub(crate) fn get(&self, btn: B0xxRaw) -> Option<TapHold> {
+        self.0.get(&btn).copied()
+    }
+
+    /// Returns whether holding some button presses `btn`.
+    pub(crate) fn holds(&self, btn: B0xxRaw) -> bool {
+        self.0.values().any(|tap_hold| tap_hold.hold == btn)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<BTreeMap<B0xxRaw, TapHold>> for TapHolds {
+    type Error = String;
+
+    fn try_from(tap_holds: BTreeMap<B0xxRaw, TapHold>) -> Result<Self, Self::Error> {
+        if let Some(btn) = tap_holds
+            .iter()
+            .find_map(|(&btn, tap_hold)| (tap_hold.hold == btn).then_some(btn))
+        {
+            return Err(format!("tap-hold button {:?} holds itself", btn));
+        }
+        Ok(Self(tap_holds))
+    }
+}
+
+/// What pressing a macro key does while a macro is already playing.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Repress {
+    /// Lets the macro playing finish.
+    #[default]
+    Ignore,
+    /// Releases everything and plays the macro from the start.
+    Restart,
+}
+
+/// One step of a macro, acting on the output directly rather than through
+/// the buttons of the b0xx.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MacroStep {
+    Press(GCButton),
+    Release(GCButton),
+    AStick(GCStickInput),
+    CStick(GCStickInput),
+    Trigger(Trigger),
+    /// Releases every button and centres the sticks and the trigger.
+    ReleaseAll,
+    WaitMs(u64),
+}
+
+/// A sequence of steps played when its key is pressed, for practice drills.
+/// Not for netplay.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Macro {
+    #[serde(default)]
+    pub(crate) repress: Repress,
+    pub(crate) steps: Vec<MacroStep>,
+}
+
+/// An internal command a combo can run.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ComboAction {
+    /// Resets the output to neutral, as a held Start does with `start_reset`.
+    Reset,
+    /// Stops writing to the pipe, leaving the controller neutral, or resumes.
+    Pause,
+    /// Logs a snapshot of the input state, as `--dump-key` does.
+    Snapshot,
+    /// Writes the session statistics, as SIGUSR1 does.
+    WriteStats,
+    /// Selects the next character's coordinates, in name order, and the
+    /// profile's own after the last.
+    NextCharacter,
+    /// Switches to the config's profile of this name, keeping the buttons
+    /// held that are still bound, e.g. `{ set_profile = "fox" }`.
+    SetProfile(String),
+}
+
+/// Runs `action` once `buttons` have all been held together for `hold_ms`.
+/// The buttons act as usual meanwhile.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "ComboSpec")]
+pub(crate) struct Combo {
+    pub(crate) buttons: std::collections::BTreeSet<B0xxRaw>,
+    pub(crate) hold_ms: u64,
+    pub(crate) action: ComboAction,
+}
+
+impl Combo {
+    pub(crate) fn hold(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.hold_ms)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComboSpec {
+    buttons: Vec<B0xxRaw>,
+    #[serde(default = "ComboSpec::default_hold_ms")]
+    hold_ms: u64,
+    action: ComboAction,
+}
+
+impl ComboSpec {
+    fn default_hold_ms() -> u64 {
+        1000
+    }
+}
+
+impl TryFrom<ComboSpec> for Combo {
+    type Error = String;
+
+    fn try_from(spec: ComboSpec) -> Result<Self, Self::Error> {
+        let ComboSpec {
+            buttons,
+            hold_ms,
+            action,
+        } = spec;
+        if buttons.is_empty() {
+            return Err("combo has no buttons".to_owned());
+        }
+        let mut set = std::collections::BTreeSet::new();
+        if let Some(btn) = buttons.iter().find(|&&btn| !set.insert(btn)) {
+            return Err(format!("combo lists {:?} twice", btn));
+        }
+        Ok(Self {
+            buttons: set,
+            hold_ms,
+            action,
+        })
+    }
+}
+
+/// The combos in order of precedence: those with more buttons first, then as
+/// listed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "Vec<Combo>")]
+pub(crate) struct Combos(pub(crate) Vec<Combo>);
+
+impl TryFrom<Vec<Combo>> for Combos {
+    type Error = String;
+
+    fn try_from(mut combos: Vec<Combo>) -> Result<Self, Self::Error> {
+        for (i, combo) in combos.iter().enumerate() {
+            if combos[..i]
+                .iter()
+                .any(|other| other.buttons == combo.buttons)
+            {
+                return Err(format!("two combos hold {:?}", combo.buttons));
+            }
+        }
+        // Stable, so that ties stay as listed.
+        combos.sort_by_key(|combo| std::cmp::Reverse(combo.buttons.len()));
+        Ok(Self(combos))
+    }
+}
+
+/// The macro bound to each macro key (`M1` to `M4`).
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "BTreeMap<B0xxRaw, Macro>")]
+pub(crate) struct Macros(BTreeMap<B0xxRaw, Macro>);
+
+impl Macros {
+    pub(crate) fn get(&self, key: B0xxRaw) -> Option<&Macro> {
+        self.0.get(&key)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<BTreeMap<B0xxRaw, Macro>> for Macros {
+    type Error = String;
+
+    fn try_from(macros: BTreeMap<B0xxRaw, Macro>) -> Result<Self, Self::Error> {
+        if let Some(key) = macros
+            .keys()
+            .find(|key| !matches!(key, B0xxRaw::M1 | B0xxRaw::M2 | B0xxRaw::M3 | B0xxRaw::M4))
+        {
+            return Err(format!("macro key {:?} is not M1, M2, M3 or M4", key));
+        }
+        Ok(Self(macros))
+    }
+}
+
+/// Emulates the B0XX 2.1 firmware's nerf of rapid left/right alternation:
+/// each press against the held opposite direction on the A-stick X axis beyond
+/// `max_reactivations` within `window_ms` outputs neutral for a frame before
+/// taking effect.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LedgedashNerf {
+    pub(crate) max_reactivations: usize,
+    pub(crate) window_ms: u64,
+}
+
+impl LedgedashNerf {
+    pub(crate) fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.window_ms)
+    }
+}
+
+/// An inclusive range of frames at 60Hz.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FrameWindow {
+    pub(crate) min: u32,
+    pub(crate) max: u32,
+}
+
+impl FrameWindow {
+    pub(crate) fn contains(self, frames: u32) -> bool {
+        (self.min..=self.max).contains(&frames)
+    }
+}
+
+/// Coordinates are given in raw analog units, i.e. multiples of 0.0125.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Coordinates {
+    /// A-stick diagonal while the tilt modifier is held without Mod X/Y.
+    pub(crate) mod_tilt_diagonal: GCStickInput,
+    /// C-stick cardinal magnitude in tilt mode.
+    pub(crate) c_stick_tilt_cardinal: Analog,
+    /// C-stick diagonal in tilt mode.
+    pub(crate) c_stick_tilt_diagonal: GCStickInput,
+    /// A-stick down diagonal with the crouch/walk option-select.
+    pub(crate) crouch_walk_diagonal: GCStickInput,
+    /// C-stick up/down angled toward the A-stick X direction held alone with
+    /// Mod X.
+    pub(crate) c_stick_mod_x_vertical: GCStickInput,
+    /// As `c_stick_mod_x_vertical`, with Mod Y.
+    pub(crate) c_stick_mod_y_vertical: GCStickInput,
+}
+
+impl std::fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            mod_tilt_diagonal,
+            c_stick_tilt_cardinal,
+            c_stick_tilt_diagonal,
+            crouch_walk_diagonal,
+            c_stick_mod_x_vertical,
+            c_stick_mod_y_vertical,
+        } = *self;
+        for (name, input) in [
+            ("mod_tilt_diagonal", mod_tilt_diagonal),
+            ("c_stick_tilt_diagonal", c_stick_tilt_diagonal),
+            ("crouch_walk_diagonal", crouch_walk_diagonal),
+            ("c_stick_mod_x_vertical", c_stick_mod_x_vertical),
+            ("c_stick_mod_y_vertical", c_stick_mod_y_vertical),
+        ] {
+            let (angle, magnitude) = polar(input);
+            writeln!(
+                f,
+                "{} = [{}, {}] ({:.2} degrees, magnitude {:.4})",
+                name,
+                input.0.get(),
+                input.1.get(),
+                angle,
+                magnitude
+            )?;
+        }
+        writeln!(f, "c_stick_tilt_cardinal = {}", c_stick_tilt_cardinal.get())
+    }
+}
+
+impl Profile {
+    /// Returns the coordinates in effect with no character selected: the
+    /// defaults, overridden by the profile's `coordinates`.
+    pub(crate) fn own_coordinates(&self) -> Coordinates {
+        let mut coordinates = Coordinates::default();
+        self.coordinates.apply(&mut coordinates);
+        coordinates
+    }
+
+    /// Returns the coordinates in effect with `character` selected: the
+    /// profile's own, overridden in turn by the character's.
+    pub(crate) fn coordinates_for(&self, character: Option<&str>) -> Result<Coordinates, String> {
+        let mut coordinates = self.own_coordinates();
+        if let Some(character) = character {
+            self.characters
+                .0
+                .get(character)
+                .ok_or_else(|| format!("profile has no character {:?}", character))?
+                .apply(&mut coordinates);
+        }
+        Ok(coordinates)
+    }
+}
+
+/// Coordinate overrides by character name, e.g. `fox` and `falco`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Characters(pub(crate) BTreeMap<String, CoordinateOverrides>);
+
+impl Characters {
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the character after `character` in name order, or none after
+    /// the last, or the first after none.
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    pub(crate) fn next(&self, character: Option<&str>) -> Option<&str> {
+        let mut names = self.0.keys().map(String::as_str);
+        match character {
+            None => names.next(),
+            Some(character) => names.find(|&name| name > character),
+        }
+    }
+}
+
+/// Any of the entries of `Coordinates`, each overriding it where present.
+/// Pairs may instead be given by angle and magnitude, see [`polar`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CoordinateOverrides {
+    #[serde(deserialize_with = "optional_coordinate")]
+    pub(crate) mod_tilt_diagonal: Option<GCStickInput>,
+    pub(crate) c_stick_tilt_cardinal: Option<Analog>,
+    #[serde(deserialize_with = "optional_coordinate")]
+    pub(crate) c_stick_tilt_diagonal: Option<GCStickInput>,
+    #[serde(deserialize_with = "optional_coordinate")]
+    pub(crate) crouch_walk_diagonal: Option<GCStickInput>,
+    #[serde(deserialize_with = "optional_coordinate")]
+    pub(crate) c_stick_mod_x_vertical: Option<GCStickInput>,
+    #[serde(deserialize_with = "optional_coordinate")]
+    pub(crate) c_stick_mod_y_vertical: Option<GCStickInput>,
+}
+
+impl From<Coordinates> for CoordinateOverrides {
+    fn from(coordinates: Coordinates) -> Self {
+        let Coordinates {
+            mod_tilt_diagonal,
+            c_stick_tilt_cardinal,
+            c_stick_tilt_diagonal,
+            crouch_walk_diagonal,
+            c_stick_mod_x_vertical,
+            c_stick_mod_y_vertical,
+        } = coordinates;
+        Self {
+            mod_tilt_diagonal: Some(mod_tilt_diagonal),
+            c_stick_tilt_cardinal: Some(c_stick_tilt_cardinal),
+            c_stick_tilt_diagonal: Some(c_stick_tilt_diagonal),
+            crouch_walk_diagonal: Some(crouch_walk_diagonal),
+            c_stick_mod_x_vertical: Some(c_stick_mod_x_vertical),
+            c_stick_mod_y_vertical: Some(c_stick_mod_y_vertical),
+        }
+    }
+}
+
+impl CoordinateOverrides {
+    pub(crate) fn apply(&self, coordinates: &mut Coordinates) {
+        let Self {
+            mod_tilt_diagonal,
+            c_stick_tilt_cardinal,
+            c_stick_tilt_diagonal,
+            crouch_walk_diagonal,
+            c_stick_mod_x_vertical,
+            c_stick_mod_y_vertical,
+        } = *self;
+        let override_with = |coordinate: &mut GCStickInput, with: Option<GCStickInput>| {
+            if let Some(with) = with {
+                *coordinate = with;
+            }
+        };
+        override_with(&mut coordinates.mod_tilt_diagonal, mod_tilt_diagonal);
+        if let Some(cardinal) = c_stick_tilt_cardinal {
+            coordinates.c_stick_tilt_cardinal = cardinal;
+        }
+        override_with(
+            &mut coordinates.c_stick_tilt_diagonal,
+            c_stick_tilt_diagonal,
+        );
+        override_with(&mut coordinates.crouch_walk_diagonal, crouch_walk_diagonal);
+        override_with(
+            &mut coordinates.c_stick_mod_x_vertical,
+            c_stick_mod_x_vertical,
+        );
+        override_with(
+            &mut coordinates.c_stick_mod_y_vertical,
+            c_stick_mod_y_vertical,
+        );
+    }
+}
+
+/// Rounding a polar coordinate onto the analog grid by more than this warns.
+const ANGLE_TOLERANCE_DEGREES: f64 = 0.25;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Coordinate {
+    Raw(GCStickInput),
+    Polar { angle: f64, magnitude: f64 },
+}
+
+fn coordinate<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<GCStickInput, D::Error> {
+    match Coordinate::deserialize(deserializer)? {
+        Coordinate::Raw(input) => Ok(input),
+        Coordinate::Polar { angle, magnitude } => {
+            let input = from_polar(angle, magnitude).map_err(serde::de::Error::custom)?;
+            let (actual, _) = polar(input);
+            if (actual - angle).abs() > ANGLE_TOLERANCE_DEGREES {
+                warn!(
+                    "{} degrees at magnitude {} rounds to [{}, {}] at {:.2} degrees",
+                    angle,
+                    magnitude,
+                    input.0.get(),
+                    input.1.get(),
+                    actual
+                );
+            }
+            Ok(input)
+        }
+    }
+}
+
+fn optional_coordinate<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<GCStickInput>, D::Error> {
+    coordinate(deserializer).map(Some)
+}
+
+/// Converts an angle in degrees counterclockwise from the positive X axis and
+/// a magnitude in [0, 1] to the analog coordinate closest in angle, breaking
+/// ties by magnitude. The result always lies within the unit circle, since
+/// anything outside it is clamped by the game.
+pub(crate) fn from_polar(angle: f64, magnitude: f64) -> Result<GCStickInput, String> {
+    if !angle.is_finite() {
+        return Err(format!("angle {} is not finite", angle));
+    }
+    if !(0.0..=1.0).contains(&magnitude) {
+        return Err(format!("magnitude {} is outside [0, 1]", magnitude));
+    }
+    let max = f64::from(Analog::MAX.get());
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (x, y) = (cos * magnitude * max, sin * magnitude * max);
+    let candidates = |v: f64| [v.floor(), v.ceil()];
+    candidates(x)
+        .into_iter()
+        .flat_map(|x| candidates(y).into_iter().map(move |y| (x, y)))
+        .filter(|&(x, y)| x.hypot(y) <= max)
+        .map(|(x, y)| {
+            let angle_error = if (x, y) == (0.0, 0.0) {
+                0.0
+            } else {
+                let diff = (y.atan2(x).to_degrees() - angle).rem_euclid(360.0);
+                diff.min(360.0 - diff)
+            };
+            let magnitude_error = (x.hypot(y) / max - magnitude).abs();
+            ((angle_error, magnitude_error), (x, y))
+        })
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).expect("errors are finite"))
+        .map(|(_, (x, y))| {
+            (
+                Analog::from_melee_f64_saturating(x),
+                Analog::from_melee_f64_saturating(y),
+            )
+        })
+        .ok_or_else(|| {
+            format!(
+                "no coordinate at {} degrees, magnitude {}",
+                angle, magnitude
+            )
+        })
+}
+
+/// Converts an analog coordinate to an angle in degrees in (-180, 180] and a
+/// magnitude, where 1 is a full deflection.
+pub(crate) fn polar((x, y): GCStickInput) -> (f64, f64) {
+    let (x, y) = (f64::from(x.get()), f64::from(y.get()));
+    (
+        y.atan2(x).to_degrees(),
+        x.hypot(y) / f64::from(Analog::MAX.get()),
+    )
+}
+
+impl Default for Coordinates {
+    fn default() -> Self {
+        Self {
+            mod_tilt_diagonal: (P5375, P5375),
+            c_stick_tilt_cardinal: P6000,
+            c_stick_tilt_diagonal: (P3125, P5125),
+            crouch_walk_diagonal: (P7125, P6875),
+            c_stick_mod_x_vertical: (P4375, P7875),
+            c_stick_mod_y_vertical: (P2875, P8125),
+        }
+    }
+}
+
+/// Writes the bindings as a config file would, with keys that alias each other
+/// listed under their button after the rest, e.g. `Start = ["KEY_F", "KEY_Y"]`.
+impl std::fmt::Display for Bindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut keys = BTreeMap::<B0xxRaw, Vec<&str>>::new();
+        for (key, &btn) in &self.0 {
+            keys.entry(btn).or_default().push(key);
+        }
+        let mut lines = Vec::new();
+        let mut aliases = Vec::new();
+        for (btn, mut keys) in keys {
+            keys.sort_unstable();
+            match keys[..] {
+                [key] => lines.push(format!("{} = \"{:?}\"", key, btn)),
+                _ => aliases.push(format!("{:?} = {:?}", btn, keys)),
+            }
+        }
+        lines.sort_unstable();
+        lines.extend(aliases);
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            bindings,
+            coordinates: _,
+            characters,
+            c_stick_mode,
+            b_reverse,
+            shield_tiers,
+            invert,
+            a_stick_scale,
+            crouch_walk,
+            dpad,
+            socd,
+            stick_coupling,
+            handedness,
+            techniques,
+            turbo,
+            short_hop,
+            sdi_limit,
+            ledgedash_nerf,
+            neutral_b,
+            start_reset,
+            z_composite,
+            trigger_ramp,
+            negative_edge,
+            light_press,
+            double_tap,
+            shield_behavior,
+            macros,
+            tap_hold,
+            autorepeat,
+            combos,
+        } = self;
+        writeln!(f, "[bindings]")?;
+        writeln!(f, "{}", bindings)?;
+        writeln!(f, "\n[coordinates]\n{}", self.own_coordinates())?;
+        writeln!(f, "characters = {:?}", characters.0)?;
+        writeln!(f, "c_stick_mode = {:?}", c_stick_mode)?;
+        writeln!(f, "b_reverse = {:?}", b_reverse)?;
+        writeln!(f, "shield_tiers = {:?}", shield_tiers.0)?;
+        writeln!(f, "invert = {:?}", invert)?;
+        writeln!(f, "a_stick_scale = {:?}", a_stick_scale.0)?;
+        writeln!(f, "crouch_walk = {:?}", crouch_walk)?;
+        writeln!(f, "dpad = {:?}", dpad)?;
+        writeln!(f, "socd = {:?}", socd)?;
+        writeln!(f, "stick_coupling = {:?}", stick_coupling)?;
+        writeln!(f, "handedness = {:?}", handedness)?;
+        writeln!(f, "techniques = {:?}", techniques)?;
+        writeln!(f, "turbo = {:?}", turbo)?;
+        writeln!(f, "short_hop = {:?}", short_hop)?;
+        writeln!(f, "sdi_limit = {:?}", sdi_limit)?;
+        writeln!(f, "ledgedash_nerf = {:?}", ledgedash_nerf)?;
+        writeln!(f, "neutral_b = {:?}", neutral_b)?;
+        writeln!(f, "start_reset = {:?}", start_reset)?;
+        writeln!(f, "z_composite = {:?}", z_composite)?;
+        writeln!(f, "trigger_ramp = {:?}", trigger_ramp)?;
+        writeln!(f, "negative_edge = {:?}", negative_edge)?;
+        writeln!(f, "light_press = {:?}", light_press)?;
+        writeln!(f, "double_tap = {:?}", double_tap)?;
+        writeln!(f, "shield_behavior = {:?}", shield_behavior.0)?;
+        writeln!(f, "macros = {:?}", macros.0)?;
+        writeln!(f, "tap_hold = {:?}", tap_hold.0)?;
+        writeln!(f, "autorepeat = {:?}", autorepeat.0)?;
+        write!(f, "combos = {:?}", combos.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Trigger {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u8::deserialize(deserializer)?;
+        Trigger::new(value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "{} is outside the trigger range [{}, {}]",
+                value,
+                Trigger::MIN.get(),
+                Trigger::MAX.get()
+            ))
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Analog {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = i8::deserialize(deserializer)?;
+        Analog::new(value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "{} is outside the analog range [{}, {}]",
+                value,
+                Analog::MIN.get(),
+                Analog::MAX.get()
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "linux-input")]
+    use evdev_rs::enums::{EV_ABS, EV_KEY, EV_SW};
+    use test_case::test_case;
+
+    #[test_case(0.0, 1.0, (80, 0); "right")]
+    #[test_case(90.0, 1.0, (0, 80); "up")]
+    #[test_case(180.0, 1.0, (-80, 0); "left")]
+    #[test_case(-90.0, 1.0, (0, -80); "down")]
+    #[test_case(270.0, 1.0, (0, -80); "down_wrapped")]
+    #[test_case(45.0, 1.0, (56, 56); "diagonal_inside_circle")]
+    #[test_case(22.96, 0.801, (59, 25); "mod_x_diagonal")]
+    #[test_case(17.0, 1.0, (76, 23); "firefox")]
+    #[test_case(17.0, 0.0, (0, 0); "zero_magnitude")]
+    fn from_polar_rounds(angle: f64, magnitude: f64, (x, y): (i8, i8)) {
+        let want = (Analog::new(x).unwrap(), Analog::new(y).unwrap());
+        assert_eq!(from_polar(angle, magnitude), Ok(want));
+    }
+
+    #[test_case(0.0, 1.01; "magnitude_too_large")]
+    #[test_case(0.0, -0.1; "magnitude_negative")]
+    #[test_case(f64::NAN, 1.0; "angle_nan")]
+    fn from_polar_rejects(angle: f64, magnitude: f64) {
+        assert!(from_polar(angle, magnitude).is_err());
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case("KEY_SPACE", Some(Source::Code(EventCode::EV_KEY(EV_KEY::KEY_SPACE))); "key")]
+    #[test_case("SW_LID", Some(Source::Code(EventCode::EV_SW(EV_SW::SW_LID))); "switch")]
+    #[test_case("ABS_HAT0X-", Some(Source::Hat(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), NEGATIVE)); "hat_negative")]
+    #[test_case("ABS_HAT1Y+", Some(Source::Hat(EventCode::EV_ABS(EV_ABS::ABS_HAT1Y), POSITIVE)); "hat_positive")]
+    #[test_case("ABS_HAT0X", None; "hat_without_direction")]
+    #[test_case("ABS_X+", None; "not_a_hat")]
+    #[test_case("SW_NONE", None; "unknown_switch")]
+    #[test_case("-", None; "only_direction")]
+    fn source_names(name: &str, want: Option<Source>) {
+        assert_eq!(source(name), want);
+    }
+
+    #[test_case((80, 0), 0.85, (68, 0); "cardinal")]
+    #[test_case((0, -80), 0.5, (0, -40); "cardinal_halved")]
+    #[test_case((56, 56), 0.85, (48, 48); "diagonal")]
+    #[test_case((59, 25), 0.85, (50, 21); "mod_x_diagonal")]
+    #[test_case((-27, 59), 0.9, (-24, 53); "mod_y_diagonal")]
+    #[test_case((80, 0), 1.2, (80, 0); "clamped")]
+    #[test_case((56, 56), 1.5, (56, 56); "diagonal_clamped")]
+    #[test_case((1, 0), 0.3, (0, 0); "rounded_to_neutral")]
+    fn scale_rounds((x, y): (i8, i8), factor: f64, (want_x, want_y): (i8, i8)) {
+        let input = (Analog::new(x).unwrap(), Analog::new(y).unwrap());
+        let want = (Analog::new(want_x).unwrap(), Analog::new(want_y).unwrap());
+        assert_eq!(Scale::new(factor).unwrap().apply(input), want);
+    }
+
+    #[test_case(0.0; "zero")]
+    #[test_case(-0.5; "negative")]
+    #[test_case(f64::INFINITY; "infinite")]
+    fn scale_rejects(factor: f64) {
+        assert!(Scale::new(factor).is_err());
+    }
+
+    #[test]
+    fn scale_keeps_angle() {
+        let all = || (-80..=80).filter_map(Analog::new);
+        for factor in [0.5, 0.75, 0.85, 0.95] {
+            let scale = Scale::new(factor).unwrap();
+            for input in all().flat_map(|x| all().map(move |y| (x, y))) {
+                let (angle, magnitude) = polar(input);
+                if magnitude > 1.0 || magnitude * factor < 0.5 {
+                    continue;
+                }
+                let (actual, actual_magnitude) = polar(scale.apply(input));
+                let diff = (actual - angle).rem_euclid(360.0);
+                assert!(
+                    diff.min(360.0 - diff) < 1.0,
+                    "{:?} scaled by {} is at {} degrees",
+                    input,
+                    factor,
+                    actual
+                );
+                assert!((actual_magnitude - magnitude * factor).abs() < 0.02);
+            }
+        }
+    }
+
+    #[test]
+    fn unit_scale_is_identity() {
+        let all = || (-80..=80).filter_map(Analog::new);
+        for input in all().flat_map(|x| all().map(move |y| (x, y))) {
+            assert_eq!(Scale::default().apply(input), input);
+        }
+    }
+
+    #[test]
+    fn polar_inverts_from_polar() {
+        for angle in [0.0, 17.0, 22.96, 45.0, 90.0, 135.0, -90.0] {
+            let (actual, magnitude) = polar(from_polar(angle, 1.0).unwrap());
+            assert!((actual - angle).abs() < 0.5, "{} != {}", actual, angle);
+            assert!(magnitude <= 1.0 && magnitude > 0.98, "{}", magnitude);
+        }
+    }
+
+    #[test]
+    fn trigger_ramp_values() {
+        let ramp = TriggerRamp {
+            max: Trigger::MAX,
+            ramp_ms: 1000,
+            interval_ms: 0,
+        };
+        let at = |ms| ramp.value_at(std::time::Duration::from_millis(ms)).get();
+        assert_eq!(
+            [at(0), at(1), at(7), at(500), at(999), at(1000), at(5000)],
+            [0, 0, 1, 70, 140, 140, 140]
+        );
+        assert_eq!(ramp.interval(), std::time::Duration::from_millis(1));
+    }
+
+    #[test_case("KEY_Y = \"Start\"\nKEY_F = \"Start\"", None; "aliased_keys")]
+    #[test_case("Start = [\"KEY_Y\", \"KEY_F\"]", None; "listed_keys")]
+    #[test_case("Start = [\"KEY_Y\", \"KEY_F\"]\nKEY_Y = \"Start\"", None; "listed_and_alone")]
+    #[test_case("Start = [\"KEY_Y\"]\nKEY_Y = \"A\"", Some("key \"KEY_Y\" is bound to both A and Start"); "listed_and_other")]
+    #[test_case("Start = [\"KEY_Y\"]\nA = [\"KEY_Y\"]", Some("key \"KEY_Y\" is bound to both A and Start"); "listed_twice")]
+    #[test_case("Start = []", Some("no keys listed under Start"); "no_keys")]
+    #[test_case("KEY_Y = [\"KEY_F\"]", Some("keys listed under \"KEY_Y\", which is not a button"); "not_a_button")]
+    fn bindings_validated(toml: &str, want: Option<&str>) {
+        let result = toml::from_str::<Bindings>(toml);
+        match want {
+            None => {
+                let bindings = result.unwrap();
+                assert_eq!(bindings.keys(B0xxRaw::Start), ["KEY_F", "KEY_Y"][..]);
+            }
+            Some(want) => {
+                let e = result.unwrap_err().to_string();
+                assert!(e.contains(want), "{}", e);
+            }
+        }
+    }
+
+    // Printing the bindings groups the keys of each button bound more than
+    // once, and parses back to the same bindings.
+    #[test_case("KEY_SPACE = \"A\"\nStart = [\"KEY_F\", \"KEY_Y\"]"; "grouped")]
+    #[test_case("KEY_SPACE = \"A\"\nKEY_Y = \"Start\"\nKEY_F = \"Start\""; "aliased_keys")]
+    #[test_case("KEY_H = \"B\"\nKEY_SPACE = \"A\""; "no_aliases")]
+    fn bindings_round_trip(toml: &str) {
+        let bindings = toml::from_str::<Bindings>(toml).unwrap();
+        let printed = bindings.to_string();
+        let reparsed = toml::from_str::<Bindings>(&printed).unwrap();
+        assert_eq!(reparsed.0, bindings.0);
+        assert_eq!(reparsed.to_string(), printed);
+        if bindings.keys(B0xxRaw::Start).len() > 1 {
+            assert!(
+                printed.ends_with("Start = [\"KEY_F\", \"KEY_Y\"]"),
+                "{}",
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn default_bindings_round_trip() {
+        let printed = Bindings::default().to_string();
+        assert!(
+            printed.contains("Start = [\"KEY_F\", \"KEY_Y\"]"),
+            "{}",
+            printed
+        );
+        assert_eq!(
+            toml::from_str::<Bindings>(&printed).unwrap().0,
+            Bindings::default().0
+        );
+    }
+
+    #[test_case(B0xxRaw::LS, None; "other")]
+    #[test_case(B0xxRaw::L, Some("tap-hold button L holds itself"); "itself")]
+    fn tap_hold_validated(hold: B0xxRaw, want: Option<&str>) {
+        let tap_holds = [(B0xxRaw::L, TapHold { hold, hold_ms: 150 })];
+        let result = TapHolds::try_from(tap_holds.into_iter().collect::<BTreeMap<_, _>>());
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(B0xxRaw::M1, None; "macro key")]
+    #[test_case(B0xxRaw::TB, Some("macro key TB is not M1, M2, M3 or M4"); "other key")]
+    fn macro_keys_validated(key: B0xxRaw, want: Option<&str>) {
+        let macros = [(
+            key,
+            Macro {
+                repress: Repress::Ignore,
+                steps: vec![MacroStep::Press(GCButton::A)],
+            },
+        )];
+        let result = Macros::try_from(macros.into_iter().collect::<BTreeMap<_, _>>());
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(&[B0xxRaw::L, B0xxRaw::R, B0xxRaw::LS, B0xxRaw::MS], 17, None; "shields")]
+    #[test_case(&[B0xxRaw::LS, B0xxRaw::Z], 17, Some("negative-edge button Z is not L, R, LS or MS"); "not a shield")]
+    #[test_case(&[B0xxRaw::L], 0, Some("negative-edge pulse_ms must be positive"); "no pulse")]
+    fn negative_edge_validated(buttons: &[B0xxRaw], pulse_ms: u64, want: Option<&str>) {
+        let result = NegativeEdge::try_from(NegativeEdgeSpec {
+            buttons: buttons.to_vec(),
+            max_hold_ms: 200,
+            pulse_ms,
+        });
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(&[B0xxRaw::L, B0xxRaw::R], 49, 17, None; "digital")]
+    #[test_case(&[B0xxRaw::LS], 49, 17, Some("light-press button LS is not L or R"); "analog")]
+    #[test_case(&[B0xxRaw::L], 0, 17, Some("light-press value must be positive"); "no value")]
+    #[test_case(&[B0xxRaw::L], 49, 0, Some("light-press delay_ms must be positive"); "no delay")]
+    fn light_press_validated(buttons: &[B0xxRaw], value: u8, delay_ms: u64, want: Option<&str>) {
+        let result = LightPress::try_from(LightPressSpec {
+            buttons: buttons.to_vec(),
+            value: Trigger::new(value).unwrap(),
+            delay_ms,
+        });
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(&[B0xxRaw::L, B0xxRaw::R], 70, 150, None; "digital")]
+    #[test_case(&[B0xxRaw::MS], 70, 150, Some("double-tap button MS is not L or R"); "analog")]
+    #[test_case(&[B0xxRaw::R], 0, 150, Some("double-tap value must be positive"); "no value")]
+    #[test_case(&[B0xxRaw::R], 70, 0, Some("double-tap window_ms must be positive"); "no window")]
+    fn double_tap_validated(buttons: &[B0xxRaw], value: u8, window_ms: u64, want: Option<&str>) {
+        let result = DoubleTap::try_from(DoubleTapSpec {
+            buttons: buttons.to_vec(),
+            value: Trigger::new(value).unwrap(),
+            window_ms,
+        });
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(B0xxRaw::LS, ShieldBehavior::Digital(B0xxRaw::R), None; "digital")]
+    #[test_case(B0xxRaw::L, ShieldBehavior::Analog(LS), None; "analog")]
+    #[test_case(
+        B0xxRaw::A,
+        ShieldBehavior::Analog(LS),
+        Some("shield behavior of A, which is not L, R, LS or MS");
+        "not shield"
+    )]
+    #[test_case(
+        B0xxRaw::R,
+        ShieldBehavior::Digital(B0xxRaw::Z),
+        Some("shield behavior of R presses Z, which is not L or R");
+        "not digital"
+    )]
+    #[test_case(
+        B0xxRaw::L,
+        ShieldBehavior::Analog(Trigger::Z),
+        Some("shield behavior of L has no analog value");
+        "no value"
+    )]
+    #[test_case(
+        B0xxRaw::MS,
+        ShieldBehavior::AnalogThenDigital { value: MS, button: B0xxRaw::R, hold_ms: 0 },
+        Some("shield behavior of MS has no hold_ms");
+        "no hold"
+    )]
+    fn shield_behavior_validated(btn: B0xxRaw, behavior: ShieldBehavior, want: Option<&str>) {
+        let result = ShieldBehaviors::try_from(BTreeMap::from([(btn, behavior)]));
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case("KEY_J", None; "key")]
+    #[test_case("SW_LID", Some("autorepeat of \"SW_LID\", which is not a key"); "switch")]
+    #[test_case("ABS_HAT0X-", Some("autorepeat of \"ABS_HAT0X-\", which is not a key"); "hat")]
+    #[test_case("KEY_NOPE", Some("autorepeat of \"KEY_NOPE\", which is not a key"); "unknown")]
+    fn autorepeat_validated(key: &str, want: Option<&str>) {
+        let result = Autorepeats::try_from(BTreeMap::from([(key.to_owned(), Autorepeat::Press)]));
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[], None; "none")]
+    #[test_case(&["KEY_LEFTMETA", "KEY_RIGHTMETA"], None; "keys")]
+    #[test_case(&["KEY_LEFTMETA", "SW_LID"], Some("focus key \"SW_LID\" is not a key"); "switch")]
+    fn focus_keys_validated(keys: &[&str], want: Option<&str>) {
+        let result =
+            FocusKeys::try_from(keys.iter().map(|&key| key.to_owned()).collect::<Vec<_>>());
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test_case(&[B0xxRaw::Start, B0xxRaw::A], None; "valid")]
+    #[test_case(&[], Some("combo has no buttons"); "empty")]
+    #[test_case(&[B0xxRaw::A, B0xxRaw::Start, B0xxRaw::A], Some("combo lists A twice"); "repeated")]
+    fn combo_validated(buttons: &[B0xxRaw], want: Option<&str>) {
+        let result = Combo::try_from(ComboSpec {
+            buttons: buttons.to_vec(),
+            hold_ms: 1000,
+            action: ComboAction::Reset,
+        });
+        assert_eq!(result.err().as_deref(), want);
+    }
+
+    #[test]
+    fn combos_ordered() {
+        let combo = |buttons: &[B0xxRaw], action| Combo {
+            buttons: buttons.iter().copied().collect(),
+            hold_ms: 1000,
+            action,
+        };
+        let combos = Combos::try_from(vec![
+            combo(&[B0xxRaw::Start, B0xxRaw::A], ComboAction::Pause),
+            combo(&[B0xxRaw::Start, B0xxRaw::B], ComboAction::Snapshot),
+            combo(
+                &[B0xxRaw::Start, B0xxRaw::L, B0xxRaw::R, B0xxRaw::A],
+                ComboAction::Reset,
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            combos
+                .0
+                .iter()
+                .map(|combo| combo.action.clone())
+                .collect::<Vec<_>>(),
+            [
+                ComboAction::Reset,
+                ComboAction::Pause,
+                ComboAction::Snapshot
+            ]
+        );
+        let result = Combos::try_from(vec![
+            combo(&[B0xxRaw::Start, B0xxRaw::A], ComboAction::Pause),
+            combo(&[B0xxRaw::A, B0xxRaw::Start], ComboAction::Reset),
+        ]);
+        assert_eq!(result.err().as_deref(), Some("two combos hold {A, Start}"));
+    }
+
+    #[test_case(r#""next_character""#, ComboAction::NextCharacter; "next_character")]
+    #[test_case(r#"{ set_profile = "fox" }"#, ComboAction::SetProfile("fox".into()); "set_profile")]
+    fn combo_action_parsed(action: &str, want: ComboAction) {
+        let toml = format!("buttons = [\"Start\", \"A\"]\naction = {}", action);
+        assert_eq!(toml::from_str::<Combo>(&toml).unwrap().action, want);
+    }
+
+    const NO_MARTH: &str = r#"a combo of profile "fox" switches to no profile named "marth""#;
+
+    #[test_case("fox", None; "defined")]
+    #[test_case(DEFAULT_PROFILE, None; "default")]
+    #[test_case("marth", Some(NO_MARTH); "undefined")]
+    fn combo_profile_validated(to: &str, want: Option<&str>) {
+        let toml = format!(
+            "[[profile.fox.combos]]\n\
+             buttons = [\"Start\", \"A\"]\n\
+             action = {{ set_profile = {:?} }}",
+            to
+        );
+        let config = toml::from_str::<Config>(&toml).unwrap();
+        assert_eq!(
+            config.validate().err().map(|e| e.to_string()).as_deref(),
+            want
+        );
+    }
+
+    #[test_case("", Some("KEY_LEFTMETA"); "defaults")]
+    #[test_case("focus_keys = []\n", None; "no_focus_keys")]
+    #[test_case("focus_keys = [\"KEY_J\"]\n", Some("KEY_J"); "chosen")]
+    #[test_case("focus_keys = [\"KEY_F1\", \"KEY_LEFTMETA\"]\n", Some("KEY_LEFTMETA"); "default")]
+    fn focus_key_binding_rejected(focus_keys: &str, bound: Option<&str>) {
+        let toml = format!(
+            "{}[profile.fox.bindings]\n\
+             KEY_J = \"A\"\n\
+             KEY_LEFTMETA = \"B\"",
+            focus_keys
+        );
+        let config = toml::from_str::<Config>(&toml).unwrap();
+        let want = bound.map(|key| {
+            format!(
+                "profile \"fox\" binds focus key {:?}, which would never press its button",
+                key
+            )
+        });
+        assert_eq!(config.validate().err().map(|e| e.to_string()), want);
+    }
+
+    #[test]
+    fn pipe_capability_validated() {
+        assert_eq!("extended".parse(), Ok(PipeCapability::Extended));
+        assert_eq!(
+            "analog".parse::<PipeCapability>(),
+            Err(String::from("unknown pipe capability \"analog\""))
+        );
+        let path = std::env::temp_dir().join(format!(
+            "tuxb0xx-pipe-capability-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "pipe_capability = \"analog\"\n").unwrap();
+        let loaded = Config::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn coordinates_for_character() {
+        let profile = Profile {
+            coordinates: CoordinateOverrides {
+                c_stick_tilt_cardinal: Some(P0000),
+                ..Default::default()
+            },
+            characters: Characters(
+                [
+                    (
+                        String::from("falco"),
+                        CoordinateOverrides {
+                            mod_tilt_diagonal: Some((P0000, P0000)),
+                            ..Default::default()
+                        },
+                    ),
+                    (String::from("fox"), CoordinateOverrides::default()),
+                ]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        assert_eq!(
+            profile.coordinates_for(None),
+            Ok(Coordinates {
+                c_stick_tilt_cardinal: P0000,
+                ..Default::default()
+            })
+        );
+        // The character's entries override the profile's, which override
+        // the defaults.
+        assert_eq!(
+            profile.coordinates_for(Some("falco")),
+            Ok(Coordinates {
+                c_stick_tilt_cardinal: P0000,
+                mod_tilt_diagonal: (P0000, P0000),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            profile.coordinates_for(Some("marth")),
+            Err(String::from("profile has no character \"marth\""))
+        );
+        let names = std::iter::successors(Some(profile.characters.next(None)), |&name| {
+            name.map(|name| profile.characters.next(Some(name)))
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(names, [Some("falco"), Some("fox"), None]);
+    }
+}
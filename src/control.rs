@@ -0,0 +1,209 @@
+//! A local control socket for other tools, e.g. a companion GUI or a script.
+//!
+//! A client sends requests as JSON objects, one per line, and reads the
+//! response to each as a line of JSON. Every request names the protocol
+//! `VERSION` it was written against, and is refused under any other. `serve`
+//! handles the socket on threads of its own and hands each request to `run`
+//! as a `remap::Event::Control`, where it goes through the same commands as
+//! combos do.
+
+use super::*;
+
+/// The version of the protocol, bumped on any incompatible change.
+pub(crate) const VERSION: u32 = 1;
+
+/// A request, e.g. `{"version": 1, "command": "set-profile", "profile": "fox"}`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub(crate) enum Request {
+    /// Changes nothing, only getting the state in response.
+    GetState,
+    /// Switches to the config's profile of this name, keeping the buttons
+    /// held that are still bound.
+    SetProfile { profile: String },
+    /// Turns a feature off, or back on.
+    Toggle { feature: Feature },
+    /// Releases everything, as the reset combo does.
+    Reset,
+    /// Selects the coordinates of the profile's character of this name, or
+    /// the profile's own for none.
+    SetCharacter { character: Option<String> },
+    /// Turns the crouch-walk option select on or off, as
+    /// `--crouch-walk-option-select` does at startup.
+    SetCrouchWalkOptionSelect { on: bool },
+    /// Replaces the SOCD resolution of every profile switched to, e.g.
+    /// `{"y": "up_priority"}`, or restores the profile's own for none.
+    SetSocd { socd: Option<config::Socd> },
+}
+
+/// What `Request::Toggle` turns off and on: the output, as the pause combo
+/// does, or a feature of the profile in use.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Feature {
+    Pause,
+    BReverse,
+    Turbo,
+    ShortHop,
+    SdiLimit,
+    LedgedashNerf,
+    NeutralB,
+    StartReset,
+    ZComposite,
+    TriggerRamp,
+    NegativeEdge,
+    LightPress,
+    DoubleTap,
+}
+
+impl Feature {
+    /// Removes the feature from `profile`, returning whether it had it.
+    /// Pausing is no part of a profile.
+    pub(crate) fn remove(self, profile: &mut config::Profile) -> bool {
+        match self {
+            Self::Pause => false,
+            Self::BReverse => profile.b_reverse.take().is_some(),
+            Self::Turbo => profile.turbo.take().is_some(),
+            Self::ShortHop => profile.short_hop.take().is_some(),
+            Self::SdiLimit => profile.sdi_limit.take().is_some(),
+            Self::LedgedashNerf => profile.ledgedash_nerf.take().is_some(),
+            Self::NeutralB => profile.neutral_b.take().is_some(),
+            Self::StartReset => profile.start_reset.take().is_some(),
+            Self::ZComposite => profile.z_composite.take().is_some(),
+            Self::TriggerRamp => profile.trigger_ramp.take().is_some(),
+            Self::NegativeEdge => profile.negative_edge.take().is_some(),
+            Self::LightPress => profile.light_press.take().is_some(),
+            Self::DoubleTap => profile.double_tap.take().is_some(),
+        }
+    }
+}
+
+/// The state of the engine, as every successful response carries it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct State {
+    /// The profile in use, unless `run` has no config to switch profiles in.
+    pub(crate) profile: Option<String>,
+    pub(crate) paused: bool,
+    /// The features of the profile turned off.
+    pub(crate) disabled: Vec<Feature>,
+    /// The character whose coordinates are selected, if any.
+    pub(crate) character: Option<String>,
+    pub(crate) crouch_walk_option_select: bool,
+    /// The SOCD resolution replacing the profile's, if any.
+    pub(crate) socd: Option<config::Socd>,
+    pub(crate) held: Vec<B0xxRaw>,
+    pub(crate) output: ControllerSnapshot,
+}
+
+/// The response to a request, with either the state after it or why it
+/// failed, e.g. `{"version": 1, "error": "no profile named \"fox\""}`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct Response {
+    pub(crate) version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) state: Option<State>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl Response {
+    pub(crate) fn new(result: Result<State, String>) -> Self {
+        let (state, error) = match result {
+            Ok(state) => (Some(state), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            version: VERSION,
+            state,
+            error,
+        }
+    }
+}
+
+/// Parses a line of a client's, refusing other versions of the protocol
+/// before looking at the rest.
+pub(crate) fn parse(line: &str) -> Result<Request, String> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: u32,
+    }
+
+    let Versioned { version } =
+        serde_json::from_str(line).map_err(|e| format!("invalid request: {}", e))?;
+    if version != VERSION {
+        return Err(format!(
+            "unsupported protocol version {}, expected {}",
+            version, VERSION
+        ));
+    }
+    serde_json::from_str(line).map_err(|e| format!("invalid request: {}", e))
+}
+
+/// Listens on `path`, replacing any socket left there by an earlier run,
+/// and hands each request to `events` along with where its response goes.
+/// Every connection is served on a thread of its own.
+pub(crate) fn serve(
+    path: &std::path::Path,
+    events: futures::channel::mpsc::UnboundedSender<anyhow::Result<remap::Event>>,
+) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt as _;
+
+    // Anything but a socket is left alone, and binding fails on it.
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .with_context(|| format!("failed to bind control socket {}", path.display()))?;
+    info!("listening for control requests on {}", path.display());
+    let _: std::thread::JoinHandle<()> = std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("failed to accept a control connection: {}", e);
+                    continue;
+                }
+            };
+            let events = events.clone();
+            let _: std::thread::JoinHandle<()> = std::thread::spawn(move || {
+                if let Err(e) = connection(stream, &events) {
+                    debug!("control connection ended: {:#}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Answers the requests of one client until it disconnects, or `run` ends.
+fn connection(
+    stream: std::os::unix::net::UnixStream,
+    events: &futures::channel::mpsc::UnboundedSender<anyhow::Result<remap::Event>>,
+) -> anyhow::Result<()> {
+    use std::io::{BufRead as _, Write as _};
+
+    let mut writer = stream.try_clone().context("failed to clone the stream")?;
+    for line in std::io::BufReader::new(stream).lines() {
+        let line = line.context("failed to read a request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match parse(&line) {
+            Ok(request) => {
+                debug!(?request, "control request");
+                let (reply, response) = futures::channel::oneshot::channel();
+                events
+                    .unbounded_send(Ok(remap::Event::Control(request, reply)))
+                    .map_err(|_| anyhow::anyhow!("the engine stopped"))?;
+                futures::executor::block_on(response)
+                    .map_err(|_| anyhow::anyhow!("the engine stopped"))?
+            }
+            Err(e) => Response::new(Err(e)),
+        };
+        let response =
+            serde_json::to_string(&response).context("failed to serialize the response")?;
+        writeln!(writer, "{}", response).context("failed to write the response")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Conversions from floats to the bounded `Analog` and `Trigger`, for
+//! features that compute a coordinate or value, e.g. `config::from_polar` and
+//! `config::TriggerRamp::value_at`.
+//!
+//! Each rounds to the nearest value, halves away from zero, and saturates at
+//! the ends of the range rather than failing, so that an edge case such as a
+//! full deflection computed as a hair over 80 can't panic.
+
+use super::*;
+
+/// A full press in Dolphin's units, as written to the pipe.
+pub(crate) const TRIGGER_UNIT: f64 = 128.;
+
+impl Analog {
+    /// Returns the coordinate closest to `value` in Melee's units, where 80 is
+    /// a full deflection. NaN is neutral.
+    pub(crate) fn from_melee_f64_saturating(value: f64) -> Self {
+        if value.is_nan() {
+            return Self::Z;
+        }
+        let value = value
+            .round()
+            .clamp(f64::from(Self::MIN.get()), f64::from(Self::MAX.get()));
+        Self::new(value as i8).expect("clamped to the range")
+    }
+}
+
+impl Trigger {
+    /// Returns the value closest to `unit` in Dolphin's units, where 1 is
+    /// `TRIGGER_UNIT`. NaN is released.
+    pub(crate) fn from_unit_f64(unit: f64) -> Self {
+        if unit.is_nan() {
+            return Self::Z;
+        }
+        let value = (unit * TRIGGER_UNIT)
+            .round()
+            .clamp(f64::from(Self::MIN.get()), f64::from(Self::MAX.get()));
+        Self::new(value as u8).expect("clamped to the range")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0.0, 0; "zero")]
+    #[test_case(0.4999, 0; "below_half")]
+    #[test_case(0.5, 1; "half")]
+    #[test_case(-0.5, -1; "negative_half")]
+    #[test_case(79.5, 80; "half_below_max")]
+    #[test_case(80.0000001, 80; "over_max")]
+    #[test_case(-80.5, -80; "under_min")]
+    #[test_case(f64::INFINITY, 80; "infinity")]
+    #[test_case(f64::NEG_INFINITY, -80; "negative_infinity")]
+    #[test_case(f64::NAN, 0; "nan")]
+    fn analog_from_melee_f64_saturating(value: f64, want: i8) {
+        assert_eq!(Analog::from_melee_f64_saturating(value).get(), want);
+    }
+
+    #[test_case(0.0, 0; "zero")]
+    #[test_case(0.5 / TRIGGER_UNIT, 1; "half")]
+    #[test_case(49. / TRIGGER_UNIT, 49; "light_shield")]
+    #[test_case(1.0, 128; "unit")]
+    #[test_case(139.5 / TRIGGER_UNIT, 140; "half_below_max")]
+    #[test_case(2.0, 140; "over_max")]
+    #[test_case(-1.0, 0; "negative")]
+    #[test_case(f64::NAN, 0; "nan")]
+    fn trigger_from_unit_f64(unit: f64, want: u8) {
+        assert_eq!(Trigger::from_unit_f64(unit).get(), want);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn analog_rounds_to_nearest(value in proptest::num::f64::ANY) {
+            let got = f64::from(Analog::from_melee_f64_saturating(value).get());
+            if value.is_nan() {
+                proptest::prop_assert_eq!(got, 0.);
+            } else if value.abs() <= 80. {
+                proptest::prop_assert!((got - value).abs() <= 0.5, "{} from {}", got, value);
+                proptest::prop_assert_eq!(got, value.round());
+            } else {
+                proptest::prop_assert_eq!(got, 80f64.copysign(value));
+            }
+        }
+
+        #[test]
+        fn trigger_rounds_to_nearest(unit in proptest::num::f64::ANY) {
+            let got = f64::from(Trigger::from_unit_f64(unit).get());
+            let value = unit * TRIGGER_UNIT;
+            if value.is_nan() || value <= 0. {
+                proptest::prop_assert_eq!(got, 0.);
+            } else if value <= 140. {
+                proptest::prop_assert!((got - value).abs() <= 0.5, "{} from {}", got, unit);
+                proptest::prop_assert_eq!(got, value.round());
+            } else {
+                proptest::prop_assert_eq!(got, 140.);
+            }
+        }
+    }
+}
@@ -0,0 +1,842 @@
+//! Stick coordinates as functions of a situation that can be enumerated.
+//!
+//! Everything other than the directions held that can affect the A-stick is
+//! a `Situation`, and `AStickTable` holds the coordinates for every one of
+//! them, built once from the profile, so that an event costs a single lookup.
+//! The C-stick has few enough situations to compute. `dump` lists every
+//! output either stick can reach through the same lookups as `Main`, `svg`
+//! draws the A-stick's, and `check_overrides` finds coordinate overrides that
+//! none of them use.
+
+use super::consts::*;
+use super::*;
+
+/// Which A-stick axes are away from neutral.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Shape {
+    XCardinal,
+    YCardinal,
+    Diagonal,
+}
+
+const SHAPES: [Shape; 3] = [Shape::XCardinal, Shape::YCardinal, Shape::Diagonal];
+
+const MODS: [B0xxState; 4] = [
+    B0xxState::NONE,
+    B0xxState::MOD_X,
+    B0xxState::MOD_Y,
+    B0xxState::MODS,
+];
+
+const C_STICK: [Option<(Axis, Direction)>; 5] = [
+    None,
+    Some((Axis::X, POSITIVE)),
+    Some((Axis::X, NEGATIVE)),
+    Some((Axis::Y, POSITIVE)),
+    Some((Axis::Y, NEGATIVE)),
+];
+
+/// How many values each field of `Situation` takes, in order.
+const RADICES: [usize; 9] = [SHAPES.len(), MODS.len(), 2, 2, 2, 2, C_STICK.len(), 2, 2];
+
+/// The A-stick inputs that select a coordinate, given the directions held.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct Situation {
+    pub(crate) shape: Shape,
+    /// Mod X and Mod Y as held.
+    pub(crate) mods: B0xxState,
+    /// Whether the tilt modifier is held, which Mod X and Mod Y override.
+    pub(crate) tilt: bool,
+    pub(crate) b: bool,
+    /// Whether either of L and R is held.
+    pub(crate) lr: bool,
+    /// Whether the opposite X direction is held too.
+    pub(crate) opposing_held: bool,
+    /// The C-stick direction if only one is active.
+    pub(crate) c_stick: Option<(Axis, Direction)>,
+    pub(crate) y_dir: Direction,
+    /// Whether the crouch/walk option-select applies to the X direction held.
+    pub(crate) crouch_walk: bool,
+}
+
+impl Situation {
+    /// How many distinct situations there are.
+    pub(crate) const LEN: usize = {
+        let mut len = 1;
+        let mut i = 0;
+        while i < RADICES.len() {
+            len *= RADICES[i];
+            i += 1;
+        }
+        len
+    };
+
+    pub(crate) fn index(self) -> usize {
+        let Self {
+            shape,
+            mods,
+            tilt,
+            b,
+            lr,
+            opposing_held,
+            c_stick,
+            y_dir,
+            crouch_walk,
+        } = self;
+        let mods = usize::from(mods.contains(B0xxState::MOD_X))
+            | usize::from(mods.contains(B0xxState::MOD_Y)) << 1;
+        // The position in `C_STICK`.
+        let c_stick = match c_stick {
+            None => 0,
+            Some((Axis::X, dir)) => 2 - usize::from(dir),
+            Some((Axis::Y, dir)) => 4 - usize::from(dir),
+        };
+        [
+            shape as usize,
+            mods,
+            usize::from(tilt),
+            usize::from(b),
+            usize::from(lr),
+            usize::from(opposing_held),
+            c_stick,
+            usize::from(y_dir),
+            usize::from(crouch_walk),
+        ]
+        .into_iter()
+        .zip(RADICES)
+        .fold(0, |index, (digit, radix)| index * radix + digit)
+    }
+
+    pub(crate) fn from_index(mut index: usize) -> Self {
+        let mut digits = [0; RADICES.len()];
+        for (digit, radix) in digits.iter_mut().zip(RADICES).rev() {
+            *digit = index % radix;
+            index /= radix;
+        }
+        let [shape, mods, tilt, b, lr, opposing_held, c_stick, y_dir, crouch_walk] = digits;
+        Self {
+            shape: SHAPES[shape],
+            mods: MODS[mods],
+            tilt: tilt == 1,
+            b: b == 1,
+            lr: lr == 1,
+            opposing_held: opposing_held == 1,
+            c_stick: C_STICK[c_stick],
+            y_dir: y_dir == 1,
+            crouch_walk: crouch_walk == 1,
+        }
+    }
+
+    /// Every situation, in index order.
+    pub(crate) fn all() -> impl Iterator<Item = Self> {
+        (0..Self::LEN).map(Self::from_index)
+    }
+}
+
+/// Returns the coordinates for `situation` with positive directions, i.e.
+/// the magnitude of each axis.
+pub(crate) fn a_stick(situation: Situation, coordinates: &config::Coordinates) -> AStickInput {
+    let Situation {
+        shape,
+        mods,
+        tilt,
+        b,
+        lr,
+        opposing_held,
+        c_stick,
+        y_dir,
+        crouch_walk,
+    } = situation;
+    // Mod X/Y take precedence over the tilt modifier.
+    let tilt = tilt && mods == B0xxState::NONE;
+    match shape {
+        Shape::XCardinal => {
+            let x = match (mods, b, opposing_held) {
+                (B0xxState::MOD_X, _, false) | (B0xxState::MOD_Y, true, false) => P6625,
+                (B0xxState::MOD_Y, false, false) => P3375,
+                (B0xxState::NONE, _, false) if tilt => P6625,
+                _ => Analog::MAX,
+            };
+            (x, P0000)
+        }
+        Shape::YCardinal => {
+            let y = if mods == B0xxState::MOD_X {
+                P5375
+            } else if mods == B0xxState::MOD_Y {
+                P7375
+            } else if tilt {
+                P5375
+            } else {
+                Analog::MAX
+            };
+            (P0000, y)
+        }
+        Shape::Diagonal => match (mods, lr, c_stick) {
+            (B0xxState::MOD_X, true, _) => (P6375, P3750),
+            (B0xxState::MOD_X, false, Some((Axis::Y, NEGATIVE))) => (P7000, P3625),
+            (B0xxState::MOD_X, false, Some((Axis::X, NEGATIVE))) => (P7875, P4875),
+            (B0xxState::MOD_X, false, Some((Axis::Y, POSITIVE))) => (P7000, P5125),
+            (B0xxState::MOD_X, false, Some((Axis::X, POSITIVE))) => (P6125, P5250),
+            (B0xxState::MOD_X, false, None) => (P7375, P3125),
+
+            (B0xxState::MOD_Y, true, _) => {
+                if y_dir {
+                    (P4750, P8750)
+                } else {
+                    (P5000, P8500)
+                }
+            }
+            (B0xxState::MOD_Y, false, Some((Axis::X, POSITIVE))) => (P6375, P7625),
+            (B0xxState::MOD_Y, false, Some((Axis::Y, POSITIVE))) => (P5125, P7000),
+            (B0xxState::MOD_Y, false, Some((Axis::X, NEGATIVE))) => (P4875, P7875),
+            (B0xxState::MOD_Y, false, Some((Axis::Y, NEGATIVE))) => (P3625, P7000),
+            (B0xxState::MOD_Y, false, None) => (P3125, P7375),
+            _ if tilt => coordinates.mod_tilt_diagonal,
+            _ if !y_dir && crouch_walk => coordinates.crouch_walk_diagonal,
+            _ => (P7000, P7000),
+        },
+    }
+}
+
+/// The result of `a_stick` for every `Situation`. Cloning shares the table.
+#[derive(Clone, Debug)]
+pub(crate) struct AStickTable(std::sync::Arc<[AStickInput]>);
+
+impl Default for AStickTable {
+    /// Builds the table for the default coordinates only once, as `Main` is
+    /// created often in tests and fuzzing.
+    fn default() -> Self {
+        static DEFAULT: std::sync::OnceLock<AStickTable> = std::sync::OnceLock::new();
+        DEFAULT
+            .get_or_init(|| Self::new(&Default::default()))
+            .clone()
+    }
+}
+
+impl AStickTable {
+    pub(crate) fn new(coordinates: &config::Coordinates) -> Self {
+        Self(
+            Situation::all()
+                .map(|situation| a_stick(situation, coordinates))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn get(&self, situation: Situation) -> AStickInput {
+        self.0[situation.index()]
+    }
+}
+
+/// The inputs that select the C-stick coordinates.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct CStickSituation {
+    pub(crate) x: Option<Direction>,
+    pub(crate) y: Option<Direction>,
+    /// Mod X and Mod Y as held.
+    pub(crate) mods: B0xxState,
+    /// The A-stick direction if only its Y axis is active.
+    pub(crate) a_stick_vertical: Option<Direction>,
+    /// The A-stick direction if only its X axis is active.
+    pub(crate) a_stick_horizontal: Option<Direction>,
+}
+
+impl CStickSituation {
+    /// Every situation.
+    pub(crate) fn all() -> impl Iterator<Item = Self> {
+        const DIRECTIONS: [Option<Direction>; 3] = [None, Some(POSITIVE), Some(NEGATIVE)];
+        DIRECTIONS.into_iter().flat_map(|x| {
+            DIRECTIONS.into_iter().flat_map(move |y| {
+                MODS.into_iter().flat_map(move |mods| {
+                    DIRECTIONS.into_iter().flat_map(move |a_stick_vertical| {
+                        DIRECTIONS
+                            .into_iter()
+                            // The A-stick can't be vertical and horizontal.
+                            .filter(move |a_stick_horizontal| {
+                                a_stick_vertical.is_none() || a_stick_horizontal.is_none()
+                            })
+                            .map(move |a_stick_horizontal| Self {
+                                x,
+                                y,
+                                mods,
+                                a_stick_vertical,
+                                a_stick_horizontal,
+                            })
+                    })
+                })
+            })
+        })
+    }
+}
+
+pub(crate) fn c_stick(
+    situation: CStickSituation,
+    mode: config::CStickMode,
+    coordinates: &config::Coordinates,
+) -> CStickInput {
+    let CStickSituation {
+        x,
+        y,
+        mods,
+        a_stick_vertical,
+        a_stick_horizontal,
+    } = situation;
+    let (cardinal, (diagonal_x, diagonal_y)) = match mode {
+        config::CStickMode::Smash => (Analog::MAX, (P5250, P8500)),
+        config::CStickMode::Tilt => (
+            coordinates.c_stick_tilt_cardinal,
+            coordinates.c_stick_tilt_diagonal,
+        ),
+    };
+    match (x, y) {
+        (None, None) => (P0000, P0000),
+        (Some(x_dir), None) => match (mods, a_stick_vertical) {
+            (B0xxState::MOD_X, Some(y_dir)) => {
+                trace!(?y_dir, "C-stick angled fsmash");
+                (P8125.neg_not(x_dir), P2875.neg_not(y_dir))
+            }
+            _ => (cardinal.neg_not(x_dir), P0000),
+        },
+        (None, Some(y_dir)) => {
+            let angled = match mods {
+                B0xxState::MOD_X => Some(coordinates.c_stick_mod_x_vertical),
+                B0xxState::MOD_Y => Some(coordinates.c_stick_mod_y_vertical),
+                _ => None,
+            };
+            match (angled, a_stick_horizontal) {
+                (Some((x, y)), Some(x_dir)) => {
+                    trace!(?x_dir, "C-stick angled vertical");
+                    (x.neg_not(x_dir), y.neg_not(y_dir))
+                }
+                _ => (P0000, cardinal.neg_not(y_dir)),
+            }
+        }
+        (Some(x_dir), Some(y_dir)) => (diagonal_x.neg_not(x_dir), diagonal_y.neg_not(y_dir)),
+    }
+}
+
+fn direction(axis: Axis, dir: Direction) -> &'static str {
+    match (axis, dir) {
+        (Axis::X, POSITIVE) => "right",
+        (Axis::X, NEGATIVE) => "left",
+        (Axis::Y, POSITIVE) => "up",
+        (Axis::Y, NEGATIVE) => "down",
+    }
+}
+
+fn mods(mods: B0xxState) -> Option<&'static str> {
+    match mods {
+        B0xxState::MOD_X => Some("Mod X"),
+        B0xxState::MOD_Y => Some("Mod Y"),
+        B0xxState::MODS => Some("Mod X+Mod Y"),
+        _ => None,
+    }
+}
+
+/// Describes an A-stick situation, listing only what is held.
+fn describe(situation: Situation, x_dir: Direction, y_dir: Direction) -> Vec<String> {
+    let Situation {
+        shape,
+        mods: held_mods,
+        tilt,
+        b,
+        lr,
+        opposing_held,
+        c_stick,
+        y_dir: _,
+        crouch_walk,
+    } = situation;
+    let directions = match shape {
+        Shape::XCardinal => direction(Axis::X, x_dir).to_string(),
+        Shape::YCardinal => direction(Axis::Y, y_dir).to_string(),
+        Shape::Diagonal => format!(
+            "{}+{}",
+            direction(Axis::X, x_dir),
+            direction(Axis::Y, y_dir)
+        ),
+    };
+    std::iter::once(directions)
+        .chain(mods(held_mods).map(String::from))
+        .chain(
+            [
+                (tilt, "tilt"),
+                (b, "B"),
+                (lr, "L/R"),
+                (opposing_held, "opposite held"),
+                (crouch_walk, "crouch/walk"),
+            ]
+            .into_iter()
+            .filter(|&(held, _)| held)
+            .map(|(_, name)| String::from(name)),
+        )
+        .chain(c_stick.map(|(axis, dir)| format!("C-{}", direction(axis, dir))))
+        .collect()
+}
+
+/// Describes a C-stick situation, listing only what is held.
+fn describe_c_stick(situation: CStickSituation) -> Vec<String> {
+    let CStickSituation {
+        x,
+        y,
+        mods: held_mods,
+        a_stick_vertical,
+        a_stick_horizontal,
+    } = situation;
+    let directions = match (x, y) {
+        (None, None) => String::from("neutral"),
+        (Some(x_dir), None) => direction(Axis::X, x_dir).to_string(),
+        (None, Some(y_dir)) => direction(Axis::Y, y_dir).to_string(),
+        (Some(x_dir), Some(y_dir)) => format!(
+            "{}+{}",
+            direction(Axis::X, x_dir),
+            direction(Axis::Y, y_dir)
+        ),
+    };
+    std::iter::once(directions)
+        .chain(mods(held_mods).map(String::from))
+        .chain(a_stick_vertical.map(|dir| format!("A-{}", direction(Axis::Y, dir))))
+        .chain(a_stick_horizontal.map(|dir| format!("A-{}", direction(Axis::X, dir))))
+        .collect()
+}
+
+/// Formats an output with the inputs producing it.
+fn format((x, y): (i8, i8), description: &[String]) -> String {
+    format!(
+        "({:.4}, {:.4}) [{}, {}] {}",
+        unit(x),
+        unit(y),
+        x,
+        y,
+        description.join(", ")
+    )
+}
+
+/// Returns the position of `a` along an axis from -1 to 1.
+fn unit(a: i8) -> f64 {
+    f64::from(a) / f64::from(Analog::MAX.get())
+}
+
+/// Formats each output once, sorted, with the situation producing it that
+/// involves the fewest inputs.
+fn list(outputs: impl Iterator<Item = (GCStickInput, Vec<String>)>) -> Vec<String> {
+    let mut simplest = std::collections::BTreeMap::<_, Vec<String>>::new();
+    for ((x, y), description) in outputs {
+        let entry = simplest
+            .entry((x.get(), y.get()))
+            .or_insert_with(|| description.clone());
+        if description.len() < entry.len() {
+            *entry = description;
+        }
+    }
+    simplest
+        .into_iter()
+        .map(|((x, y), description)| format((x, y), &description))
+        .collect()
+}
+
+/// Every A-stick situation `main` can look up, with the X and Y directions
+/// held.
+fn a_stick_situations(
+    main: &Main,
+    crouch_walk_option_select: bool,
+) -> impl Iterator<Item = (Situation, Direction, Direction)> + '_ {
+    const BOTH: [Direction; 2] = [POSITIVE, NEGATIVE];
+    // Only what `Main::a_stick_coordinates` can look up, i.e. the direction of
+    // an inactive axis is positive, the opposite X direction can't be held
+    // without the X axis active, crouch/walk follows the profile, and the
+    // C-stick only refines angles in Melee.
+    Situation::all()
+        .flat_map(|situation| {
+            let (x_dirs, y_dirs): (&[Direction], &[Direction]) = match situation.shape {
+                Shape::XCardinal => (&BOTH, &[POSITIVE]),
+                Shape::YCardinal => (&[POSITIVE], &BOTH),
+                Shape::Diagonal => (&BOTH, &BOTH),
+            };
+            x_dirs
+                .iter()
+                .flat_map(move |&x_dir| y_dirs.iter().map(move |&y_dir| (situation, x_dir, y_dir)))
+        })
+        .filter(move |&(situation, x_dir, y_dir)| {
+            situation.y_dir == y_dir
+                && !(situation.shape == Shape::YCardinal && situation.opposing_held)
+                && situation.crouch_walk
+                    == main.crouch_walk_applies(crouch_walk_option_select, x_dir)
+                && (situation.c_stick.is_none()
+                    || (main.game.melee_refinements()
+                        && main.stick_coupling.c_stick_refines_a_stick))
+        })
+}
+
+/// Every situation `main` can look the C-stick coordinates up in, i.e. the
+/// A-stick's directions only count if they angle the C-stick.
+fn c_stick_situations(main: &Main) -> impl Iterator<Item = CStickSituation> + '_ {
+    CStickSituation::all().filter(|situation| {
+        main.stick_coupling.a_stick_angles_c_stick
+            || (situation.a_stick_vertical.is_none() && situation.a_stick_horizontal.is_none())
+    })
+}
+
+/// Lists every coordinate `main` can output on either stick.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn dump(main: &Main, crouch_walk_option_select: bool) -> Vec<String> {
+    let a_stick = a_stick_situations(main, crouch_walk_option_select)
+        .map(|(situation, x_dir, y_dir)| {
+            (
+                main.a_stick_output(situation, x_dir, y_dir),
+                describe(situation, x_dir, y_dir),
+            )
+        })
+        .chain(std::iter::once((
+            main.invert.a_stick.apply((P0000, P0000)),
+            vec![String::from("neutral")],
+        )));
+    let c_stick = c_stick_situations(main)
+        .map(|situation| (main.c_stick_output(situation), describe_c_stick(situation)));
+    std::iter::once(String::from("A-stick:"))
+        .chain(list(a_stick))
+        .chain(std::iter::once(String::from("C-stick:")))
+        .chain(list(c_stick))
+        .collect()
+}
+
+/// Every coordinate `main` can output on either stick, as `dump` lists them
+/// but unsorted and possibly repeated.
+pub(crate) fn outputs(
+    main: &Main,
+    crouch_walk_option_select: bool,
+) -> impl Iterator<Item = (Stick, GCStickInput)> + '_ {
+    a_stick_situations(main, crouch_walk_option_select)
+        .map(|(situation, x_dir, y_dir)| (Stick::A, main.a_stick_output(situation, x_dir, y_dir)))
+        .chain(std::iter::once((
+            Stick::A,
+            main.invert.a_stick.apply((P0000, P0000)),
+        )))
+        .chain(c_stick_situations(main).map(|situation| (Stick::C, main.c_stick_output(situation))))
+}
+
+/// Copies one field of `config::Coordinates` from the second set to the first.
+type CopyField = fn(&mut config::Coordinates, &config::Coordinates);
+
+/// Each field of `config::Coordinates` by name.
+const FIELDS: [(&str, CopyField); 6] = [
+    ("mod_tilt_diagonal", |to, from| {
+        to.mod_tilt_diagonal = from.mod_tilt_diagonal
+    }),
+    ("c_stick_tilt_cardinal", |to, from| {
+        to.c_stick_tilt_cardinal = from.c_stick_tilt_cardinal
+    }),
+    ("c_stick_tilt_diagonal", |to, from| {
+        to.c_stick_tilt_diagonal = from.c_stick_tilt_diagonal
+    }),
+    ("crouch_walk_diagonal", |to, from| {
+        to.crouch_walk_diagonal = from.crouch_walk_diagonal
+    }),
+    ("c_stick_mod_x_vertical", |to, from| {
+        to.c_stick_mod_x_vertical = from.c_stick_mod_x_vertical
+    }),
+    ("c_stick_mod_y_vertical", |to, from| {
+        to.c_stick_mod_y_vertical = from.c_stick_mod_y_vertical
+    }),
+];
+
+/// Coordinates overridden to no effect, and those left at their defaults
+/// while others are overridden.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub(crate) struct Overrides {
+    /// Overridden, but never output under the profile's other settings.
+    pub(crate) unreachable: Vec<&'static str>,
+    /// Output, but falling back to the default as only some are overridden.
+    pub(crate) defaulted: Vec<&'static str>,
+    /// A-stick coordinates output with a direction held, but inside Melee's
+    /// deadzone so that they do nothing: the profile's own in order, then
+    /// those only a character's reaches, by character in name order. Empty
+    /// outside of Melee.
+    pub(crate) deadzoned: Vec<(Option<String>, GCStickInput)>,
+}
+
+/// Cross-checks `profile`'s coordinate overrides against every situation it
+/// can reach. A coordinate is reachable if setting it to two different values
+/// changes some output, counting only situations whose modifiers are bound.
+/// Those situations' A-stick coordinates are also checked against Melee's
+/// deadzone, per `in_deadzone`, under the profile's own coordinates and each
+/// character's.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn check_overrides(
+    profile: &config::Profile,
+    crouch_walk_option_select: bool,
+    game: Game,
+) -> Overrides {
+    let bound = |btn| !profile.bindings.keys(btn).is_empty() || profile.tap_hold.holds(btn);
+    let mods_bound = |mods: B0xxState| {
+        [
+            (B0xxState::MOD_X, B0xxRaw::MX),
+            (B0xxState::MOD_Y, B0xxRaw::MY),
+        ]
+        .into_iter()
+        .all(|(held, btn)| !mods.contains(held) || bound(btn))
+    };
+    let outputs = |coordinates: &config::Coordinates| {
+        let main = Main {
+            game,
+            ..Main::new(&config::Profile {
+                coordinates: (*coordinates).into(),
+                ..profile.clone()
+            })
+        };
+        let a_stick = a_stick_situations(&main, crouch_walk_option_select)
+            .filter(|(situation, _, _)| {
+                mods_bound(situation.mods) && (!situation.tilt || bound(B0xxRaw::MT))
+            })
+            .map(|(situation, x_dir, y_dir)| main.a_stick_output(situation, x_dir, y_dir))
+            .collect::<Vec<_>>();
+        let c_stick = c_stick_situations(&main)
+            .filter(|situation| mods_bound(situation.mods))
+            .map(|situation| main.c_stick_output(situation))
+            .collect::<Vec<_>>();
+        (a_stick, c_stick)
+    };
+    let uniform = |a| {
+        let a = Analog::new(a).expect("within range");
+        config::Coordinates {
+            mod_tilt_diagonal: (a, a),
+            c_stick_tilt_cardinal: a,
+            c_stick_tilt_diagonal: (a, a),
+            crouch_walk_diagonal: (a, a),
+            c_stick_mod_x_vertical: (a, a),
+            c_stick_mod_y_vertical: (a, a),
+        }
+    };
+    let own_coordinates = profile.own_coordinates();
+    let (low, high) = (uniform(20), uniform(60));
+    // A coordinate is overridden if present in the profile's table, whatever
+    // its value, and so the same whichever coordinates the table applies to.
+    let applied = |to: config::Coordinates| {
+        let mut coordinates = to;
+        profile.coordinates.apply(&mut coordinates);
+        coordinates
+    };
+    let (applied_low, applied_high) = (applied(low), applied(high));
+    let fields = FIELDS.map(|(name, copy)| {
+        let with = |from: &config::Coordinates| {
+            let mut coordinates = own_coordinates;
+            copy(&mut coordinates, from);
+            coordinates
+        };
+        let overridden = with(&applied_low) == with(&applied_high);
+        let reachable = outputs(&with(&low)) != outputs(&with(&high));
+        (name, overridden, reachable)
+    });
+    let partial = fields.iter().any(|&(_, overridden, _)| overridden);
+    let deadzoned_with = |coordinates: &config::Coordinates| {
+        let mut deadzoned = outputs(coordinates)
+            .0
+            .into_iter()
+            .filter(|&a| in_deadzone(a))
+            .collect::<Vec<_>>();
+        deadzoned.sort();
+        deadzoned.dedup();
+        deadzoned
+    };
+    let mut deadzoned = Vec::new();
+    if game.melee_refinements() {
+        let own = deadzoned_with(&own_coordinates);
+        for character in profile.characters.0.keys() {
+            let coordinates = profile
+                .coordinates_for(Some(character))
+                .expect("a character of the profile");
+            deadzoned.extend(
+                deadzoned_with(&coordinates)
+                    .into_iter()
+                    .filter(|a| !own.contains(a))
+                    .map(|a| (Some(character.clone()), a)),
+            );
+        }
+        let _ = deadzoned.splice(0..0, own.into_iter().map(|a| (None, a)));
+    }
+    Overrides {
+        unreachable: fields
+            .iter()
+            .filter(|&&(_, overridden, reachable)| overridden && !reachable)
+            .map(|&(name, _, _)| name)
+            .collect(),
+        defaulted: fields
+            .iter()
+            .filter(|&&(_, overridden, reachable)| partial && !overridden && reachable)
+            .map(|&(name, _, _)| name)
+            .collect(),
+        deadzoned,
+    }
+}
+
+/// The modifier an A-stick coordinate is reached with, which colors its
+/// point in `svg`.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum Category {
+    Unmodified,
+    ModX,
+    ModY,
+}
+
+impl Category {
+    pub(crate) const ALL: [Self; 3] = [Self::Unmodified, Self::ModX, Self::ModY];
+
+    /// Mod X and Mod Y together select the same coordinates as neither.
+    fn of(mods: B0xxState) -> Self {
+        match mods {
+            B0xxState::MOD_X => Self::ModX,
+            B0xxState::MOD_Y => Self::ModY,
+            _ => Self::Unmodified,
+        }
+    }
+
+    /// The `class` of its points.
+    pub(crate) fn class(self) -> &'static str {
+        match self {
+            Self::Unmodified => "unmodified",
+            Self::ModX => "mod-x",
+            Self::ModY => "mod-y",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Self::Unmodified => "#555555",
+            Self::ModX => "#d62728",
+            Self::ModY => "#1f77b4",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Unmodified => "unmodified",
+            Self::ModX => "Mod X",
+            Self::ModY => "Mod Y",
+        }
+    }
+}
+
+/// Whether `situation` selects one of the diagonals refined by the C-stick
+/// for firefox and up-B angles.
+fn refined_angle(situation: Situation) -> bool {
+    situation.shape == Shape::Diagonal
+        && matches!(situation.mods, B0xxState::MOD_X | B0xxState::MOD_Y)
+        && !situation.lr
+        && situation.c_stick.is_some()
+}
+
+/// Draws every coordinate `main` can output on the A-stick, once per
+/// modifier reaching it, over the unit circle and the deadzone. Each point's
+/// title is its line in `dump`, and angle refinements are labeled with their
+/// angle in degrees.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn svg(main: &Main, crouch_walk_option_select: bool) -> String {
+    use std::fmt::Write as _;
+
+    struct Point {
+        description: Vec<String>,
+        refined_angle: bool,
+    }
+
+    let mut points = std::collections::BTreeMap::<_, Point>::new();
+    let outputs = a_stick_situations(main, crouch_walk_option_select)
+        .map(|(situation, x_dir, y_dir)| {
+            (
+                Category::of(situation.mods),
+                main.a_stick_output(situation, x_dir, y_dir),
+                describe(situation, x_dir, y_dir),
+                refined_angle(situation),
+            )
+        })
+        .chain(std::iter::once((
+            Category::Unmodified,
+            main.invert.a_stick.apply((P0000, P0000)),
+            vec![String::from("neutral")],
+            false,
+        )));
+    for (category, (x, y), description, refined) in outputs {
+        let point = points
+            .entry((category, x.get(), y.get()))
+            .or_insert_with(|| Point {
+                description: description.clone(),
+                refined_angle: false,
+            });
+        if description.len() < point.description.len() {
+            point.description = description;
+        }
+        point.refined_angle |= refined;
+    }
+
+    // The unit circle has a radius of 400 pixels, and Y points down.
+    const MARGIN: f64 = 1.25;
+    let deadzone = unit(P2875.get());
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        -MARGIN,
+        -MARGIN,
+        2.0 * MARGIN,
+        2.0 * MARGIN,
+        800.0 * MARGIN,
+        800.0 * MARGIN
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white"/>"#,
+        -MARGIN,
+        -MARGIN,
+        2.0 * MARGIN,
+        2.0 * MARGIN
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect class="deadzone" x="{}" y="{}" width="{}" height="{}" fill="#eeeeee"><title>deadzone</title></rect>"##,
+        -deadzone,
+        -deadzone,
+        2.0 * deadzone,
+        2.0 * deadzone
+    );
+    let _ = writeln!(
+        svg,
+        r#"<path class="axes" d="M -1 0 H 1 M 0 -1 V 1" stroke="silver" stroke-width="0.003"/>"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<circle class="unit-circle" cx="0" cy="0" r="1" fill="none" stroke="black" stroke-width="0.004"/>"#
+    );
+    for (&(category, x, y), point) in &points {
+        let (cx, cy) = (unit(x), -unit(y));
+        let _ = writeln!(
+            svg,
+            r#"<circle class="{}" cx="{:.4}" cy="{:.4}" r="0.012" fill="{}"><title>{}</title></circle>"#,
+            category.class(),
+            cx,
+            cy,
+            category.color(),
+            format((x, y), &point.description)
+        );
+        if point.refined_angle {
+            let angle = unit(y).abs().atan2(unit(x).abs()).to_degrees();
+            let _ = writeln!(
+                svg,
+                r#"<text class="angle" x="{:.4}" y="{:.4}" font-size="0.035" fill="{}">{:.1}°</text>"#,
+                cx + 0.018,
+                cy + 0.012,
+                category.color(),
+                angle
+            );
+        }
+    }
+    for (i, category) in Category::ALL.into_iter().enumerate() {
+        let y = -MARGIN + 0.08 + 0.06 * i as f64;
+        let _ = writeln!(
+            svg,
+            r#"<text class="legend" x="{}" y="{:.2}" font-size="0.045" fill="{}">● {}</text>"#,
+            -MARGIN + 0.05,
+            y,
+            category.color(),
+            category.name()
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
@@ -0,0 +1,52 @@
+//! Prints every event the keyboard reports, as evtest does, alongside the
+//! button each would press under the profile, to debug the device and the
+//! mapping in one step.
+
+use super::*;
+
+/// Passes `output` the `line` of each event read from `keyboard` until
+/// `exit` yields, e.g. on Ctrl-C, or the keyboard is disconnected.
+pub(crate) async fn run(
+    keyboard: impl futures::Stream<Item = std::io::Result<evdev_rs::InputEvent>> + Unpin,
+    exit: impl futures::Stream<Item = ()> + Unpin,
+    remapper: &Remapper,
+    mut output: impl FnMut(String),
+) -> anyhow::Result<()> {
+    let keyboard = keyboard
+        .map(|event| event.context("failed to read keyboard event").map(Some))
+        .chain(futures::stream::iter([Err(anyhow::anyhow!(
+            "keyboard disconnected"
+        ))]));
+    let mut events = futures::stream::select(keyboard, exit.map(|()| Ok(None)));
+    while let Some(event) = events.next().await {
+        match event? {
+            Some(event) => output(line(&event, remapper)),
+            None => return Ok(()),
+        }
+    }
+    unreachable!("the keyboard ends with an error")
+}
+
+/// Describes an event, e.g. `1700000000.000123 EV_KEY KEY_J 1 A`: the
+/// time, type, code and value, and the button its key is bound to, `focus`
+/// for a focus key, or `-`.
+pub(crate) fn line(event: &evdev_rs::InputEvent, remapper: &Remapper) -> String {
+    let btn = if remapper.focus.contains(&event.event_code) {
+        String::from("focus")
+    } else {
+        remapper
+            .binding(event)
+            .map_or_else(|| String::from("-"), |btn| format!("{:?}", btn))
+    };
+    format!(
+        "{}.{:06} {} {} {} {}",
+        event.time.tv_sec,
+        event.time.tv_usec,
+        event
+            .event_type()
+            .map_or_else(|| String::from("?"), |event_type| event_type.to_string()),
+        event.event_code,
+        event.value,
+        btn
+    )
+}
@@ -0,0 +1,161 @@
+//! A virtual frame counter, so that the logs say which of the game's frames
+//! each write falls into, rather than leaving it to be worked out from
+//! microsecond timestamps whether two inputs landed on the same frame.
+//!
+//! Frames are counted at `--frame-rate`, 60Hz by default as in Melee, from
+//! the start of the process or from the first button event. The counter runs
+//! at the game's rate but isn't in phase with it, so writes on neighbouring
+//! frames here may still be read on the same frame of the game. Each frame
+//! number is worked out from the monotonic time elapsed since the epoch rather
+//! than counted up as frames pass, so that sleeping, e.g. while idle, never
+//! shifts the frames after.
+
+use super::*;
+
+/// When frame 0 starts.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Epoch {
+    /// When the process starts.
+    #[default]
+    Start,
+    /// When the first button event is read, as timestamped by the kernel.
+    FirstEvent,
+}
+
+impl std::str::FromStr for Epoch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(Self::Start),
+            "first-event" => Ok(Self::FirstEvent),
+            _ => Err(format!("unknown frame epoch {:?}", s)),
+        }
+    }
+}
+
+/// A frame rate in Hz, finite and at least a millihertz, as `--frame-rate`
+/// gives it to both the frame counter and `--quantize`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Rate(f64);
+
+impl Rate {
+    pub(crate) fn new(hz: f64) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            hz.is_finite() && hz >= 0.001,
+            "expected a frame rate of at least 0.001Hz, got {}",
+            hz
+        );
+        Ok(Self(hz))
+    }
+
+    /// Returns how long each frame lasts.
+    pub(crate) fn period(self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.0.recip())
+    }
+}
+
+/// Numbers frames from an epoch, shared by everything logging them, e.g. the
+/// command log's thread.
+#[derive(Clone, Debug)]
+pub(crate) struct FrameClock {
+    /// The rate rounded to the millihertz, e.g. 59940 for NTSC's 59.94Hz, so
+    /// that frames are counted in integers without rounding errors adding up.
+    millihertz: u128,
+    epoch: std::sync::Arc<std::sync::OnceLock<Timestamp>>,
+}
+
+impl FrameClock {
+    /// Counts frames at `rate`, from `now` if `epoch` is the start.
+    pub(crate) fn new(rate: Rate, epoch: Epoch, now: Timestamp) -> Self {
+        let clock = Self {
+            millihertz: (rate.0 * 1000.).round() as u128,
+            epoch: Default::default(),
+        };
+        if epoch == Epoch::Start {
+            clock.start(now);
+        }
+        clock
+    }
+
+    /// Starts counting at `time`, unless counting already.
+    pub(crate) fn start(&self, time: Timestamp) {
+        let _: Result<(), Timestamp> = self.epoch.set(time);
+    }
+
+    /// Returns the frame `time` falls into, or `None` until counting starts.
+    /// A time before the epoch falls into frame 0.
+    pub(crate) fn frame(&self, time: Timestamp) -> Option<u64> {
+        let elapsed = time - *self.epoch.get()?;
+        (elapsed.as_nanos() * self.millihertz / 1_000_000_000_000)
+            .try_into()
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn at(nanos: u64) -> Timestamp {
+        Timestamp::default() + std::time::Duration::from_nanos(nanos)
+    }
+
+    #[test_case(60., 0, 0; "epoch")]
+    #[test_case(60., 16_666_666, 0; "end_of_first")]
+    #[test_case(60., 16_666_667, 1; "start_of_second")]
+    #[test_case(60., 50_000_000, 3; "exact_boundary")]
+    #[test_case(60., 49_999_999, 2; "before_exact_boundary")]
+    #[test_case(60., 3_600_000_000_000, 216_000; "an_hour_later")]
+    #[test_case(60., 8_640_000_000_000_000, 518_400_000; "a_hundred_days_later")]
+    #[test_case(59.94, 16_683_350, 0; "ntsc_end_of_first")]
+    #[test_case(59.94, 16_683_351, 1; "ntsc_start_of_second")]
+    fn frame_boundaries(rate: f64, nanos: u64, want: u64) {
+        let frames = FrameClock::new(Rate::new(rate).unwrap(), Epoch::Start, at(0));
+        assert_eq!(frames.frame(at(nanos)), Some(want));
+    }
+
+    #[test]
+    fn first_event_epoch() {
+        let frames = FrameClock::new(Rate::new(60.).unwrap(), Epoch::FirstEvent, at(0));
+        assert_eq!(frames.frame(at(1_000_000_000)), None);
+        frames.start(at(1_000_000_000));
+        // Only the first event starts the count, and clones share it.
+        frames.clone().start(at(2_000_000_000));
+        assert_eq!(frames.frame(at(1_016_666_666)), Some(0));
+        assert_eq!(frames.frame(at(1_016_666_667)), Some(1));
+        assert_eq!(frames.frame(at(2_000_000_000)), Some(60));
+        assert_eq!(frames.frame(at(0)), Some(0));
+    }
+
+    proptest::proptest! {
+        // However long since the epoch, each frame starts at the first
+        // nanosecond at or after its exact start, with no error adding up.
+        #[test]
+        fn frames_start_on_time(rate in proptest::sample::select(vec![30., 59.94, 60., 120.]), n in 1u64..100_000_000) {
+            let frames = FrameClock::new(Rate::new(rate).unwrap(), Epoch::Start, at(0));
+            let millihertz = (rate * 1000.).round() as u128;
+            let start = (u128::from(n) * 1_000_000_000_000).div_ceil(millihertz) as u64;
+            proptest::prop_assert_eq!(frames.frame(at(start)), Some(n));
+            proptest::prop_assert_eq!(frames.frame(at(start - 1)), Some(n - 1));
+        }
+    }
+
+    #[test_case(60., true; "melee")]
+    #[test_case(0.001, true; "slowest")]
+    #[test_case(0., false; "zero")]
+    #[test_case(-60., false; "negative")]
+    #[test_case(f64::NAN, false; "nan")]
+    #[test_case(f64::INFINITY, false; "infinite")]
+    fn rate_validated(hz: f64, valid: bool) {
+        assert_eq!(Rate::new(hz).is_ok(), valid);
+    }
+
+    #[test_case("start", Ok(Epoch::Start); "start")]
+    #[test_case("first-event", Ok(Epoch::FirstEvent); "first_event")]
+    #[test_case("first", Err(()); "unknown")]
+    fn epoch_parses(s: &str, want: Result<Epoch, ()>) {
+        assert_eq!(s.parse::<Epoch>().map_err(|_| ()), want);
+    }
+}
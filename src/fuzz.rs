@@ -0,0 +1,109 @@
+//! Fuzzing support.
+//!
+//! `run` decodes arbitrary bytes into a profile and a sequence of button
+//! toggles, feeds them through `Main`, and checks invariants after every step.
+//! The cargo-fuzz target in `fuzz/` calls it, and the unit tests run it over
+//! pseudo-random inputs.
+
+use super::*;
+
+const DIRECTIONS: [B0xxRaw; 4] = [B0xxRaw::Left, B0xxRaw::Right, B0xxRaw::Down, B0xxRaw::Up];
+
+/// Buttons whose change may change the trigger: the shields, and digital L/R
+/// which override them.
+const TRIGGER_BUTTONS: [B0xxRaw; 4] = [B0xxRaw::LS, B0xxRaw::MS, B0xxRaw::L, B0xxRaw::R];
+
+/// The first byte selects the profile. Each following pair of bytes toggles a
+/// button, so that presses and releases always alternate as on a real
+/// keyboard, after a delay of up to 255ms.
+pub(crate) fn run(data: &[u8]) {
+    let (&options, events) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let crouch_walk_option_select = options & 0x01 != 0;
+    let mut main = Main {
+        b_reverse_helper: (options & 0x02 != 0).then_some(config::BReverseHelper {
+            window_ms: 50,
+            flick_ms: 25,
+        }),
+        dpad: match (options >> 2) & 0x03 {
+            0 => config::DPadActivation::Layer,
+            1 => config::DPadActivation::ModAndStart,
+            _ => config::DPadActivation::BothMods,
+        },
+        c_stick_mode: if options & 0x10 != 0 {
+            config::CStickMode::Tilt
+        } else {
+            config::CStickMode::Smash
+        },
+        crouch_walk: config::CrouchWalk {
+            left: options & 0x20 != 0,
+            right: options & 0x40 != 0,
+        },
+        raw: (options & 0x80 != 0).then(RawEngine::default),
+        a_stick_reference: Some(Default::default()),
+        ..Default::default()
+    };
+    let mut now = Timestamp::default();
+    for event in events.chunks_exact(2) {
+        let btn = B0xxRaw::ALL[usize::from(event[0]) % B0xxRaw::ALL.len()];
+        now = now + std::time::Duration::from_millis(event[1].into());
+        if let Some(deadline) = main.deadline().filter(|&deadline| deadline <= now) {
+            let inputs = main.process_deadline(deadline, crouch_walk_option_select);
+            check(&main, None, &inputs);
+        }
+        let pressed = !main.held.contains(&btn);
+        let inputs = main.process_b0xx(
+            B0xxEvent {
+                time: now,
+                btn,
+                pressed,
+            },
+            crouch_walk_option_select,
+        );
+        check(&main, Some(btn), &inputs);
+    }
+}
+
+/// Checks the invariants that must hold after `btn`, if any, changed and
+/// `main` emitted `inputs`.
+fn check(main: &Main, btn: Option<B0xxRaw>, inputs: &[Input]) {
+    for input in inputs {
+        match *input {
+            // `Analog` can't hold anything else, but inversion negates it.
+            Input::Stick(_, (x, y)) => {
+                for value in [x, y] {
+                    assert!(
+                        (Analog::MIN..=Analog::MAX).contains(&value),
+                        "{:?} out of range after {:?}",
+                        input,
+                        btn
+                    );
+                }
+            }
+            Input::Trigger(_) => assert!(
+                btn.is_some_and(|btn| TRIGGER_BUTTONS.contains(&btn)),
+                "{:?} after {:?}",
+                input,
+                btn
+            ),
+            Input::Button(..) => {}
+        }
+    }
+    if let Some((x, y)) = main.a_stick_reference {
+        assert!(
+            !x.diverges(main.a_stick.x) && !y.diverges(main.a_stick.y),
+            "A-stick diverged from the reference model: {}",
+            main.snapshot()
+        );
+    }
+    if !DIRECTIONS.iter().any(|btn| main.held.contains(btn)) {
+        assert_eq!(
+            main.output.a_stick,
+            (P0000, P0000),
+            "A-stick not neutral with no direction held: {}",
+            main.snapshot()
+        );
+    }
+}
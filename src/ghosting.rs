@@ -0,0 +1,123 @@
+//! A guided check for keyboards that drop keys pressed together.
+//!
+//! Cheap keyboards can only report so many keys at once within some clusters,
+//! and silently drop the rest. `run` prompts for combinations used in play
+//! and reports any button whose press never arrived alongside the others.
+
+use super::*;
+
+/// Combinations to prompt for, each with what it is used for.
+pub(crate) const COMBOS: &[(&str, &[B0xxRaw])] = &[
+    ("up-B", &[B0xxRaw::Up, B0xxRaw::B]),
+    (
+        "SOCD with up",
+        &[B0xxRaw::Left, B0xxRaw::Right, B0xxRaw::Up],
+    ),
+    (
+        "wavedash",
+        &[B0xxRaw::Y, B0xxRaw::Down, B0xxRaw::Right, B0xxRaw::R],
+    ),
+    (
+        "firefox angle",
+        &[B0xxRaw::MX, B0xxRaw::Up, B0xxRaw::Right, B0xxRaw::CD],
+    ),
+    (
+        "Mod Y diagonal with B",
+        &[B0xxRaw::MY, B0xxRaw::Down, B0xxRaw::Left, B0xxRaw::B],
+    ),
+    ("D-pad", &[B0xxRaw::MX, B0xxRaw::MY, B0xxRaw::CU]),
+];
+
+/// Follows the presses and releases for one combination.
+#[derive(Debug)]
+pub(crate) struct Check {
+    expected: std::collections::BTreeSet<B0xxRaw>,
+    held: std::collections::BTreeSet<B0xxRaw>,
+    /// The most of `expected` held at once so far.
+    most: std::collections::BTreeSet<B0xxRaw>,
+}
+
+impl Check {
+    pub(crate) fn new(combo: &[B0xxRaw]) -> Self {
+        Self {
+            expected: combo.iter().copied().collect(),
+            held: Default::default(),
+            most: Default::default(),
+        }
+    }
+
+    /// Returns whether the combination has been pressed and everything
+    /// released again.
+    pub(crate) fn event(&mut self, btn: B0xxRaw, pressed: Pressed) -> bool {
+        if pressed {
+            let _ = self.held.insert(btn);
+            let overlap = self
+                .held
+                .intersection(&self.expected)
+                .copied()
+                .collect::<std::collections::BTreeSet<_>>();
+            if overlap.len() > self.most.len() {
+                self.most = overlap;
+            }
+        } else {
+            let _ = self.held.remove(&btn);
+        }
+        !self.most.is_empty() && self.held.is_empty()
+    }
+
+    /// Returns the buttons that never arrived while the rest were held.
+    pub(crate) fn missing(&self) -> Vec<B0xxRaw> {
+        self.expected.difference(&self.most).copied().collect()
+    }
+}
+
+/// Prompts for each of `COMBOS` in turn, reading `keyboard` until it is
+/// released, and prints the clusters that ghost.
+#[cfg(feature = "linux-input")]
+pub(crate) async fn run(
+    keyboard: &mut (impl futures::Stream<Item = std::io::Result<evdev_rs::InputEvent>> + Unpin),
+    remapper: &mut Remapper,
+    bindings: &config::Bindings,
+) -> anyhow::Result<()> {
+    let keys = |btns: &[B0xxRaw]| {
+        btns.iter()
+            .map(|&btn| format!("{:?} ({})", btn, bindings.keys(btn).join(" or ")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut ghosted = Vec::new();
+    for &(name, combo) in COMBOS {
+        println!("{}: press and hold {}, then release", name, keys(combo));
+        let mut check = Check::new(combo);
+        loop {
+            let event = keyboard
+                .try_next()
+                .await
+                .context("failed to read keyboard event")?
+                .ok_or_else(|| anyhow::anyhow!("keyboard disconnected"))?;
+            if remapper
+                .evdev_to_b0xx(event)
+                .into_iter()
+                .any(|e| check.event(e.btn, e.pressed))
+            {
+                break;
+            }
+        }
+        match &check.missing()[..] {
+            [] => println!("  ok"),
+            missing => {
+                println!("  never arrived with the rest: {}", keys(missing));
+                ghosted.push((name, missing.to_vec()));
+            }
+        }
+    }
+    if ghosted.is_empty() {
+        println!("no ghosting detected");
+    } else {
+        println!("ghosting clusters:");
+        for (name, missing) in ghosted {
+            println!("  {}: {}", name, keys(&missing));
+        }
+    }
+    Ok(())
+}
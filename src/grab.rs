@@ -0,0 +1,149 @@
+//! Noticing another process's grab of the keyboard.
+//!
+//! Whichever process holds EVIOCGRAB on the keyboard, e.g. a compositor or
+//! another remapper such as kmonad, gets every event from it, and this one
+//! none. `conflict` probes for such a grab at startup and names the processes
+//! with the keyboard open, and `SilenceWatch` hints at one when no button
+//! event arrives for a while. The system is reached through `Devices`, which
+//! tests stand in for.
+
+use super::*;
+
+/// A process with a device open.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Holder {
+    pub(crate) pid: u32,
+    /// As in `/proc/<pid>/comm`.
+    pub(crate) name: String,
+}
+
+impl std::fmt::Display for Holder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+/// What `conflict` needs of the system.
+pub(crate) trait Devices {
+    /// Grabs the device at `path` and releases it at once.
+    fn probe_grab(&self, path: &std::path::Path) -> std::io::Result<()>;
+
+    /// Returns the other processes with the device at `path` open.
+    fn holders(&self, path: &std::path::Path) -> std::io::Result<Vec<Holder>>;
+}
+
+/// The devices and processes of the running system.
+pub(crate) struct System;
+
+impl Devices for System {
+    fn probe_grab(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut device = evdev_rs::Device::new_from_path(path)?;
+        device.grab(evdev_rs::GrabMode::Grab)?;
+        device.grab(evdev_rs::GrabMode::Ungrab)
+    }
+
+    fn holders(&self, path: &std::path::Path) -> std::io::Result<Vec<Holder>> {
+        // The keyboard may be named by a symlink, e.g. in /dev/input/by-id.
+        let device = std::fs::canonicalize(path)?;
+        let mut holders = Vec::new();
+        for entry in std::fs::read_dir("/proc")? {
+            let entry = entry?;
+            let pid = match entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            {
+                Some(pid) if pid != std::process::id() => pid,
+                _ => continue,
+            };
+            // Processes exit meanwhile, and those of other users can't be
+            // looked into without privileges.
+            let fds = match std::fs::read_dir(entry.path().join("fd")) {
+                Ok(fds) => fds,
+                Err(_) => continue,
+            };
+            if fds
+                .filter_map(Result::ok)
+                .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == device))
+            {
+                let name = std::fs::read_to_string(entry.path().join("comm"))
+                    .map(|comm| comm.trim_end().to_owned())
+                    .unwrap_or_else(|_| String::from("?"));
+                holders.push(Holder { pid, name });
+            }
+        }
+        Ok(holders)
+    }
+}
+
+/// Returns a warning if another process has grabbed the device at `path`.
+/// Failing to probe is only logged, as the device may still work.
+pub(crate) fn conflict(devices: &impl Devices, path: &std::path::Path) -> Option<String> {
+    match devices.probe_grab(path) {
+        Ok(()) => return None,
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {}
+        Err(e) => {
+            debug!("failed to probe {} for a grab: {}", path.display(), e);
+            return None;
+        }
+    }
+    let holders = match devices.holders(path) {
+        Ok(holders) if holders.is_empty() => String::from("no other process visibly has it open"),
+        Ok(holders) => format!(
+            "it is open in {}",
+            holders
+                .iter()
+                .map(Holder::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(e) => format!("failed to list the processes with it open: {}", e),
+    };
+    Some(format!(
+        "{} is grabbed by another process, so no key presses will arrive; {}. \
+         stop that process, e.g. another remapper such as kmonad, or have it leave the keyboard \
+         alone",
+        path.display(),
+        holders
+    ))
+}
+
+/// Hints at another process's grab if no button event arrives for a while
+/// after the start.
+pub(crate) struct SilenceWatch {
+    after: std::time::Duration,
+    /// Until the first button event.
+    deadline: Option<Timestamp>,
+}
+
+impl SilenceWatch {
+    pub(crate) fn new(start: Timestamp, after: std::time::Duration) -> Self {
+        Self {
+            after,
+            deadline: Some(start + after),
+        }
+    }
+
+    /// Ends the watch, as the keyboard evidently reaches this process.
+    pub(crate) fn event(&mut self) {
+        self.deadline = None;
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Timestamp> {
+        self.deadline
+    }
+
+    /// Returns the hint once the deadline passes, and never again.
+    pub(crate) fn process_deadline(&mut self, now: Timestamp) -> Option<String> {
+        if !matches!(self.deadline, Some(deadline) if now >= deadline) {
+            return None;
+        }
+        self.deadline = None;
+        Some(format!(
+            "no button event in the {:?} since starting; if keys are being pressed, another \
+             process may have grabbed the keyboard, e.g. a compositor or another remapper such \
+             as kmonad, or none of them may be bound",
+            self.after
+        ))
+    }
+}
@@ -0,0 +1,141 @@
+//! What each sink does when a write fails, and how its writes have gone.
+//!
+//! `Monitored` wraps a sink with a `Policy` of its own and counts its sends,
+//! errors, drops and reconnects in a `Health`, which goes in the session
+//! statistics. Every change of status is logged.
+
+use super::*;
+
+/// What a sink does when a write fails.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Policy {
+    /// Ends the session with the error.
+    #[default]
+    Fatal,
+    /// Drops the command and carries on.
+    Drop,
+    /// Reconnects and writes the command again, dropping it if either fails.
+    Retry,
+}
+
+impl std::str::FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fatal" => Ok(Self::Fatal),
+            "drop" => Ok(Self::Drop),
+            "retry" => Ok(Self::Retry),
+            _ => Err(format!("unknown sink policy {:?}", s)),
+        }
+    }
+}
+
+/// Whether the last write succeeded.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Status {
+    #[default]
+    Healthy,
+    /// The last write failed and its command was dropped.
+    Failing,
+    /// A write failed under `Policy::Fatal`.
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub(crate) struct Health {
+    pub(crate) status: Status,
+    /// Commands written, including those written again after reconnecting.
+    pub(crate) sent: u64,
+    /// Failed writes and reconnects.
+    pub(crate) errors: u64,
+    pub(crate) dropped: u64,
+    /// Successful reconnects.
+    pub(crate) reconnects: u64,
+}
+
+/// A sink with a failure policy and health metrics.
+#[cfg(feature = "linux-input")]
+pub(crate) struct Monitored<S> {
+    name: &'static str,
+    sink: S,
+    policy: Policy,
+    health: Health,
+}
+
+#[cfg(feature = "linux-input")]
+impl<S: Sink> Monitored<S> {
+    pub(crate) fn new(name: &'static str, sink: S, policy: Policy) -> Self {
+        Self {
+            name,
+            sink,
+            policy,
+            health: Default::default(),
+        }
+    }
+
+    fn set_status(&mut self, status: Status, error: Option<&anyhow::Error>) {
+        if status == self.health.status {
+            return;
+        }
+        self.health.status = status;
+        match error {
+            Some(e) => warn!("sink {} is {:?}: {:#}", self.name, status, e),
+            None => info!("sink {} is {:?}", self.name, status),
+        }
+    }
+
+    /// Reconnects and writes `pipe_input` again.
+    fn retry(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+        self.sink.reconnect()?;
+        self.health.reconnects += 1;
+        self.sink.send(pipe_input)
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl<S: Sink> Sink for Monitored<S> {
+    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+        let e = match self.sink.send(pipe_input) {
+            Ok(()) => {
+                self.health.sent += 1;
+                self.set_status(Status::Healthy, None);
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        self.health.errors += 1;
+        let e = match self.policy {
+            Policy::Fatal => {
+                self.set_status(Status::Failed, Some(&e));
+                return Err(e);
+            }
+            Policy::Drop => e,
+            Policy::Retry => match self.retry(pipe_input) {
+                Ok(()) => {
+                    self.health.sent += 1;
+                    self.set_status(Status::Healthy, None);
+                    return Ok(());
+                }
+                Err(retry) => {
+                    self.health.errors += 1;
+                    retry
+                }
+            },
+        };
+        self.health.dropped += 1;
+        self.set_status(Status::Failing, Some(&e));
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> anyhow::Result<()> {
+        self.sink.reconnect()
+    }
+
+    fn health(&self) -> std::collections::BTreeMap<&'static str, Health> {
+        let mut health = self.sink.health();
+        let _ = health.insert(self.name, self.health);
+        health
+    }
+}
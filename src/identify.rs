@@ -0,0 +1,353 @@
+//! Picks the keyboard to read from among the input devices.
+//!
+//! Each device is described by its name, physical path and unique identifier,
+//! and those the filter excludes are skipped. Of the rest, those with letter
+//! keys are taken to be keyboards, and if there are several the keyboard is
+//! whichever first reports a key press. Without a filter or a terminal to ask
+//! on, this is left to `evdev_utils`. `probe` instead reads every candidate
+//! for a while, printing which one each key event comes from.
+//!
+//! At startup the keyboard may not have appeared yet, e.g. a wireless
+//! receiver still enumerating at login, so `retry` identifies it again with
+//! exponential backoff until it does. Any other error, e.g. the devices
+//! being unreadable, fails at once.
+
+use super::*;
+
+/// What identifies an input device to a `config::DeviceFilter`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Descriptor {
+    pub(crate) name: Option<String>,
+    pub(crate) phys: Option<String>,
+    pub(crate) uniq: Option<String>,
+}
+
+impl Descriptor {
+    #[cfg(feature = "linux-input")]
+    fn of(device: &impl evdev_rs::DeviceWrapper) -> Self {
+        Self {
+            name: device.name().map(String::from),
+            phys: device.phys().map(String::from),
+            uniq: device.uniq().map(String::from),
+        }
+    }
+
+    /// Returns the first field `pattern` matches, by name and value.
+    fn matched_by(
+        &self,
+        config::DevicePattern(pattern): &config::DevicePattern,
+    ) -> Option<(&str, &str)> {
+        [
+            ("name", &self.name),
+            ("phys", &self.phys),
+            ("uniq", &self.uniq),
+        ]
+        .into_iter()
+        .find_map(|(field, value)| {
+            value
+                .as_deref()
+                .filter(|value| pattern.matches(value))
+                .map(|value| (field, value))
+        })
+    }
+}
+
+/// Returns why `filter` excludes the device described by `descriptor`, if it
+/// does.
+pub(crate) fn exclusion(filter: &config::DeviceFilter, descriptor: &Descriptor) -> Option<String> {
+    if !filter.allow.is_empty()
+        && !filter
+            .allow
+            .iter()
+            .any(|pattern| descriptor.matched_by(pattern).is_some())
+    {
+        return Some(String::from("matches no allowed pattern"));
+    }
+    filter.ignore.iter().find_map(|pattern| {
+        descriptor.matched_by(pattern).map(|(field, value)| {
+            format!(
+                "{} {:?} matches ignored pattern {:?}",
+                field,
+                value,
+                pattern.0.as_str()
+            )
+        })
+    })
+}
+
+/// No keyboard is among the input devices considered, which `retry` waits
+/// for one to appear on.
+#[derive(Debug)]
+pub(crate) struct NotFound;
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no keyboard among the input devices considered")
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// How long to wait for a key press when asking which keyboard to use.
+#[cfg(feature = "linux-input")]
+const CONFIRM_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Returns the path of the keyboard, considering only the devices `filter`
+/// doesn't exclude. Where several look like keyboards, the user is asked to
+/// press a key on the one to use if `interactive`.
+#[cfg(feature = "linux-input")]
+pub(crate) async fn keyboard(
+    filter: &config::DeviceFilter,
+    interactive: bool,
+) -> anyhow::Result<std::path::PathBuf> {
+    use futures::FutureExt as _;
+
+    let mut candidates = candidates(filter)?;
+    if candidates.is_empty() {
+        return Err(NotFound.into());
+    }
+    if filter.is_empty() && !interactive {
+        return evdev_utils::identify_keyboard().await;
+    }
+    if candidates.len() == 1 {
+        return Ok(candidates.remove(0));
+    }
+    info!(?candidates, "found several keyboards");
+    let devices = candidates
+        .into_iter()
+        .map(|path| {
+            let device = AsyncDevice::new(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            Ok((path, device))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if !interactive {
+        info!("waiting for a key press on one of them");
+        return first_key_press(devices).await;
+    }
+    println!("press any key on the keyboard you want to use");
+    futures::select! {
+        path = first_key_press(devices).boxed_local().fuse() => path,
+        _ = futures::FutureExt::fuse(async_io::Timer::after(CONFIRM_TIMEOUT)) => {
+            Err(anyhow::anyhow!("no key pressed within {:?}", CONFIRM_TIMEOUT))
+        }
+    }
+}
+
+/// The first wait of `retry`, which doubles after each failed attempt.
+pub(crate) const RETRY_MIN: std::time::Duration = std::time::Duration::from_millis(250);
+/// The longest wait of `retry`.
+pub(crate) const RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Runs `identify` until it finds a keyboard, passing `wait` how long to wait
+/// between attempts, from `RETRY_MIN` doubling up to `RETRY_MAX`. Fails with
+/// the last error once the waits add up to `timeout`, if any, and at once
+/// with any error but `NotFound`.
+pub(crate) async fn retry<T, I, W>(
+    mut identify: impl FnMut() -> I,
+    timeout: Option<std::time::Duration>,
+    mut wait: impl FnMut(std::time::Duration) -> W,
+) -> anyhow::Result<T>
+where
+    I: std::future::Future<Output = anyhow::Result<T>>,
+    W: std::future::Future<Output = ()>,
+{
+    let mut delay = RETRY_MIN;
+    let mut waited = std::time::Duration::ZERO;
+    loop {
+        let e = match identify().await {
+            Ok(found) => return Ok(found),
+            Err(e) if e.is::<NotFound>() => e,
+            Err(e) => return Err(e),
+        };
+        let delay_now = match timeout {
+            Some(timeout) => delay.min(timeout.saturating_sub(waited)),
+            None => delay,
+        };
+        if delay_now.is_zero() {
+            return Err(e);
+        }
+        if waited.is_zero() {
+            warn!("{:#}, waiting for a keyboard to appear", e);
+        } else {
+            debug!("{:#}, retrying in {:?}", e, delay_now);
+        }
+        wait(delay_now).await;
+        waited += delay_now;
+        delay = (delay * 2).min(RETRY_MAX);
+    }
+}
+
+/// Returns the paths of the input devices that `filter` doesn't exclude and
+/// that have letter keys, in order. If there are none but some devices
+/// couldn't be opened for lack of permission, fails with the first of those
+/// errors instead, as a keyboard may be among them.
+#[cfg(feature = "linux-input")]
+fn candidates(filter: &config::DeviceFilter) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    use evdev_rs::enums::{EventCode, EV_KEY};
+    use evdev_rs::DeviceWrapper as _;
+
+    let mut denied = None;
+    let candidates = glob::glob("/dev/input/event*")
+        .expect("valid pattern")
+        .filter_map(Result::ok)
+        .filter(|path| {
+            let device = match evdev_rs::Device::new_from_path(path) {
+                Ok(device) => device,
+                Err(e) => {
+                    debug!("skipping {}: {}", path.display(), e);
+                    if e.kind() == std::io::ErrorKind::PermissionDenied && denied.is_none() {
+                        denied = Some(
+                            anyhow::Error::new(e)
+                                .context(format!("failed to open {}", path.display())),
+                        );
+                    }
+                    return false;
+                }
+            };
+            let descriptor = Descriptor::of(&device);
+            if let Some(reason) = exclusion(filter, &descriptor) {
+                debug!(?descriptor, "excluding {}: {}", path.display(), reason);
+                return false;
+            }
+            device.has_event_code(&EventCode::EV_KEY(EV_KEY::KEY_A))
+        })
+        .collect::<Vec<_>>();
+    match denied {
+        Some(e) if candidates.is_empty() => Err(e),
+        _ => Ok(candidates),
+    }
+}
+
+/// Returns the path of whichever of `devices` first reports a key press.
+/// The rest are closed when it returns.
+#[cfg(feature = "linux-input")]
+pub(crate) async fn first_key_press<S>(
+    devices: Vec<(std::path::PathBuf, S)>,
+) -> anyhow::Result<std::path::PathBuf>
+where
+    S: futures::Stream<Item = std::io::Result<evdev_rs::InputEvent>> + 'static,
+{
+    use futures::StreamExt as _;
+
+    let mut events = futures::stream::select_all(
+        devices
+            .into_iter()
+            .map(|(path, device)| device.map(move |event| (path.clone(), event)).boxed_local()),
+    );
+    while let Some((path, event)) = events.next().await {
+        let event = event.with_context(|| format!("failed to read {}", path.display()))?;
+        if matches!(event.event_code, evdev_rs::enums::EventCode::EV_KEY(_)) && event.value == 1 {
+            return Ok(path);
+        }
+    }
+    Err(anyhow::anyhow!("every input device was disconnected"))
+}
+
+/// How long `--probe` prints key events for.
+#[cfg(feature = "linux-input")]
+pub(crate) const PROBE_DURATION: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Prints a line per key event on any device `filter` doesn't exclude that
+/// looks like a keyboard, until `duration` passes or every device is
+/// disconnected. A device failing is only logged.
+#[cfg(feature = "linux-input")]
+pub(crate) async fn probe(
+    filter: &config::DeviceFilter,
+    duration: std::time::Duration,
+) -> anyhow::Result<()> {
+    use evdev_rs::DeviceWrapper as _;
+
+    let devices = candidates(filter)?
+        .into_iter()
+        .filter_map(|path| {
+            let name = evdev_rs::Device::new_from_path(&path)
+                .ok()
+                .and_then(|device| device.name().map(String::from));
+            match AsyncDevice::new(&path) {
+                Ok(device) => Some((path, name, device)),
+                Err(e) => {
+                    warn!("failed to open {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        return Err(NotFound.into());
+    }
+    println!(
+        "press keys for {:?} to see which device each comes from",
+        duration
+    );
+    key_events(devices, duration, |line| println!("{}", line)).await
+}
+
+/// Passes `output` the `probe_line` of each key event on `devices`, each
+/// with its path and name, until `duration` passes or every device ends.
+#[cfg(feature = "linux-input")]
+pub(crate) async fn key_events<S>(
+    devices: Vec<(std::path::PathBuf, Option<String>, S)>,
+    duration: std::time::Duration,
+    mut output: impl FnMut(String),
+) -> anyhow::Result<()>
+where
+    S: futures::Stream<Item = std::io::Result<evdev_rs::InputEvent>> + 'static,
+{
+    use futures::StreamExt as _;
+
+    let mut events =
+        futures::stream::select_all(devices.into_iter().map(|(path, name, device)| {
+            // A device stops at its first error, e.g. once unplugged.
+            device
+                .scan(false, |failed, event| {
+                    let done = std::mem::replace(failed, event.is_err());
+                    futures::future::ready((!done).then_some(event))
+                })
+                .map(move |event| (path.clone(), name.clone(), event))
+                .boxed_local()
+        }))
+        .fuse();
+    let timer = futures::FutureExt::fuse(async_io::Timer::after(duration));
+    futures::pin_mut!(timer);
+    loop {
+        futures::select! {
+            next = events.next() => match next {
+                Some((path, name, Ok(event))) => {
+                    if let Some(line) = probe_line(&path, name.as_deref(), &event) {
+                        output(line);
+                    }
+                }
+                Some((path, _, Err(e))) => warn!("stopped reading {}: {}", path.display(), e),
+                None => return Err(anyhow::anyhow!("every input device was disconnected")),
+            },
+            _ = timer => return Ok(()),
+        }
+    }
+}
+
+/// Describes a key event for `probe`, e.g.
+/// `/dev/input/event3 KEY_J pressed "AT Translated Set 2 keyboard"`, or
+/// returns `None` for other events.
+#[cfg(feature = "linux-input")]
+pub(crate) fn probe_line(
+    path: &std::path::Path,
+    name: Option<&str>,
+    event: &evdev_rs::InputEvent,
+) -> Option<String> {
+    if !matches!(event.event_code, evdev_rs::enums::EventCode::EV_KEY(_)) {
+        return None;
+    }
+    let action = match event.value {
+        0 => "released",
+        1 => "pressed",
+        _ => "repeated",
+    };
+    Some(format!(
+        "{} {} {} {:?}",
+        path.display(),
+        event.event_code,
+        action,
+        name.unwrap_or("unnamed")
+    ))
+}
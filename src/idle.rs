@@ -0,0 +1,108 @@
+//! Idling while the keyboard is left alone.
+//!
+//! Once no button event has arrived for a while and nothing is held or
+//! pending, `run` stops its periodic timers, e.g. the latency report and the
+//! consistency check, so that an idle session doesn't wake a laptop's CPU.
+//! The next button event wakes it. Events may have been missed meanwhile,
+//! e.g. across a suspend, so the keys down are read back from the keyboard
+//! with `keys_down`, and the buttons they hold pressed before the waking
+//! event is processed.
+
+use super::*;
+
+/// The events that bring the buttons held in line with the keyboard, as of
+/// the given time.
+pub(crate) type Resync = Box<dyn FnMut(Timestamp) -> anyhow::Result<Vec<B0xxEvent>>>;
+
+/// Goes idle once no button event has arrived for a while, and wakes on the
+/// next.
+pub(crate) struct IdleWatch {
+    after: std::time::Duration,
+    /// When to go idle unless a button event comes first, or `None` while
+    /// idle.
+    deadline: Option<Timestamp>,
+    resync: Resync,
+}
+
+impl IdleWatch {
+    pub(crate) fn new(start: Timestamp, after: std::time::Duration, resync: Resync) -> Self {
+        Self {
+            after,
+            deadline: Some(start + after),
+            resync,
+        }
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.deadline.is_none()
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Timestamp> {
+        self.deadline
+    }
+
+    /// Notes a button event. If it wakes the watch, returns the events to
+    /// process before it, which are none if reading the keyboard failed.
+    pub(crate) fn event(&mut self, now: Timestamp) -> Option<Vec<B0xxEvent>> {
+        let woke = self.is_idle();
+        self.deadline = Some(now + self.after);
+        if !woke {
+            return None;
+        }
+        let resync = (self.resync)(now).unwrap_or_else(|e| {
+            warn!("failed to resync the keys held on waking: {:#}", e);
+            Vec::new()
+        });
+        info!(resynced = resync.len(), "woke from idle");
+        Some(resync)
+    }
+
+    /// Goes idle once the deadline passes, if `quiet`, i.e. nothing is held
+    /// or pending, and otherwise waits another period. Returns whether it
+    /// went idle.
+    pub(crate) fn process_deadline(&mut self, now: Timestamp, quiet: bool) -> bool {
+        if !matches!(self.deadline, Some(deadline) if now >= deadline) {
+            return false;
+        }
+        if !quiet {
+            self.deadline = Some(now + self.after);
+            return false;
+        }
+        self.deadline = None;
+        info!(
+            "idle after {:?} without a button event, pausing the periodic timers",
+            self.after
+        );
+        true
+    }
+}
+
+/// Returns the keys the keyboard at `path` reports down, per EVIOCGKEY.
+pub(crate) fn keys_down(path: &std::path::Path) -> std::io::Result<Vec<evdev_rs::enums::EV_KEY>> {
+    use std::os::unix::io::AsRawFd as _;
+
+    /// `KEY_MAX` + 1 bits.
+    const LEN: usize = 0x300 / 8;
+    // _IOC(_IOC_READ, 'E', 0x18, LEN), as EVIOCGKEY(LEN) expands to.
+    const EVIOCGKEY: libc::c_ulong =
+        (2 << 30) | ((LEN as libc::c_ulong) << 16) | ((b'E' as libc::c_ulong) << 8) | 0x18;
+
+    let file = std::fs::File::open(path)?;
+    let mut bits = [0u8; LEN];
+    // SAFETY: `bits` is valid for writes of the `LEN` bytes the request
+    // names, and `file` is open for as long as the call.
+    let r = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGKEY as _, bits.as_mut_ptr()) };
+    if r < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(bits
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &byte)| {
+            (0..8)
+                .filter(move |bit| byte & (1 << bit) != 0)
+                .map(move |bit| (i * 8 + bit) as u32)
+        })
+        .filter_map(evdev_rs::enums::int_to_ev_key)
+        .collect())
+}
@@ -1,24 +1,324 @@
 #![deny(unused_results)]
 
-use std::io::Write as _;
+#[cfg(feature = "linux-input")]
+use anyhow::Context as _;
 
 use argh::FromArgs;
-use either::Either;
+#[cfg(feature = "linux-input")]
 use evdev_utils::AsyncDevice;
-use futures::{StreamExt as _, TryStreamExt as _};
-use log::{debug, info, trace};
+#[cfg(feature = "linux-input")]
+use futures::{FutureExt as _, StreamExt as _, TryStreamExt as _};
+use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
+#[cfg(feature = "linux-input")]
+use tracing::error;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, trace, warn};
+
+mod certify;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod combo;
+mod command_log;
+mod config;
+#[cfg(feature = "linux-input")]
+mod control;
+mod coord;
+mod coordinates;
+#[cfg(feature = "linux-input")]
+mod event_dump;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod frame;
+#[cfg(any(test, fuzzing))]
+pub(crate) mod fuzz;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod ghosting;
+#[cfg(feature = "linux-input")]
+mod grab;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod health;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod identify;
+#[cfg(feature = "linux-input")]
+mod idle;
+mod pipeline;
+#[cfg(feature = "linux-input")]
+mod recovery;
+#[cfg(feature = "linux-input")]
+pub(crate) mod remap;
+#[cfg(all(test, feature = "linux-input"))]
+mod scenario;
+mod self_test;
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+mod techniques;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod tui;
+#[cfg(feature = "linux-input")]
+mod viewer;
 
 #[derive(FromArgs)]
 /// Hako input remapping arguments.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
 struct Args {
     /// log level
-    #[argh(option, short = 'l', default = "log::LevelFilter::Info")]
-    log_level: log::LevelFilter,
-    /// enable crouch/walk option-select
+    #[argh(option, short = 'l', default = "LevelFilter::INFO")]
+    log_level: LevelFilter,
+    /// "text" (default), or "json" for one object per line including the span
+    /// of the event being processed
+    #[argh(option, default = "LogFormat::Text")]
+    log_format: LogFormat,
+    /// enable crouch/walk option-select in both directions, overriding the
+    /// profile
     #[argh(switch)]
     crouch_walk_option_select: bool,
+    /// path to a TOML configuration file
+    #[argh(option, short = 'c')]
+    config: Option<std::path::PathBuf>,
+    /// configuration profile to use
+    #[argh(option, short = 'p', default = "String::from(config::DEFAULT_PROFILE)")]
+    profile: String,
+    /// milliseconds to hold back every input by, e.g. to match netplay delay
+    #[argh(option, default = "0")]
+    delay: u64,
+    /// only write to the pipe once per frame
+    #[argh(switch)]
+    quantize: bool,
+    /// frame rate in Hz used by --quantize and to number the frames in the
+    /// logs
+    #[argh(option, default = "60.0")]
+    frame_rate: f64,
+    /// "start" (default) to number the frames in the logs from the start of
+    /// the process, or "first-event" from the first button event
+    #[argh(option, default = "frame::Epoch::Start")]
+    frame_epoch: frame::Epoch,
+    /// "b0xx" (default), or "raw" for plain gamepad input without SOCD
+    /// handling or modifiers
+    #[argh(option, default = "Engine::B0xx")]
+    engine: Engine,
+    /// "melee" (default), or "platform-fighter" for games other than Melee
+    /// reading the full range of the sticks, e.g. Rivals of Aether or Slap
+    /// City, independently of the profile
+    #[argh(option, default = "Game::Melee")]
+    game: Game,
+    /// "fatal" (default) to exit when writing to the pipe fails, "drop" to
+    /// drop the command and carry on, or "retry" to reopen the pipe and write
+    /// it again
+    #[argh(option, default = "health::Policy::Fatal")]
+    pipe_policy: health::Policy,
+    /// "standard" for the commands every Dolphin build accepts, or
+    /// "extended" to also write the analog trigger free of the analog shield
+    /// with its digital presses; overrides the config's, which defaults to
+    /// "standard"
+    #[argh(option)]
+    pipe_capability: Option<config::PipeCapability>,
+    /// print the selected profile's mapping and exit
+    #[argh(switch)]
+    print_mapping: bool,
+    /// print every coordinate the selected profile can output on either stick,
+    /// with the inputs that produce it, and exit
+    #[argh(switch)]
+    validate_coordinates: bool,
+    /// print a report of whether the selected profile keeps to tournament
+    /// rules, e.g. no macros or turbo and every coordinate in range, and
+    /// exit, failing if any check fails
+    #[argh(switch)]
+    certify: bool,
+    /// turn off every feature that outputs or times inputs of its own, e.g.
+    /// macros, turbo and the short hop helper, whatever the config says, and
+    /// log those turned off; --certify then checks the profile as run
+    #[argh(switch)]
+    tournament: bool,
+    /// path to write an SVG of every coordinate the selected profile can
+    /// output on the A-stick to, and exit
+    #[argh(option)]
+    viz: Option<std::path::PathBuf>,
+    /// evdev key name, e.g. KEY_F12, that logs a snapshot of the input state
+    /// when pressed instead of being remapped
+    #[argh(option)]
+    dump_key: Option<String>,
+    /// run a reference model of the SOCD rules alongside the engine and log
+    /// wherever they disagree, and log A-stick coordinates output inside
+    /// Melee's deadzone; always on in debug builds
+    #[argh(switch)]
+    check_invariants: bool,
+    /// write canned input through a temporary FIFO, check what is read back
+    /// and exit, to confirm the environment works without a keyboard or
+    /// Dolphin
+    #[argh(switch)]
+    self_test: bool,
+    /// prompt for key combinations used in play, hold each down and report
+    /// any key the keyboard drops, then exit
+    #[argh(switch)]
+    ghosting_test: bool,
+    /// print every event the keyboard reports with the button each would
+    /// press under the profile, or "-", until Ctrl-C, e.g. to debug the
+    /// keyboard and the mapping at once
+    #[argh(switch)]
+    dump_events: bool,
+    /// log percentiles of the latency from keyboard event to pipe write every
+    /// this many seconds, and on exit
+    #[argh(option)]
+    latency_report: Option<u64>,
+    /// path to write session statistics to as JSON on SIGUSR1 and on exit
+    #[argh(option)]
+    stats: Option<std::path::PathBuf>,
+    /// path to log every command written to the pipe to, each with the
+    /// monotonic time in nanoseconds, e.g. to line up with a replay
+    #[argh(option)]
+    command_log: Option<std::path::PathBuf>,
+    /// print the count of each command type and the gaps between commands in
+    /// a --command-log file and exit
+    #[argh(option)]
+    analyze_command_log: Option<std::path::PathBuf>,
+    /// path to save the buttons held and the output to while running, to
+    /// notice on the next start if the process died and release what it left
+    /// pressed; defaults to tuxb0xx/state.json in $XDG_CONFIG_HOME, or else
+    /// in ~/.config
+    #[argh(option)]
+    state_file: Option<std::path::PathBuf>,
+    /// neither save nor check the state file
+    #[argh(switch)]
+    no_state_file: bool,
+    /// compare the buttons held with the state derived from them every this
+    /// many seconds, at least 1, logging any contradiction, e.g. after a lost
+    /// release
+    #[argh(option)]
+    check_consistency: Option<u64>,
+    /// with --check-consistency, rebuild the state from the buttons held on a
+    /// contradiction and write the difference to the pipe
+    #[argh(switch)]
+    heal: bool,
+    /// show the buttons held, the stick coordinates and trigger sent, and the
+    /// modifier and shield state in the terminal while running
+    #[argh(switch)]
+    tui: bool,
+    /// glob matched against input device names, physical paths and unique
+    /// identifiers to skip when identifying the keyboard, in addition to the
+    /// config's; may be repeated
+    #[argh(option)]
+    ignore_device: Vec<config::DevicePattern>,
+    /// glob a device must match to be identified as the keyboard, in addition
+    /// to the config's; may be repeated
+    #[argh(option)]
+    allow_device: Vec<config::DevicePattern>,
+    /// never prompt for a key press on the keyboard to use, even on a
+    /// terminal with several keyboards connected, e.g. in scripts
+    #[argh(switch)]
+    non_interactive: bool,
+    /// print the device, key and device name of each key event on every
+    /// device that could be the keyboard for 30 seconds, and exit, e.g. to
+    /// tell which one is under your hands
+    #[argh(switch)]
+    probe: bool,
+    /// seconds to keep looking for the keyboard at startup if none is found,
+    /// or 0 to exit at once; looks until one appears by default
+    #[argh(option)]
+    wait_for_device: Option<u64>,
+    /// seconds without a button event after starting before hinting that
+    /// another process may have grabbed the keyboard, or 0 never (default 10)
+    #[argh(option, default = "10")]
+    silence_hint: u64,
+    /// read the keyboard on a thread of its own, queueing up to this many
+    /// events for processing, so that slow output never delays reading it;
+    /// read where the events are processed by default
+    #[argh(option)]
+    reader_queue: Option<usize>,
+    /// "block" (default) for the reader to wait for room in a full
+    /// --reader-queue, or "drop-oldest" to drop the oldest event queued with a
+    /// warning and read back the keys down
+    #[argh(option, default = "pipeline::Overflow::Block")]
+    reader_overflow: pipeline::Overflow,
+    /// seconds without a button event before pausing the periodic timers,
+    /// e.g. --latency-report's, until the next, or 0 never (default 300)
+    #[argh(option, default = "300")]
+    idle_after: u64,
+    /// path of a Unix socket to take requests from other tools on, e.g. to
+    /// switch profiles; see the README for the protocol
+    #[argh(option)]
+    control_socket: Option<std::path::PathBuf>,
+    /// local TCP port to send the buttons held on in the b0xx-viewer's
+    /// serial format, for the overlay to show them
+    #[argh(option)]
+    viewer_port: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Engine {
+    B0xx,
+    Raw,
+}
+
+impl std::str::FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "b0xx" => Ok(Self::B0xx),
+            "raw" => Ok(Self::Raw),
+            _ => Err(format!("unknown engine {:?}", s)),
+        }
+    }
+}
+
+/// What reads the pipe, which decides how stick coordinates are written and
+/// whether Melee's refinements apply.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Game {
+    #[default]
+    Melee,
+    /// Any other platform fighter, which takes the b0xx's range as the whole
+    /// of the stick's and has no use for Melee's firefox angles or crouch/walk
+    /// option-select.
+    PlatformFighter,
+}
+
+impl std::str::FromStr for Game {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "melee" => Ok(Self::Melee),
+            "platform-fighter" => Ok(Self::PlatformFighter),
+            _ => Err(format!("unknown game {:?}", s)),
+        }
+    }
+}
+
+impl Game {
+    /// Returns the pipe value of a stick axis at `a`, from 0 to 1.
+    fn stick_value(self, a: Analog) -> f64 {
+        let a = f64::from(a.get());
+        match self {
+            // Melee reads 128 steps below the centre and 127 above.
+            Self::Melee => 0.5 + 0.5 * if a < 0.0 { a / 128. } else { a / 127. },
+            Self::PlatformFighter => 0.5 + 0.5 * a / f64::from(Analog::MAX.get()),
+        }
+    }
+
+    /// Whether the firefox angles and crouch/walk option-select apply.
+    fn melee_refinements(self) -> bool {
+        self == Self::Melee
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum LogFormat {
+    Text,
+    Json,
 }
 
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format {:?}", s)),
+        }
+    }
+}
+
+#[cfg(feature = "linux-input")]
 fn log_event(event: &evdev_rs::InputEvent) {
     use evdev_rs::enums::EventCode;
     match event.event_code {
@@ -29,58 +329,197 @@ fn log_event(event: &evdev_rs::InputEvent) {
     }
 }
 
-struct Remapper;
+#[cfg(feature = "linux-input")]
+#[derive(Default)]
+struct Remapper {
+    bindings: std::collections::HashMap<config::Source, B0xxRaw>,
+    /// The bound sources currently held, in the order they were pressed.
+    pressed: Vec<config::Source>,
+    /// The keys whose autorepeat isn't dropped.
+    autorepeat: std::collections::HashMap<config::Source, config::Autorepeat>,
+    /// Whether the raw engine reads the events, for `Autorepeat::Raw`.
+    raw: bool,
+    /// Keys that pause or resume rather than being remapped.
+    focus: std::collections::HashSet<evdev_rs::enums::EventCode>,
+}
 
+#[cfg(feature = "linux-input")]
 impl Remapper {
-    fn keyboard_to_b0xx(&self, c: evdev_rs::enums::EventCode) -> Option<B0xxRaw> {
-        use evdev_rs::enums::{EventCode, EV_KEY};
-        match c {
-            EventCode::EV_KEY(EV_KEY::KEY_SEMICOLON) => Some(B0xxRaw::L),
-            EventCode::EV_KEY(EV_KEY::KEY_O) => Some(B0xxRaw::Left),
-            EventCode::EV_KEY(EV_KEY::KEY_E) => Some(B0xxRaw::Down),
-            EventCode::EV_KEY(EV_KEY::KEY_U) => Some(B0xxRaw::Right),
-            EventCode::EV_KEY(EV_KEY::KEY_LEFTSHIFT) => Some(B0xxRaw::MX),
-            EventCode::EV_KEY(EV_KEY::KEY_LEFTCTRL) => Some(B0xxRaw::MY),
-            EventCode::EV_KEY(EV_KEY::KEY_Y) | EventCode::EV_KEY(EV_KEY::KEY_F) => {
-                Some(B0xxRaw::Start)
-            }
-            EventCode::EV_KEY(EV_KEY::KEY_G) => Some(B0xxRaw::R),
-            EventCode::EV_KEY(EV_KEY::KEY_C) => Some(B0xxRaw::Y),
-            EventCode::EV_KEY(EV_KEY::KEY_R) => Some(B0xxRaw::LS),
-            EventCode::EV_KEY(EV_KEY::KEY_S) => Some(B0xxRaw::MS),
-            EventCode::EV_KEY(EV_KEY::KEY_H) => Some(B0xxRaw::B),
-            EventCode::EV_KEY(EV_KEY::KEY_T) => Some(B0xxRaw::X),
-            EventCode::EV_KEY(EV_KEY::KEY_N) => Some(B0xxRaw::Z),
-            EventCode::EV_KEY(EV_KEY::KEY_Z) => Some(B0xxRaw::Up),
-            EventCode::EV_KEY(EV_KEY::KEY_ESC) => Some(B0xxRaw::CD),
-            EventCode::EV_KEY(EV_KEY::KEY_BACKSPACE) => Some(B0xxRaw::CL),
-            EventCode::EV_KEY(EV_KEY::KEY_DOWN) => Some(B0xxRaw::CU),
-            EventCode::EV_KEY(EV_KEY::KEY_ENTER) => Some(B0xxRaw::CR),
-            EventCode::EV_KEY(EV_KEY::KEY_SPACE) => Some(B0xxRaw::A),
-            _ => None,
+    fn new(bindings: std::collections::HashMap<config::Source, B0xxRaw>) -> Self {
+        Self {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    /// Binds the keys to `profile`, returning the buttons the keys held
+    /// press under it. The keys held stay held, so that their releases still
+    /// come through, unless the profile leaves them unbound.
+    fn rebind(&mut self, profile: &config::Profile) -> Vec<B0xxRaw> {
+        let bindings = profile.bindings.sources();
+        let pressed = self
+            .pressed
+            .drain(..)
+            .filter(|source| bindings.contains_key(source))
+            .collect();
+        *self = Self {
+            autorepeat: profile.autorepeat.sources(),
+            raw: self.raw,
+            focus: std::mem::take(&mut self.focus),
+            pressed,
+            ..Self::new(bindings)
+        };
+        self.held()
+    }
+
+    /// Returns the buttons held by any of their keys, in the order they were
+    /// first held.
+    fn held(&self) -> Vec<B0xxRaw> {
+        let mut held = Vec::new();
+        for &btn in self
+            .pressed
+            .iter()
+            .filter_map(|source| self.bindings.get(source))
+        {
+            if !held.contains(&btn) {
+                held.push(btn);
+            }
         }
+        held
+    }
+
+    /// Returns the button `event` is bound to, without changing what is
+    /// held. A hat is bound per direction, so centring it is bound to none.
+    fn binding(&self, event: &evdev_rs::InputEvent) -> Option<B0xxRaw> {
+        use config::Source;
+        use evdev_rs::enums::EventCode;
+
+        let source = match event.event_code {
+            EventCode::EV_KEY(_) | EventCode::EV_SW(_) => Source::Code(event.event_code),
+            EventCode::EV_ABS(_) if event.value != 0 => {
+                Source::Hat(event.event_code, event.value > 0)
+            }
+            _ => return None,
+        };
+        self.bindings.get(&source).copied()
     }
 
+    fn is_held(&self, btn: B0xxRaw) -> bool {
+        self.pressed
+            .iter()
+            .any(|source| self.bindings.get(source) == Some(&btn))
+    }
+
+    /// Returns the events for changes in whether buttons are held. Autorepeat
+    /// is dropped, unless its key's `Autorepeat` says to release and press
+    /// again, as are presses and releases of a key while another key bound to
+    /// the same button holds it. A hat moving straight from one
+    /// direction to the other releases the first before pressing the second.
     fn evdev_to_b0xx(
-        &self,
+        &mut self,
         evdev_rs::InputEvent {
             time,
             event_code,
             value,
         }: evdev_rs::InputEvent,
+    ) -> SmallVec<[B0xxEvent; 2]> {
+        use config::Source;
+        use evdev_rs::enums::EventCode;
+
+        if self.focus.contains(&event_code) {
+            return smallvec![];
+        }
+        let changes: SmallVec<[(Source, Pressed); 2]> = match event_code {
+            // 2 is autorepeat.
+            EventCode::EV_KEY(_) if value == 2 => {
+                let source = Source::Code(event_code);
+                let repress = match self.autorepeat.get(&source) {
+                    Some(config::Autorepeat::Press) => true,
+                    Some(config::Autorepeat::Raw) => self.raw,
+                    Some(config::Autorepeat::Drop) | None => false,
+                };
+                if repress {
+                    smallvec![(source, RELEASED), (source, PRESSED)]
+                } else {
+                    smallvec![]
+                }
+            }
+            EventCode::EV_KEY(_) | EventCode::EV_SW(_) => {
+                smallvec![(Source::Code(event_code), value != 0)]
+            }
+            EventCode::EV_ABS(_) => {
+                let (from, to) = if value < 0 {
+                    (POSITIVE, NEGATIVE)
+                } else {
+                    (NEGATIVE, POSITIVE)
+                };
+                smallvec![
+                    (Source::Hat(event_code, from), RELEASED),
+                    (Source::Hat(event_code, to), value != 0),
+                ]
+            }
+            _ => smallvec![],
+        };
+        let time = Timestamp::from_realtime(time.as_raw());
+        changes
+            .into_iter()
+            .filter_map(|(source, pressed)| self.change(time, source, pressed))
+            .collect()
+    }
+
+    /// Returns whether `event` presses a focus key, which pauses or resumes
+    /// instead.
+    fn focus_pressed(&self, event: &evdev_rs::InputEvent) -> bool {
+        event.value == 1 && self.focus.contains(&event.event_code)
+    }
+
+    /// Returns the events bringing the keys held in line with `down`, the
+    /// keys the keyboard reports down, e.g. after events may have been
+    /// missed. Switches and hats are left as they are.
+    fn resync(&mut self, time: Timestamp, down: &[evdev_rs::enums::EV_KEY]) -> Vec<B0xxEvent> {
+        use evdev_rs::enums::EventCode;
+
+        let mut keys = self
+            .bindings
+            .keys()
+            .filter_map(|&source| match source {
+                config::Source::Code(EventCode::EV_KEY(key)) => Some((source, key)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Presses first, so that a button moving between aliases stays held,
+        // then in a fixed order.
+        keys.sort_by_key(|&(_, key)| (!down.contains(&key), key as u32));
+        keys.into_iter()
+            .filter_map(|(source, key)| {
+                let pressed = down.contains(&key);
+                (self.pressed.contains(&source) != pressed)
+                    .then(|| self.change(time, source, pressed))
+                    .flatten()
+            })
+            .collect()
+    }
+
+    /// Presses or releases `source`, returning the event if that changes
+    /// whether its button is held.
+    fn change(
+        &mut self,
+        time: Timestamp,
+        source: config::Source,
+        pressed: Pressed,
     ) -> Option<B0xxEvent> {
-        if value == 2 {
-            return None;
+        let btn = *self.bindings.get(&source)?;
+        let was_held = self.is_held(btn);
+        if !pressed {
+            self.pressed.retain(|&held| held != source);
+        } else if !self.pressed.contains(&source) {
+            self.pressed.push(source);
         }
-        Some(B0xxEvent {
-            time: time.as_raw(),
-            pressed: value == 1,
-            btn: self.keyboard_to_b0xx(event_code)?,
-        })
+        (self.is_held(btn) != was_held).then_some(B0xxEvent { time, btn, pressed })
     }
 }
 
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Hash, Eq, Ord, PartialEq, PartialOrd, Debug, Deserialize, Serialize)]
 enum B0xxRaw {
     A,
     B,
@@ -96,12 +535,80 @@ enum B0xxRaw {
     Up,
     MX,
     MY,
+    MT,
+    /// D-pad layer.
+    DP,
     LS,
     MS,
     CU,
     CD,
     CL,
     CR,
+    /// Dedicated D-pad directions, independent of the C-stick.
+    DU,
+    DD,
+    DL,
+    DR,
+    /// Fires the profile's turbo button while held.
+    TB,
+    /// Presses X for the profile's short-hop duration.
+    SH,
+    /// Ramps the analog trigger while held, per the profile's trigger ramp.
+    TR,
+    /// Play the profile's macros bound to them.
+    M1,
+    M2,
+    M3,
+    M4,
+}
+
+impl B0xxRaw {
+    /// Every button, in declaration order, so that `ALL[btn as usize] == btn`.
+    const ALL: [Self; 33] = [
+        Self::A,
+        Self::B,
+        Self::L,
+        Self::R,
+        Self::X,
+        Self::Y,
+        Self::Z,
+        Self::Start,
+        Self::Left,
+        Self::Right,
+        Self::Down,
+        Self::Up,
+        Self::MX,
+        Self::MY,
+        Self::MT,
+        Self::DP,
+        Self::LS,
+        Self::MS,
+        Self::CU,
+        Self::CD,
+        Self::CL,
+        Self::CR,
+        Self::DU,
+        Self::DD,
+        Self::DL,
+        Self::DR,
+        Self::TB,
+        Self::SH,
+        Self::TR,
+        Self::M1,
+        Self::M2,
+        Self::M3,
+        Self::M4,
+    ];
+
+    /// Returns the controller button this outputs as is, if any.
+    fn button(self) -> Option<Button> {
+        match self.into() {
+            B0xx::Pure(Pure::Button(btn)) => Some(Button::Pure(btn)),
+            B0xx::Pure(Pure::DPad(axis, dir)) => Some(Button::DPad(axis, dir)),
+            B0xx::Impure(Impure::Button(btn)) => Some(Button::Impure(btn)),
+            _ => None,
+        }
+    }
 }
 
 impl From<B0xxRaw> for B0xx {
@@ -121,16 +628,30 @@ impl From<B0xxRaw> for B0xx {
             B0xxRaw::Up => B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, POSITIVE)),
             B0xxRaw::MX => B0xx::Impure(Impure::ModX),
             B0xxRaw::MY => B0xx::Impure(Impure::ModY),
+            B0xxRaw::MT => B0xx::Impure(Impure::ModTilt),
+            B0xxRaw::DP => B0xx::Impure(Impure::DPadLayer),
             B0xxRaw::LS => B0xx::Pure(Pure::Shield(Shield::Light)),
             B0xxRaw::MS => B0xx::Pure(Pure::Shield(Shield::Medium)),
             B0xxRaw::CU => B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, POSITIVE)),
             B0xxRaw::CD => B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, NEGATIVE)),
             B0xxRaw::CR => B0xx::Impure(Impure::Stick(Stick::C, Axis::X, POSITIVE)),
             B0xxRaw::CL => B0xx::Impure(Impure::Stick(Stick::C, Axis::X, NEGATIVE)),
+            B0xxRaw::DU => B0xx::Pure(Pure::DPad(Axis::Y, POSITIVE)),
+            B0xxRaw::DD => B0xx::Pure(Pure::DPad(Axis::Y, NEGATIVE)),
+            B0xxRaw::DR => B0xx::Pure(Pure::DPad(Axis::X, POSITIVE)),
+            B0xxRaw::DL => B0xx::Pure(Pure::DPad(Axis::X, NEGATIVE)),
+            B0xxRaw::TB => B0xx::Impure(Impure::Turbo),
+            B0xxRaw::SH => B0xx::Impure(Impure::ShortHop),
+            B0xxRaw::TR => B0xx::Impure(Impure::TriggerRamp),
+            B0xxRaw::M1 => B0xx::Impure(Impure::Macro(1)),
+            B0xxRaw::M2 => B0xx::Impure(Impure::Macro(2)),
+            B0xxRaw::M3 => B0xx::Impure(Impure::Macro(3)),
+            B0xxRaw::M4 => B0xx::Impure(Impure::Macro(4)),
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
 enum GCButton {
     A,
     B,
@@ -146,6 +667,23 @@ enum GCButton {
     Start,
 }
 
+impl GCButton {
+    const ALL: [Self; 12] = [
+        Self::A,
+        Self::B,
+        Self::DUp,
+        Self::DDown,
+        Self::DLeft,
+        Self::DRight,
+        Self::L,
+        Self::R,
+        Self::X,
+        Self::Y,
+        Self::Z,
+        Self::Start,
+    ];
+}
+
 impl From<Button> for GCButton {
     fn from(button: Button) -> GCButton {
         match button {
@@ -165,6 +703,25 @@ impl From<Button> for GCButton {
     }
 }
 
+impl From<GCButton> for Button {
+    fn from(button: GCButton) -> Button {
+        match button {
+            GCButton::A => Button::Pure(ButtonPure::A),
+            GCButton::X => Button::Pure(ButtonPure::X),
+            GCButton::Y => Button::Pure(ButtonPure::Y),
+            GCButton::Z => Button::Pure(ButtonPure::Z),
+            GCButton::Start => Button::Pure(ButtonPure::Start),
+            GCButton::B => Button::Impure(ButtonImpure::B),
+            GCButton::L => Button::Impure(ButtonImpure::L),
+            GCButton::R => Button::Impure(ButtonImpure::R),
+            GCButton::DUp => Button::DPad(Axis::Y, POSITIVE),
+            GCButton::DDown => Button::DPad(Axis::Y, NEGATIVE),
+            GCButton::DRight => Button::DPad(Axis::X, POSITIVE),
+            GCButton::DLeft => Button::DPad(Axis::X, NEGATIVE),
+        }
+    }
+}
+
 impl From<ButtonImpure> for GCButton {
     fn from(button: ButtonImpure) -> GCButton {
         match button {
@@ -208,6 +765,7 @@ enum Axis {
 enum Pure {
     Button(ButtonPure),
     Shield(Shield),
+    DPad(Axis, Direction),
 }
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
@@ -216,6 +774,13 @@ enum Impure {
     Stick(Stick, Axis, Direction),
     ModX,
     ModY,
+    ModTilt,
+    DPadLayer,
+    Turbo,
+    ShortHop,
+    TriggerRamp,
+    /// The macro key of that number.
+    Macro(u8),
 }
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
@@ -224,23 +789,73 @@ enum B0xx {
     Impure(Impure),
 }
 
+/// A point in time on the monotonic clock.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+struct Timestamp(std::time::Duration);
+
+#[cfg(feature = "linux-input")]
+impl Timestamp {
+    fn now() -> Self {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `now` is valid for writes.
+        let r = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        assert_eq!(r, 0, "failed to read the monotonic clock");
+        Self(std::time::Duration::new(
+            now.tv_sec as u64,
+            now.tv_nsec as u32,
+        ))
+    }
+
+    /// Converts a timestamp on the realtime clock, which evdev uses by
+    /// default, by measuring its age against the current real time. Unlike
+    /// the timestamp itself, the result is unaffected by the realtime clock
+    /// being stepped between events.
+    fn from_realtime(time: libc::timeval) -> Self {
+        let time = std::time::UNIX_EPOCH
+            + std::time::Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        let age = std::time::SystemTime::now()
+            .duration_since(time)
+            .unwrap_or_default();
+        Self(Self::now().0.saturating_sub(age))
+    }
+}
+
+impl std::ops::Add<std::time::Duration> for Timestamp {
+    type Output = Self;
+
+    fn add(self, duration: std::time::Duration) -> Self {
+        Self(self.0 + duration)
+    }
+}
+
+impl std::ops::Sub for Timestamp {
+    type Output = std::time::Duration;
+
+    /// Saturates to zero if `earlier` is in fact later.
+    fn sub(self, earlier: Self) -> std::time::Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 struct B0xxEvent {
-    time: libc::timeval,
+    time: Timestamp,
     btn: B0xxRaw,
     pressed: Pressed,
 }
 
 impl B0xxEvent {
+    #[cfg(test)]
+    fn new(btn: B0xxRaw, pressed: Pressed, time: Timestamp) -> Self {
+        Self { time, btn, pressed }
+    }
+
     #[cfg(test)]
     fn new_without_time(btn: B0xxRaw, pressed: Pressed) -> Self {
-        Self {
-            time: libc::timeval {
-                tv_sec: 0,
-                tv_usec: 0,
-            },
-            btn,
-            pressed,
-        }
+        Self::new(btn, pressed, Timestamp::default())
     }
 }
 
@@ -248,6 +863,28 @@ bounded_integer::bounded_integer! {
     enum Analog { -80..=80 }
 }
 
+impl Serialize for Analog {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i8(self.get())
+    }
+}
+
+impl Analog {
+    /// The largest magnitude Melee reads as neutral on an A-stick axis.
+    const DEADZONE: Self = Self::P22;
+
+    /// Whether Melee reads the axis as neutral.
+    fn in_deadzone(self) -> bool {
+        self.get().abs() <= Self::DEADZONE.get()
+    }
+}
+
+/// Whether Melee reads an A-stick coordinate as neutral, i.e. both axes are
+/// within the deadzone.
+fn in_deadzone((x, y): GCStickInput) -> bool {
+    x.in_deadzone() && y.in_deadzone()
+}
+
 #[allow(dead_code)]
 mod consts {
     use super::Analog;
@@ -338,6 +975,12 @@ use consts::*;
 bounded_integer::bounded_integer! {
     enum Trigger { 0..=140 }
 }
+
+impl Serialize for Trigger {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.get())
+    }
+}
 const LS: Trigger = Trigger::P49;
 const MS: Trigger = Trigger::P94;
 
@@ -351,6 +994,7 @@ type GCStickInput = (Analog, Analog);
 type AStickInput = GCStickInput;
 type CStickInput = GCStickInput;
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum DolphinPipeInput {
     Button(GCButton, Pressed),
     Trigger(Trigger),
@@ -358,12 +1002,31 @@ enum DolphinPipeInput {
 }
 
 impl DolphinPipeInput {
-    fn into_input_string(self) -> String {
+    /// Returns the pipe command, writing to the triggers per `handedness`, the
+    /// sticks as `game` reads them, and only what `capability` accepts.
+    fn into_input_string(
+        self,
+        handedness: config::Handedness,
+        game: Game,
+        capability: config::PipeCapability,
+    ) -> String {
+        let mut command = String::new();
+        let _ = self.write_command(&mut command, handedness, game, capability);
+        command
+    }
+
+    /// Appends the pipe command to `out`, as `into_input_string` returns it
+    /// but without allocating beyond what `out` does.
+    fn write_command(
+        self,
+        out: &mut impl std::fmt::Write,
+        handedness: config::Handedness,
+        game: Game,
+        capability: config::PipeCapability,
+    ) -> std::fmt::Result {
         match self {
-            Self::Button(button, pressed) => format!(
-                "{} {}\n",
-                if pressed { "PRESS" } else { "RELEASE" },
-                match button {
+            Self::Button(button, pressed) => {
+                let name = match handedness.button(button) {
                     GCButton::A => "A",
                     GCButton::B => "B",
                     GCButton::DUp => "D_Up",
@@ -376,77 +1039,76 @@ impl DolphinPipeInput {
                     GCButton::Y => "Y",
                     GCButton::Z => "Z",
                     GCButton::Start => "START",
+                };
+                writeln!(
+                    out,
+                    "{} {}",
+                    if pressed { "PRESS" } else { "RELEASE" },
+                    name
+                )?;
+                // A real trigger only clicks fully pressed, so the one free
+                // of the analog shield is pressed and released all the way.
+                match (capability, button) {
+                    (config::PipeCapability::Extended, GCButton::L | GCButton::R)
+                        if name != handedness.analog_trigger() =>
+                    {
+                        writeln!(out, "SET {} {}", name, u8::from(pressed))
+                    }
+                    _ => Ok(()),
                 }
-            ),
-            Self::Trigger(trigger) => format!("SET L {}\n", (trigger.get() as f64) / 128.),
-            Self::Stick(stick, (x, y)) => {
-                fn convert(a: Analog) -> f64 {
-                    let a = a.get() as f64;
-                    0.5 + 0.5 * if a < 0.0 { a / 128. } else { a / 127. }
-                }
-
-                format!(
-                    "SET {} {} {}",
-                    match stick {
-                        Stick::A => "MAIN",
-                        Stick::C => "C",
-                    },
-                    convert(x),
-                    convert(y)
-                )
             }
+            Self::Trigger(trigger) => writeln!(
+                out,
+                "SET {} {}",
+                handedness.analog_trigger(),
+                f64::from(trigger.get()) / coord::TRIGGER_UNIT
+            ),
+            Self::Stick(stick, (x, y)) => writeln!(
+                out,
+                "SET {} {} {}",
+                match stick {
+                    Stick::A => "MAIN",
+                    Stick::C => "C",
+                },
+                game.stick_value(x),
+                game.stick_value(y)
+            ),
         }
     }
 }
 
-/// Intermediary representation of a possibly composite input.
+/// Intermediary representation of an input.
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
 enum Input {
     Button(Button, Pressed),
     Stick(Stick, GCStickInput),
     Trigger(Trigger),
-    ModifiedPress(AStickInput, ButtonImpure),
-    ReleaseModifier(ButtonImpure, AStickInput),
-    CStickModifier { a: AStickInput, c: CStickInput },
 }
 
 impl Input {
-    fn into_pipe_inputs(self) -> impl IntoIterator<Item = DolphinPipeInput> {
+    fn into_pipe_input(self) -> DolphinPipeInput {
         match self {
-            Self::Button(button, pressed) => Either::Left(std::iter::once(
-                DolphinPipeInput::Button(button.into(), pressed),
-            )),
-            Self::Trigger(trigger) => {
-                Either::Left(std::iter::once(DolphinPipeInput::Trigger(trigger)))
-            }
-            Self::Stick(stick, stick_input) => {
-                Either::Left(std::iter::once(DolphinPipeInput::Stick(stick, stick_input)))
-            }
-            Self::ModifiedPress(a_stick_input, button_impure) => Either::Right(
-                [
-                    DolphinPipeInput::Stick(Stick::A, a_stick_input),
-                    DolphinPipeInput::Button(button_impure.into(), PRESSED),
-                ]
-                .into_iter(),
-            ),
-            Self::ReleaseModifier(button_impure, a_stick_input) => Either::Right(
-                [
-                    DolphinPipeInput::Button(button_impure.into(), RELEASED),
-                    DolphinPipeInput::Stick(Stick::A, a_stick_input),
-                ]
-                .into_iter(),
-            ),
-            Self::CStickModifier { a, c } => Either::Right(
-                [
-                    DolphinPipeInput::Stick(Stick::C, c),
-                    DolphinPipeInput::Stick(Stick::A, a),
-                ]
-                .into_iter(),
-            ),
+            Self::Button(button, pressed) => DolphinPipeInput::Button(button.into(), pressed),
+            Self::Stick(stick, stick_input) => DolphinPipeInput::Stick(stick, stick_input),
+            Self::Trigger(trigger) => DolphinPipeInput::Trigger(trigger),
+        }
+    }
+}
+
+impl From<DolphinPipeInput> for Input {
+    fn from(pipe_input: DolphinPipeInput) -> Self {
+        match pipe_input {
+            DolphinPipeInput::Button(button, pressed) => Self::Button(button.into(), pressed),
+            DolphinPipeInput::Stick(stick, stick_input) => Self::Stick(stick, stick_input),
+            DolphinPipeInput::Trigger(trigger) => Self::Trigger(trigger),
         }
     }
 }
 
+/// The inputs resulting from a single event, in the order they must be
+/// written. No event produces more than three.
+type Inputs = SmallVec<[Input; 3]>;
+
 bitflags::bitflags! {
     #[derive(Default)]
     struct B0xxState: u16 {
@@ -457,12 +1119,22 @@ bitflags::bitflags! {
         const R = 0x004;
         const MOD_X = 0x008;
         const MOD_Y = 0x010;
+        const MOD_TILT = 0x020;
+        const DPAD_LAYER = 0x040;
+        const START = 0x080;
 
         const MODS = Self::MOD_X.bits | Self::MOD_Y.bits;
         const LR = Self::L.bits | Self::R.bits;
     }
 }
 
+/// Serializes as the names of the set flags, e.g. `"B | MOD_X"`.
+impl Serialize for B0xxState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:?}", self))
+    }
+}
+
 type Direction = bool;
 const POSITIVE: Direction = true;
 const NEGATIVE: Direction = false;
@@ -471,12 +1143,14 @@ type Pressed = bool;
 const PRESSED: Pressed = true;
 const RELEASED: Pressed = false;
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
 enum AxisState {
     // No direction is active, but the direction if present is held.
     Null(Option<Direction>),
     // Direction is active and whether the opposing direction is pressed.
     Active(Direction, Pressed),
+    // Both directions are held and cancel out, under `CStickSocd::Neutral`.
+    Neutral,
 }
 
 impl std::default::Default for AxisState {
@@ -488,18 +1162,24 @@ impl std::default::Default for AxisState {
 impl AxisState {
     fn active(self) -> Option<Direction> {
         match self {
-            Self::Null(_) => None,
+            Self::Null(_) | Self::Neutral => None,
             Self::Active(dir, _) => Some(dir),
         }
     }
 
     fn active_unique(self) -> Option<Direction> {
         match self {
-            Self::Null(_) => None,
+            Self::Null(_) | Self::Neutral => None,
             Self::Active(dir, opposite) => (!opposite).then_some(dir),
         }
     }
 
+    /// Returns whether the negative and positive directions are held.
+    fn held(self) -> [Pressed; 2] {
+        [NEGATIVE, POSITIVE]
+            .map(|dir| self.state_in_dir(dir) != AxisButtonState::Inactive(RELEASED))
+    }
+
     fn state_in_dir(self, dir: Direction) -> AxisButtonState {
         match self {
             Self::Null(optional_pressed_dir) => {
@@ -512,6 +1192,7 @@ impl AxisState {
                     AxisButtonState::Inactive(opposite_pressed)
                 }
             }
+            Self::Neutral => AxisButtonState::Inactive(PRESSED),
         }
     }
 
@@ -531,12 +1212,87 @@ impl AxisState {
                     Self::Active(active, RELEASED)
                 }
             }
+            // Only entered by `transition_neutral`, but handled for completeness.
+            Self::Neutral if !pressed => Self::Null(Some(!dir)),
+            // Pressing a held direction or releasing an unheld one, which only
+            // happens when several keys are bound to the same direction.
             _ => *self,
         }
     }
+
+    /// Like `transition`, but `priority` wins whenever both directions are
+    /// held, whatever the order they were pressed in, and releasing either
+    /// leaves the other active.
+    fn transition_prioritized(&mut self, priority: Direction, dir: Direction, pressed: Pressed) {
+        let mut held = self.held();
+        held[dir as usize] = pressed;
+        *self = match held {
+            [false, false] => Self::Null(None),
+            [true, true] => Self::Active(priority, PRESSED),
+            [_, positive] => Self::Active(positive, RELEASED),
+        };
+    }
+
+    /// Like `transition`, but neither direction wins while both are held, and
+    /// releasing either leaves the other active.
+    fn transition_neutral(&mut self, dir: Direction, pressed: Pressed) {
+        let mut held = self.held();
+        held[dir as usize] = pressed;
+        *self = match held {
+            [false, false] => Self::Null(None),
+            [true, true] => Self::Neutral,
+            [_, positive] => Self::Active(positive, RELEASED),
+        };
+    }
+}
+
+/// A plain model of the SOCD rules that `AxisState` implements: the last
+/// direction pressed wins, and once it is released the other direction stays a
+/// no-op until it is released too. With a priority, that direction wins
+/// instead, and the other takes over once it is released.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct AxisReference {
+    /// Indexed by direction.
+    held: [bool; 2],
+    active: Option<Direction>,
+    priority: Option<Direction>,
+}
+
+impl AxisReference {
+    fn with_priority(priority: Option<Direction>) -> Self {
+        Self {
+            priority,
+            ..Default::default()
+        }
+    }
+
+    fn transition(&mut self, dir: Direction, pressed: Pressed) {
+        self.held[dir as usize] = pressed;
+        let other_held = self.held[!dir as usize];
+        if pressed {
+            if !other_held || self.priority != Some(!dir) {
+                self.active = Some(dir);
+            }
+        } else if self.active == Some(dir) {
+            self.active = (other_held && self.priority.is_some()).then_some(!dir);
+        }
+    }
+
+    fn active(self) -> Option<Direction> {
+        self.active
+    }
+
+    fn active_unique(self) -> Option<Direction> {
+        self.active.filter(|&dir| !self.held[!dir as usize])
+    }
+
+    /// Returns whether `state` behaves differently from the model.
+    fn diverges(self, state: AxisState) -> bool {
+        (self.active(), self.active_unique()) != (state.active(), state.active_unique())
+    }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize)]
 enum AxisButtonState {
     Active,
     Inactive(Pressed),
@@ -552,7 +1308,7 @@ impl AxisButtonState {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize)]
 enum DualModeAxisState {
     Neither(AxisState),
     // The direction that is still enabled and its state.
@@ -587,14 +1343,41 @@ impl DualModeAxisState {
         }
     }
 
-    // TODO: This function is complicated and needs unit tests.
+    /// Returns whether the negative and positive directions are held, in
+    /// either mode.
+    fn held(self) -> [Pressed; 2] {
+        match self {
+            Self::Both => [PRESSED; 2],
+            Self::Single(normal, state) => [NEGATIVE, POSITIVE]
+                .map(|dir| dir != normal || state != AxisButtonState::Inactive(RELEASED)),
+            Self::Neither(axis_state) => axis_state.held(),
+        }
+    }
+
+    /// Returns the directions held in alt mode.
+    fn alt_held(self) -> &'static [Direction] {
+        match self {
+            Self::Both => &[POSITIVE, NEGATIVE],
+            Self::Single(POSITIVE, _) => &[NEGATIVE],
+            Self::Single(NEGATIVE, _) => &[POSITIVE],
+            Self::Neither(_) => &[],
+        }
+    }
+
+    // See `tests::axis_model` for an exhaustive check of this function.
     /// Returns true iff alt mode was released as a result of the transition.
     ///
     /// # Panics
     ///
     /// Panics if the input is inconsistent with current state. No-ops are
     /// ignored and do not cause a panic.
-    fn transition(&mut self, dir: Direction, pressed: Pressed, alt_on_pressed: bool) -> bool {
+    fn transition(
+        &mut self,
+        dir: Direction,
+        pressed: Pressed,
+        alt_on_pressed: bool,
+        socd: config::CStickSocd,
+    ) -> bool {
         let (new_state, alt_released) = (|s| {
             match s {
                 Self::Both => {
@@ -604,11 +1387,9 @@ impl DualModeAxisState {
                             dir
                         );
                     }
+                    // The released direction is no longer in alt mode.
                     if !pressed {
-                        return (
-                            Self::Single(!dir, AxisButtonState::Inactive(RELEASED)),
-                            true,
-                        );
+                        return (Self::Single(dir, AxisButtonState::Inactive(RELEASED)), true);
                     }
                 }
                 Self::Single(normal_dir, state) => {
@@ -642,730 +1423,9641 @@ impl DualModeAxisState {
                 }
                 Self::Neither(mut axis_state) => {
                     if pressed && alt_on_pressed {
-                        return (Self::Single(!dir, axis_state.state_in_dir(dir)), false);
+                        return (Self::Single(!dir, axis_state.state_in_dir(!dir)), false);
+                    }
+                    match socd {
+                        config::CStickSocd::SecondInput => axis_state.transition(dir, pressed),
+                        config::CStickSocd::Neutral => axis_state.transition_neutral(dir, pressed),
                     }
-                    axis_state.transition(dir, pressed);
                     return (Self::Neither(axis_state), false);
                 }
             }
-            return (s, false);
+            (s, false)
         })(*self);
         *self = new_state;
         alt_released
     }
 }
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-enum ShieldState {
-    Null,
-    M(bool),
-    L,
-    ML,
-    LM,
-}
-
-impl std::default::Default for ShieldState {
-    fn default() -> Self {
-        Self::Null
-    }
+/// Tracks held shield buttons along with the trigger value each selected when
+/// pressed, and which of them is being output. The digital L/R presses of
+/// shield buttons are tracked apart, so that a button may hold either or
+/// both, per `config::ShieldBehavior`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+struct ShieldState {
+    held: Vec<(B0xxRaw, Trigger)>,
+    active: Option<B0xxRaw>,
+    /// The shield buttons holding digital L or R, each with whether it drops
+    /// the analog trigger while held.
+    digital: std::collections::BTreeMap<B0xxRaw, (B0xxRaw, bool)>,
+    /// When each `analog_then_digital` button still held presses its digital
+    /// button.
+    pending: std::collections::BTreeMap<B0xxRaw, Timestamp>,
 }
 
 impl ShieldState {
-    fn transition(&mut self, shield: Shield, pressed: Pressed) -> Option<Trigger> {
-        let (new, rtn) = match (*self, shield, pressed) {
-            (Self::Null, Shield::Light, PRESSED) => (Self::L, Some(LS)),
-            (Self::Null, Shield::Medium, PRESSED) => (Self::M(PRESSED), Some(MS)),
-            (Self::M(_), Shield::Light, PRESSED) => (Self::ML, Some(LS)),
-            (Self::L, Shield::Medium, PRESSED) => (Self::LM, Some(MS)),
-            (Self::ML, Shield::Light, RELEASED) => (Self::M(RELEASED), Some(Trigger::Z)),
-            (Self::LM, Shield::Light, RELEASED) => (Self::M(PRESSED), None),
-            (Self::ML, Shield::Medium, RELEASED) => (Self::L, None),
-            (Self::LM, Shield::Medium, RELEASED) => (Self::L, Some(LS)),
-            (Self::M(PRESSED), Shield::Medium, RELEASED) | (Self::L, Shield::Light, RELEASED) => {
-                (Self::Null, Some(Trigger::Z))
-            }
-            (Self::M(RELEASED), Shield::Medium, RELEASED) => (Self::Null, None),
-            _ => (*self, None),
-        };
-        *self = new;
-        rtn
+    /// Returns the value being output, if any.
+    fn value(&self) -> Option<Trigger> {
+        let active = self.active?;
+        self.held
+            .iter()
+            .find(|&&(held, _)| held == active)
+            .map(|&(_, value)| value)
     }
-}
 
-trait NegExt: std::ops::Neg {
-    fn neg_not(self, b: bool) -> Self;
-}
+    fn press(&mut self, btn: B0xxRaw, value: Trigger) -> Option<Trigger> {
+        self.held.retain(|&(held, _)| held != btn);
+        self.held.push((btn, value));
+        self.active = Some(btn);
+        Some(value)
+    }
 
-impl<N: std::ops::Neg<Output = N>> NegExt for N {
-    fn neg_not(self, b: bool) -> N {
-        if b {
-            self
-        } else {
-            -self
+    /// Releasing the active button returns to the highest held value below
+    /// it, if any. Higher held values stay inactive until pressed again.
+    fn release(&mut self, btn: B0xxRaw) -> Option<Trigger> {
+        let i = self.held.iter().position(|&(held, _)| held == btn)?;
+        let (_, value) = self.held.remove(i);
+        if self.active != Some(btn) {
+            return None;
         }
+        let fallback = self
+            .held
+            .iter()
+            .filter(|(_, held)| held.get() < value.get())
+            .max_by_key(|(_, held)| held.get())
+            .copied();
+        self.active = fallback.map(|(held, _)| held);
+        Some(fallback.map_or(Trigger::Z, |(_, held)| held))
     }
-}
 
-#[derive(Default)]
-struct StickState {
-    x: AxisState,
-    y: AxisState,
-    gc_input: GCStickInput,
-}
+    /// Returns whether some shield button holds digital `button`.
+    fn digital_held(&self, button: B0xxRaw) -> bool {
+        self.digital.values().any(|&(held, _)| held == button)
+    }
 
-impl StickState {
-    fn update(&mut self, input: GCStickInput) -> Option<GCStickInput> {
-        (self.gc_input != input).then(|| {
-            self.gc_input = input;
-            input
-        })
+    /// Returns whether digital L/R held drops the analog trigger.
+    fn dropped(&self) -> bool {
+        self.digital.values().any(|&(_, drops)| drops)
+    }
+
+    /// Returns the `analog_then_digital` button due to press its digital
+    /// button, if any.
+    fn pop(&mut self, now: Timestamp) -> Option<B0xxRaw> {
+        let btn = self
+            .pending
+            .iter()
+            .find(|&(_, &at)| now >= at)
+            .map(|(&btn, _)| btn)?;
+        let _ = self.pending.remove(&btn);
+        Some(btn)
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
-struct CStickState {
-    x: DualModeAxisState,
-    y: DualModeAxisState,
-    gc_input: GCStickInput,
+/// What a shield button press or release did, for `SessionStats`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ShieldTransition {
+    /// Pressed with no shield active.
+    Activated,
+    /// Pressed while another shield was active.
+    Replaced,
+    /// The active shield was released with no lower one held.
+    Released,
+    /// The active shield was released and a lower held one took over.
+    FellBack,
+    /// An inactive shield was released.
+    ReleasedInactive,
 }
 
-// Simplify the callsite by using a more specific form.
-impl CStickState {
-    // Returns the unique axis and direction that is active and no other
-    // buttons are pressed.
-    fn unique_cardinal(&self) -> Option<(Axis, Direction)> {
-        match (self.x.active_unique(), self.y.active_unique()) {
-            (Some(dir), None) => Some((Axis::X, dir)),
-            (None, Some(dir)) => Some((Axis::Y, dir)),
-            (None, None) | (Some(_), Some(_)) => None,
-        }
+/// Counters for practice feedback and spotting anomalies, e.g. a chattering
+/// key with a suspiciously high count.
+#[derive(Debug, Default, Serialize)]
+struct SessionStats {
+    presses: ButtonCounts,
+    /// Presses of any button in each minute since the first.
+    presses_per_minute: Vec<u64>,
+    /// Direction presses that overrode the held, active opposite direction,
+    /// by the direction pressed.
+    socd_overrides: ButtonCounts,
+    /// The same overrides by axis.
+    socd_overrides_by_axis: AxisCounts,
+    shield_transitions: ShieldTransitions,
+    /// Direction presses while already held and active, by the direction.
+    rollover_suspects: ButtonCounts,
+    techniques: std::collections::BTreeMap<techniques::Technique, TechniqueStats>,
+    hold_durations: HoldDurations,
+    /// The health of each sink, as of when the stats were written.
+    sinks: std::collections::BTreeMap<&'static str, health::Health>,
+    /// The keyboard events queued for processing under `--reader-queue`, as
+    /// of when the stats were written.
+    reader_queue: Option<pipeline::QueueStats>,
+    #[serde(skip)]
+    start: Option<Timestamp>,
+}
+
+/// A count per button.
+#[derive(Clone, Debug)]
+struct ButtonCounts([u64; B0xxRaw::ALL.len()]);
+
+impl Default for ButtonCounts {
+    fn default() -> Self {
+        Self([0; B0xxRaw::ALL.len()])
     }
+}
 
-    fn update(&mut self, input: GCStickInput) -> Option<GCStickInput> {
-        (self.gc_input != input).then(|| {
-            self.gc_input = input;
-            input
-        })
+impl ButtonCounts {
+    fn add(&mut self, btn: B0xxRaw) {
+        self.0[btn as usize] += 1;
     }
 
-    fn transition(
-        &mut self,
-        axis: Axis,
-        dir: Direction,
-        pressed: Pressed,
-        dpad_enabled: bool,
-    ) -> bool {
-        return match axis {
-            Axis::X => self.x.transition(dir, pressed, dpad_enabled),
-            Axis::Y => self.y.transition(dir, pressed, dpad_enabled),
-        };
+    /// The buttons counted at least once, with their count.
+    fn counted(&self) -> impl Iterator<Item = (B0xxRaw, u64)> + '_ {
+        B0xxRaw::ALL
+            .into_iter()
+            .map(|btn| (btn, self.0[btn as usize]))
+            .filter(|&(_, count)| count != 0)
     }
 }
 
-#[derive(Default)]
-struct Main {
-    state: B0xxState,
-    a_stick: StickState,
-    c_stick: CStickState,
-    shield_state: ShieldState,
+/// Serializes as a map from each button counted to its count.
+impl Serialize for ButtonCounts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.counted())
+    }
 }
 
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
-enum Shield {
-    Light,
-    Medium,
+/// A count per stick axis. Like `ButtonCounts`, serializes only the nonzero
+/// counts.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+struct AxisCounts {
+    #[serde(rename = "a_stick.x", skip_serializing_if = "is_zero")]
+    a_stick_x: u64,
+    #[serde(rename = "a_stick.y", skip_serializing_if = "is_zero")]
+    a_stick_y: u64,
+    #[serde(rename = "c_stick.x", skip_serializing_if = "is_zero")]
+    c_stick_x: u64,
+    #[serde(rename = "c_stick.y", skip_serializing_if = "is_zero")]
+    c_stick_y: u64,
 }
 
-impl std::convert::From<Shield> for Trigger {
-    fn from(s: Shield) -> Self {
-        match s {
-            Shield::Light => LS,
-            Shield::Medium => MS,
+/// A count per `ShieldTransition`, serializing only the nonzero counts.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+struct ShieldTransitions {
+    #[serde(skip_serializing_if = "is_zero")]
+    activated: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    replaced: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    released: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    fell_back: u64,
+    #[serde(skip_serializing_if = "is_zero")]
+    released_inactive: u64,
+}
+
+/// Whether a count is zero, to leave it out when serializing.
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+struct TechniqueStats {
+    attempts: u64,
+    in_window: u64,
+}
+
+impl SessionStats {
+    fn press(&mut self, btn: B0xxRaw, now: Timestamp) {
+        self.presses.add(btn);
+        let start = *self.start.get_or_insert(now);
+        let minute = ((now - start).as_secs() / 60) as usize;
+        if self.presses_per_minute.len() <= minute {
+            self.presses_per_minute.resize(minute + 1, 0);
         }
+        self.presses_per_minute[minute] += 1;
     }
-}
 
-impl Main {
-    fn update_c_stick(&mut self) -> Option<GCStickInput> {
-        let input = match (self.c_stick.x.active(), self.c_stick.y.active()) {
-            (None, None) => (P0000, P0000),
-            (Some(x_dir), None) => {
-                if self.state & B0xxState::MODS == B0xxState::MOD_X {
-                    match (self.a_stick.x, self.a_stick.y) {
-                        (AxisState::Null(_), AxisState::Active(y_dir, _)) => {
-                            (P8125.neg_not(x_dir), P2875.neg_not(y_dir))
-                        }
-                        _ => (Analog::MAX.neg_not(x_dir), P0000),
-                    }
-                } else {
-                    (Analog::MAX.neg_not(x_dir), P0000)
-                }
-            }
-            (None, Some(y_dir)) => (P0000, Analog::MAX.neg_not(y_dir)),
-            (Some(x_dir), Some(y_dir)) => (P5250.neg_not(x_dir), P8500.neg_not(y_dir)),
-        };
-        // TODO: GCStickInput should be stored separately to the CStick state.
-        self.c_stick.update(input)
+    fn release(&mut self, btn: B0xxRaw, held: std::time::Duration) {
+        self.hold_durations.record(btn, held);
     }
 
-    fn update_a_stick(&mut self, crouch_walk_option_select: bool) -> Option<GCStickInput> {
-        let input = match (self.a_stick.x, self.a_stick.y) {
-            (AxisState::Null(_), AxisState::Null(_)) => (P0000, P0000),
-            (AxisState::Active(x_dir, opposing_held), AxisState::Null(_)) => {
-                let x = match (
-                    self.state & B0xxState::MODS,
-                    self.state.contains(B0xxState::B),
-                    opposing_held,
-                ) {
-                    (B0xxState::MOD_X, _, false) | (B0xxState::MOD_Y, true, false) => P6625,
-                    (B0xxState::MOD_Y, false, false) => P3375,
-                    _ => Analog::MAX,
-                };
-                (x.neg_not(x_dir), P0000)
-            }
-            (AxisState::Null(_), AxisState::Active(y_dir, _)) => {
-                let y = if self.state & B0xxState::MODS == B0xxState::MOD_X {
-                    P5375
-                } else if self.state & B0xxState::MODS == B0xxState::MOD_Y {
-                    P7375
-                } else {
-                    Analog::MAX
-                };
-                (P0000, y.neg_not(y_dir))
-            }
-            // Diagonals.
-            (AxisState::Active(x_dir, _), AxisState::Active(y_dir, _)) => {
-                let (x, y) = match (
-                    self.state & B0xxState::MODS,
-                    self.state.intersects(B0xxState::LR),
-                    self.c_stick.unique_cardinal(),
-                ) {
-                    (B0xxState::MOD_X, true, _) => (P6375, P3750),
-                    (B0xxState::MOD_X, false, Some((Axis::Y, NEGATIVE))) => (P7000, P3625),
-                    (B0xxState::MOD_X, false, Some((Axis::X, NEGATIVE))) => (P7875, P4875),
-                    (B0xxState::MOD_X, false, Some((Axis::Y, POSITIVE))) => (P7000, P5125),
-                    (B0xxState::MOD_X, false, Some((Axis::X, POSITIVE))) => (P6125, P5250),
-                    (B0xxState::MOD_X, false, None) => (P7375, P3125),
-
-                    (B0xxState::MOD_Y, true, _) => {
-                        if y_dir {
-                            (P4750, P8750)
-                        } else {
-                            (P5000, P8500)
-                        }
-                    }
-                    (B0xxState::MOD_Y, false, Some((Axis::X, POSITIVE))) => (P6375, P7625),
-                    (B0xxState::MOD_Y, false, Some((Axis::Y, POSITIVE))) => (P5125, P7000),
-                    (B0xxState::MOD_Y, false, Some((Axis::X, NEGATIVE))) => (P4875, P7875),
-                    (B0xxState::MOD_Y, false, Some((Axis::Y, NEGATIVE))) => (P3625, P7000),
-                    (B0xxState::MOD_Y, false, None) => (P3125, P7375),
-                    _ => {
-                        if !y_dir && crouch_walk_option_select {
-                            (P7125, P6875)
-                        } else {
-                            (P7000, P7000)
-                        }
-                    }
-                };
-                (x.neg_not(x_dir), y.neg_not(y_dir))
-            }
-        };
-        self.a_stick.update(input)
+    fn socd_override(&mut self, btn: B0xxRaw, stick: Stick, axis: Axis) {
+        self.socd_overrides.add(btn);
+        let by_axis = &mut self.socd_overrides_by_axis;
+        *match (stick, axis) {
+            (Stick::A, Axis::X) => &mut by_axis.a_stick_x,
+            (Stick::A, Axis::Y) => &mut by_axis.a_stick_y,
+            (Stick::C, Axis::X) => &mut by_axis.c_stick_x,
+            (Stick::C, Axis::Y) => &mut by_axis.c_stick_y,
+        } += 1;
     }
 
-    fn process_b0xx(
-        &mut self,
-        B0xxEvent {
-            time: _,
-            btn,
-            pressed,
-        }: B0xxEvent,
-        crouch_walk_option_select: bool,
-    ) -> Option<Input> {
-        let impure = match btn.into() {
-            B0xx::Pure(pure) => {
-                return match pure {
-                    Pure::Button(btn_pure) => Some(Input::Button(Button::Pure(btn_pure), pressed)),
-                    Pure::Shield(shield) => self
-                        .shield_state
-                        .transition(shield, pressed)
-                        .map(Input::Trigger),
-                };
-            }
-            B0xx::Impure(impure) => impure,
-        };
-        match impure {
-            Impure::Button(btn) => {
-                match btn {
-                    ButtonImpure::B => {
-                        self.state.set(B0xxState::B, pressed);
-                    }
-                    ButtonImpure::L => {
-                        self.state.set(B0xxState::L, pressed);
-                    }
-                    ButtonImpure::R => {
-                        self.state.set(B0xxState::R, pressed);
-                    }
-                }
-                return Some(
-                    if let Some(new) = self.update_a_stick(crouch_walk_option_select) {
-                        self.a_stick.gc_input = new;
-                        if pressed {
-                            Input::ModifiedPress(new, btn)
-                        } else {
-                            Input::ReleaseModifier(btn, new)
-                        }
-                    } else {
-                        Input::Button(Button::Impure(btn), pressed)
-                    },
-                );
-            }
-            Impure::Stick(Stick::C, axis, dir) => {
-                let dpad_enabled = self.state.contains(B0xxState::MODS);
-                let dpad_released = self.c_stick.transition(axis, dir, pressed, dpad_enabled);
+    fn rollover_suspect(&mut self, btn: B0xxRaw) {
+        self.rollover_suspects.add(btn);
+    }
 
-                if dpad_enabled && pressed {
-                    return Some(Input::Button(Button::DPad(axis, dir), PRESSED));
-                }
-                if dpad_released {
-                    return Some(Input::Button(Button::DPad(axis, dir), RELEASED));
-                }
-            }
-            Impure::Stick(Stick::A, Axis::X, dir) => self.a_stick.x.transition(dir, pressed),
-            Impure::Stick(Stick::A, Axis::Y, dir) => self.a_stick.y.transition(dir, pressed),
-            Impure::ModX => self.state.set(B0xxState::MOD_X, pressed),
-            Impure::ModY => self.state.set(B0xxState::MOD_Y, pressed),
-        }
+    fn shield_transition(&mut self, transition: ShieldTransition) {
+        let transitions = &mut self.shield_transitions;
+        *match transition {
+            ShieldTransition::Activated => &mut transitions.activated,
+            ShieldTransition::Replaced => &mut transitions.replaced,
+            ShieldTransition::Released => &mut transitions.released,
+            ShieldTransition::FellBack => &mut transitions.fell_back,
+            ShieldTransition::ReleasedInactive => &mut transitions.released_inactive,
+        } += 1;
+    }
 
-        match (
-            self.update_a_stick(crouch_walk_option_select),
-            self.update_c_stick(),
-        ) {
-            (None, None) => None,
-            (Some(new_a), None) => Some(Input::Stick(Stick::A, new_a)),
-            (None, Some(new_c)) => Some(Input::Stick(Stick::C, new_c)),
-            (Some(new_a), Some(new_c)) => Some(Input::CStickModifier { a: new_a, c: new_c }),
+    #[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+    fn technique(&mut self, detection: &techniques::Detection) {
+        let stats = self.techniques.entry(detection.technique).or_default();
+        stats.attempts += 1;
+        if detection.in_window() {
+            stats.in_window += 1;
         }
     }
+
+    /// Writes the counters as JSON to `path`.
+    #[cfg(feature = "linux-input")]
+    fn write(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self).context("failed to serialize stats")?;
+        std::fs::write(path, json)
+            .with_context(|| format!("failed to write stats to {}", path.display()))
+    }
 }
 
-struct OutputSink {
-    file: std::fs::File,
+/// Counts how long each button was held, from press to release, in buckets of
+/// `HoldDurations::BUCKET`, e.g. to practice releasing a lightshield or
+/// shorthopping with consistent timing. Recording never allocates.
+#[derive(Clone, Debug)]
+struct HoldDurations {
+    /// Indexed by button, then by bucket. The last bucket counts every hold
+    /// of `HoldDurations::MAX` or longer.
+    counts: Vec<[u32; Self::BUCKETS + 1]>,
 }
 
-impl OutputSink {
-    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
-        let cmd = pipe_input.into_input_string();
-        debug!("writing: {}", cmd);
-        let _ = self.file.write(cmd.as_bytes())?;
-        Ok(())
+impl Default for HoldDurations {
+    fn default() -> Self {
+        Self {
+            counts: vec![[0; Self::BUCKETS + 1]; B0xxRaw::ALL.len()],
+        }
     }
 }
 
-fn main() {
-    let Args {
-        log_level,
-        crouch_walk_option_select,
-    } = argh::from_env();
+impl HoldDurations {
+    /// About a thirty-third of a frame.
+    const BUCKET: std::time::Duration = std::time::Duration::from_micros(500);
+    const BUCKETS: usize = 800;
+    const MAX: std::time::Duration = std::time::Duration::from_micros(500 * Self::BUCKETS as u64);
+    const QUANTILES: [(&'static str, f64); 4] =
+        [("p10", 0.1), ("p50", 0.5), ("p90", 0.9), ("p99", 0.99)];
 
-    simple_logger::SimpleLogger::new()
-        .with_level(log::LevelFilter::Warn)
-        .with_module_level(std::module_path!(), log_level)
-        .init()
-        .expect("failed to initialize logger");
+    fn record(&mut self, btn: B0xxRaw, held: std::time::Duration) {
+        let bucket = (held.as_micros() / Self::BUCKET.as_micros()).min(Self::BUCKETS as u128);
+        let count = &mut self.counts[btn as usize][bucket as usize];
+        *count = count.saturating_add(1);
+    }
 
-    let keeb_path = futures::executor::block_on(evdev_utils::identify_keyboard())
-        .expect("failed to identify keyboard");
-    info!("found keyboard {:?}", keeb_path);
+    fn count(&self, btn: B0xxRaw) -> u64 {
+        self.counts[btn as usize]
+            .iter()
+            .copied()
+            .map(u64::from)
+            .sum()
+    }
 
-    let mut keeb_device = AsyncDevice::new(keeb_path)
-        .expect("failed to create keyboard device")
-        .fuse();
+    /// Returns the end of the bucket of the hold below which `quantile` of
+    /// `btn`'s holds fall, or `None` if there were none, or it was at least
+    /// `HoldDurations::MAX`.
+    fn quantile(&self, btn: B0xxRaw, quantile: f64) -> Option<std::time::Duration> {
+        let count = self.count(btn);
+        let rank = ((quantile * count as f64).ceil() as u64).clamp(1, count.max(1));
+        let mut seen = 0;
+        self.counts[btn as usize]
+            .iter()
+            .position(|&n| {
+                seen += u64::from(n);
+                n != 0 && seen >= rank
+            })
+            .filter(|&bucket| bucket < Self::BUCKETS)
+            .map(|bucket| Self::BUCKET * (bucket as u32 + 1))
+    }
 
-    let remapper = Remapper;
-    let mut main = Main::default();
-    let mut sink = OutputSink {
-        file: std::fs::OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("/home/tone/.config/SlippiOnline/Pipes/pipe")
-            .expect("failed to open pipe"),
-    };
-    let fut = async {
-        loop {
-            futures::select! {
-                r = keeb_device.try_next() => {
-                    let event = r.expect("keyboard event stream error")
-                        .expect("keyboard event stream ended unexpectedly");
-                    log_event(&event);
-                    let e = match remapper.evdev_to_b0xx(event) {
-                        Some(e) => e,
-                        None => continue,
-                    };
-                    if let Some(input) = main.process_b0xx(e, crouch_walk_option_select) {
-                        for pipe_input in input.into_pipe_inputs() {
-                            sink.send(pipe_input).expect("failed to write to pipe");
-                        }
-                    }
+    fn millis(duration: std::time::Duration) -> f64 {
+        duration.as_micros() as f64 / 1000.0
+    }
+
+    /// The buttons held at least once, with their count.
+    fn held(&self) -> impl Iterator<Item = (B0xxRaw, u64)> + '_ {
+        B0xxRaw::ALL
+            .into_iter()
+            .map(|btn| (btn, self.count(btn)))
+            .filter(|&(_, count)| count != 0)
+    }
+}
+
+/// Serializes as a map from each button held to its count, quantiles in
+/// milliseconds, `null` at or beyond `HoldDurations::MAX`, and the start in
+/// milliseconds and count of each of its nonempty buckets.
+impl Serialize for HoldDurations {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Summary {
+            holds: u64,
+            quantiles: std::collections::BTreeMap<&'static str, Option<f64>>,
+            buckets: Vec<(f64, u32)>,
+        }
+
+        serializer.collect_map(self.held().map(|(btn, holds)| {
+            let summary = Summary {
+                holds,
+                quantiles: Self::QUANTILES
+                    .into_iter()
+                    .map(|(name, quantile)| (name, self.quantile(btn, quantile).map(Self::millis)))
+                    .collect(),
+                buckets: self.counts[btn as usize]
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &n)| n != 0)
+                    .map(|(bucket, &n)| (Self::millis(Self::BUCKET * bucket as u32), n))
+                    .collect(),
+            };
+            (btn, summary)
+        }))
+    }
+}
+
+/// One line per button held, e.g. `LS: 12 holds, p10 3.5ms, p50 4.5ms, p90
+/// 6ms, p99 >400ms`.
+impl std::fmt::Display for HoldDurations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (btn, holds)) in self.held().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:?}: {} holds", btn, holds)?;
+            for (name, quantile) in Self::QUANTILES {
+                match self.quantile(btn, quantile) {
+                    Some(held) => write!(f, ", {} {}ms", name, Self::millis(held))?,
+                    None => write!(f, ", {} >{}ms", name, Self::MAX.as_millis())?,
                 }
             }
         }
-    };
-    futures::executor::block_on(fut);
+        Ok(())
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use itertools::Itertools as _;
-    use test_case::test_case;
+/// How many entries `EventTrace` keeps.
+const EVENT_TRACE_LEN: usize = 200;
 
-    const CARDINALS: [(Axis, Direction); 4] = [
-        (Axis::X, POSITIVE),
-        (Axis::X, NEGATIVE),
-        (Axis::Y, POSITIVE),
-        (Axis::Y, NEGATIVE),
-    ];
+/// An event and what it emitted, for `EventTrace`.
+#[derive(Clone, Debug)]
+struct EventTraceEntry {
+    time: Timestamp,
+    /// The button changed, or `None` for a deadline.
+    event: Option<(B0xxRaw, Pressed)>,
+    inputs: Inputs,
+}
 
-    const DIAGONALS: [(Direction, Direction); 4] = [
-        (POSITIVE, POSITIVE),
-        (POSITIVE, NEGATIVE),
-        (NEGATIVE, NEGATIVE),
-        (NEGATIVE, POSITIVE),
-    ];
+/// The most recent events, kept so that the lead-up to a crash can be logged.
+/// Recording an event never allocates.
+#[derive(Debug)]
+struct EventTrace {
+    entries: Vec<EventTraceEntry>,
+    /// Where the next entry goes once `entries` is full.
+    next: usize,
+}
 
-    impl From<B0xx> for B0xxRaw {
-        fn from(b: B0xx) -> B0xxRaw {
-            match b {
-                B0xx::Pure(Pure::Button(ButtonPure::A)) => B0xxRaw::A,
-                B0xx::Pure(Pure::Button(ButtonPure::X)) => B0xxRaw::X,
-                B0xx::Pure(Pure::Button(ButtonPure::Y)) => B0xxRaw::Y,
-                B0xx::Pure(Pure::Button(ButtonPure::Z)) => B0xxRaw::Z,
-                B0xx::Pure(Pure::Button(ButtonPure::Start)) => B0xxRaw::Start,
-                B0xx::Impure(Impure::Button(ButtonImpure::B)) => B0xxRaw::B,
-                B0xx::Impure(Impure::Button(ButtonImpure::L)) => B0xxRaw::L,
-                B0xx::Impure(Impure::Button(ButtonImpure::R)) => B0xxRaw::R,
-                B0xx::Impure(Impure::Stick(Stick::A, Axis::X, NEGATIVE)) => B0xxRaw::Left,
-                B0xx::Impure(Impure::Stick(Stick::A, Axis::X, POSITIVE)) => B0xxRaw::Right,
-                B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, NEGATIVE)) => B0xxRaw::Down,
-                B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, POSITIVE)) => B0xxRaw::Up,
-                B0xx::Impure(Impure::ModX) => B0xxRaw::MX,
-                B0xx::Impure(Impure::ModY) => B0xxRaw::MY,
-                B0xx::Pure(Pure::Shield(Shield::Light)) => B0xxRaw::LS,
-                B0xx::Pure(Pure::Shield(Shield::Medium)) => B0xxRaw::MS,
-                B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, POSITIVE)) => B0xxRaw::CU,
-                B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, NEGATIVE)) => B0xxRaw::CD,
-                B0xx::Impure(Impure::Stick(Stick::C, Axis::X, POSITIVE)) => B0xxRaw::CR,
-                B0xx::Impure(Impure::Stick(Stick::C, Axis::X, NEGATIVE)) => B0xxRaw::CL,
-            }
+impl Default for EventTrace {
+    fn default() -> Self {
+        Self {
+            entries: Vec::with_capacity(EVENT_TRACE_LEN),
+            next: 0,
         }
     }
+}
 
-    impl From<(Stick, Axis, Direction)> for B0xxRaw {
-        fn from((stick, axis, dir): (Stick, Axis, Direction)) -> B0xxRaw {
-            match (stick, axis, dir) {
-                (Stick::A, Axis::X, POSITIVE) => B0xxRaw::Right,
-                (Stick::A, Axis::X, NEGATIVE) => B0xxRaw::Left,
-                (Stick::A, Axis::Y, POSITIVE) => B0xxRaw::Up,
-                (Stick::A, Axis::Y, NEGATIVE) => B0xxRaw::Down,
-                (Stick::C, Axis::X, POSITIVE) => B0xxRaw::CR,
-                (Stick::C, Axis::X, NEGATIVE) => B0xxRaw::CL,
-                (Stick::C, Axis::Y, POSITIVE) => B0xxRaw::CU,
-                (Stick::C, Axis::Y, NEGATIVE) => B0xxRaw::CD,
-            }
+impl EventTrace {
+    fn record(&mut self, entry: EventTraceEntry) {
+        if self.entries.len() < EVENT_TRACE_LEN {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+            self.next = (self.next + 1) % EVENT_TRACE_LEN;
         }
     }
 
-    #[test_case(&[
-        (B0xxRaw::LS, PRESSED, Some(Input::Trigger(LS))),
-        (B0xxRaw::MS, PRESSED, Some(Input::Trigger(MS))),
-        (B0xxRaw::MS, RELEASED, Some(Input::Trigger(LS))),
-        (B0xxRaw::LS, RELEASED, Some(Input::Trigger(Trigger::Z))),
-    ]; "shield1")]
-    #[test_case(&[
-        (B0xxRaw::LS, PRESSED, Some(Input::Trigger(LS))),
-        (B0xxRaw::MS, PRESSED, Some(Input::Trigger(MS))),
-        (B0xxRaw::LS, RELEASED, None),
-        (B0xxRaw::LS, PRESSED, Some(Input::Trigger(LS))),
-        (B0xxRaw::LS, RELEASED, Some(Input::Trigger(Trigger::Z))),
-        (B0xxRaw::MS, RELEASED, None),
-    ]; "shield2")]
-    #[test_case(&[
-        (B0xxRaw::MS, PRESSED, Some(Input::Trigger(MS))),
-        (B0xxRaw::LS, PRESSED, Some(Input::Trigger(LS))),
-        (B0xxRaw::MS, RELEASED, None),
-        (B0xxRaw::LS, RELEASED, Some(Input::Trigger(Trigger::Z))),
-    ]; "shield3")]
-    fn steps(steps: &[(B0xxRaw, Pressed, Option<Input>)]) {
-        let mut main = Main::default();
-        for &(btn, pressed, want) in steps.into_iter() {
-            assert_eq!(
-                main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false),
-                want
-            );
-        }
+    /// Returns the entries from oldest to newest.
+    fn entries(&self) -> impl Iterator<Item = &EventTraceEntry> {
+        let (newer, older) = self.entries.split_at(self.next);
+        older.iter().chain(newer)
     }
+}
 
-    #[test_case(&[], P7000, P7000; "a_stick")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::MY], P7000, P7000; "a_stick_both_mod")]
-    #[test_case(&[B0xxRaw::MX], P7375, P3125; "mod_x")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::CD], P7000, P3625; "mod_x1")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::CL], P7875, P4875; "mod_x2")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::CU], P7000, P5125; "mod_x3")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::CR], P6125, P5250; "mod_x4")]
-    #[test_case(&[B0xxRaw::MY, B0xxRaw::CR], P6375, P7625; "mod_y4")]
-    #[test_case(&[B0xxRaw::MY, B0xxRaw::CU], P5125, P7000; "mod_y3")]
-    #[test_case(&[B0xxRaw::MY, B0xxRaw::CL], P4875, P7875; "mod_y2")]
-    #[test_case(&[B0xxRaw::MY, B0xxRaw::CD], P3625, P7000; "mod_y1")]
-    #[test_case(&[B0xxRaw::MY], P3125, P7375; "mod_y")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::L], P6375, P3750; "mod_x_l")]
-    #[test_case(&[B0xxRaw::MX, B0xxRaw::R], P6375, P3750; "mod_x_r")]
-    fn analog(buttons: &[B0xxRaw], x_positive: Analog, y_positive: Analog) {
-        for x in [POSITIVE, NEGATIVE] {
-            for y in [POSITIVE, NEGATIVE] {
-                let mut buttons = buttons
-                    .iter()
-                    .copied()
-                    .chain(
-                        [(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()].into_iter(),
-                    )
-                    .collect::<Vec<_>>();
-                let want = (x_positive.neg_not(x), y_positive.neg_not(y));
-                permutohedron::heap_recursive(&mut buttons, |buttons| {
-                    let mut main = Main::default();
-                    let got = buttons
-                        .iter()
-                        .fold(None, |_, &btn| {
-                            main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
-                        })
-                        .expect("final b0xx input resulted in null GC input");
-                    let got = match got {
-                        Input::ModifiedPress(a_stick, btn) => {
-                            assert_eq!(
-                                B0xx::Impure(Impure::Button(btn)),
-                                (*buttons.last().unwrap()).into()
-                            );
-                            a_stick
-                        }
-                        Input::Stick(Stick::A, a_stick) => a_stick,
-                        Input::CStickModifier { a, c: _ } => a,
-                        _ => panic!("unexpected GC input on final b0xx input: {:?}", got),
-                    };
-                    assert_eq!(got, want);
-                });
-            }
+trait NegExt: std::ops::Neg {
+    fn neg_not(self, b: bool) -> Self;
+}
+
+impl<N: std::ops::Neg<Output = N>> NegExt for N {
+    fn neg_not(self, b: bool) -> N {
+        if b {
+            self
+        } else {
+            -self
         }
     }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+struct StickState {
+    x: AxisState,
+    y: AxisState,
+}
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Serialize)]
+struct CStickState {
+    x: DualModeAxisState,
+    y: DualModeAxisState,
+}
+
+// Simplify the callsite by using a more specific form.
+impl CStickState {
+    /// Returns the D-pad buttons latched by presses in alt mode.
+    fn dpad_held(&self) -> impl Iterator<Item = Button> + '_ {
+        [(Axis::X, self.x), (Axis::Y, self.y)]
+            .into_iter()
+            .flat_map(|(axis, state)| {
+                state
+                    .alt_held()
+                    .iter()
+                    .map(move |&dir| Button::DPad(axis, dir))
+            })
+    }
+
+    // Returns the unique axis and direction that is active and no other
+    // buttons are pressed.
+    fn unique_cardinal(&self) -> Option<(Axis, Direction)> {
+        match (self.x.active_unique(), self.y.active_unique()) {
+            (Some(dir), None) => Some((Axis::X, dir)),
+            (None, Some(dir)) => Some((Axis::Y, dir)),
+            (None, None) | (Some(_), Some(_)) => None,
+        }
+    }
+
+    fn transition(
+        &mut self,
+        axis: Axis,
+        dir: Direction,
+        pressed: Pressed,
+        dpad_enabled: bool,
+        socd: config::CStickSocd,
+    ) -> bool {
+        match axis {
+            Axis::X => self.x.transition(dir, pressed, dpad_enabled, socd),
+            Axis::Y => self.y.transition(dir, pressed, dpad_enabled, socd),
+        }
+    }
+}
+
+/// The state of everything written to the pipe, as maintained by `Main` and
+/// the frame quantizer.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+struct ControllerSnapshot {
+    buttons: std::collections::BTreeSet<GCButton>,
+    a_stick: AStickInput,
+    c_stick: CStickInput,
+    trigger: Trigger,
+}
+
+impl std::default::Default for ControllerSnapshot {
+    fn default() -> Self {
+        Self {
+            buttons: Default::default(),
+            a_stick: (P0000, P0000),
+            c_stick: (P0000, P0000),
+            trigger: Trigger::Z,
+        }
+    }
+}
+
+impl ControllerSnapshot {
+    fn apply(&mut self, pipe_input: DolphinPipeInput) {
+        match pipe_input {
+            DolphinPipeInput::Button(button, PRESSED) => {
+                let _ = self.buttons.insert(button);
+            }
+            DolphinPipeInput::Button(button, RELEASED) => {
+                let _ = self.buttons.remove(&button);
+            }
+            DolphinPipeInput::Trigger(trigger) => self.trigger = trigger,
+            DolphinPipeInput::Stick(Stick::A, a) => self.a_stick = a,
+            DolphinPipeInput::Stick(Stick::C, c) => self.c_stick = c,
+        }
+    }
+}
+
+/// Whether `pipe_inputs` write the C-stick before the A-stick, if both. The
+/// A-stick coordinates of e.g. angled smashes and the C-stick with modifiers
+/// are chosen for the C-stick coordinates written alongside them, so every
+/// batch of inputs written for one change, quantized frames included, must
+/// write the C-stick first for the game never to read the new A-stick with
+/// the old C-stick.
+fn sticks_ordered(pipe_inputs: impl IntoIterator<Item = DolphinPipeInput>) -> bool {
+    !pipe_inputs
+        .into_iter()
+        .skip_while(|pipe_input| !matches!(pipe_input, DolphinPipeInput::Stick(Stick::A, _)))
+        .any(|pipe_input| matches!(pipe_input, DolphinPipeInput::Stick(Stick::C, _)))
+}
+
+/// Returns the minimal pipe inputs that take `old` to `new`. Buttons come
+/// before sticks and the trigger, releases before presses, the C-stick before
+/// the A-stick per `sticks_ordered`, and each group is in a fixed order, so
+/// that equal snapshots always give equal output.
+fn diff(old: &ControllerSnapshot, new: &ControllerSnapshot) -> Vec<DolphinPipeInput> {
+    let releases = old
+        .buttons
+        .difference(&new.buttons)
+        .map(|&button| DolphinPipeInput::Button(button, RELEASED));
+    let presses = new
+        .buttons
+        .difference(&old.buttons)
+        .map(|&button| DolphinPipeInput::Button(button, PRESSED));
+    let sticks = [
+        (Stick::C, old.c_stick, new.c_stick),
+        (Stick::A, old.a_stick, new.a_stick),
+    ]
+    .into_iter()
+    .filter(|(_, from, to)| from != to)
+    .map(|(stick, _, to)| DolphinPipeInput::Stick(stick, to));
+    let trigger = (old.trigger != new.trigger).then_some(DolphinPipeInput::Trigger(new.trigger));
+    releases
+        .chain(presses)
+        .chain(sticks)
+        .chain(trigger)
+        .collect()
+}
+
+/// Progress of the B-reverse helper.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+enum BReverse {
+    #[default]
+    Idle,
+    /// B was pressed while holding `dir`, and pressing the opposite direction
+    /// before `until` starts a flick.
+    Armed { dir: Direction, until: Timestamp },
+    /// The opposite direction is output until `until`.
+    Flicking { until: Timestamp },
+    /// The flick is over, and the X axis stays neutral until it transitions.
+    Returned,
+}
+
+fn turbo_button(turbo: config::Turbo) -> Button {
+    turbo
+        .button
+        .button()
+        .expect("turbo button is validated on load")
+}
+
+/// Progress of the turbo button, fired while its key is held.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+enum TurboState {
+    #[default]
+    Idle,
+    /// The key was pressed at `since`, and `cycles` presses have been
+    /// completed since. Times are counted from `since` so that they don't
+    /// drift with timer latency.
+    Firing {
+        since: Timestamp,
+        cycles: u32,
+        pressed: Pressed,
+    },
+}
+
+/// Progress of the short-hop key's X press.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+enum ShortHop {
+    #[default]
+    Idle,
+    /// X is pressed until `until`.
+    Pressed { until: Timestamp },
+}
+
+/// Progress of the buttons with a tap-hold behaviour, per `config::TapHolds`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+struct TapHoldState {
+    /// When each button pressed and neither tapped nor held yet is held.
+    pending: std::collections::BTreeMap<B0xxRaw, Timestamp>,
+    /// The buttons acting as their hold button.
+    holding: std::collections::BTreeSet<B0xxRaw>,
+}
+
+/// Progress of the macro playing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+struct MacroRun {
+    key: B0xxRaw,
+    /// The index of the next step.
+    step: usize,
+    /// When the next step is due.
+    next: Timestamp,
+}
+
+/// Progress of the trigger ramp.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+enum TriggerRampState {
+    #[default]
+    Idle,
+    /// The key was pressed at `since`, and the next value is due at `next`
+    /// unless the ramp reached its end.
+    Ramping {
+        since: Timestamp,
+        next: Option<Timestamp>,
+    },
+}
+
+/// Delays A-stick direction presses that follow a press of the opposite
+/// direction too closely, per `config::SdiLimit`.
+#[derive(Debug)]
+struct SdiLimiter {
+    interval: std::time::Duration,
+    x: AxisLimiter,
+    y: AxisLimiter,
+}
+
+/// The schedule of one axis. Once a press is delayed, everything after it on
+/// the axis is delayed as much, keeping the order and the time each
+/// direction is held, until the axis is released.
+#[derive(Debug, Default)]
+struct AxisLimiter {
+    delay: std::time::Duration,
+    /// The last press and when it takes effect.
+    last_press: Option<(Direction, Timestamp)>,
+    /// How many directions are held once the queue is applied.
+    held: u8,
+    queue: std::collections::VecDeque<(Timestamp, Direction, Pressed)>,
+}
+
+impl SdiLimiter {
+    fn new(limit: config::SdiLimit) -> Self {
+        Self {
+            interval: limit.interval(),
+            x: Default::default(),
+            y: Default::default(),
+        }
+    }
+
+    fn axis(&mut self, axis: Axis) -> &mut AxisLimiter {
+        match axis {
+            Axis::X => &mut self.x,
+            Axis::Y => &mut self.y,
+        }
+    }
+
+    /// Returns whether the press or release of `dir` at `now` must wait, in
+    /// which case it is queued.
+    fn defer(&mut self, now: Timestamp, axis: Axis, dir: Direction, pressed: Pressed) -> bool {
+        let interval = self.interval;
+        let axis = self.axis(axis);
+        if axis.queue.is_empty() && axis.held == 0 {
+            axis.delay = std::time::Duration::ZERO;
+        }
+        let mut due = now + axis.delay;
+        if pressed {
+            if let Some((last, at)) = axis.last_press {
+                if last != dir {
+                    due = due.max(at + interval);
+                }
+            }
+            axis.delay = due - now;
+            axis.last_press = Some((dir, due));
+            axis.held += 1;
+        } else {
+            axis.held = axis.held.saturating_sub(1);
+        }
+        if due <= now && axis.queue.is_empty() {
+            return false;
+        }
+        axis.queue.push_back((due, dir, pressed));
+        true
+    }
+
+    fn deadline(&self) -> Option<Timestamp> {
+        [&self.x, &self.y]
+            .into_iter()
+            .filter_map(|axis| axis.queue.front().map(|&(due, _, _)| due))
+            .min()
+    }
+
+    /// Removes and returns the earliest queued press or release due by `now`.
+    fn pop(&mut self, now: Timestamp) -> Option<(Timestamp, Axis, Direction, Pressed)> {
+        let (axis, due) = [(Axis::X, &self.x), (Axis::Y, &self.y)]
+            .into_iter()
+            .filter_map(|(axis, limiter)| limiter.queue.front().map(|&(due, _, _)| (axis, due)))
+            .filter(|&(_, due)| due <= now)
+            .min_by_key(|&(_, due)| due)?;
+        let (_, dir, pressed) = self.axis(axis).queue.pop_front()?;
+        Some((due, axis, dir, pressed))
+    }
+}
+
+/// Wraps the A-stick X axis transitions to apply `config::LedgedashNerf`.
+#[derive(Debug)]
+struct LedgedashNerf {
+    config: config::LedgedashNerf,
+    /// When recent reactivations happened, oldest first.
+    reactivations: std::collections::VecDeque<Timestamp>,
+    /// The X axis outputs neutral until this time.
+    neutral_until: Option<Timestamp>,
+}
+
+impl LedgedashNerf {
+    /// How long a nerfed reactivation outputs neutral: one frame at 60Hz, so
+    /// that the game polls it.
+    const NEUTRAL: std::time::Duration = std::time::Duration::from_micros(16_667);
+
+    fn new(config: config::LedgedashNerf) -> Self {
+        Self {
+            config,
+            reactivations: Default::default(),
+            neutral_until: None,
+        }
+    }
+
+    /// Applies the press or release of `dir` at `now` to `axis`.
+    fn transition(
+        &mut self,
+        axis: &mut AxisState,
+        now: Timestamp,
+        dir: Direction,
+        pressed: Pressed,
+    ) {
+        axis.transition(dir, pressed);
+        if !pressed || *axis != AxisState::Active(dir, PRESSED) {
+            return;
+        }
+        let window = self.config.window();
+        while matches!(self.reactivations.front(), Some(&at) if at + window <= now) {
+            let _ = self.reactivations.pop_front();
+        }
+        if self.reactivations.len() >= self.config.max_reactivations {
+            debug!(
+                reactivations = self.reactivations.len(),
+                "ledgedash nerf: neutral for a frame"
+            );
+            self.neutral_until = Some(now + Self::NEUTRAL);
+        }
+        self.reactivations.push_back(now);
+    }
+
+    fn neutral(&self) -> bool {
+        self.neutral_until.is_some()
+    }
+
+    /// Returns whether the neutral output ended by `now`.
+    fn process_deadline(&mut self, now: Timestamp) -> bool {
+        match self.neutral_until {
+            Some(until) if now >= until => {
+                self.neutral_until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Stands in front of the input state for the buttons of
+/// `config::NegativeEdge`, so that it only sees their presses once released.
+#[derive(Debug, Serialize)]
+struct NegativeEdge {
+    #[serde(skip)]
+    config: config::NegativeEdge,
+    /// When each button held was pressed.
+    pressed: std::collections::BTreeMap<B0xxRaw, Timestamp>,
+    /// When each button pressed on release is released.
+    pulses: std::collections::BTreeMap<B0xxRaw, Timestamp>,
+}
+
+impl NegativeEdge {
+    fn new(config: config::NegativeEdge) -> Self {
+        Self {
+            config,
+            pressed: Default::default(),
+            pulses: Default::default(),
+        }
+    }
+
+    fn applies(&self, btn: B0xxRaw) -> bool {
+        self.config.buttons.contains(&btn)
+    }
+
+    /// Returns whether the input state sees `btn` pressed.
+    fn pulsing(&self, btn: B0xxRaw) -> bool {
+        self.pulses.contains_key(&btn)
+    }
+
+    /// Takes the press or release of `btn` at `now`, and returns whether the
+    /// input state is to see it pressed now. A release within the pulse of an
+    /// earlier one extends the pulse instead.
+    fn transition(&mut self, btn: B0xxRaw, pressed: Pressed, now: Timestamp) -> bool {
+        if pressed {
+            let _ = self.pressed.insert(btn, now);
+            return false;
+        }
+        let Some(since) = self.pressed.remove(&btn) else {
+            return false;
+        };
+        if now - since > self.config.max_hold() {
+            debug!(?btn, held = ?(now - since), "negative edge: held too long, discarded");
+            return false;
+        }
+        debug!(?btn, held = ?(now - since), "negative edge: pressed on release");
+        self.pulses.insert(btn, now + self.config.pulse()).is_none()
+    }
+
+    fn deadline(&self) -> Option<Timestamp> {
+        self.pulses.values().copied().min()
+    }
+
+    /// Returns a button whose pulse ended by `now`, if any.
+    fn pop(&mut self, now: Timestamp) -> Option<B0xxRaw> {
+        let (&btn, _) = self.pulses.iter().find(|&(_, &until)| now >= until)?;
+        let _ = self.pulses.remove(&btn);
+        Some(btn)
+    }
+}
+
+/// Stands in front of the input state for the buttons of
+/// `config::LightPress`, setting the analog trigger ahead of their presses.
+#[derive(Debug, Serialize)]
+struct LightPress {
+    #[serde(skip)]
+    config: config::LightPress,
+    /// When each button held with the light value out is to be pressed.
+    pending: std::collections::BTreeMap<B0xxRaw, Timestamp>,
+    /// Buttons pressed after the light value, until released.
+    pressed: std::collections::BTreeSet<B0xxRaw>,
+}
+
+impl LightPress {
+    fn new(config: config::LightPress) -> Self {
+        Self {
+            config,
+            pending: Default::default(),
+            pressed: Default::default(),
+        }
+    }
+
+    fn applies(&self, btn: B0xxRaw) -> bool {
+        self.config.buttons.contains(&btn)
+    }
+
+    /// Returns whether the light value is out for some button.
+    fn active(&self) -> bool {
+        !self.pending.is_empty() || !self.pressed.is_empty()
+    }
+
+    fn deadline(&self) -> Option<Timestamp> {
+        self.pending.values().copied().min()
+    }
+
+    /// Returns a button due to be pressed by `now`, if any, and counts it as
+    /// pressed.
+    fn pop(&mut self, now: Timestamp) -> Option<B0xxRaw> {
+        let (&btn, _) = self.pending.iter().find(|&(_, &at)| now >= at)?;
+        let _ = self.pending.remove(&btn);
+        let _ = self.pressed.insert(btn);
+        Some(btn)
+    }
+}
+
+/// Stands in front of the input state for the buttons of `config::DoubleTap`,
+/// telling a double tap from a single press.
+#[derive(Debug, Serialize)]
+struct DoubleTap {
+    #[serde(skip)]
+    config: config::DoubleTap,
+    /// The progress of each button pressed since its window started.
+    taps: std::collections::BTreeMap<B0xxRaw, Tap>,
+}
+
+/// Progress of a button of `DoubleTap`. The window starts at the first press,
+/// so a second press on its end is a first press again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+enum Tap {
+    /// Pressed, with the window ending at `until`.
+    Held { until: Timestamp },
+    /// Pressed and released, with the window ending at `until`.
+    Released { until: Timestamp },
+    /// Pressed again within the window, holding the analog value.
+    Analog,
+    /// Held past the window, pressed as usual.
+    Digital,
+}
+
+impl DoubleTap {
+    fn new(config: config::DoubleTap) -> Self {
+        Self {
+            config,
+            taps: Default::default(),
+        }
+    }
+
+    fn applies(&self, btn: B0xxRaw) -> bool {
+        self.config.buttons.contains(&btn)
+    }
+
+    /// Returns whether the input state is yet to see `btn` pressed, or never
+    /// will as it holds the analog value.
+    fn defers(&self, btn: B0xxRaw) -> bool {
+        matches!(
+            self.taps.get(&btn),
+            Some(Tap::Held { .. } | Tap::Released { .. } | Tap::Analog)
+        )
+    }
+
+    fn deadline(&self) -> Option<Timestamp> {
+        self.taps
+            .values()
+            .filter_map(|tap| match *tap {
+                Tap::Held { until } | Tap::Released { until } => Some(until),
+                Tap::Analog | Tap::Digital => None,
+            })
+            .min()
+    }
+
+    /// Returns a button whose window passed by `now`, if any, with whether
+    /// it is still held, in which case it counts as pressed as usual.
+    fn pop(&mut self, now: Timestamp) -> Option<(B0xxRaw, Pressed)> {
+        let (btn, held) = self.taps.iter().find_map(|(&btn, tap)| match *tap {
+            Tap::Held { until } if now >= until => Some((btn, PRESSED)),
+            Tap::Released { until } if now >= until => Some((btn, RELEASED)),
+            _ => None,
+        })?;
+        if held {
+            let _ = self.taps.insert(btn, Tap::Digital);
+        } else {
+            let _ = self.taps.remove(&btn);
+        }
+        Some((btn, held))
+    }
+}
+
+#[derive(Default)]
+struct Main {
+    state: B0xxState,
+    a_stick: StickState,
+    c_stick: CStickState,
+    shield_state: ShieldState,
+    shield_tiers: config::ShieldTiers,
+    shield_behavior: config::ShieldBehaviors,
+    coordinates: config::Coordinates,
+    /// The character whose overrides `coordinates` includes, if any, as
+    /// selected through `remap::Profiles`.
+    character: Option<String>,
+    /// Built from `coordinates`.
+    a_stick_table: coordinates::AStickTable,
+    c_stick_mode: config::CStickMode,
+    b_reverse_helper: Option<config::BReverseHelper>,
+    b_reverse: BReverse,
+    turbo: Option<config::Turbo>,
+    turbo_state: TurboState,
+    short_hop_helper: Option<config::ShortHop>,
+    short_hop: ShortHop,
+    trigger_ramp: Option<config::TriggerRamp>,
+    trigger_ramp_state: TriggerRampState,
+    macros: config::Macros,
+    macro_run: Option<MacroRun>,
+    tap_holds: config::TapHolds,
+    tap_hold_state: TapHoldState,
+    sdi_limiter: Option<SdiLimiter>,
+    ledgedash_nerf: Option<LedgedashNerf>,
+    negative_edge: Option<NegativeEdge>,
+    light_press: Option<LightPress>,
+    double_tap: Option<DoubleTap>,
+    neutral_b_helper: Option<config::NeutralBHelper>,
+    /// The A-stick X axis outputs neutral while B is held, per
+    /// `neutral_b_helper`.
+    neutral_b: bool,
+    invert: config::Inversion,
+    a_stick_scale: config::Scale,
+    crouch_walk: config::CrouchWalk,
+    dpad: config::DPadActivation,
+    socd: config::Socd,
+    stick_coupling: config::StickCoupling,
+    game: Game,
+    start_reset: Option<config::StartReset>,
+    z_composite: Option<config::ZComposite>,
+    /// When Start, if still held, resets the output, per `start_reset`.
+    start_reset_at: Option<Timestamp>,
+    /// Replaces all of the above when present.
+    raw: Option<RawEngine>,
+    last_press: std::collections::HashMap<B0xxRaw, Timestamp>,
+    /// Every button currently held, regardless of how it is interpreted. As
+    /// `Remapper` only passes on changes, a button held by several keys stays
+    /// held until the last is released.
+    held: std::collections::BTreeSet<B0xxRaw>,
+    /// The buttons of `held` in the order they were pressed, so that they
+    /// are pressed again in that order when the state is rebuilt, e.g. for
+    /// the second of two opposite directions to still win.
+    press_order: Vec<B0xxRaw>,
+    /// Buttons held through the last reset, whose releases are ignored.
+    held_through_reset: std::collections::BTreeSet<B0xxRaw>,
+    /// What has been output so far, kept apart from the input state above.
+    output: ControllerSnapshot,
+    /// Tracks the A-stick axes with `AxisReference` when present, logging any
+    /// divergence.
+    a_stick_reference: Option<(AxisReference, AxisReference)>,
+    /// When present, A-stick coordinates output inside Melee's deadzone with
+    /// a direction held are logged, once each, and kept here.
+    deadzoned: Option<std::collections::BTreeSet<GCStickInput>>,
+    stats: SessionStats,
+    event_trace: EventTrace,
+}
+
+/// The parts of `Main` that change with input, for debugging.
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    state: B0xxState,
+    held: &'a std::collections::BTreeSet<B0xxRaw>,
+    a_stick: &'a StickState,
+    c_stick: &'a CStickState,
+    shield_state: &'a ShieldState,
+    b_reverse: BReverse,
+    turbo_state: TurboState,
+    short_hop: ShortHop,
+    trigger_ramp: TriggerRampState,
+    macro_run: Option<MacroRun>,
+    tap_hold: &'a TapHoldState,
+    negative_edge: Option<&'a NegativeEdge>,
+    light_press: Option<&'a LightPress>,
+    double_tap: Option<&'a DoubleTap>,
+    neutral_b: bool,
+    character: Option<&'a str>,
+    /// The D-pad buttons latched in `c_stick`.
+    dpad: Vec<GCButton>,
+    output: &'a ControllerSnapshot,
+}
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Shield {
+    Light,
+    Medium,
+}
+
+impl std::convert::From<Shield> for Trigger {
+    fn from(s: Shield) -> Self {
+        match s {
+            Shield::Light => LS,
+            Shield::Medium => MS,
+        }
+    }
+}
+
+/// Logs a state machine's value before and after an event, if it changed.
+fn trace_transition<T: PartialEq + std::fmt::Debug>(machine: &str, before: T, after: T) {
+    if before != after {
+        trace!(machine, ?before, ?after, "transitioned");
+    }
+}
+
+/// Last-pressed-wins axis without any of the b0xx SOCD rules.
+#[derive(Clone, Copy, Debug, Default)]
+struct RawAxis {
+    negative: Pressed,
+    positive: Pressed,
+    last: Option<Direction>,
+}
+
+impl RawAxis {
+    fn transition(&mut self, dir: Direction, pressed: Pressed) {
+        let (held, opposing) = if dir {
+            (&mut self.positive, self.negative)
+        } else {
+            (&mut self.negative, self.positive)
+        };
+        *held = pressed;
+        if pressed {
+            self.last = Some(dir);
+        } else if self.last == Some(dir) {
+            self.last = opposing.then_some(!dir);
+        }
+    }
+
+    fn value(&self) -> Analog {
+        self.last.map_or(P0000, |dir| Analog::MAX.neg_not(dir))
+    }
+}
+
+/// Maps b0xx buttons straight to controller input, for use as a generic
+/// gamepad. Each stick axis outputs its last pressed direction at full
+/// magnitude, shield buttons output their default values, and modifiers do
+/// nothing.
+#[derive(Clone, Debug, Default)]
+struct RawEngine {
+    a_stick: (RawAxis, RawAxis),
+    c_stick: (RawAxis, RawAxis),
+    invert: config::Inversion,
+}
+
+impl RawEngine {
+    fn process_b0xx(&mut self, btn: B0xxRaw, pressed: Pressed) -> Option<Input> {
+        match btn.into() {
+            B0xx::Pure(Pure::Button(btn)) => Some(Input::Button(Button::Pure(btn), pressed)),
+            B0xx::Pure(Pure::DPad(axis, dir)) => {
+                Some(Input::Button(Button::DPad(axis, dir), pressed))
+            }
+            B0xx::Impure(Impure::Button(btn)) => Some(Input::Button(Button::Impure(btn), pressed)),
+            B0xx::Pure(Pure::Shield(shield)) => Some(Input::Trigger(if pressed {
+                shield.into()
+            } else {
+                Trigger::Z
+            })),
+            B0xx::Impure(Impure::Stick(stick, axis, dir)) => {
+                let ((x, y), invert) = match stick {
+                    Stick::A => (&mut self.a_stick, self.invert.a_stick),
+                    Stick::C => (&mut self.c_stick, self.invert.c_stick),
+                };
+                let old = (x.value(), y.value());
+                match axis {
+                    Axis::X => x.transition(dir, pressed),
+                    Axis::Y => y.transition(dir, pressed),
+                }
+                let new = (x.value(), y.value());
+                (new != old).then(|| Input::Stick(stick, invert.apply(new)))
+            }
+            B0xx::Impure(
+                Impure::ModX
+                | Impure::ModY
+                | Impure::ModTilt
+                | Impure::DPadLayer
+                | Impure::Turbo
+                | Impure::ShortHop
+                | Impure::TriggerRamp
+                | Impure::Macro(_),
+            ) => None,
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+impl Main {
+    fn new(profile: &config::Profile) -> Self {
+        let coordinates = profile.own_coordinates();
+        Self {
+            coordinates,
+            a_stick_table: coordinates::AStickTable::new(&coordinates),
+            c_stick_mode: profile.c_stick_mode,
+            b_reverse_helper: profile.b_reverse,
+            turbo: profile.turbo,
+            short_hop_helper: profile.short_hop,
+            trigger_ramp: profile.trigger_ramp,
+            macros: profile.macros.clone(),
+            tap_holds: profile.tap_hold.clone(),
+            sdi_limiter: profile.sdi_limit.map(SdiLimiter::new),
+            ledgedash_nerf: profile.ledgedash_nerf.map(LedgedashNerf::new),
+            negative_edge: profile.negative_edge.clone().map(NegativeEdge::new),
+            light_press: profile.light_press.clone().map(LightPress::new),
+            double_tap: profile.double_tap.clone().map(DoubleTap::new),
+            shield_behavior: profile.shield_behavior.clone(),
+            neutral_b_helper: profile.neutral_b,
+            invert: profile.invert,
+            a_stick_scale: profile.a_stick_scale,
+            crouch_walk: profile.crouch_walk,
+            dpad: profile.dpad,
+            socd: profile.socd,
+            stick_coupling: profile.stick_coupling,
+            start_reset: profile.start_reset,
+            z_composite: profile.z_composite,
+            ..Default::default()
+        }
+    }
+
+    fn c_stick_coordinates(&self) -> CStickInput {
+        let a_stick = if self.stick_coupling.a_stick_angles_c_stick {
+            (self.a_stick.x, self.a_stick.y)
+        } else {
+            Default::default()
+        };
+        let situation = coordinates::CStickSituation {
+            x: self.c_stick.x.active(),
+            y: self.c_stick.y.active(),
+            mods: self.state & B0xxState::MODS,
+            a_stick_vertical: match a_stick {
+                (AxisState::Null(_), AxisState::Active(y_dir, _)) => Some(y_dir),
+                _ => None,
+            },
+            a_stick_horizontal: match a_stick {
+                (AxisState::Active(x_dir, _), AxisState::Null(_)) => Some(x_dir),
+                _ => None,
+            },
+        };
+        self.c_stick_output(situation)
+    }
+
+    /// Returns the C-stick output in `situation`.
+    fn c_stick_output(&self, situation: coordinates::CStickSituation) -> CStickInput {
+        self.invert.c_stick.apply(coordinates::c_stick(
+            situation,
+            self.c_stick_mode,
+            &self.coordinates,
+        ))
+    }
+
+    fn a_stick_coordinates(&self, crouch_walk_option_select: bool) -> AStickInput {
+        // The B-reverse helper, neutral-B and the ledgedash nerf may each
+        // hold the X axis neutral.
+        let x_neutral = self.b_reverse == BReverse::Returned
+            || self.neutral_b
+            || self
+                .ledgedash_nerf
+                .as_ref()
+                .is_some_and(LedgedashNerf::neutral);
+        let x = if x_neutral {
+            AxisState::default()
+        } else {
+            self.a_stick.x
+        };
+        // The A-stick is never neutral with both directions held.
+        let (shape, x_dir, y_dir, opposing_held) = match (x, self.a_stick.y) {
+            (AxisState::Null(_) | AxisState::Neutral, AxisState::Null(_) | AxisState::Neutral) => {
+                return self.invert.a_stick.apply((P0000, P0000))
+            }
+            (AxisState::Active(x_dir, opposing_held), AxisState::Null(_) | AxisState::Neutral) => (
+                coordinates::Shape::XCardinal,
+                x_dir,
+                POSITIVE,
+                opposing_held,
+            ),
+            (AxisState::Null(_) | AxisState::Neutral, AxisState::Active(y_dir, _)) => {
+                (coordinates::Shape::YCardinal, POSITIVE, y_dir, false)
+            }
+            (AxisState::Active(x_dir, opposing_held), AxisState::Active(y_dir, _)) => {
+                (coordinates::Shape::Diagonal, x_dir, y_dir, opposing_held)
+            }
+        };
+        let situation = coordinates::Situation {
+            shape,
+            mods: self.state & B0xxState::MODS,
+            tilt: self.state.contains(B0xxState::MOD_TILT),
+            b: self.state.contains(B0xxState::B),
+            lr: self.state.intersects(B0xxState::LR),
+            opposing_held,
+            c_stick: self.c_stick.unique_cardinal().filter(|_| {
+                self.game.melee_refinements() && self.stick_coupling.c_stick_refines_a_stick
+            }),
+            y_dir,
+            crouch_walk: self.crouch_walk_applies(crouch_walk_option_select, x_dir),
+        };
+        trace!(?situation, "A-stick coordinates");
+        self.a_stick_output(situation, x_dir, y_dir)
+    }
+
+    /// Whether the crouch/walk option-select applies to `x_dir` held.
+    fn crouch_walk_applies(&self, crouch_walk_option_select: bool, x_dir: Direction) -> bool {
+        self.game.melee_refinements()
+            && (crouch_walk_option_select || self.crouch_walk.enabled(x_dir))
+    }
+
+    /// Returns the A-stick output in `situation` with the axes pointing in
+    /// `x_dir` and `y_dir`.
+    fn a_stick_output(
+        &self,
+        situation: coordinates::Situation,
+        x_dir: Direction,
+        y_dir: Direction,
+    ) -> AStickInput {
+        let (x, y) = self.a_stick_table.get(situation);
+        self.invert.a_stick.apply(
+            self.a_stick_scale
+                .apply((x.neg_not(x_dir), y.neg_not(y_dir))),
+        )
+    }
+
+    /// Returns the A-stick coordinates if they differ from those last output.
+    fn a_stick_change(&self, crouch_walk_option_select: bool) -> Option<AStickInput> {
+        let a = self.a_stick_coordinates(crouch_walk_option_select);
+        (a != self.output.a_stick).then_some(a)
+    }
+
+    /// Returns the C-stick coordinates if they differ from those last output.
+    fn c_stick_change(&self) -> Option<CStickInput> {
+        let c = self.c_stick_coordinates();
+        (c != self.output.c_stick).then_some(c)
+    }
+
+    /// Returns the inputs for whichever sticks differ from those last output,
+    /// the C-stick first per `sticks_ordered`.
+    fn sticks_change(&self, crouch_walk_option_select: bool) -> Inputs {
+        self.c_stick_change()
+            .map(|new_c| Input::Stick(Stick::C, new_c))
+            .into_iter()
+            .chain(
+                self.a_stick_change(crouch_walk_option_select)
+                    .map(|new_a| Input::Stick(Stick::A, new_a)),
+            )
+            .collect()
+    }
+
+    /// Records `inputs` as output.
+    fn emit(&mut self, inputs: Inputs) -> Inputs {
+        debug_assert!(
+            sticks_ordered(inputs.iter().map(|input| input.into_pipe_input())),
+            "A-stick before C-stick: {:?}",
+            inputs
+        );
+        for &input in &inputs {
+            self.output.apply(input.into_pipe_input());
+        }
+        self.check_deadzone(&inputs);
+        debug!(?inputs, "emitted");
+        inputs
+    }
+
+    /// Logs A-stick coordinates among `inputs` that Melee reads as neutral
+    /// although a direction is held, if enabled, the first time each is
+    /// output. A centred stick is left out, as the SOCD resolution and the
+    /// limiters output it on purpose.
+    fn check_deadzone(&mut self, inputs: &[Input]) {
+        let direction_held = self
+            .held
+            .iter()
+            .any(|&btn| matches!(btn.into(), B0xx::Impure(Impure::Stick(Stick::A, _, _))));
+        let deadzoned = match &mut self.deadzoned {
+            Some(deadzoned) if direction_held => deadzoned,
+            _ => return,
+        };
+        for input in inputs {
+            if let &Input::Stick(Stick::A, a) = input {
+                if a != (P0000, P0000) && in_deadzone(a) && deadzoned.insert(a) {
+                    warn!(
+                        "A-stick coordinate [{}, {}] is inside Melee's deadzone, so does nothing",
+                        a.0.get(),
+                        a.1.get()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the time at which `process_deadline` must next be called, if
+    /// any.
+    fn deadline(&self) -> Option<Timestamp> {
+        let b_reverse = match self.b_reverse {
+            BReverse::Flicking { until } => Some(until),
+            BReverse::Idle | BReverse::Armed { .. } | BReverse::Returned => None,
+        };
+        let short_hop = match self.short_hop {
+            ShortHop::Pressed { until } => Some(until),
+            ShortHop::Idle => None,
+        };
+        let trigger_ramp = match self.trigger_ramp_state {
+            TriggerRampState::Ramping { next, .. } => next,
+            TriggerRampState::Idle => None,
+        };
+        b_reverse
+            .into_iter()
+            .chain(self.start_reset_at)
+            .chain(self.turbo_deadline())
+            .chain(short_hop)
+            .chain(trigger_ramp)
+            .chain(self.macro_run.map(|run| run.next))
+            .chain(self.tap_hold_state.pending.values().copied().min())
+            .chain(self.sdi_limiter.as_ref().and_then(SdiLimiter::deadline))
+            .chain(
+                self.ledgedash_nerf
+                    .as_ref()
+                    .and_then(|nerf| nerf.neutral_until),
+            )
+            .chain(self.negative_edge.as_ref().and_then(NegativeEdge::deadline))
+            .chain(self.light_press.as_ref().and_then(LightPress::deadline))
+            .chain(self.double_tap.as_ref().and_then(DoubleTap::deadline))
+            .chain(self.shield_state.pending.values().copied().min())
+            .min()
+    }
+
+    fn process_deadline(&mut self, now: Timestamp, crouch_walk_option_select: bool) -> Inputs {
+        let _span = tracing::debug_span!("deadline", ?now).entered();
+        if matches!(self.start_reset_at, Some(at) if now >= at) {
+            return self.reset_at(now);
+        }
+        let mut due = false;
+        let mut inputs = Inputs::new();
+        match self.b_reverse {
+            BReverse::Flicking { until } if now >= until => {
+                due = true;
+                trace_transition("b_reverse", self.b_reverse, BReverse::Returned);
+                self.b_reverse = BReverse::Returned;
+                inputs.extend(
+                    self.a_stick_change(crouch_walk_option_select)
+                        .map(|new_a| Input::Stick(Stick::A, new_a)),
+                );
+            }
+            _ => {}
+        }
+        match self.short_hop {
+            ShortHop::Pressed { until } if now >= until => {
+                due = true;
+                trace_transition("short_hop", self.short_hop, ShortHop::Idle);
+                self.short_hop = ShortHop::Idle;
+                inputs.push(Input::Button(Button::Pure(ButtonPure::X), RELEASED));
+            }
+            _ => {}
+        }
+        if let (
+            Some(ramp),
+            TriggerRampState::Ramping {
+                since,
+                next: Some(next),
+            },
+        ) = (self.trigger_ramp, self.trigger_ramp_state)
+        {
+            if now >= next {
+                due = true;
+                let elapsed = now - since;
+                self.trigger_ramp_state = TriggerRampState::Ramping {
+                    since,
+                    next: (elapsed < ramp.ramp()).then_some(now + ramp.interval()),
+                };
+                inputs.extend(self.trigger_change(ramp.value_at(elapsed)));
+            }
+        }
+        while let Some(btn) = self
+            .tap_hold_state
+            .pending
+            .iter()
+            .find(|&(_, &at)| now >= at)
+            .map(|(&btn, _)| btn)
+        {
+            due = true;
+            let _ = self.tap_hold_state.pending.remove(&btn);
+            let _ = self.tap_hold_state.holding.insert(btn);
+            let hold = self
+                .tap_holds
+                .get(btn)
+                .expect("pending buttons have one")
+                .hold;
+            debug!(?btn, ?hold, "tap-hold: held");
+            inputs.extend(self.interpret(now, hold, PRESSED, true, crouch_walk_option_select));
+        }
+        if matches!(self.macro_run, Some(run) if now >= run.next) {
+            due = true;
+            let target = self.output.clone();
+            inputs.extend(self.macro_advance(target, crouch_walk_option_select));
+        }
+        if matches!(self.turbo_deadline(), Some(deadline) if now >= deadline) {
+            due = true;
+            inputs.extend(self.turbo_toggle());
+        }
+        if let Some(nerf) = &mut self.ledgedash_nerf {
+            if nerf.process_deadline(now) {
+                due = true;
+                inputs.extend(
+                    self.a_stick_change(crouch_walk_option_select)
+                        .map(|new_a| Input::Stick(Stick::A, new_a)),
+                );
+            }
+        }
+        while let Some((at, axis, dir, pressed)) = self
+            .sdi_limiter
+            .as_mut()
+            .and_then(|limiter| limiter.pop(now))
+        {
+            due = true;
+            self.a_stick_transition(at, axis, dir, pressed);
+            inputs.extend(self.sticks_change(crouch_walk_option_select));
+        }
+        while let Some(btn) = self
+            .negative_edge
+            .as_mut()
+            .and_then(|negative_edge| negative_edge.pop(now))
+        {
+            due = true;
+            inputs.extend(self.interpret(now, btn, RELEASED, true, crouch_walk_option_select));
+        }
+        while let Some(btn) = self
+            .light_press
+            .as_mut()
+            .and_then(|light_press| light_press.pop(now))
+        {
+            due = true;
+            debug!(?btn, "light press: pressed");
+            inputs.extend(self.interpret(now, btn, PRESSED, true, crouch_walk_option_select));
+        }
+        while let Some((btn, held)) = self
+            .double_tap
+            .as_mut()
+            .and_then(|double_tap| double_tap.pop(now))
+        {
+            due = true;
+            inputs.extend(self.interpret(now, btn, PRESSED, true, crouch_walk_option_select));
+            if held {
+                debug!(?btn, "double tap: held past the window");
+            } else {
+                debug!(?btn, "double tap: single tap");
+                inputs.extend(self.interpret(now, btn, RELEASED, true, crouch_walk_option_select));
+            }
+        }
+        while let Some(btn) = self.shield_state.pop(now) {
+            due = true;
+            if let config::ShieldBehavior::AnalogThenDigital { button, .. } =
+                self.shield_behavior.get(btn)
+            {
+                debug!(?btn, ?button, "shield: digital press after the hold");
+                inputs.extend(self.digital_change(
+                    btn,
+                    button,
+                    false,
+                    PRESSED,
+                    crouch_walk_option_select,
+                ));
+            }
+        }
+        if !due {
+            return inputs;
+        }
+        let inputs = self.emit(inputs);
+        self.event_trace.record(EventTraceEntry {
+            time: now,
+            event: None,
+            inputs: inputs.clone(),
+        });
+        inputs
+    }
+
+    /// Returns Z's analog trigger value if Z is held, per `z_composite`.
+    fn z_trigger(&self) -> Option<Trigger> {
+        self.z_composite
+            .filter(|_| self.held.contains(&B0xxRaw::Z))
+            .map(|z_composite| z_composite.trigger)
+    }
+
+    /// Returns the analog trigger value of the shield or Z, whichever is
+    /// output when digital L/R doesn't drop it, if any.
+    fn analog_value(&self) -> Option<Trigger> {
+        self.shield_state.value().or_else(|| self.z_trigger())
+    }
+
+    /// Returns the output of Z pressed or released, per `z_composite`: A,
+    /// held as long as either A or Z is, and the analog trigger, beneath any
+    /// shield or digital L/R.
+    fn z_composite_change(&self, z_composite: config::ZComposite, pressed: Pressed) -> Inputs {
+        let value = if pressed {
+            z_composite.trigger
+        } else {
+            Trigger::Z
+        };
+        let trigger = (self.shield_state.value().is_none()
+            && !self.shield_state.dropped()
+            && !self.trigger_ramping())
+        .then_some(Input::Trigger(value));
+        let a = (!self.held.contains(&B0xxRaw::A))
+            .then_some(Input::Button(Button::Pure(ButtonPure::A), pressed));
+        // The trigger is pressed before A and released after it, as the
+        // shield is around digital L/R.
+        if pressed {
+            trigger.into_iter().chain(a).collect()
+        } else {
+            a.into_iter().chain(trigger).collect()
+        }
+    }
+
+    /// Resets and emits the change in output at `now`, as a deadline does.
+    fn reset_at(&mut self, now: Timestamp) -> Inputs {
+        let inputs = self.reset();
+        let inputs = self.emit(inputs);
+        self.event_trace.record(EventTraceEntry {
+            time: now,
+            event: None,
+            inputs: inputs.clone(),
+        });
+        inputs
+    }
+
+    /// Switches to `profile`, keeping what doesn't come from it, e.g. the
+    /// statistics and the engine, and presses `held` again under it in order,
+    /// e.g. the buttons the keys held press under its bindings, in the order
+    /// they were pressed. Only the change in output is returned, so that e.g.
+    /// a shield or a direction held through the switch stays held rather than
+    /// being released. Buttons held through a reset are still ignored until
+    /// released.
+    fn switch_profile(
+        &mut self,
+        now: Timestamp,
+        profile: &config::Profile,
+        mut held: Vec<B0xxRaw>,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let old = std::mem::take(self);
+        held.retain(|btn| !old.held_through_reset.contains(btn));
+        *self = Main {
+            raw: old.raw.map(|_| RawEngine {
+                invert: profile.invert,
+                ..Default::default()
+            }),
+            a_stick_reference: old.a_stick_reference.map(|_| {
+                (
+                    AxisReference::default(),
+                    AxisReference::with_priority(profile.socd.y.priority()),
+                )
+            }),
+            deadzoned: old.deadzoned,
+            game: old.game,
+            last_press: old.last_press,
+            held_through_reset: old.held_through_reset,
+            output: old.output,
+            stats: old.stats,
+            event_trace: old.event_trace,
+            ..Main::new(profile)
+        };
+        let inputs = self.replay(now, held, crouch_walk_option_select);
+        self.event_trace.record(EventTraceEntry {
+            time: now,
+            event: None,
+            inputs: inputs.clone(),
+        });
+        inputs
+    }
+
+    /// Forgets the input state, keeping the configuration, and returns the
+    /// inputs that take the output to neutral. Buttons held at the time are
+    /// ignored until released.
+    fn reset(&mut self) -> Inputs {
+        info!("resetting to neutral");
+        self.held_through_reset = std::mem::take(&mut self.held);
+        self.press_order.clear();
+        self.clear();
+        diff(&self.output, &ControllerSnapshot::default())
+            .into_iter()
+            .map(Input::from)
+            .collect()
+    }
+
+    /// Describes every contradiction between the buttons held and the state
+    /// derived from them, e.g. a modifier still set after its release, or an
+    /// output left pressed with nothing held.
+    fn inconsistencies(&self) -> Vec<String> {
+        let mut found = Vec::new();
+        // The raw engine keeps none of the derived state.
+        if self.raw.is_none() {
+            for (flag, btn) in [
+                (B0xxState::B, B0xxRaw::B),
+                (B0xxState::L, B0xxRaw::L),
+                (B0xxState::R, B0xxRaw::R),
+                (B0xxState::MOD_X, B0xxRaw::MX),
+                (B0xxState::MOD_Y, B0xxRaw::MY),
+                (B0xxState::MOD_TILT, B0xxRaw::MT),
+                (B0xxState::DPAD_LAYER, B0xxRaw::DP),
+                (B0xxState::START, B0xxRaw::Start),
+            ] {
+                let seen = match btn {
+                    B0xxRaw::L | B0xxRaw::R => self.digital_seen(btn),
+                    _ => self.seen_held(btn),
+                };
+                match (self.state.contains(flag), seen) {
+                    (true, false) => {
+                        found.push(format!("{:?} is set but {:?} is not held", flag, btn))
+                    }
+                    (false, true) => {
+                        found.push(format!("{:?} is held but {:?} is not set", btn, flag))
+                    }
+                    _ => {}
+                }
+            }
+            // Directions delayed by the SDI limit are held before the axis
+            // state follows.
+            let sdi_pending = self
+                .sdi_limiter
+                .as_ref()
+                .is_some_and(|limiter| limiter.deadline().is_some());
+            for (name, state, held, buttons) in [
+                (
+                    "A-stick X",
+                    format!("{:?}", self.a_stick.x),
+                    (!sdi_pending).then(|| self.a_stick.x.held()),
+                    [B0xxRaw::Left, B0xxRaw::Right],
+                ),
+                (
+                    "A-stick Y",
+                    format!("{:?}", self.a_stick.y),
+                    (!sdi_pending).then(|| self.a_stick.y.held()),
+                    [B0xxRaw::Down, B0xxRaw::Up],
+                ),
+                (
+                    "C-stick X",
+                    format!("{:?}", self.c_stick.x),
+                    Some(self.c_stick.x.held()),
+                    [B0xxRaw::CL, B0xxRaw::CR],
+                ),
+                (
+                    "C-stick Y",
+                    format!("{:?}", self.c_stick.y),
+                    Some(self.c_stick.y.held()),
+                    [B0xxRaw::CD, B0xxRaw::CU],
+                ),
+            ] {
+                let Some(held) = held else { continue };
+                if held != buttons.map(|btn| self.seen_held(btn)) {
+                    let buttons = buttons
+                        .into_iter()
+                        .filter(|&btn| self.seen_held(btn))
+                        .collect::<Vec<_>>();
+                    found.push(format!("{} is {} but {:?} are held", name, state, buttons));
+                }
+            }
+        }
+        if self.held.is_empty()
+            && self.held_through_reset.is_empty()
+            && self.deadline().is_none()
+            && self.output != ControllerSnapshot::default()
+        {
+            found.push(format!(
+                "nothing is held but the output is {}",
+                serde_json::to_string(&self.output).unwrap_or_default()
+            ));
+        }
+        found
+    }
+
+    /// Returns whether the input state is to see `btn` held. Unlike whether
+    /// its keys hold it, this follows negative-edge pulses and tap-hold
+    /// resolution.
+    fn seen_held(&self, btn: B0xxRaw) -> bool {
+        let negative_edge = self.negative_edge.as_ref();
+        let deferred = self.tap_holds.get(btn).is_some()
+            || negative_edge.is_some_and(|negative_edge| negative_edge.applies(btn))
+            || self
+                .light_press
+                .as_ref()
+                .is_some_and(|light_press| light_press.pending.contains_key(&btn))
+            || self
+                .double_tap
+                .as_ref()
+                .is_some_and(|double_tap| double_tap.defers(btn));
+        (self.held.contains(&btn) && !deferred)
+            || negative_edge.is_some_and(|negative_edge| negative_edge.pulsing(btn))
+            || self
+                .tap_hold_state
+                .holding
+                .iter()
+                .any(|&holding| self.tap_holds.get(holding).is_some_and(|t| t.hold == btn))
+    }
+
+    /// Returns whether the input state is to see digital `button`, L or R,
+    /// held by whichever shield buttons press it, per `shield_behavior`.
+    fn digital_seen(&self, button: B0xxRaw) -> bool {
+        [B0xxRaw::L, B0xxRaw::R, B0xxRaw::LS, B0xxRaw::MS]
+            .into_iter()
+            .filter(|&btn| self.seen_held(btn))
+            .any(|btn| match self.shield_behavior.get(btn) {
+                config::ShieldBehavior::Standard => btn == button,
+                config::ShieldBehavior::Digital(pressed) => pressed == button,
+                config::ShieldBehavior::Analog(_) => false,
+                config::ShieldBehavior::AnalogThenDigital {
+                    button: pressed, ..
+                } => pressed == button && !self.shield_state.pending.contains_key(&btn),
+            })
+    }
+
+    /// Rebuilds the input state by pressing the buttons held again, in order,
+    /// from scratch, and returns the inputs taking the output to what the
+    /// rebuilt state outputs. The session statistics are kept as they were.
+    fn heal(&mut self, now: Timestamp, crouch_walk_option_select: bool) -> Inputs {
+        let held = self.held_in_press_order();
+        info!(?held, "rebuilding the input state");
+        self.held_through_reset.clear();
+        self.replay(now, held, crouch_walk_option_select)
+    }
+
+    /// Returns the buttons held in the order they were pressed, followed by
+    /// any held without their press being seen, e.g. with the state out of
+    /// line with them.
+    fn held_in_press_order(&self) -> Vec<B0xxRaw> {
+        let mut held = self
+            .press_order
+            .iter()
+            .copied()
+            .filter(|btn| self.held.contains(btn))
+            .collect::<Vec<_>>();
+        for &btn in &self.held {
+            if !held.contains(&btn) {
+                held.push(btn);
+            }
+        }
+        held
+    }
+
+    /// Presses `held` in order from a cleared input state, and returns the
+    /// inputs taking the output as last written to what the new state
+    /// outputs. The session statistics are kept as they were.
+    fn replay(
+        &mut self,
+        now: Timestamp,
+        held: Vec<B0xxRaw>,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let output = std::mem::take(&mut self.output);
+        let stats = std::mem::take(&mut self.stats);
+        let last_press = std::mem::take(&mut self.last_press);
+        self.held.clear();
+        self.press_order.clear();
+        self.clear();
+        for btn in held {
+            for input in self.transition(
+                B0xxEvent {
+                    time: now,
+                    btn,
+                    pressed: PRESSED,
+                },
+                crouch_walk_option_select,
+            ) {
+                self.output.apply(input.into_pipe_input());
+            }
+        }
+        self.stats = stats;
+        self.last_press = last_press;
+        let rebuilt = std::mem::replace(&mut self.output, output);
+        let inputs = diff(&self.output, &rebuilt)
+            .into_iter()
+            .map(Input::from)
+            .collect();
+        self.emit(inputs)
+    }
+
+    /// Forgets the input state derived from the buttons held, keeping the
+    /// configuration.
+    fn clear(&mut self) {
+        self.state = Default::default();
+        self.a_stick = Default::default();
+        self.c_stick = Default::default();
+        self.shield_state = Default::default();
+        self.b_reverse = BReverse::Idle;
+        self.turbo_state = TurboState::Idle;
+        self.short_hop = ShortHop::Idle;
+        self.trigger_ramp_state = TriggerRampState::Idle;
+        self.macro_run = None;
+        self.tap_hold_state = Default::default();
+        self.neutral_b = false;
+        self.start_reset_at = None;
+        if let Some(limiter) = &mut self.sdi_limiter {
+            limiter.x = Default::default();
+            limiter.y = Default::default();
+        }
+        if let Some(nerf) = &mut self.ledgedash_nerf {
+            *nerf = LedgedashNerf::new(nerf.config);
+        }
+        if let Some(negative_edge) = &mut self.negative_edge {
+            negative_edge.pressed.clear();
+            negative_edge.pulses.clear();
+        }
+        if let Some(light_press) = &mut self.light_press {
+            light_press.pending.clear();
+            light_press.pressed.clear();
+        }
+        if let Some(double_tap) = &mut self.double_tap {
+            double_tap.taps.clear();
+        }
+        if let Some(raw) = &mut self.raw {
+            *raw = RawEngine {
+                invert: raw.invert,
+                ..Default::default()
+            };
+        }
+        if let Some((x, y)) = &mut self.a_stick_reference {
+            *x = AxisReference::with_priority(x.priority);
+            *y = AxisReference::with_priority(y.priority);
+        }
+    }
+
+    /// Applies the press or release of an A-stick direction to the input
+    /// state.
+    fn a_stick_transition(&mut self, now: Timestamp, axis: Axis, dir: Direction, pressed: Pressed) {
+        match axis {
+            Axis::X => {
+                self.b_reverse = match (self.b_reverse_helper, self.b_reverse) {
+                    (Some(helper), BReverse::Armed { dir: armed, until }) if now <= until => {
+                        if pressed && dir != armed {
+                            BReverse::Flicking {
+                                until: now + helper.flick(),
+                            }
+                        } else {
+                            self.b_reverse
+                        }
+                    }
+                    _ => BReverse::Idle,
+                };
+                match &mut self.ledgedash_nerf {
+                    Some(nerf) => nerf.transition(&mut self.a_stick.x, now, dir, pressed),
+                    None => self.a_stick.x.transition(dir, pressed),
+                }
+            }
+            Axis::Y => match self.socd.y.priority() {
+                Some(priority) => self
+                    .a_stick
+                    .y
+                    .transition_prioritized(priority, dir, pressed),
+                None => self.a_stick.y.transition(dir, pressed),
+            },
+        }
+        self.check_a_stick_axis(axis, dir, pressed);
+    }
+
+    /// Returns whether B pressed at `now` is meant as neutral-B, per
+    /// `neutral_b_helper`.
+    fn neutral_b_applies(&self, now: Timestamp) -> bool {
+        let helper = match self.neutral_b_helper {
+            Some(helper) => helper,
+            None => return false,
+        };
+        if self.state.intersects(B0xxState::MOD_X | B0xxState::MOD_Y)
+            || self.a_stick.y.active().is_some()
+        {
+            return false;
+        }
+        let key = match self.a_stick.x.active() {
+            Some(POSITIVE) => B0xxRaw::Right,
+            Some(NEGATIVE) => B0xxRaw::Left,
+            None => return false,
+        };
+        self.last_press
+            .get(&key)
+            .is_some_and(|&pressed| now - pressed <= helper.window())
+    }
+
+    /// Presses X when the short-hop key is pressed, if configured, and
+    /// schedules its release. Further presses, and the key's release, do
+    /// nothing until then, as does the key while X's own key is held.
+    fn short_hop_key(&mut self, now: Timestamp, pressed: Pressed) -> Inputs {
+        let helper = match self.short_hop_helper {
+            Some(helper) => helper,
+            None => return Inputs::new(),
+        };
+        if !pressed || self.short_hop != ShortHop::Idle || self.held.contains(&B0xxRaw::X) {
+            return Inputs::new();
+        }
+        let until = now + helper.release();
+        debug!("short hop: releasing X at {:?}", until);
+        self.short_hop = ShortHop::Pressed { until };
+        smallvec![Input::Button(Button::Pure(ButtonPure::X), PRESSED)]
+    }
+
+    /// Starts ramping the trigger when its key is pressed, if configured, and
+    /// returns it to the shield's value when released.
+    fn trigger_ramp_key(&mut self, now: Timestamp, pressed: Pressed) -> Inputs {
+        let ramp = match self.trigger_ramp {
+            Some(ramp) => ramp,
+            None => return Inputs::new(),
+        };
+        if !pressed {
+            self.trigger_ramp_state = TriggerRampState::Idle;
+            let value = if self.shield_state.dropped() {
+                Trigger::Z
+            } else {
+                self.analog_value().unwrap_or(Trigger::Z)
+            };
+            return self.trigger_change(value);
+        }
+        debug!(max = ?ramp.max, "trigger ramp: over {:?}", ramp.ramp());
+        self.trigger_ramp_state = TriggerRampState::Ramping {
+            since: now,
+            next: Some(now + ramp.interval()),
+        };
+        self.trigger_change(ramp.value_at(std::time::Duration::ZERO))
+    }
+
+    /// Holds back the press of a button with a tap-hold behaviour until it is
+    /// either released, tapping it, or held for the threshold, pressing its
+    /// hold button instead until released.
+    fn tap_hold_key(
+        &mut self,
+        now: Timestamp,
+        btn: B0xxRaw,
+        tap_hold: config::TapHold,
+        pressed: Pressed,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        if pressed {
+            let _ = self
+                .tap_hold_state
+                .pending
+                .insert(btn, now + tap_hold.threshold());
+            return Inputs::new();
+        }
+        if self.tap_hold_state.pending.remove(&btn).is_some() {
+            debug!(?btn, "tap-hold: tapped");
+            let mut inputs = self.interpret(now, btn, PRESSED, true, crouch_walk_option_select);
+            inputs.extend(self.interpret(now, btn, RELEASED, true, crouch_walk_option_select));
+            return inputs;
+        }
+        if self.tap_hold_state.holding.remove(&btn) {
+            return self.interpret(
+                now,
+                tap_hold.hold,
+                RELEASED,
+                true,
+                crouch_walk_option_select,
+            );
+        }
+        Inputs::new()
+    }
+
+    /// Plays the macro bound to `btn` when pressed, if any. Pressing it while
+    /// a macro is playing does what the macro's `repress` says.
+    fn macro_key(
+        &mut self,
+        now: Timestamp,
+        btn: B0xxRaw,
+        pressed: Pressed,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let repress = match self.macros.get(btn) {
+            Some(m) if pressed => m.repress,
+            _ => return Inputs::new(),
+        };
+        let target = match (self.macro_run, repress) {
+            (None, _) => self.output.clone(),
+            (Some(run), config::Repress::Ignore) => {
+                debug!(?btn, playing = ?run.key, "macro: already playing, ignored");
+                return Inputs::new();
+            }
+            (Some(run), config::Repress::Restart) => {
+                debug!(?btn, playing = ?run.key, "macro: restarted");
+                ControllerSnapshot::default()
+            }
+        };
+        warn!(?btn, "macro fired, for practice only");
+        self.macro_run = Some(MacroRun {
+            key: btn,
+            step: 0,
+            next: now,
+        });
+        self.macro_advance(target, crouch_walk_option_select)
+    }
+
+    /// Plays the steps of the macro due up to the next wait, starting from
+    /// `target` as the output, and returns the change in output. The macro
+    /// ends with the output the buttons still held make.
+    fn macro_advance(
+        &mut self,
+        mut target: ControllerSnapshot,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        while let Some(mut run) = self.macro_run {
+            let steps = &self
+                .macros
+                .get(run.key)
+                .expect("the macro playing is configured")
+                .steps;
+            let Some(&step) = steps.get(run.step) else {
+                debug!(key = ?run.key, "macro: done");
+                self.macro_run = None;
+                target = self.held_output(crouch_walk_option_select);
+                break;
+            };
+            run.step += 1;
+            match step {
+                config::MacroStep::Press(button) => {
+                    target.apply(DolphinPipeInput::Button(button, PRESSED))
+                }
+                config::MacroStep::Release(button) => {
+                    target.apply(DolphinPipeInput::Button(button, RELEASED))
+                }
+                config::MacroStep::AStick(coordinates) => {
+                    target.apply(DolphinPipeInput::Stick(Stick::A, coordinates))
+                }
+                config::MacroStep::CStick(coordinates) => {
+                    target.apply(DolphinPipeInput::Stick(Stick::C, coordinates))
+                }
+                config::MacroStep::Trigger(trigger) => {
+                    target.apply(DolphinPipeInput::Trigger(trigger))
+                }
+                config::MacroStep::ReleaseAll => target = ControllerSnapshot::default(),
+                config::MacroStep::WaitMs(ms) => {
+                    // Counted from when the step was due, so that waits don't
+                    // drift with timer latency.
+                    run.next = run.next + std::time::Duration::from_millis(ms);
+                    self.macro_run = Some(run);
+                    break;
+                }
+            }
+            self.macro_run = Some(run);
+        }
+        diff(&self.output, &target)
+            .into_iter()
+            .map(Input::from)
+            .collect()
+    }
+
+    /// Returns whether the trigger ramp overrides the trigger.
+    fn trigger_ramping(&self) -> bool {
+        self.trigger_ramp_state != TriggerRampState::Idle
+    }
+
+    /// Returns the trigger input setting it to `value`, if it differs from the
+    /// output.
+    fn trigger_change(&self, value: Trigger) -> Inputs {
+        (value != self.output.trigger)
+            .then_some(Input::Trigger(value))
+            .into_iter()
+            .collect()
+    }
+
+    /// Starts firing the turbo button when its key is pressed, if configured,
+    /// and stops when it is released.
+    fn turbo_key(&mut self, now: Timestamp, pressed: Pressed) -> Inputs {
+        let turbo = match self.turbo {
+            Some(turbo) => turbo,
+            None => return Inputs::new(),
+        };
+        if !pressed {
+            return self.stop_turbo();
+        }
+        if self.turbo_state != TurboState::Idle {
+            return Inputs::new();
+        }
+        info!(
+            "turbo firing {:?} at {}Hz, held {:?} of every {:?}",
+            turbo.button,
+            turbo.rate_hz,
+            turbo.held(),
+            turbo.period()
+        );
+        self.turbo_state = TurboState::Firing {
+            since: now,
+            cycles: 0,
+            pressed: PRESSED,
+        };
+        smallvec![Input::Button(turbo_button(turbo), PRESSED)]
+    }
+
+    /// Stops firing the turbo button, releasing it if it is pressed.
+    fn stop_turbo(&mut self) -> Inputs {
+        match (self.turbo, std::mem::take(&mut self.turbo_state)) {
+            (Some(turbo), TurboState::Firing { pressed, .. }) => {
+                info!("turbo stopped");
+                if pressed {
+                    smallvec![Input::Button(turbo_button(turbo), RELEASED)]
+                } else {
+                    Inputs::new()
+                }
+            }
+            _ => Inputs::new(),
+        }
+    }
+
+    /// Returns when the turbo button is next pressed or released.
+    fn turbo_deadline(&self) -> Option<Timestamp> {
+        match (self.turbo, self.turbo_state) {
+            (
+                Some(turbo),
+                TurboState::Firing {
+                    since,
+                    cycles,
+                    pressed,
+                },
+            ) => Some(
+                since
+                    + turbo.period() * cycles
+                    + if pressed {
+                        turbo.held()
+                    } else {
+                        turbo.period()
+                    },
+            ),
+            _ => None,
+        }
+    }
+
+    /// Releases or presses the turbo button at its deadline.
+    fn turbo_toggle(&mut self) -> Option<Input> {
+        let (turbo, since, cycles, pressed) = match (self.turbo, self.turbo_state) {
+            (
+                Some(turbo),
+                TurboState::Firing {
+                    since,
+                    cycles,
+                    pressed,
+                },
+            ) => (turbo, since, cycles, pressed),
+            _ => return None,
+        };
+        self.turbo_state = TurboState::Firing {
+            since,
+            cycles: if pressed { cycles } else { cycles + 1 },
+            pressed: !pressed,
+        };
+        Some(Input::Button(turbo_button(turbo), !pressed))
+    }
+
+    /// Steps the reference model of an A-stick axis, if enabled, and logs if
+    /// it no longer agrees with the axis state.
+    fn check_a_stick_axis(&mut self, axis: Axis, dir: Direction, pressed: Pressed) {
+        let (reference_x, reference_y) = match &mut self.a_stick_reference {
+            Some(references) => references,
+            None => return,
+        };
+        let (reference, state) = match axis {
+            Axis::X => (reference_x, self.a_stick.x),
+            Axis::Y => (reference_y, self.a_stick.y),
+        };
+        reference.transition(dir, pressed);
+        if reference.diverges(state) {
+            warn!(
+                "A-stick {:?} axis diverged from the reference model after {:?} {}: {:?} vs {:?}",
+                axis, dir, pressed, state, reference
+            );
+        }
+    }
+
+    /// Returns the input and output state as a single line of JSON.
+    fn snapshot(&self) -> String {
+        let Self {
+            state,
+            held,
+            a_stick,
+            c_stick,
+            shield_state,
+            b_reverse,
+            output,
+            ..
+        } = self;
+        serde_json::to_string(&Snapshot {
+            state: *state,
+            held,
+            a_stick,
+            c_stick,
+            shield_state,
+            b_reverse: *b_reverse,
+            turbo_state: self.turbo_state,
+            short_hop: self.short_hop,
+            trigger_ramp: self.trigger_ramp_state,
+            macro_run: self.macro_run,
+            tap_hold: &self.tap_hold_state,
+            negative_edge: self.negative_edge.as_ref(),
+            light_press: self.light_press.as_ref(),
+            double_tap: self.double_tap.as_ref(),
+            neutral_b: self.neutral_b,
+            character: self.character.as_deref(),
+            dpad: self.dpad_held().into_iter().map(GCButton::from).collect(),
+            output,
+        })
+        .expect("snapshot contains only serializable values")
+    }
+
+    /// Returns the D-pad buttons logically held, whether latched through the
+    /// C-stick, which the raw engine never does, or by their own keys.
+    fn dpad_held(&self) -> Vec<Button> {
+        let latched = self
+            .raw
+            .is_none()
+            .then(|| self.c_stick.dpad_held())
+            .into_iter()
+            .flatten();
+        let dedicated = self.held.iter().filter_map(|&btn| match btn.into() {
+            B0xx::Pure(Pure::DPad(axis, dir)) => Some(Button::DPad(axis, dir)),
+            _ => None,
+        });
+        let mut held = Vec::new();
+        for button in latched.chain(dedicated) {
+            if !held.contains(&button) {
+                held.push(button);
+            }
+        }
+        held
+    }
+
+    /// Returns how many of its own key and the C-stick in alt mode hold a
+    /// D-pad direction. It is output while either does.
+    fn dpad_holders(&self, axis: Axis, dir: Direction) -> usize {
+        let latched = match axis {
+            Axis::X => self.c_stick.x,
+            Axis::Y => self.c_stick.y,
+        }
+        .alt_held()
+        .contains(&dir);
+        let dedicated = self
+            .held
+            .iter()
+            .any(|&btn| B0xx::from(btn) == B0xx::Pure(Pure::DPad(axis, dir)));
+        usize::from(latched) + usize::from(dedicated)
+    }
+
+    /// Returns the D-pad input for a holder of it having been added or
+    /// removed, if that changed whether it is output.
+    fn dpad_change(&self, axis: Axis, dir: Direction, pressed: Pressed) -> Inputs {
+        let holders = self.dpad_holders(axis, dir);
+        if (pressed && holders == 1) || (!pressed && holders == 0) {
+            smallvec![Input::Button(Button::DPad(axis, dir), pressed)]
+        } else {
+            smallvec![]
+        }
+    }
+
+    /// Returns the buttons output as they are logically held, e.g. A for Z
+    /// under `z_composite`, the turbo button while it is pressed, and digital
+    /// L/R while a shield behavior holds it.
+    fn buttons_held(&self) -> std::collections::BTreeSet<GCButton> {
+        self.held
+            .iter()
+            .filter_map(|&btn| match (btn.button(), self.z_composite) {
+                (Some(Button::Pure(ButtonPure::Z)), Some(_)) if self.raw.is_none() => {
+                    Some(Button::Pure(ButtonPure::A))
+                }
+                (button, _) => button,
+            })
+            .chain(self.dpad_held())
+            .chain(match (self.turbo, self.turbo_state) {
+                (
+                    Some(turbo),
+                    TurboState::Firing {
+                        pressed: PRESSED, ..
+                    },
+                ) => turbo.button.button(),
+                _ => None,
+            })
+            .chain(
+                matches!(self.short_hop, ShortHop::Pressed { .. })
+                    .then_some(Button::Pure(ButtonPure::X)),
+            )
+            .chain(
+                [B0xxRaw::L, B0xxRaw::R]
+                    .into_iter()
+                    .filter(|&btn| self.shield_state.digital_held(btn))
+                    .filter_map(B0xxRaw::button),
+            )
+            .map(GCButton::from)
+            .collect()
+    }
+
+    /// Returns the output the buttons held make on their own, without
+    /// anything played over them, e.g. by a macro.
+    fn held_output(&self, crouch_walk_option_select: bool) -> ControllerSnapshot {
+        let trigger = if self.trigger_ramping() {
+            // The ramp sets the trigger on its own schedule.
+            self.output.trigger
+        } else if self.shield_state.dropped() {
+            Trigger::Z
+        } else {
+            self.analog_value().unwrap_or(Trigger::Z)
+        };
+        ControllerSnapshot {
+            buttons: self.buttons_held(),
+            a_stick: self.a_stick_coordinates(crouch_walk_option_select),
+            c_stick: self.c_stick_coordinates(),
+            trigger,
+        }
+    }
+
+    /// Returns the commands that bring a pipe in any state in line with this
+    /// one: every button pressed or released as it is logically held, then
+    /// the sticks, the C-stick first, and trigger as last output.
+    fn resync(&self) -> Vec<DolphinPipeInput> {
+        let held = self.buttons_held();
+        let ControllerSnapshot {
+            a_stick,
+            c_stick,
+            trigger,
+            ..
+        } = self.output;
+        GCButton::ALL
+            .into_iter()
+            .map(|button| DolphinPipeInput::Button(button, held.contains(&button)))
+            .chain([
+                DolphinPipeInput::Stick(Stick::C, c_stick),
+                DolphinPipeInput::Stick(Stick::A, a_stick),
+                DolphinPipeInput::Trigger(trigger),
+            ])
+            .collect()
+    }
+
+    /// Returns the recent events, oldest first, followed by the snapshot, as
+    /// lines to log.
+    fn dump(&self) -> Vec<String> {
+        self.event_trace
+            .entries()
+            .map(
+                |EventTraceEntry {
+                     time,
+                     event,
+                     inputs,
+                 }| match event {
+                    Some((btn, pressed)) => format!(
+                        "{:?} {:?} {}: {:?}",
+                        time.0,
+                        btn,
+                        if *pressed { "pressed" } else { "released" },
+                        inputs
+                    ),
+                    None => format!("{:?} deadline: {:?}", time.0, inputs),
+                },
+            )
+            .chain(std::iter::once(format!("snapshot: {}", self.snapshot())))
+            .collect()
+    }
+
+    /// Returns when `btn` was last pressed, if ever.
+    #[cfg(test)]
+    fn last_press(&self, btn: B0xxRaw) -> Option<Timestamp> {
+        self.last_press.get(&btn).copied()
+    }
+
+    fn process_b0xx(&mut self, event: B0xxEvent, crouch_walk_option_select: bool) -> Inputs {
+        let _span =
+            tracing::debug_span!("b0xx", btn = ?event.btn, pressed = event.pressed).entered();
+        let state = self.state;
+        let a_stick = self.a_stick;
+        let c_stick = self.c_stick;
+        // Only cloned to be traced, as it may allocate.
+        let shield_state =
+            tracing::enabled!(tracing::Level::TRACE).then(|| self.shield_state.clone());
+        let b_reverse = self.b_reverse;
+        let (time, btn, pressed) = (event.time, event.btn, event.pressed);
+        let inputs = self.transition(event, crouch_walk_option_select);
+        trace_transition("state", state, self.state);
+        trace_transition("a_stick", a_stick, self.a_stick);
+        trace_transition("c_stick", c_stick, self.c_stick);
+        if let Some(shield_state) = shield_state {
+            trace_transition("shield_state", &shield_state, &self.shield_state);
+        }
+        trace_transition("b_reverse", b_reverse, self.b_reverse);
+        let inputs = self.emit(inputs);
+        self.event_trace.record(EventTraceEntry {
+            time,
+            event: Some((btn, pressed)),
+            inputs: inputs.clone(),
+        });
+        inputs
+    }
+
+    /// Updates the input state and returns the resulting change in output.
+    fn transition(
+        &mut self,
+        B0xxEvent {
+            time: now,
+            btn,
+            pressed,
+        }: B0xxEvent,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        if self.held_through_reset.remove(&btn) && !pressed {
+            return smallvec![];
+        }
+        if let (B0xxRaw::Start, Some(start_reset)) = (btn, self.start_reset) {
+            self.start_reset_at = pressed.then(|| now + start_reset.hold());
+        }
+        let changed = if pressed {
+            self.held.insert(btn)
+        } else {
+            self.held.remove(&btn)
+        };
+        if changed && pressed {
+            self.press_order.push(btn);
+        } else if changed {
+            self.press_order.retain(|&held| held != btn);
+        }
+        if pressed {
+            self.stats.press(btn, now);
+            if let Some(previous) = self.last_press.insert(btn, now) {
+                trace!(since_previous = ?(now - previous), "repeated press");
+            }
+        } else if let Some(&pressed_at) = self.last_press.get(&btn).filter(|_| changed) {
+            self.stats.release(btn, now - pressed_at);
+        }
+        if let Some(raw) = &mut self.raw {
+            return raw.process_b0xx(btn, pressed).into_iter().collect();
+        }
+        if let Some(tap_hold) = self.tap_holds.get(btn) {
+            return self.tap_hold_key(now, btn, tap_hold, pressed, crouch_walk_option_select);
+        }
+        if let Some(negative_edge) = &mut self.negative_edge {
+            if negative_edge.applies(btn) {
+                return if negative_edge.transition(btn, pressed, now) {
+                    self.interpret(now, btn, PRESSED, true, crouch_walk_option_select)
+                } else {
+                    Inputs::new()
+                };
+            }
+        }
+        if self
+            .double_tap
+            .as_ref()
+            .is_some_and(|double_tap| double_tap.applies(btn))
+        {
+            return self.double_tap_key(now, btn, pressed, changed, crouch_walk_option_select);
+        }
+        // Another shield behavior takes the place of the light press.
+        if self
+            .light_press
+            .as_ref()
+            .is_some_and(|light_press| light_press.applies(btn))
+            && self.shield_behavior.get(btn) == config::ShieldBehavior::Standard
+        {
+            return self.light_press_key(now, btn, pressed, changed, crouch_walk_option_select);
+        }
+        self.interpret(now, btn, pressed, changed, crouch_walk_option_select)
+    }
+
+    /// Handles a button of `light_press`. Its press only sets the light value
+    /// while nothing else sets the trigger, and is otherwise immediate.
+    fn light_press_key(
+        &mut self,
+        now: Timestamp,
+        btn: B0xxRaw,
+        pressed: Pressed,
+        changed: bool,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let idle = self.analog_value().is_none()
+            && !self.shield_state.dropped()
+            && !self.trigger_ramping();
+        let Some(light_press) = &mut self.light_press else {
+            return Inputs::new();
+        };
+        if pressed {
+            if light_press.pending.contains_key(&btn) || light_press.pressed.contains(&btn) {
+                return Inputs::new();
+            }
+            if !idle && !light_press.active() {
+                return self.interpret(now, btn, pressed, changed, crouch_walk_option_select);
+            }
+            let _ = light_press
+                .pending
+                .insert(btn, now + light_press.config.delay());
+            let value = light_press.config.value;
+            return self.trigger_change(value);
+        }
+        if light_press.pending.remove(&btn).is_some() {
+            debug!(?btn, "light press: released before the press");
+            return if idle && !light_press.active() {
+                self.trigger_change(Trigger::Z)
+            } else {
+                Inputs::new()
+            };
+        }
+        let _ = light_press.pressed.remove(&btn);
+        self.interpret(now, btn, RELEASED, changed, crouch_walk_option_select)
+    }
+
+    /// Handles a button of `double_tap`. A first press waits out the window,
+    /// and a second press within it holds the analog value until released.
+    fn double_tap_key(
+        &mut self,
+        now: Timestamp,
+        btn: B0xxRaw,
+        pressed: Pressed,
+        changed: bool,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let Some(double_tap) = &mut self.double_tap else {
+            return Inputs::new();
+        };
+        let tap = double_tap.taps.get(&btn).copied();
+        if pressed {
+            return match tap {
+                None => {
+                    let until = now + double_tap.config.window();
+                    let _ = double_tap.taps.insert(btn, Tap::Held { until });
+                    Inputs::new()
+                }
+                Some(Tap::Released { .. }) => {
+                    debug!(?btn, "double tap: analog");
+                    let value = double_tap.config.value;
+                    let _ = double_tap.taps.insert(btn, Tap::Analog);
+                    self.analog_change(btn, Some(value), PRESSED)
+                }
+                // A press again without a release changes nothing.
+                Some(Tap::Held { .. } | Tap::Analog | Tap::Digital) => Inputs::new(),
+            };
+        }
+        match tap {
+            Some(Tap::Held { until }) => {
+                let _ = double_tap.taps.insert(btn, Tap::Released { until });
+                Inputs::new()
+            }
+            Some(Tap::Analog) => {
+                let _ = double_tap.taps.remove(&btn);
+                self.analog_change(btn, None, RELEASED)
+            }
+            Some(Tap::Digital) => {
+                let _ = double_tap.taps.remove(&btn);
+                self.interpret(now, btn, RELEASED, changed, crouch_walk_option_select)
+            }
+            Some(Tap::Released { .. }) | None => Inputs::new(),
+        }
+    }
+
+    /// Presses or releases the shield button `btn`'s analog value, `value`
+    /// as selected when pressed, and returns the change in trigger. The
+    /// analog shield is overridden while digital L/R drops it.
+    fn analog_change(&mut self, btn: B0xxRaw, value: Option<Trigger>, pressed: Pressed) -> Inputs {
+        let value = if pressed {
+            let replaced = self.shield_state.value().is_some();
+            let value = value.and_then(|value| self.shield_state.press(btn, value));
+            if value.is_some() {
+                self.stats.shield_transition(if replaced {
+                    ShieldTransition::Replaced
+                } else {
+                    ShieldTransition::Activated
+                });
+            }
+            value
+        } else {
+            let value = self.shield_state.release(btn);
+            self.stats.shield_transition(match value {
+                None => ShieldTransition::ReleasedInactive,
+                Some(Trigger::Z) => ShieldTransition::Released,
+                Some(_) => ShieldTransition::FellBack,
+            });
+            // Z's analog value is beneath every shield.
+            value.map(|value| match (value, self.z_trigger()) {
+                (Trigger::Z, Some(z)) => z,
+                _ => value,
+            })
+        };
+        value
+            .filter(|_| !self.shield_state.dropped() && !self.trigger_ramping())
+            .map(Input::Trigger)
+            .into_iter()
+            .collect()
+    }
+
+    /// Presses or releases digital `button`, L or R, for the shield button
+    /// `btn`, which drops the analog trigger while held if `drops`, and
+    /// returns the resulting change in output.
+    fn digital_change(
+        &mut self,
+        btn: B0xxRaw,
+        button: B0xxRaw,
+        drops: bool,
+        pressed: Pressed,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        let was_held = self.shield_state.digital_held(button);
+        let was_dropped = self.shield_state.dropped();
+        if pressed {
+            let _ = self.shield_state.digital.insert(btn, (button, drops));
+        } else if self.shield_state.digital.remove(&btn).is_none() {
+            return Inputs::new();
+        }
+        let held = self.shield_state.digital_held(button);
+        let dropped = self.shield_state.dropped();
+        let (flag, impure) = match button {
+            B0xxRaw::L => (B0xxState::L, ButtonImpure::L),
+            _ => (B0xxState::R, ButtonImpure::R),
+        };
+        self.state.set(flag, held);
+        // The analog shield is dropped when the first of digital L/R that
+        // drops it is pressed and restored when the last is released.
+        let shield = self
+            .analog_value()
+            .filter(|_| dropped != was_dropped && !self.trigger_ramping())
+            .map(|value| Input::Trigger(if dropped { Trigger::Z } else { value }));
+        // Releasing the last of L/R also drops the light value of
+        // `light_press`, once no button uses it.
+        let light = (was_dropped
+            && !dropped
+            && shield.is_none()
+            && !self.trigger_ramping()
+            && !self.light_press.as_ref().is_some_and(LightPress::active)
+            && self.output.trigger != Trigger::Z)
+            .then_some(Input::Trigger(Trigger::Z));
+        let stick = self
+            .a_stick_change(crouch_walk_option_select)
+            .map(|new_a| Input::Stick(Stick::A, new_a));
+        // Another shield button holding `button` keeps it pressed.
+        let button = (held != was_held).then_some(Input::Button(Button::Impure(impure), pressed));
+        // The stick is modified before the press and restored after the
+        // release, and the shield likewise around both.
+        if pressed {
+            shield.into_iter().chain(stick).chain(button).collect()
+        } else {
+            button
+                .into_iter()
+                .chain(stick)
+                .chain(shield)
+                .chain(light)
+                .collect()
+        }
+    }
+
+    /// Updates the input state derived from the buttons held, as opposed to
+    /// the held buttons themselves, and returns the resulting change in
+    /// output. `changed` is whether `btn` was not already in that state.
+    fn interpret(
+        &mut self,
+        now: Timestamp,
+        btn: B0xxRaw,
+        pressed: Pressed,
+        changed: bool,
+        crouch_walk_option_select: bool,
+    ) -> Inputs {
+        match self.shield_behavior.get(btn) {
+            config::ShieldBehavior::Standard => {}
+            // A press again without a release changes nothing.
+            _ if pressed && !changed => return Inputs::new(),
+            config::ShieldBehavior::Digital(button) => {
+                return self.digital_change(btn, button, false, pressed, crouch_walk_option_select)
+            }
+            config::ShieldBehavior::Analog(value) => {
+                return self.analog_change(btn, Some(value), pressed)
+            }
+            config::ShieldBehavior::AnalogThenDigital {
+                value,
+                button,
+                hold_ms,
+            } => {
+                if pressed {
+                    let _ = self
+                        .shield_state
+                        .pending
+                        .insert(btn, now + std::time::Duration::from_millis(hold_ms));
+                    return self.analog_change(btn, Some(value), PRESSED);
+                }
+                // The button is released before the analog trigger, as
+                // `light_press` does.
+                let digital = if self.shield_state.pending.remove(&btn).is_none() {
+                    self.digital_change(btn, button, false, RELEASED, crouch_walk_option_select)
+                } else {
+                    Inputs::new()
+                };
+                return digital
+                    .into_iter()
+                    .chain(self.analog_change(btn, None, RELEASED))
+                    .collect();
+            }
+        }
+        let impure = match btn.into() {
+            B0xx::Pure(pure) => {
+                return match pure {
+                    Pure::Button(btn_pure) => {
+                        if btn_pure == ButtonPure::Start {
+                            self.state.set(B0xxState::START, pressed);
+                        }
+                        // X's own key takes over from the short-hop key,
+                        // keeping X pressed and releasing it as usual.
+                        if let (ButtonPure::X, PRESSED, ShortHop::Pressed { .. }) =
+                            (btn_pure, pressed, self.short_hop)
+                        {
+                            debug!("short hop: cancelled by X");
+                            self.short_hop = ShortHop::Idle;
+                            return smallvec![];
+                        }
+                        if let Some(z_composite) = self.z_composite {
+                            match btn_pure {
+                                ButtonPure::Z => {
+                                    return self.z_composite_change(z_composite, pressed)
+                                }
+                                // Z holds A down as well.
+                                ButtonPure::A if self.held.contains(&B0xxRaw::Z) => {
+                                    return smallvec![]
+                                }
+                                _ => {}
+                            }
+                        }
+                        smallvec![Input::Button(Button::Pure(btn_pure), pressed)]
+                    }
+                    Pure::DPad(axis, dir) => {
+                        if !changed {
+                            return smallvec![];
+                        }
+                        self.dpad_change(axis, dir, pressed)
+                    }
+                    Pure::Shield(shield) => {
+                        let modifier = match self.state & B0xxState::MODS {
+                            B0xxState::MOD_X => Some(config::Modifier::ModX),
+                            B0xxState::MOD_Y => Some(config::Modifier::ModY),
+                            _ => None,
+                        };
+                        let value = self.shield_tiers.select(shield, modifier);
+                        self.analog_change(btn, value, pressed)
+                    }
+                };
+            }
+            B0xx::Impure(impure) => impure,
+        };
+        if let (Impure::Stick(stick, axis, dir), PRESSED) = (impure, pressed) {
+            let active = match (stick, axis) {
+                (Stick::A, Axis::X) => self.a_stick.x.active(),
+                (Stick::A, Axis::Y) => self.a_stick.y.active(),
+                (Stick::C, Axis::X) => self.c_stick.x.active(),
+                (Stick::C, Axis::Y) => self.c_stick.y.active(),
+            };
+            if active == Some(!dir) {
+                self.stats.socd_override(btn, stick, axis);
+            }
+            // The release in between was lost, which keyboards with limited
+            // rollover can do when a third key is pressed in the cluster.
+            if active == Some(dir) && !changed {
+                warn!(
+                    ?btn,
+                    held = ?self.held,
+                    "direction pressed again while active without a release, suspect key rollover"
+                );
+                self.stats.rollover_suspect(btn);
+            }
+        }
+        match impure {
+            Impure::Button(ButtonImpure::B) => {
+                self.state.set(B0xxState::B, pressed);
+                if let (Some(helper), Some(dir), PRESSED) = (
+                    self.b_reverse_helper,
+                    self.a_stick.x.active_unique(),
+                    pressed,
+                ) {
+                    self.b_reverse = BReverse::Armed {
+                        dir,
+                        until: now + helper.window(),
+                    };
+                }
+                self.neutral_b = pressed && self.neutral_b_applies(now);
+                let stick = self
+                    .a_stick_change(crouch_walk_option_select)
+                    .map(|new_a| Input::Stick(Stick::A, new_a));
+                let button = Input::Button(Button::Impure(ButtonImpure::B), pressed);
+                // The stick is modified before the press and restored after
+                // the release.
+                return if pressed {
+                    stick.into_iter().chain([button]).collect()
+                } else {
+                    [button].into_iter().chain(stick).collect()
+                };
+            }
+            Impure::Button(_) => {
+                return self.digital_change(btn, btn, true, pressed, crouch_walk_option_select)
+            }
+            Impure::Stick(Stick::C, axis, dir) => {
+                let dpad_enabled = self.dpad.enabled(self.state);
+                let dpad_released =
+                    self.c_stick
+                        .transition(axis, dir, pressed, dpad_enabled, self.socd.c_stick);
+
+                if dpad_enabled && pressed {
+                    return self.dpad_change(axis, dir, PRESSED);
+                }
+                if dpad_released {
+                    return self.dpad_change(axis, dir, RELEASED);
+                }
+            }
+            Impure::Stick(Stick::A, axis, dir) => {
+                if let Some(limiter) = &mut self.sdi_limiter {
+                    if limiter.defer(now, axis, dir, pressed) {
+                        debug!(?axis, dir, pressed, "delayed by the SDI limit");
+                        return Inputs::new();
+                    }
+                }
+                self.a_stick_transition(now, axis, dir, pressed);
+            }
+            Impure::ModX => self.state.set(B0xxState::MOD_X, pressed),
+            Impure::ModY => self.state.set(B0xxState::MOD_Y, pressed),
+            Impure::ModTilt => self.state.set(B0xxState::MOD_TILT, pressed),
+            Impure::DPadLayer => self.state.set(B0xxState::DPAD_LAYER, pressed),
+            Impure::Turbo => return self.turbo_key(now, pressed),
+            Impure::ShortHop => return self.short_hop_key(now, pressed),
+            Impure::TriggerRamp => return self.trigger_ramp_key(now, pressed),
+            Impure::Macro(_) => {
+                return self.macro_key(now, btn, pressed, crouch_walk_option_select)
+            }
+        }
+
+        // C-stick buttons may also change the A-stick, and A-stick buttons the
+        // C-stick.
+        self.sticks_change(crouch_walk_option_select)
+    }
+}
+
+/// Where pipe commands go, e.g. `OutputSink`, or whatever a tool embedding
+/// `remap::run` collects them in.
+#[cfg(feature = "linux-input")]
+trait Sink {
+    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()>;
+
+    fn send_input(&mut self, input: Input) -> anyhow::Result<()> {
+        self.send(input.into_pipe_input())
+    }
+
+    /// Reopens whatever the sink writes to, e.g. after the reader went away.
+    fn reconnect(&mut self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("the sink cannot reconnect"))
+    }
+
+    /// Returns the health of every monitored sink, by name.
+    fn health(&self) -> std::collections::BTreeMap<&'static str, health::Health> {
+        Default::default()
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+        (**self).send(pipe_input)
+    }
+
+    fn reconnect(&mut self) -> anyhow::Result<()> {
+        (**self).reconnect()
+    }
+
+    fn health(&self) -> std::collections::BTreeMap<&'static str, health::Health> {
+        (**self).health()
+    }
+}
+
+/// Writes pipe commands to `file`, which is the pipe except in dry runs.
+#[cfg(feature = "linux-input")]
+struct OutputSink<W = std::fs::File> {
+    file: W,
+    /// Opens `file` again to reconnect, if it can be.
+    reopen: Option<fn() -> std::io::Result<W>>,
+    /// Mirrors every command written.
+    command_log: Option<command_log::CommandLog>,
+    handedness: config::Handedness,
+    game: Game,
+    capability: config::PipeCapability,
+    /// Reused for every command, so that formatting one doesn't allocate.
+    command: String,
+    /// What the commands written leave the controller at, to write again to
+    /// the reader reconnected to.
+    written: ControllerSnapshot,
+}
+
+#[cfg(feature = "linux-input")]
+impl<W: std::io::Write> Sink for OutputSink<W> {
+    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+        self.command.clear();
+        pipe_input
+            .write_command(
+                &mut self.command,
+                self.handedness,
+                self.game,
+                self.capability,
+            )
+            .context("failed to format the command")?;
+        let cmd = &self.command;
+        debug!("writing: {}", cmd);
+        let _ = self.file.write(cmd.as_bytes())?;
+        if let Some(command_log) = &self.command_log {
+            command_log.record(Timestamp::now(), cmd);
+        }
+        self.written.apply(pipe_input);
+        Ok(())
+    }
+
+    /// Reopens the file and, as the reader may have started over from
+    /// neutral, e.g. Dolphin restarting, writes again what was written.
+    fn reconnect(&mut self) -> anyhow::Result<()> {
+        let reopen = self
+            .reopen
+            .ok_or_else(|| anyhow::anyhow!("nothing to reopen"))?;
+        self.file = reopen().context("failed to reopen")?;
+        for pipe_input in diff(&ControllerSnapshot::default(), &self.written) {
+            self.send(pipe_input)?;
+        }
+        Ok(())
+    }
+}
+
+/// Holds inputs back for a fixed delay, releasing them in the order they were
+/// queued. Inputs from the same event are due at the same time, so they are
+/// released together.
+struct DelayQueue {
+    delay: std::time::Duration,
+    queue: std::collections::VecDeque<(Timestamp, Input)>,
+}
+
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+impl DelayQueue {
+    fn new(delay: std::time::Duration) -> Self {
+        Self {
+            delay,
+            queue: Default::default(),
+        }
+    }
+
+    fn push(&mut self, now: Timestamp, input: Input) {
+        self.queue.push_back((now + self.delay, input))
+    }
+
+    /// Returns when the next input is due. An input is never released before
+    /// those queued ahead of it, even if it is due earlier.
+    fn deadline(&self) -> Option<Timestamp> {
+        self.queue.front().map(|&(due, _)| due)
+    }
+
+    fn pop_due(&mut self, now: Timestamp) -> Option<Input> {
+        match self.queue.front() {
+            Some(&(due, _)) if due <= now => self.queue.pop_front().map(|(_, input)| input),
+            _ => None,
+        }
+    }
+}
+
+/// Coalesces output into one write per tick, since Dolphin only reads the
+/// pipe once per frame.
+struct FrameQuantizer {
+    period: std::time::Duration,
+    next_tick: Timestamp,
+    flushed: ControllerSnapshot,
+    pending: ControllerSnapshot,
+    /// Buttons whose pending state changed since the last tick.
+    changed: std::collections::BTreeSet<GCButton>,
+}
+
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+impl FrameQuantizer {
+    fn new(period: std::time::Duration, now: Timestamp) -> Self {
+        Self {
+            period,
+            next_tick: now + period,
+            flushed: Default::default(),
+            pending: Default::default(),
+            changed: Default::default(),
+        }
+    }
+
+    fn apply(&mut self, input: Input) {
+        let pipe_input = input.into_pipe_input();
+        if let DolphinPipeInput::Button(button, _) = pipe_input {
+            let _ = self.changed.insert(button);
+        }
+        self.pending.apply(pipe_input);
+    }
+
+    /// Returns the next tick if there is anything to flush on it.
+    fn deadline(&self) -> Option<Timestamp> {
+        (self.pending != self.flushed || !self.changed.is_empty()).then_some(self.next_tick)
+    }
+
+    /// Returns the pipe inputs to flush if a tick is due. A button that was
+    /// pressed and released again since the last tick, or vice versa, is
+    /// flushed in its intermediate state so that the tap isn't swallowed, and
+    /// its final state follows on the next tick.
+    fn tick(&mut self, now: Timestamp) -> Vec<DolphinPipeInput> {
+        if now < self.next_tick {
+            return Vec::new();
+        }
+        // Stay on the grid unless a whole tick was missed, e.g. when idle.
+        self.next_tick = if now - self.next_tick < self.period {
+            self.next_tick + self.period
+        } else {
+            now + self.period
+        };
+        let mut target = self.pending.clone();
+        for button in std::mem::take(&mut self.changed) {
+            let pressed = target.buttons.contains(&button);
+            if pressed == self.flushed.buttons.contains(&button) {
+                target.apply(DolphinPipeInput::Button(button, !pressed));
+            }
+        }
+        let diff = diff(&self.flushed, &target);
+        self.flushed = target;
+        diff
+    }
+}
+
+/// Counts latencies in microseconds in buckets that widen with magnitude, as an
+/// HDR histogram does, so that quantiles are accurate to within one part in
+/// `LatencyHistogram::SUB_BUCKETS / 2` at any scale in constant space.
+#[derive(Clone, Debug)]
+struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; Self::BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    const SUB_BUCKET_BITS: u32 = 5;
+    /// Values below this each get their own bucket. Above it, every doubling
+    /// of magnitude is split into `SUB_BUCKETS / 2` buckets.
+    const SUB_BUCKETS: u64 = 1 << Self::SUB_BUCKET_BITS;
+    const BUCKETS: usize = (Self::SUB_BUCKETS
+        + (u64::BITS - Self::SUB_BUCKET_BITS) as u64 * Self::SUB_BUCKETS / 2)
+        as usize;
+
+    fn bucket(value: u64) -> usize {
+        let bits = u64::BITS - value.leading_zeros();
+        if bits <= Self::SUB_BUCKET_BITS {
+            return value as usize;
+        }
+        let shift = bits - Self::SUB_BUCKET_BITS;
+        let half = Self::SUB_BUCKETS / 2;
+        (Self::SUB_BUCKETS + u64::from(shift - 1) * half + ((value >> shift) - half)) as usize
+    }
+
+    /// Returns the smallest value counted in `bucket`.
+    fn bucket_start(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < Self::SUB_BUCKETS {
+            return bucket;
+        }
+        let half = Self::SUB_BUCKETS / 2;
+        let above = bucket - Self::SUB_BUCKETS;
+        (above % half + half) << (above / half + 1)
+    }
+
+    /// Returns the largest value counted in `bucket`.
+    fn bucket_end(bucket: usize) -> u64 {
+        if bucket + 1 == Self::BUCKETS {
+            u64::MAX
+        } else {
+            Self::bucket_start(bucket + 1) - 1
+        }
+    }
+
+    fn record(&mut self, latency: std::time::Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.counts[Self::bucket(micros)] += 1;
+        self.count += 1;
+        self.max = self.max.max(micros);
+    }
+
+    /// Returns an upper bound on the latency below which `quantile` of those
+    /// recorded fall, or `None` if none were.
+    fn quantile(&self, quantile: f64) -> Option<std::time::Duration> {
+        let rank = ((quantile * self.count as f64).ceil() as u64).clamp(1, self.count.max(1));
+        let mut seen = 0;
+        self.counts.iter().enumerate().find_map(|(bucket, &count)| {
+            seen += count;
+            (count != 0 && seen >= rank)
+                .then(|| std::time::Duration::from_micros(Self::bucket_end(bucket).min(self.max)))
+        })
+    }
+}
+
+impl std::fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.quantile(0.5), self.quantile(0.95), self.quantile(0.99)) {
+            (Some(p50), Some(p95), Some(p99)) => write!(
+                f,
+                "{} events: p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+                self.count,
+                p50,
+                p95,
+                p99,
+                std::time::Duration::from_micros(self.max)
+            ),
+            _ => write!(f, "no events"),
+        }
+    }
+}
+
+/// Carries inputs from `Main` to the pipe, optionally through a delay and
+/// frame quantization.
+#[cfg(feature = "linux-input")]
+struct Output<S = OutputSink> {
+    sink: S,
+    delay_queue: Option<DelayQueue>,
+    quantizer: Option<FrameQuantizer>,
+}
+
+#[cfg(feature = "linux-input")]
+impl<S: Sink> Output<S> {
+    fn send(&mut self, now: Timestamp, input: Input) -> anyhow::Result<()> {
+        match &mut self.delay_queue {
+            Some(delay_queue) => {
+                delay_queue.push(now, input);
+                Ok(())
+            }
+            None => self.release(input),
+        }
+    }
+
+    fn release(&mut self, input: Input) -> anyhow::Result<()> {
+        match &mut self.quantizer {
+            Some(quantizer) => {
+                quantizer.apply(input);
+                Ok(())
+            }
+            None => self.sink.send_input(input),
+        }
+    }
+
+    /// Returns whether `send` writes to the pipe before returning.
+    fn writes_immediately(&self) -> bool {
+        self.delay_queue.is_none() && self.quantizer.is_none()
+    }
+
+    fn deadline(&self) -> Option<Timestamp> {
+        self.delay_queue
+            .as_ref()
+            .and_then(DelayQueue::deadline)
+            .into_iter()
+            .chain(self.quantizer.as_ref().and_then(FrameQuantizer::deadline))
+            .min()
+    }
+
+    fn process_deadline(&mut self, now: Timestamp) -> anyhow::Result<()> {
+        while let Some(input) = self
+            .delay_queue
+            .as_mut()
+            .and_then(|delay_queue| delay_queue.pop_due(now))
+        {
+            self.release(input)?;
+        }
+        if let Some(quantizer) = &mut self.quantizer {
+            for pipe_input in quantizer.tick(now) {
+                self.sink.send(pipe_input)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "linux-input"))]
+// The fuzz target compiles this file in as a module, which leaves it unused.
+#[cfg_attr(fuzzing, allow(dead_code))]
+fn main() {
+    eprintln!("tuxb0xx was built without the linux-input feature and cannot read input devices");
+    std::process::exit(1);
+}
+
+/// Path of Dolphin's pipe input.
+#[cfg(feature = "linux-input")]
+const PIPE_PATH: &str = "/home/tone/.config/SlippiOnline/Pipes/pipe";
+
+/// Opens the pipe at `path` again without waiting for Dolphin to read it, so
+/// that reconnecting fails rather than blocks while it is closed. Writes then
+/// block again, as to the pipe first opened.
+#[cfg(feature = "linux-input")]
+fn reopen_pipe(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt as _;
+    use std::os::unix::io::AsRawFd as _;
+
+    let file = std::fs::OpenOptions::new()
+        .append(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    // SAFETY: `file` is open for as long as the calls.
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    // SAFETY: as above.
+    if flags < 0
+        || unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, flags & !libc::O_NONBLOCK) } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Returns the default path of the state file, in the config directory
+/// named by `var`, e.g. `std::env::var_os`, and outside Dolphin's pipe
+/// directory as it treats every file there as a pipe. `None` if neither
+/// `XDG_CONFIG_HOME` nor `HOME` names one.
+#[cfg(feature = "linux-input")]
+fn default_state_path(
+    var: impl Fn(&str) -> Option<std::ffi::OsString>,
+) -> Option<std::path::PathBuf> {
+    // Relative paths are invalid, as per the XDG base directory spec.
+    let absolute = |path: std::path::PathBuf| Some(path).filter(|path| path.is_absolute());
+    var("XDG_CONFIG_HOME")
+        .and_then(|dir| absolute(dir.into()))
+        .or_else(|| {
+            var("HOME").and_then(|home| absolute(std::path::PathBuf::from(home).join(".config")))
+        })
+        .map(|dir| dir.join("tuxb0xx/state.json"))
+}
+
+/// An error that ends the program, categorized so that scripts can tell
+/// failures apart by exit code.
+#[cfg(feature = "linux-input")]
+#[derive(Debug)]
+enum Fatal {
+    /// `--self-test` failed.
+    SelfTest(anyhow::Error),
+    /// The config file, profile or arguments are invalid.
+    Config(anyhow::Error),
+    /// No keyboard could be identified.
+    Keyboard(anyhow::Error),
+    /// The keyboard device failed to open or stopped producing events.
+    Device(anyhow::Error),
+    /// The pipe failed to open or could not be written.
+    Pipe(anyhow::Error),
+    /// `--certify` found a check failing.
+    Certify(anyhow::Error),
+}
+
+#[cfg(feature = "linux-input")]
+impl Fatal {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::SelfTest(_) => 1,
+            Self::Config(_) => 2,
+            Self::Keyboard(_) => 3,
+            Self::Device(_) => 4,
+            Self::Pipe(_) => 5,
+            Self::Certify(_) => 6,
+        }
+    }
+
+    fn error(&self) -> &anyhow::Error {
+        match self {
+            Self::SelfTest(e)
+            | Self::Config(e)
+            | Self::Keyboard(e)
+            | Self::Device(e)
+            | Self::Pipe(e)
+            | Self::Certify(e) => e,
+        }
+    }
+
+    /// Returns how the user might fix the error, if known.
+    fn hint(&self) -> Option<&'static str> {
+        use std::io::ErrorKind;
+        let kind = self
+            .error()
+            .chain()
+            .find_map(|e| e.downcast_ref::<std::io::Error>())
+            .map(std::io::Error::kind);
+        match (self, kind) {
+            (Self::Keyboard(_) | Self::Device(_), Some(ErrorKind::PermissionDenied)) => Some(
+                "reading /dev/input requires membership of the input group, e.g. \
+                 `sudo usermod -aG input $USER`, then logging in again",
+            ),
+            (Self::Keyboard(_), _) => Some("check that a keyboard is connected"),
+            (Self::Pipe(_), Some(ErrorKind::NotFound)) => {
+                Some("enable pipe input for the controller port in Dolphin, which creates the pipe")
+            }
+            (Self::Pipe(_), Some(ErrorKind::BrokenPipe)) => Some("Dolphin closed the pipe"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error())
+    }
+}
+
+#[cfg(feature = "linux-input")]
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        if let Some(hint) = e.hint() {
+            eprintln!("hint: {}", hint);
+        }
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Cycles through the levels useful for watching a session, starting over
+/// from info for any other.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+fn next_log_level(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::INFO => LevelFilter::DEBUG,
+        LevelFilter::DEBUG => LevelFilter::TRACE,
+        _ => LevelFilter::INFO,
+    }
+}
+
+#[cfg(feature = "linux-input")]
+fn run() -> Result<(), Fatal> {
+    let Args {
+        log_level,
+        log_format,
+        crouch_walk_option_select,
+        config,
+        profile: profile_name,
+        delay,
+        quantize,
+        frame_rate,
+        frame_epoch,
+        engine,
+        game,
+        pipe_policy,
+        pipe_capability,
+        print_mapping,
+        validate_coordinates,
+        certify,
+        tournament,
+        viz,
+        dump_key,
+        check_invariants,
+        self_test,
+        ghosting_test,
+        dump_events,
+        latency_report,
+        stats,
+        command_log,
+        analyze_command_log,
+        state_file,
+        no_state_file,
+        check_consistency,
+        heal,
+        tui,
+        ignore_device,
+        allow_device,
+        non_interactive,
+        wait_for_device,
+        probe,
+        silence_hint,
+        reader_queue,
+        reader_overflow,
+        idle_after,
+        control_socket,
+        viewer_port,
+    } = argh::from_env();
+    let frame_rate = frame::Rate::new(frame_rate)
+        .context("invalid --frame-rate")
+        .map_err(Fatal::Config)?;
+    let frames = frame::FrameClock::new(frame_rate, frame_epoch, Timestamp::now());
+    if check_consistency == Some(0) {
+        return Err(Fatal::Config(anyhow::anyhow!(
+            "--check-consistency must be at least 1 second"
+        )));
+    }
+
+    let mut log_level = log_level;
+    let log_filter = |level| {
+        tracing_subscriber::filter::Targets::new()
+            .with_default(LevelFilter::WARN)
+            .with_target(std::module_path!(), level)
+    };
+    let log_filter_handle = {
+        use tracing_subscriber::prelude::*;
+
+        let layer = match log_format {
+            LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+            LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+        };
+        // Reloadable so that SIGUSR2 can change the level mid-session.
+        let (filter, handle) = tracing_subscriber::reload::Layer::new(log_filter(log_level));
+        tracing_subscriber::registry()
+            .with(layer)
+            .with(filter)
+            .try_init()
+            .expect("failed to initialize logger");
+        handle
+    };
+    // Logs panics like everything else, so that they precede the event dump
+    // in the same format, then reports them as usual, e.g. with a backtrace
+    // if RUST_BACKTRACE asks for one.
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("{}", info);
+        report(info);
+    }));
+
+    let config = config
+        .map(|path| config::Config::load(&path))
+        .transpose()
+        .map_err(Fatal::Config)?
+        .unwrap_or_default();
+    let configured = config.profile(&profile_name).map_err(Fatal::Config)?;
+    // The config is left as written, for the report to tell what was turned
+    // off.
+    let profile = if tournament {
+        let (locked, suppressed) = certify::lockout(&configured, engine);
+        for name in suppressed {
+            info!(
+                "--tournament turned off {} in profile {:?}",
+                name, profile_name
+            );
+        }
+        locked
+    } else {
+        configured.clone()
+    };
+    let overrides = coordinates::check_overrides(&profile, crouch_walk_option_select, game);
+    for name in overrides.unreachable {
+        warn!(
+            "coordinate {} is overridden but never output under this profile",
+            name
+        );
+    }
+    for (character, (x, y)) in overrides.deadzoned {
+        let of = character
+            .map(|character| format!(" of character {:?}", character))
+            .unwrap_or_default();
+        warn!(
+            "A-stick coordinate [{}, {}]{} is inside Melee's deadzone, so does nothing",
+            x.get(),
+            y.get(),
+            of
+        );
+    }
+    if !overrides.defaulted.is_empty() {
+        info!(
+            "coordinates {:?} are not overridden, so fall back to the defaults",
+            overrides.defaulted
+        );
+    }
+    let dump_key = dump_key
+        .map(|key| config::key_code(&key).ok_or_else(|| anyhow::anyhow!("unknown key {:?}", key)))
+        .transpose()
+        .map_err(Fatal::Config)?;
+    if print_mapping {
+        println!("{}", profile);
+        return Ok(());
+    }
+    if let Some(path) = analyze_command_log {
+        let analysis = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read command log {}", path.display()))
+            .and_then(|log| command_log::analyze(&log))
+            .map_err(Fatal::Config)?;
+        println!("{}", analysis);
+        return Ok(());
+    }
+    if validate_coordinates {
+        for line in coordinates::dump(&Main::new(&profile), crouch_walk_option_select) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    if certify {
+        let report = certify::certify(
+            &profile_name,
+            &configured,
+            engine,
+            crouch_walk_option_select,
+            tournament,
+        );
+        println!("{}", report);
+        if report.failed() > 0 {
+            return Err(Fatal::Certify(anyhow::anyhow!(
+                "profile {:?} failed {} checks",
+                profile_name,
+                report.failed()
+            )));
+        }
+        return Ok(());
+    }
+    if let Some(path) = viz {
+        std::fs::write(
+            &path,
+            coordinates::svg(&Main::new(&profile), crouch_walk_option_select),
+        )
+        .with_context(|| format!("failed to write {}", path.display()))
+        .map_err(Fatal::Config)?;
+        return Ok(());
+    }
+    if self_test {
+        self_test::run().map_err(Fatal::SelfTest)?;
+        println!("self-test passed");
+        return Ok(());
+    }
+
+    let mut devices = config.devices.clone();
+    devices.ignore.extend(ignore_device);
+    devices.allow.extend(allow_device);
+    if probe {
+        return futures::executor::block_on(identify::probe(&devices, identify::PROBE_DURATION))
+            .map_err(Fatal::Keyboard);
+    }
+    let interactive = {
+        use std::io::IsTerminal as _;
+        !non_interactive && std::io::stdin().is_terminal()
+    };
+    let keeb_path = futures::executor::block_on(identify::retry(
+        || identify::keyboard(&devices, interactive),
+        wait_for_device.map(std::time::Duration::from_secs),
+        |delay| async move {
+            let _: std::time::Instant = async_io::Timer::after(delay).await;
+        },
+    ))
+    .context("failed to identify keyboard")
+    .map_err(Fatal::Keyboard)?;
+    info!("found keyboard {:?}", keeb_path);
+    if let Some(warning) = grab::conflict(&grab::System, &keeb_path) {
+        warn!("{}", warning);
+    }
+
+    let mut keeb_device = AsyncDevice::new(&keeb_path)
+        .with_context(|| format!("failed to open keyboard {}", keeb_path.display()))
+        .map_err(Fatal::Device)?
+        .fuse();
+
+    let mut remapper = Remapper {
+        autorepeat: profile.autorepeat.sources(),
+        raw: engine == Engine::Raw,
+        focus: config.focus_keys.codes(),
+        ..Remapper::new(profile.bindings.sources())
+    };
+    if ghosting_test {
+        return futures::executor::block_on(ghosting::run(
+            &mut keeb_device,
+            &mut remapper,
+            &profile.bindings,
+        ))
+        .map_err(Fatal::Device);
+    }
+    if dump_events {
+        let exit = signal_hook_async_std::Signals::new([
+            signal_hook::consts::SIGINT,
+            signal_hook::consts::SIGTERM,
+        ])
+        .expect("failed to register signal handlers")
+        .map(|_| ());
+        return futures::executor::block_on(event_dump::run(
+            &mut keeb_device,
+            exit,
+            &remapper,
+            |line| println!("{}", line),
+        ))
+        .map_err(Fatal::Device);
+    }
+    let mut main = Main {
+        raw: (engine == Engine::Raw).then(|| RawEngine {
+            invert: profile.invert,
+            ..Default::default()
+        }),
+        a_stick_reference: (check_invariants || cfg!(debug_assertions)).then(|| {
+            (
+                AxisReference::default(),
+                AxisReference::with_priority(profile.socd.y.priority()),
+            )
+        }),
+        deadzoned: (game.melee_refinements() && (check_invariants || cfg!(debug_assertions)))
+            .then(Default::default),
+        game,
+        ..Main::new(&profile)
+    };
+    let sink = OutputSink {
+        file: std::fs::OpenOptions::new()
+            .append(true)
+            .open(PIPE_PATH)
+            .with_context(|| format!("failed to open pipe {}", PIPE_PATH))
+            .map_err(Fatal::Pipe)?,
+        reopen: Some(|| reopen_pipe(PIPE_PATH.as_ref())),
+        command_log: command_log
+            .map(|path| command_log::CommandLog::create(&path, frames.clone()))
+            .transpose()
+            .map_err(Fatal::Config)?,
+        handedness: profile.handedness,
+        game,
+        capability: pipe_capability.unwrap_or(config.pipe_capability),
+        command: String::new(),
+        written: Default::default(),
+    };
+    let mut sink = health::Monitored::new("pipe", sink, pipe_policy);
+    #[cfg(feature = "tui")]
+    let tui = tui
+        .then(tui::Tui::new)
+        .transpose()
+        .context("failed to set up the terminal")
+        .map_err(Fatal::Config)?;
+    #[cfg(not(feature = "tui"))]
+    if tui {
+        return Err(Fatal::Config(anyhow::anyhow!(
+            "--tui requires building with the tui feature"
+        )));
+    }
+    if heal && check_consistency.is_none() {
+        return Err(Fatal::Config(anyhow::anyhow!(
+            "--heal requires --check-consistency"
+        )));
+    }
+    let write_error = |e: anyhow::Error| Fatal::Pipe(e.context("failed to write to pipe"));
+    let state_path = if no_state_file {
+        None
+    } else {
+        let path = state_file.or_else(|| default_state_path(|var| std::env::var_os(var)));
+        if path.is_none() {
+            warn!("neither XDG_CONFIG_HOME nor HOME is set, so no state file is saved");
+        }
+        path
+    };
+    if let Some(path) = &state_path {
+        for pipe_input in recovery::recover(path) {
+            sink.send(pipe_input).map_err(write_error)?;
+        }
+    }
+    let mut publisher = remap::Publisher::default();
+    if let Some(port) = viewer_port {
+        // Room for a burst of events, as one missed could leave a button
+        // shown held.
+        let _: std::net::SocketAddr =
+            viewer::serve(port, publisher.subscribe(1024)).map_err(Fatal::Config)?;
+    }
+    let (keyboard, reader_queue, queue_dropped) = match reader_queue {
+        None => (
+            keeb_device
+                .map(|r| r.context("failed to read keyboard event"))
+                .left_stream(),
+            None,
+            None,
+        ),
+        Some(capacity) => {
+            // The reader reopens the keyboard on its own thread.
+            drop(keeb_device);
+            let reader = pipeline::spawn_reader(
+                {
+                    let path = keeb_path.clone();
+                    move || {
+                        AsyncDevice::new(&path)
+                            .with_context(|| format!("failed to open keyboard {}", path.display()))
+                    }
+                },
+                capacity,
+                reader_overflow,
+            );
+            let stats = reader.stats();
+            let dropped = reader.stats();
+            (reader.right_stream(), Some(stats), Some(dropped))
+        }
+    };
+    // The keyboard's events are remapped per the profile switched to.
+    let remapper = std::rc::Rc::new(std::cell::RefCell::new(remapper));
+    let options = remap::Options {
+        runtime: remap::RuntimeOptions {
+            profile: profile_name,
+            crouch_walk_option_select,
+            ..Default::default()
+        },
+        delay: std::time::Duration::from_millis(delay),
+        quantize: quantize.then(|| frame_rate.period()),
+        detectors: profile.techniques.map(techniques::Detectors::new),
+        latency_report: latency_report.map(std::time::Duration::from_secs),
+        stats,
+        state_file: state_path.map(|path| recovery::StateFile::new(path, recovery::SAVE_PERIOD)),
+        consistency_check: check_consistency.map(std::time::Duration::from_secs),
+        heal,
+        combos: combo::Detector::new(profile.combos),
+        silence: (silence_hint != 0).then(|| {
+            grab::SilenceWatch::new(
+                Timestamp::now(),
+                std::time::Duration::from_secs(silence_hint),
+            )
+        }),
+        idle: (idle_after != 0).then(|| {
+            idle::IdleWatch::new(
+                Timestamp::now(),
+                std::time::Duration::from_secs(idle_after),
+                Box::new({
+                    let remapper = std::rc::Rc::clone(&remapper);
+                    let keeb_path = keeb_path.clone();
+                    move |now| {
+                        let down = idle::keys_down(&keeb_path).with_context(|| {
+                            format!("failed to read the keys down on {}", keeb_path.display())
+                        })?;
+                        Ok(remapper.borrow_mut().resync(now, &down))
+                    }
+                }),
+            )
+        }),
+        publisher,
+        profiles: Some(remap::Profiles {
+            config,
+            disabled: Default::default(),
+            lockout: tournament.then_some(engine),
+            rebind: Box::new({
+                let remapper = std::rc::Rc::clone(&remapper);
+                move |profile: &config::Profile| remapper.borrow_mut().rebind(profile)
+            }),
+        }),
+        reader_queue,
+        frames: Some(frames),
+        #[cfg(feature = "tui")]
+        tui,
+    };
+    let (control, control_events) = futures::channel::mpsc::unbounded();
+    if let Some(path) = &control_socket {
+        control::serve(path, control).map_err(Fatal::Config)?;
+    }
+
+    let keyboard = keyboard
+        .map({
+            let keeb_path = keeb_path.clone();
+            // Events the reader queue dropped as of the last event read.
+            let mut dropped = 0;
+            move |r| -> SmallVec<[anyhow::Result<remap::Event>; 2]> {
+                let event = match r {
+                    Ok(event) => event,
+                    Err(e) => return smallvec![Err(e)],
+                };
+                log_event(&event);
+                if Some(event.event_code) == dump_key {
+                    return if event.value == 1 {
+                        smallvec![Ok(remap::Event::Snapshot)]
+                    } else {
+                        SmallVec::new()
+                    };
+                }
+                let mut remapper = remapper.borrow_mut();
+                if remapper.focus_pressed(&event) {
+                    info!("focus key {:?} pressed", event.event_code);
+                    return smallvec![Ok(remap::Event::Pause)];
+                }
+                // The events the reader queue dropped may have been releases, so
+                // the keys down are read back from the keyboard. The events still
+                // queued are all newer than those dropped, so each key still ends
+                // up as the keyboard has it.
+                let resync = match queue_dropped.as_ref().map(pipeline::Stats::get) {
+                    Some(stats) if stats.dropped != dropped => {
+                        dropped = stats.dropped;
+                        let time = Timestamp::from_realtime(event.time.as_raw());
+                        match idle::keys_down(&keeb_path) {
+                            Ok(down) => remapper.resync(time, &down),
+                            Err(e) => {
+                                warn!("failed to read the keys down after dropping events: {}", e);
+                                Vec::new()
+                            }
+                        }
+                    }
+                    _ => Vec::new(),
+                };
+                resync
+                    .into_iter()
+                    .chain(remapper.evdev_to_b0xx(event))
+                    .map(|e| Ok(remap::Event::B0xx(e)))
+                    .collect()
+            }
+        })
+        .flat_map(futures::stream::iter)
+        .chain(futures::stream::iter([Err(anyhow::anyhow!(
+            "keyboard disconnected"
+        ))]));
+    // Exiting through the loop rather than being killed lets the reports
+    // be written.
+    let signals = signal_hook_async_std::Signals::new([
+        signal_hook::consts::SIGINT,
+        signal_hook::consts::SIGTERM,
+        signal_hook::consts::SIGUSR1,
+        signal_hook::consts::SIGUSR2,
+    ])
+    .expect("failed to register signal handlers")
+    .map(move |signal| match signal {
+        signal_hook::consts::SIGUSR1 => vec![Ok(remap::Event::WriteStats)],
+        signal_hook::consts::SIGUSR2 => {
+            log_level = next_log_level(log_level);
+            match log_filter_handle.reload(log_filter(log_level)) {
+                Ok(()) => info!("log level is now {}", log_level),
+                Err(e) => warn!("failed to change the log level: {}", e),
+            }
+            Vec::new()
+        }
+        _ => {
+            info!("exiting on signal {:?}", signal);
+            vec![Ok(remap::Event::Exit)]
+        }
+    })
+    .flat_map(futures::stream::iter);
+
+    let dump = |main: &Main| {
+        error!("last {} events:", main.event_trace.entries.len());
+        for line in main.dump() {
+            error!("{}", line);
+        }
+    };
+    // The loop only borrows `main`, so it can still be dumped once a panic
+    // unwinds out of it.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        futures::executor::block_on(remap::run(
+            &mut main,
+            // A request read off the socket before a key event is applied
+            // before the event is processed.
+            remap::prioritize(futures::stream::select(signals, control_events), keyboard),
+            sink,
+            options,
+        ))
+    }));
+    if let Some(path) = &control_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    let result = match result {
+        Ok(result) => result,
+        Err(panic) => {
+            dump(&main);
+            std::panic::resume_unwind(panic)
+        }
+    };
+    result.map_err(|e| {
+        dump(&main);
+        match e {
+            remap::Error::Events(e) => Fatal::Device(e),
+            remap::Error::Sink(e) => write_error(e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "linux-input")]
+    use evdev_rs::enums::{EventCode, EV_ABS, EV_KEY, EV_REL, EV_SW, EV_SYN};
+    use itertools::Itertools as _;
+    use std::time::Duration;
+    use test_case::test_case;
+
+    const CARDINALS: [(Axis, Direction); 4] = [
+        (Axis::X, POSITIVE),
+        (Axis::X, NEGATIVE),
+        (Axis::Y, POSITIVE),
+        (Axis::Y, NEGATIVE),
+    ];
+
+    const DIAGONALS: [(Direction, Direction); 4] = [
+        (POSITIVE, POSITIVE),
+        (POSITIVE, NEGATIVE),
+        (NEGATIVE, NEGATIVE),
+        (NEGATIVE, POSITIVE),
+    ];
+
+    const INVERSIONS: [config::AxisInversion; 4] = [
+        config::AxisInversion { x: false, y: false },
+        config::AxisInversion { x: true, y: false },
+        config::AxisInversion { x: false, y: true },
+        config::AxisInversion { x: true, y: true },
+    ];
+
+    impl From<B0xx> for B0xxRaw {
+        fn from(b: B0xx) -> B0xxRaw {
+            match b {
+                B0xx::Pure(Pure::Button(ButtonPure::A)) => B0xxRaw::A,
+                B0xx::Pure(Pure::Button(ButtonPure::X)) => B0xxRaw::X,
+                B0xx::Pure(Pure::Button(ButtonPure::Y)) => B0xxRaw::Y,
+                B0xx::Pure(Pure::Button(ButtonPure::Z)) => B0xxRaw::Z,
+                B0xx::Pure(Pure::Button(ButtonPure::Start)) => B0xxRaw::Start,
+                B0xx::Impure(Impure::Button(ButtonImpure::B)) => B0xxRaw::B,
+                B0xx::Impure(Impure::Button(ButtonImpure::L)) => B0xxRaw::L,
+                B0xx::Impure(Impure::Button(ButtonImpure::R)) => B0xxRaw::R,
+                B0xx::Impure(Impure::Stick(Stick::A, Axis::X, NEGATIVE)) => B0xxRaw::Left,
+                B0xx::Impure(Impure::Stick(Stick::A, Axis::X, POSITIVE)) => B0xxRaw::Right,
+                B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, NEGATIVE)) => B0xxRaw::Down,
+                B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, POSITIVE)) => B0xxRaw::Up,
+                B0xx::Impure(Impure::ModX) => B0xxRaw::MX,
+                B0xx::Impure(Impure::ModY) => B0xxRaw::MY,
+                B0xx::Impure(Impure::ModTilt) => B0xxRaw::MT,
+                B0xx::Impure(Impure::DPadLayer) => B0xxRaw::DP,
+                B0xx::Pure(Pure::Shield(Shield::Light)) => B0xxRaw::LS,
+                B0xx::Pure(Pure::Shield(Shield::Medium)) => B0xxRaw::MS,
+                B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, POSITIVE)) => B0xxRaw::CU,
+                B0xx::Impure(Impure::Stick(Stick::C, Axis::Y, NEGATIVE)) => B0xxRaw::CD,
+                B0xx::Impure(Impure::Stick(Stick::C, Axis::X, POSITIVE)) => B0xxRaw::CR,
+                B0xx::Impure(Impure::Stick(Stick::C, Axis::X, NEGATIVE)) => B0xxRaw::CL,
+                B0xx::Pure(Pure::DPad(Axis::Y, POSITIVE)) => B0xxRaw::DU,
+                B0xx::Pure(Pure::DPad(Axis::Y, NEGATIVE)) => B0xxRaw::DD,
+                B0xx::Pure(Pure::DPad(Axis::X, POSITIVE)) => B0xxRaw::DR,
+                B0xx::Pure(Pure::DPad(Axis::X, NEGATIVE)) => B0xxRaw::DL,
+                B0xx::Impure(Impure::Turbo) => B0xxRaw::TB,
+                B0xx::Impure(Impure::ShortHop) => B0xxRaw::SH,
+                B0xx::Impure(Impure::TriggerRamp) => B0xxRaw::TR,
+                B0xx::Impure(Impure::Macro(n)) => {
+                    [B0xxRaw::M1, B0xxRaw::M2, B0xxRaw::M3, B0xxRaw::M4][usize::from(n) - 1]
+                }
+            }
+        }
+    }
+
+    impl From<(Stick, Axis, Direction)> for B0xxRaw {
+        fn from((stick, axis, dir): (Stick, Axis, Direction)) -> B0xxRaw {
+            match (stick, axis, dir) {
+                (Stick::A, Axis::X, POSITIVE) => B0xxRaw::Right,
+                (Stick::A, Axis::X, NEGATIVE) => B0xxRaw::Left,
+                (Stick::A, Axis::Y, POSITIVE) => B0xxRaw::Up,
+                (Stick::A, Axis::Y, NEGATIVE) => B0xxRaw::Down,
+                (Stick::C, Axis::X, POSITIVE) => B0xxRaw::CR,
+                (Stick::C, Axis::X, NEGATIVE) => B0xxRaw::CL,
+                (Stick::C, Axis::Y, POSITIVE) => B0xxRaw::CU,
+                (Stick::C, Axis::Y, NEGATIVE) => B0xxRaw::CD,
+            }
+        }
+    }
+
+    /// Presses `buttons` in order and returns the inputs from the last press.
+    fn press_all(main: &mut Main, buttons: &[B0xxRaw], crouch_walk_option_select: bool) -> Inputs {
+        buttons.iter().fold(Inputs::new(), |_, &btn| {
+            main.process_b0xx(
+                B0xxEvent::new_without_time(btn, PRESSED),
+                crouch_walk_option_select,
+            )
+        })
+    }
+
+    /// Returns the inputs expected when pressing the last of `buttons` changes
+    /// the A-stick to `a_stick`, with any C-stick button held at its smash
+    /// cardinal.
+    fn a_stick_change_on_press(buttons: &[B0xxRaw], a_stick: AStickInput) -> Vec<Input> {
+        let last = (*buttons.last().unwrap()).into();
+        let c_stick = buttons.iter().find_map(|&btn| match btn.into() {
+            B0xx::Impure(Impure::Stick(Stick::C, axis, dir)) => Some((axis, dir)),
+            _ => None,
+        });
+        let c_stick_changed = match (last, c_stick) {
+            (B0xx::Impure(Impure::Stick(Stick::C, _, _)), _) => true,
+            // Completing the A-stick diagonal ends the Mod X angled fsmash.
+            (B0xx::Impure(Impure::Stick(Stick::A, Axis::X, _)), Some((Axis::X, _))) => {
+                buttons.contains(&B0xxRaw::MX)
+            }
+            // Likewise the angled vertical smash, with either mod alone.
+            (B0xx::Impure(Impure::Stick(Stick::A, Axis::Y, _)), Some((Axis::Y, _))) => {
+                buttons.contains(&B0xxRaw::MX) != buttons.contains(&B0xxRaw::MY)
+            }
+            _ => false,
+        };
+        let c_stick = c_stick
+            .filter(|_| c_stick_changed)
+            .map(|(axis, dir)| match axis {
+                Axis::X => (Analog::MAX.neg_not(dir), P0000),
+                Axis::Y => (P0000, Analog::MAX.neg_not(dir)),
+            })
+            .map(|c_stick| Input::Stick(Stick::C, c_stick));
+        let a_stick = Input::Stick(Stick::A, a_stick);
+        match last {
+            B0xx::Impure(Impure::Button(btn)) => {
+                vec![a_stick, Input::Button(Button::Impure(btn), PRESSED)]
+            }
+            _ => c_stick.into_iter().chain([a_stick]).collect(),
+        }
+    }
+
+    #[test_case(&[
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::MS, RELEASED, &[Input::Trigger(LS)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield1")]
+    #[test_case(&[
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::LS, RELEASED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+        (B0xxRaw::MS, RELEASED, &[]),
+    ]; "shield2")]
+    #[test_case(&[
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::MS, RELEASED, &[]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield3")]
+    #[test_case(&[
+        (B0xxRaw::MX, PRESSED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(Trigger::P72)]),
+        (B0xxRaw::MX, RELEASED, &[]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_tier_mod_x")]
+    #[test_case(&[
+        (B0xxRaw::MY, PRESSED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(Trigger::P110)]),
+        (B0xxRaw::MY, RELEASED, &[]),
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::LS, RELEASED, &[]),
+        (B0xxRaw::MS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_tier_mod_y")]
+    #[test_case(&[
+        (B0xxRaw::MX, PRESSED, &[]),
+        (B0xxRaw::MY, PRESSED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_tier_both_mods")]
+    #[test_case(&[
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::MY, PRESSED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(Trigger::P110)]),
+        (B0xxRaw::MY, RELEASED, &[]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(MS)]),
+        (B0xxRaw::MS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_tier_fallback")]
+    #[test_case(&[
+        (B0xxRaw::MX, PRESSED, &[]),
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(Trigger::P72)]),
+        (B0xxRaw::MX, RELEASED, &[]),
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::MS, RELEASED, &[Input::Trigger(Trigger::P72)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_tier_mod_release_while_held")]
+    #[test_case(&[
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::R, PRESSED, &[Input::Trigger(Trigger::Z), Input::Button(Button::Impure(ButtonImpure::R), PRESSED)]),
+        (B0xxRaw::R, RELEASED, &[Input::Button(Button::Impure(ButtonImpure::R), RELEASED), Input::Trigger(LS)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_then_digital")]
+    #[test_case(&[
+        (B0xxRaw::R, PRESSED, &[Input::Button(Button::Impure(ButtonImpure::R), PRESSED)]),
+        (B0xxRaw::LS, PRESSED, &[]),
+        (B0xxRaw::R, RELEASED, &[Input::Button(Button::Impure(ButtonImpure::R), RELEASED), Input::Trigger(LS)]),
+        (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "digital_then_shield")]
+    #[test_case(&[
+        (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+        (B0xxRaw::L, PRESSED, &[Input::Trigger(Trigger::Z), Input::Button(Button::Impure(ButtonImpure::L), PRESSED)]),
+        (B0xxRaw::LS, RELEASED, &[]),
+        (B0xxRaw::L, RELEASED, &[Input::Button(Button::Impure(ButtonImpure::L), RELEASED)]),
+    ]; "shield_released_under_digital")]
+    #[test_case(&[
+        (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+        (B0xxRaw::L, PRESSED, &[Input::Trigger(Trigger::Z), Input::Button(Button::Impure(ButtonImpure::L), PRESSED)]),
+        (B0xxRaw::R, PRESSED, &[Input::Button(Button::Impure(ButtonImpure::R), PRESSED)]),
+        (B0xxRaw::L, RELEASED, &[Input::Button(Button::Impure(ButtonImpure::L), RELEASED)]),
+        (B0xxRaw::R, RELEASED, &[Input::Button(Button::Impure(ButtonImpure::R), RELEASED), Input::Trigger(MS)]),
+        (B0xxRaw::MS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+    ]; "shield_both_digital")]
+    fn steps(steps: &[(B0xxRaw, Pressed, &[Input])]) {
+        let mut main = Main::default();
+        for &(btn, pressed, want) in steps {
+            assert_eq!(
+                main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false)[..],
+                *want,
+                "{:?} {}",
+                btn,
+                pressed
+            );
+        }
+    }
+
+    #[test]
+    fn self_test_sequences() {
+        for (name, sequence) in self_test::SEQUENCES {
+            let mut main = Main::default();
+            for &(btn, pressed, want) in sequence.iter() {
+                assert_eq!(
+                    main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false)[..],
+                    *want,
+                    "{} {:?} {}",
+                    name,
+                    btn,
+                    pressed
+                );
+            }
+            assert!(main.held.is_empty(), "{} leaves {:?} held", name, main.held);
+        }
+    }
+
+    #[test_case(&[], P7000, P7000; "a_stick")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::MY], P7000, P7000; "a_stick_both_mod")]
+    #[test_case(&[B0xxRaw::MX], P7375, P3125; "mod_x")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CD], P7000, P3625; "mod_x1")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CL], P7875, P4875; "mod_x2")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CU], P7000, P5125; "mod_x3")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CR], P6125, P5250; "mod_x4")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CR], P6375, P7625; "mod_y4")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CU], P5125, P7000; "mod_y3")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CL], P4875, P7875; "mod_y2")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CD], P3625, P7000; "mod_y1")]
+    #[test_case(&[B0xxRaw::MY], P3125, P7375; "mod_y")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::L], P6375, P3750; "mod_x_l")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::R], P6375, P3750; "mod_x_r")]
+    #[test_case(&[B0xxRaw::MT], P5375, P5375; "mod_tilt")]
+    fn analog(buttons: &[B0xxRaw], x_positive: Analog, y_positive: Analog) {
+        for ((x, y), invert) in DIAGONALS.into_iter().cartesian_product(INVERSIONS) {
+            let mut buttons = buttons
+                .iter()
+                .copied()
+                .chain([(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()])
+                .collect::<Vec<_>>();
+            let want = (
+                x_positive.neg_not(x != invert.x),
+                y_positive.neg_not(y != invert.y),
+            );
+            permutohedron::heap_recursive(&mut buttons, |buttons| {
+                let mut main = Main {
+                    invert: config::Inversion {
+                        a_stick: invert,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let got = press_all(&mut main, buttons, false);
+                assert_eq!(got[..], a_stick_change_on_press(buttons, want));
+            });
+        }
+    }
+
+    // Mirrors `analog` with the C-stick refinement disabled, where the
+    // C-stick leaves the modifier diagonals alone.
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CD], P7375, P3125; "mod_x1")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CL], P7375, P3125; "mod_x2")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CU], P7375, P3125; "mod_x3")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::CR], P7375, P3125; "mod_x4")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CR], P3125, P7375; "mod_y4")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CU], P3125, P7375; "mod_y3")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CL], P3125, P7375; "mod_y2")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::CD], P3125, P7375; "mod_y1")]
+    fn analog_without_c_stick_refinement(
+        buttons: &[B0xxRaw],
+        x_positive: Analog,
+        y_positive: Analog,
+    ) {
+        for ((x, y), invert) in DIAGONALS.into_iter().cartesian_product(INVERSIONS) {
+            let mut buttons = buttons
+                .iter()
+                .copied()
+                .chain([(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()])
+                .collect::<Vec<_>>();
+            let want = (
+                x_positive.neg_not(x != invert.x),
+                y_positive.neg_not(y != invert.y),
+            );
+            permutohedron::heap_recursive(&mut buttons, |buttons| {
+                let mut main = Main {
+                    invert: config::Inversion {
+                        a_stick: invert,
+                        ..Default::default()
+                    },
+                    stick_coupling: config::StickCoupling {
+                        c_stick_refines_a_stick: false,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                // Pressing the C-stick last changes nothing on the A-stick.
+                let _ = press_all(&mut main, buttons, false);
+                assert_eq!(main.output.a_stick, want, "{:?}", buttons);
+            });
+        }
+    }
+
+    // With the A-stick's angling of the C-stick disabled, the C-stick
+    // outputs its cardinals whatever the A-stick holds.
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::Down, B0xxRaw::CR], true, (P8125, -P2875); "angled_fsmash")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::Down, B0xxRaw::CR], false, (Analog::MAX, P0000); "fsmash")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::Left, B0xxRaw::CU], true, (-P2875, P8125); "angled_up_smash")]
+    #[test_case(&[B0xxRaw::MY, B0xxRaw::Left, B0xxRaw::CU], false, (P0000, Analog::MAX); "up_smash")]
+    fn a_stick_angles_c_stick(buttons: &[B0xxRaw], enabled: bool, want: CStickInput) {
+        let mut main = Main {
+            stick_coupling: config::StickCoupling {
+                a_stick_angles_c_stick: enabled,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let _ = press_all(&mut main, buttons, false);
+        assert_eq!(main.output.c_stick, want);
+    }
 
     #[test_case(false, &[B0xxRaw::MY, B0xxRaw::L], P4750, P8750, P5000, P8500; "mod_y_l")]
     #[test_case(false, &[B0xxRaw::MY, B0xxRaw::R], P4750, P8750, P5000, P8500; "mod_y_r")]
     #[test_case(true, &[], P7000, P7000, P7125, P6875; "crouch_walk_option_select")]
     fn analog_top_bottom(
         crouch_walk_option_select: bool,
-        buttons: &[B0xxRaw],
-        x_top: Analog,
-        y_top: Analog,
-        x_bottom: Analog,
-        y_bottom: Analog,
+        buttons: &[B0xxRaw],
+        x_top: Analog,
+        y_top: Analog,
+        x_bottom: Analog,
+        y_bottom: Analog,
+    ) {
+        for x in [POSITIVE, NEGATIVE] {
+            for y in [POSITIVE, NEGATIVE] {
+                let mut buttons = buttons
+                    .iter()
+                    .copied()
+                    .chain([(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()])
+                    .collect::<Vec<_>>();
+                let want = if y {
+                    (x_top.neg_not(x), y_top.neg_not(y))
+                } else {
+                    (x_bottom.neg_not(x), y_bottom.neg_not(y))
+                };
+                permutohedron::heap_recursive(&mut buttons, |buttons| {
+                    let mut main = Main::default();
+                    let got = press_all(&mut main, buttons, crouch_walk_option_select);
+                    assert_eq!(got[..], a_stick_change_on_press(buttons, want));
+                });
+            }
+        }
+    }
+
+    #[test_case(true, false, (P7125, P6875); "left")]
+    #[test_case(false, true, (P7125, P6875); "right")]
+    #[test_case(true, true, (P7375, P6625); "both_configured")]
+    fn crouch_walk_per_direction(left: bool, right: bool, diagonal: GCStickInput) {
+        for (x, y) in DIAGONALS {
+            let mut buttons = [(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()];
+            let (x_positive, y_positive) = if !y && if x { right } else { left } {
+                diagonal
+            } else {
+                (P7000, P7000)
+            };
+            let want = (x_positive.neg_not(x), y_positive.neg_not(y));
+            permutohedron::heap_recursive(&mut buttons, |buttons| {
+                let coordinates = config::Coordinates {
+                    crouch_walk_diagonal: diagonal,
+                    ..Default::default()
+                };
+                let mut main = Main {
+                    crouch_walk: config::CrouchWalk { left, right },
+                    a_stick_table: coordinates::AStickTable::new(&coordinates),
+                    coordinates,
+                    ..Default::default()
+                };
+                let got = press_all(&mut main, buttons, false);
+                assert_eq!(got[..], [Input::Stick(Stick::A, want)]);
+            });
+        }
+    }
+
+    #[test_case(config::CStickMode::Smash, P5250, P8500; "smash")]
+    #[test_case(config::CStickMode::Tilt, P3125, P5125; "tilt")]
+    fn c_stick_diagonals(c_stick_mode: config::CStickMode, x_positive: Analog, y_positive: Analog) {
+        for x in [POSITIVE, NEGATIVE] {
+            for y in [POSITIVE, NEGATIVE] {
+                let mut buttons = [(Stick::C, Axis::X, x).into(), (Stick::C, Axis::Y, y).into()];
+                let c_stick = (x_positive.neg_not(x), y_positive.neg_not(y));
+                permutohedron::heap_recursive(&mut buttons, |buttons| {
+                    let mut main = Main {
+                        c_stick_mode,
+                        ..Default::default()
+                    };
+                    let got = press_all(&mut main, buttons, false);
+                    assert_eq!(got[..], [Input::Stick(Stick::C, c_stick)]);
+                });
+            }
+        }
+    }
+
+    #[test_case(&[], Stick::A, Analog::MAX, Analog::MAX; "a_stick")]
+    #[test_case(&[B0xxRaw::MX], Stick::A, P6625, P5375; "a_stick_mod_x")]
+    #[test_case(&[B0xxRaw::MY], Stick::A, P3375, P7375; "a_stick_mod_y")]
+    #[test_case(&[B0xxRaw::MT], Stick::A, P6625, P5375; "a_stick_mod_tilt")]
+    #[test_case(&[], Stick::C, Analog::MAX, Analog::MAX; "c_stick")]
+    fn cardinals(buttons: &[B0xxRaw], stick: Stick, x_positive: Analog, y_positive: Analog) {
+        for ((axis, dir), invert) in CARDINALS.into_iter().cartesian_product(INVERSIONS) {
+            let mut buttons = buttons
+                .iter()
+                .copied()
+                .chain(std::iter::once((stick, axis, dir).into()))
+                .collect::<Vec<_>>();
+            let want = match axis {
+                Axis::X => (x_positive.neg_not(dir != invert.x), P0000),
+                Axis::Y => (P0000, y_positive.neg_not(dir != invert.y)),
+            };
+            permutohedron::heap_recursive(&mut buttons, |buttons| {
+                let mut main = Main {
+                    invert: config::Inversion {
+                        a_stick: invert,
+                        c_stick: invert,
+                    },
+                    ..Default::default()
+                };
+                let got = press_all(&mut main, buttons, false);
+                assert_eq!(got[..], [Input::Stick(stick, want)]);
+            });
+        }
+    }
+
+    #[test_case(config::DPadActivation::BothMods, &[B0xxRaw::MX, B0xxRaw::MY]; "both_mods")]
+    #[test_case(config::DPadActivation::Layer, &[B0xxRaw::DP]; "layer")]
+    #[test_case(config::DPadActivation::ModAndStart, &[B0xxRaw::MX, B0xxRaw::Start]; "mod_x_and_start")]
+    #[test_case(config::DPadActivation::ModAndStart, &[B0xxRaw::Start, B0xxRaw::MY]; "mod_y_and_start")]
+    fn dpad(dpad: config::DPadActivation, activation: &[B0xxRaw]) {
+        for ((axis, dir), invert) in CARDINALS.into_iter().cartesian_product(INVERSIONS) {
+            let mut main = Main {
+                invert: config::Inversion {
+                    a_stick: invert,
+                    c_stick: invert,
+                },
+                dpad,
+                ..Default::default()
+            };
+            for &btn in activation {
+                let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+            }
+            let got = main.process_b0xx(
+                B0xxEvent::new_without_time((Stick::C, axis, dir).into(), PRESSED),
+                false,
+            );
+            assert_eq!(got[..], [Input::Button(Button::DPad(axis, dir), PRESSED)]);
+        }
+    }
+
+    #[test_case(config::DPadActivation::Layer, &[B0xxRaw::MX, B0xxRaw::MY]; "layer")]
+    #[test_case(config::DPadActivation::ModAndStart, &[B0xxRaw::MX, B0xxRaw::MY]; "mod_and_start")]
+    #[test_case(config::DPadActivation::BothMods, &[B0xxRaw::MX, B0xxRaw::Start]; "both_mods")]
+    fn dpad_inactive(dpad: config::DPadActivation, buttons: &[B0xxRaw]) {
+        for (axis, dir) in CARDINALS {
+            let mut main = Main {
+                dpad,
+                ..Default::default()
+            };
+            for &btn in buttons {
+                let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+            }
+            let got = main.process_b0xx(
+                B0xxEvent::new_without_time((Stick::C, axis, dir).into(), PRESSED),
+                false,
+            );
+            assert!(matches!(got[..], [Input::Stick(Stick::C, _)]), "{:?}", got);
+        }
+    }
+
+    // When a C-stick button is acting as dpad, and the activation is released,
+    // diagonals should not be modified by it.
+    #[test_case(config::DPadActivation::BothMods, &[B0xxRaw::MX, B0xxRaw::MY], (P7375, P3125); "both_mods")]
+    #[test_case(config::DPadActivation::Layer, &[B0xxRaw::DP], (P7000, P7000); "layer")]
+    #[test_case(config::DPadActivation::Layer, &[B0xxRaw::MY, B0xxRaw::DP], (P3125, P7375); "layer_with_mod")]
+    #[test_case(config::DPadActivation::ModAndStart, &[B0xxRaw::MX, B0xxRaw::Start], (P7375, P3125); "start_released")]
+    #[test_case(config::DPadActivation::ModAndStart, &[B0xxRaw::Start, B0xxRaw::MX], (P7000, P7000); "mod_released")]
+    fn dpad_not_modify(
+        dpad: config::DPadActivation,
+        activation: &[B0xxRaw],
+        (x_positive, y_positive): GCStickInput,
+    ) {
+        for ((c_axis, c_dir), (x_dir, y_dir)) in CARDINALS.into_iter().cartesian_product(DIAGONALS)
+        {
+            let mut main = Main {
+                dpad,
+                ..Default::default()
+            };
+            for &btn in activation {
+                let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+            }
+            let _ = main.process_b0xx(
+                B0xxEvent::new_without_time((Stick::C, c_axis, c_dir).into(), PRESSED),
+                false,
+            );
+            let _ = main.process_b0xx(
+                B0xxEvent::new_without_time(*activation.last().unwrap(), RELEASED),
+                false,
+            );
+            let _ = main.process_b0xx(
+                B0xxEvent::new_without_time((Stick::A, Axis::X, x_dir).into(), PRESSED),
+                false,
+            );
+            let got = main.process_b0xx(
+                B0xxEvent::new_without_time((Stick::A, Axis::Y, y_dir).into(), PRESSED),
+                false,
+            );
+            let want = (x_positive.neg_not(x_dir), y_positive.neg_not(y_dir));
+            assert_eq!(got[..], [Input::Stick(Stick::A, want)]);
+        }
+    }
+
+    // D-pad up is held by its own key and by the C-stick in alt mode at once,
+    // and only released once neither holds it.
+    #[test_case(&[(B0xxRaw::DU, PRESSED, Some(PRESSED)), (B0xxRaw::DU, RELEASED, Some(RELEASED))]; "dedicated")]
+    #[test_case(&[(B0xxRaw::MX, PRESSED, None), (B0xxRaw::DU, PRESSED, Some(PRESSED)), (B0xxRaw::MY, PRESSED, None), (B0xxRaw::CU, PRESSED, None), (B0xxRaw::DU, RELEASED, None), (B0xxRaw::CU, RELEASED, Some(RELEASED))]; "dedicated_first_released_first")]
+    #[test_case(&[(B0xxRaw::MX, PRESSED, None), (B0xxRaw::DU, PRESSED, Some(PRESSED)), (B0xxRaw::MY, PRESSED, None), (B0xxRaw::CU, PRESSED, None), (B0xxRaw::CU, RELEASED, None), (B0xxRaw::DU, RELEASED, Some(RELEASED))]; "dedicated_first_released_last")]
+    #[test_case(&[(B0xxRaw::MX, PRESSED, None), (B0xxRaw::MY, PRESSED, None), (B0xxRaw::CU, PRESSED, Some(PRESSED)), (B0xxRaw::DU, PRESSED, None), (B0xxRaw::CU, RELEASED, None), (B0xxRaw::DU, RELEASED, Some(RELEASED))]; "latched_first_released_first")]
+    #[test_case(&[(B0xxRaw::MX, PRESSED, None), (B0xxRaw::MY, PRESSED, None), (B0xxRaw::CU, PRESSED, Some(PRESSED)), (B0xxRaw::DU, PRESSED, None), (B0xxRaw::DU, RELEASED, None), (B0xxRaw::CU, RELEASED, Some(RELEASED))]; "latched_first_released_last")]
+    #[test_case(&[(B0xxRaw::MX, PRESSED, None), (B0xxRaw::MY, PRESSED, None), (B0xxRaw::CU, PRESSED, Some(PRESSED)), (B0xxRaw::MX, RELEASED, None), (B0xxRaw::DU, PRESSED, None), (B0xxRaw::CU, RELEASED, None), (B0xxRaw::DU, RELEASED, Some(RELEASED))]; "mod_released_while_latched")]
+    fn dpad_dedicated(events: &[(B0xxRaw, Pressed, Option<Pressed>)]) {
+        let mut main = Main::default();
+        for &(btn, pressed, want) in events {
+            let got = main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false);
+            let want = want
+                .map(|pressed| Input::Button(Button::DPad(Axis::Y, POSITIVE), pressed))
+                .into_iter()
+                .collect::<Vec<_>>();
+            assert_eq!(got[..], want, "{:?} {}", btn, pressed);
+        }
+        assert!(main.dpad_held().is_empty());
+    }
+
+    #[test]
+    fn dpad_dedicated_raw() {
+        let mut main = Main {
+            raw: Some(Default::default()),
+            ..Default::default()
+        };
+        let got = press_all(&mut main, &[B0xxRaw::MX, B0xxRaw::MY, B0xxRaw::DL], false);
+        assert_eq!(
+            got[..],
+            [Input::Button(Button::DPad(Axis::X, NEGATIVE), PRESSED)]
+        );
+        assert_eq!(main.dpad_held(), [Button::DPad(Axis::X, NEGATIVE)]);
+    }
+
+    /// Feeds `events` through a remapper binding J and K to A, L to X, the lid
+    /// switch to light shield and the horizontal hat to C-left and C-right,
+    /// and returns the events it passes on, checking after each that it
+    /// agrees with `Main` on what is held.
+    #[cfg(feature = "linux-input")]
+    fn remap(events: &[(EventCode, i32)]) -> Vec<(B0xxRaw, Pressed)> {
+        remap_with(Default::default(), false, events)
+    }
+
+    /// As `remap`, with `autorepeat` handling of keys and `raw` as under the
+    /// raw engine.
+    #[cfg(feature = "linux-input")]
+    fn remap_with(
+        autorepeat: std::collections::HashMap<config::Source, config::Autorepeat>,
+        raw: bool,
+        events: &[(EventCode, i32)],
+    ) -> Vec<(B0xxRaw, Pressed)> {
+        let mut remapper = Remapper::new(
+            [
+                ("KEY_J", B0xxRaw::A),
+                ("KEY_K", B0xxRaw::A),
+                ("KEY_L", B0xxRaw::X),
+                ("SW_LID", B0xxRaw::LS),
+                ("ABS_HAT0X-", B0xxRaw::CL),
+                ("ABS_HAT0X+", B0xxRaw::CR),
+            ]
+            .into_iter()
+            .map(|(name, btn)| (config::source(name).unwrap(), btn))
+            .collect(),
+        );
+        remapper.autorepeat = autorepeat;
+        remapper.raw = raw;
+        let mut main = Main::default();
+        let mut remapped = Vec::new();
+        for &(code, value) in events {
+            let event = evdev_rs::InputEvent::new(&evdev_rs::TimeVal::new(0, 0), &code, value);
+            for e in remapper.evdev_to_b0xx(event) {
+                remapped.push((e.btn, e.pressed));
+                let _ = main.process_b0xx(e, false);
+            }
+            assert_eq!(
+                remapper
+                    .held()
+                    .into_iter()
+                    .collect::<std::collections::BTreeSet<_>>(),
+                main.held,
+                "after {:?} {}",
+                code,
+                value
+            );
+        }
+        remapped
+    }
+
+    // A button is held from the first of its keys pressed to the last
+    // released, and autorepeat changes nothing.
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_J, 2), (EV_KEY::KEY_J, 2), (EV_KEY::KEY_J, 0)], &[(B0xxRaw::A, PRESSED), (B0xxRaw::A, RELEASED)]; "autorepeat")]
+    #[test_case(&[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_K, 1), (EV_KEY::KEY_J, 0), (EV_KEY::KEY_K, 2), (EV_KEY::KEY_K, 0)], &[(B0xxRaw::A, PRESSED), (B0xxRaw::A, RELEASED)]; "alias_released_first")]
+    #[test_case(&[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_K, 1), (EV_KEY::KEY_K, 0), (EV_KEY::KEY_J, 0)], &[(B0xxRaw::A, PRESSED), (B0xxRaw::A, RELEASED)]; "alias_released_last")]
+    #[test_case(&[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_L, 1), (EV_KEY::KEY_J, 0)], &[(B0xxRaw::A, PRESSED), (B0xxRaw::X, PRESSED), (B0xxRaw::A, RELEASED)]; "distinct")]
+    #[test_case(&[(EV_KEY::KEY_Q, 1), (EV_KEY::KEY_Q, 0)], &[]; "unbound")]
+    #[test_case(&[(EV_KEY::KEY_J, 0)], &[]; "released_unheld")]
+    fn remapper_held(keys: &[(EV_KEY, i32)], want: &[(B0xxRaw, Pressed)]) {
+        let events = keys
+            .iter()
+            .map(|&(key, value)| (EventCode::EV_KEY(key), value))
+            .collect::<Vec<_>>();
+        assert_eq!(remap(&events), want);
+    }
+
+    #[cfg(feature = "linux-input")]
+    const J_REPEATED: &[(EV_KEY, i32)] =
+        &[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_J, 2), (EV_KEY::KEY_J, 0)];
+    #[cfg(feature = "linux-input")]
+    const A_TAPPED: &[(B0xxRaw, Pressed)] = &[(B0xxRaw::A, PRESSED), (B0xxRaw::A, RELEASED)];
+
+    // Autorepeat presses a button again only as its key's handling says, and
+    // not while another key holds it too.
+    #[cfg(feature = "linux-input")]
+    #[test_case(
+        config::Autorepeat::Drop,
+        false,
+        J_REPEATED,
+        A_TAPPED;
+        "drop"
+    )]
+    #[test_case(
+        config::Autorepeat::Press,
+        false,
+        &[(EV_KEY::KEY_J, 1), (EV_KEY::KEY_J, 2), (EV_KEY::KEY_J, 2), (EV_KEY::KEY_J, 0)],
+        &A_TAPPED.repeat(3);
+        "press"
+    )]
+    #[test_case(
+        config::Autorepeat::Press,
+        false,
+        &[
+            (EV_KEY::KEY_J, 1),
+            (EV_KEY::KEY_K, 1),
+            (EV_KEY::KEY_J, 2),
+            (EV_KEY::KEY_J, 0),
+            (EV_KEY::KEY_K, 0),
+        ],
+        A_TAPPED;
+        "press_aliased"
+    )]
+    #[test_case(
+        config::Autorepeat::Press,
+        false,
+        &[(EV_KEY::KEY_K, 1), (EV_KEY::KEY_K, 2), (EV_KEY::KEY_K, 0)],
+        A_TAPPED;
+        "other_key"
+    )]
+    #[test_case(
+        config::Autorepeat::Raw,
+        false,
+        J_REPEATED,
+        A_TAPPED;
+        "raw_b0xx_engine"
+    )]
+    #[test_case(
+        config::Autorepeat::Raw,
+        true,
+        J_REPEATED,
+        &A_TAPPED.repeat(2);
+        "raw_raw_engine"
+    )]
+    fn remapper_autorepeat(
+        autorepeat: config::Autorepeat,
+        raw: bool,
+        keys: &[(EV_KEY, i32)],
+        want: &[(B0xxRaw, Pressed)],
+    ) {
+        let events = keys
+            .iter()
+            .map(|&(key, value)| (EventCode::EV_KEY(key), value))
+            .collect::<Vec<_>>();
+        let autorepeat = [(
+            config::Source::Code(EventCode::EV_KEY(EV_KEY::KEY_J)),
+            autorepeat,
+        )];
+        assert_eq!(remap_with(autorepeat.into(), raw, &events), want);
+    }
+
+    // Switches are held while on, and each hat direction while the hat points
+    // that way.
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[(EventCode::EV_SW(EV_SW::SW_LID), 1), (EventCode::EV_SW(EV_SW::SW_LID), 0)], &[(B0xxRaw::LS, PRESSED), (B0xxRaw::LS, RELEASED)]; "switch")]
+    #[test_case(&[(EventCode::EV_SW(EV_SW::SW_DOCK), 1)], &[]; "unbound_switch")]
+    #[test_case(&[(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), -1), (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 0)], &[(B0xxRaw::CL, PRESSED), (B0xxRaw::CL, RELEASED)]; "hat_tap")]
+    #[test_case(&[(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 1), (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 1), (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 0)], &[(B0xxRaw::CR, PRESSED), (B0xxRaw::CR, RELEASED)]; "hat_repeated")]
+    #[test_case(&[(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), -1), (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 1), (EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 0)], &[(B0xxRaw::CL, PRESSED), (B0xxRaw::CL, RELEASED), (B0xxRaw::CR, PRESSED), (B0xxRaw::CR, RELEASED)]; "hat_reversed")]
+    #[test_case(&[(EventCode::EV_ABS(EV_ABS::ABS_HAT0Y), 1)], &[]; "unbound_hat")]
+    #[test_case(&[(EventCode::EV_ABS(EV_ABS::ABS_X), 100)], &[]; "other_axis")]
+    #[test_case(&[(EventCode::EV_SW(EV_SW::SW_LID), 1), (EventCode::EV_KEY(EV_KEY::KEY_J), 1), (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)], &[(B0xxRaw::LS, PRESSED), (B0xxRaw::A, PRESSED)]; "mixed")]
+    fn remapper_other_events(events: &[(EventCode, i32)], want: &[(B0xxRaw, Pressed)]) {
+        assert_eq!(remap(events), want);
+    }
+
+    // A focus key's press pauses or resumes instead of being remapped, even
+    // when bound, and its release and autorepeat do nothing.
+    #[cfg(feature = "linux-input")]
+    #[test_case(1, true; "press")]
+    #[test_case(2, false; "autorepeat")]
+    #[test_case(0, false; "release")]
+    fn remapper_focus(value: i32, want: bool) {
+        let mut remapper = Remapper {
+            focus: [EventCode::EV_KEY(EV_KEY::KEY_LEFTMETA)].into(),
+            ..Remapper::new(
+                [(config::source("KEY_LEFTMETA").unwrap(), B0xxRaw::A)]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+        let event = |code| {
+            evdev_rs::InputEvent::new(
+                &evdev_rs::TimeVal::new(0, 0),
+                &EventCode::EV_KEY(code),
+                value,
+            )
+        };
+        assert_eq!(remapper.focus_pressed(&event(EV_KEY::KEY_LEFTMETA)), want);
+        assert!(!remapper.focus_pressed(&event(EV_KEY::KEY_J)));
+        assert!(remapper
+            .evdev_to_b0xx(event(EV_KEY::KEY_LEFTMETA))
+            .is_empty());
+        assert!(remapper.held().is_empty());
+    }
+
+    // Resyncing presses and releases only what the keys down change, and
+    // leaves switches alone.
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[], &[EV_KEY::KEY_J, EV_KEY::KEY_L], &[(B0xxRaw::A, PRESSED), (B0xxRaw::X, PRESSED)]; "pressed")]
+    #[test_case(&[EventCode::EV_KEY(EV_KEY::KEY_J), EventCode::EV_KEY(EV_KEY::KEY_L)], &[], &[(B0xxRaw::A, RELEASED), (B0xxRaw::X, RELEASED)]; "released")]
+    #[test_case(&[EventCode::EV_KEY(EV_KEY::KEY_J)], &[EV_KEY::KEY_K, EV_KEY::KEY_L], &[(B0xxRaw::X, PRESSED)]; "alias")]
+    #[test_case(&[EventCode::EV_KEY(EV_KEY::KEY_J), EventCode::EV_SW(EV_SW::SW_LID)], &[EV_KEY::KEY_J, EV_KEY::KEY_Q], &[]; "unchanged")]
+    fn remapper_resync(pressed: &[EventCode], down: &[EV_KEY], want: &[(B0xxRaw, Pressed)]) {
+        let mut remapper = Remapper::new(
+            [
+                ("KEY_J", B0xxRaw::A),
+                ("KEY_K", B0xxRaw::A),
+                ("KEY_L", B0xxRaw::X),
+                ("SW_LID", B0xxRaw::LS),
+            ]
+            .into_iter()
+            .map(|(name, btn)| (config::source(name).unwrap(), btn))
+            .collect(),
+        );
+        for code in pressed {
+            let event = evdev_rs::InputEvent::new(&evdev_rs::TimeVal::new(0, 0), code, 1);
+            let _ = remapper.evdev_to_b0xx(event);
+        }
+        let resynced = remapper
+            .resync(Timestamp::default(), down)
+            .into_iter()
+            .map(|e| (e.btn, e.pressed))
+            .collect::<Vec<_>>();
+        assert_eq!(resynced, want);
+        assert!(remapper.resync(Timestamp::default(), down).is_empty());
+    }
+
+    // However two keys bound to the same button interleave, the button is
+    // pressed once and released once, by the last key released.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remapper_aliases_interleaved() {
+        let mut events = [
+            (EV_KEY::KEY_J, 1),
+            (EV_KEY::KEY_J, 0),
+            (EV_KEY::KEY_K, 1),
+            (EV_KEY::KEY_K, 0),
+        ];
+        permutohedron::heap_recursive(&mut events, |events| {
+            let position = |event| events.iter().position(|&e| e == event).unwrap();
+            for key in [EV_KEY::KEY_J, EV_KEY::KEY_K] {
+                if position((key, 0)) < position((key, 1)) {
+                    return;
+                }
+            }
+            let remapped = remap(
+                &events
+                    .iter()
+                    .map(|&(key, value)| (EventCode::EV_KEY(key), value))
+                    .collect::<Vec<_>>(),
+            );
+            let overlapping = position((EV_KEY::KEY_J, 1)).max(position((EV_KEY::KEY_K, 1)))
+                < position((EV_KEY::KEY_J, 0)).min(position((EV_KEY::KEY_K, 0)));
+            let want: &[_] = if overlapping {
+                &[(B0xxRaw::A, PRESSED), (B0xxRaw::A, RELEASED)]
+            } else {
+                &[
+                    (B0xxRaw::A, PRESSED),
+                    (B0xxRaw::A, RELEASED),
+                    (B0xxRaw::A, PRESSED),
+                    (B0xxRaw::A, RELEASED),
+                ]
+            };
+            assert_eq!(remapped, want, "{:?}", events);
+        });
+    }
+
+    // Buttons that pass straight through are replayed to a reopened pipe too.
+    #[test]
+    fn pure_button_resync() {
+        let buttons = [
+            B0xxRaw::A,
+            B0xxRaw::X,
+            B0xxRaw::Y,
+            B0xxRaw::Z,
+            B0xxRaw::Start,
+        ];
+        let mut main = Main::default();
+        let _ = press_all(&mut main, &buttons, false);
+        let mut reconnected = ControllerSnapshot::default();
+        for pipe_input in main.resync() {
+            reconnected.apply(pipe_input);
+        }
+        assert_eq!(reconnected, main.output);
+        assert_eq!(
+            reconnected.buttons,
+            [
+                GCButton::A,
+                GCButton::X,
+                GCButton::Y,
+                GCButton::Z,
+                GCButton::Start
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    // Digital L/R that only a shield behavior holds is replayed to a reopened
+    // pipe too.
+    #[test]
+    fn digital_shield_resync() {
+        let mut main = Main {
+            shield_behavior: config::ShieldBehaviors::try_from(std::collections::BTreeMap::from([
+                (B0xxRaw::LS, config::ShieldBehavior::Digital(B0xxRaw::R)),
+            ]))
+            .unwrap(),
+            ..Default::default()
+        };
+        let _ = press_all(&mut main, &[B0xxRaw::LS], false);
+        let mut reconnected = ControllerSnapshot::default();
+        for pipe_input in main.resync() {
+            reconnected.apply(pipe_input);
+        }
+        assert_eq!(reconnected, main.output);
+        assert_eq!(reconnected.buttons, [GCButton::R].into_iter().collect());
+    }
+
+    // A latched D-pad press outlives the modifiers that made it, and is
+    // replayed to a pipe that was reopened while it was held.
+    #[test_case(&[(B0xxRaw::CU, PRESSED)], &[GCButton::DUp]; "held")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::MX, RELEASED)], &[GCButton::DUp]; "mod_released")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::CD, PRESSED)], &[GCButton::DUp, GCButton::DDown]; "both_directions")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::CL, PRESSED)], &[GCButton::DLeft, GCButton::DUp]; "both_axes")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::DU, PRESSED), (B0xxRaw::DD, PRESSED)], &[GCButton::DUp, GCButton::DDown]; "dedicated")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::CU, RELEASED)], &[]; "released")]
+    #[test_case(&[(B0xxRaw::CU, PRESSED), (B0xxRaw::MX, RELEASED), (B0xxRaw::MY, RELEASED), (B0xxRaw::CU, RELEASED)], &[]; "released_after_mods")]
+    fn dpad_resync(events: &[(B0xxRaw, Pressed)], want: &[GCButton]) {
+        let mut main = Main::default();
+        for (btn, pressed) in [
+            (B0xxRaw::A, PRESSED),
+            (B0xxRaw::Right, PRESSED),
+            (B0xxRaw::MX, PRESSED),
+            (B0xxRaw::MY, PRESSED),
+        ]
+        .into_iter()
+        .chain(events.iter().copied())
+        {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false);
+        }
+        let dpad = main
+            .dpad_held()
+            .into_iter()
+            .map(GCButton::from)
+            .collect::<Vec<_>>();
+        assert_eq!(dpad, want);
+        let snapshot = main.snapshot();
+        let dpad = format!("\"dpad\":{}", serde_json::to_string(want).unwrap());
+        assert!(snapshot.contains(&dpad), "{}", snapshot);
+
+        // Whatever the pipe was left with, the burst restores the output.
+        let mut reconnected = ControllerSnapshot {
+            buttons: GCButton::ALL.into_iter().collect(),
+            ..Default::default()
+        };
+        let burst = main.resync();
+        for &pipe_input in &burst {
+            reconnected.apply(pipe_input);
+        }
+        assert_eq!(reconnected, main.output);
+        let commands = burst
+            .into_iter()
+            .map(|pipe_input| {
+                pipe_input.into_input_string(
+                    config::Handedness::Standard,
+                    Game::Melee,
+                    config::PipeCapability::Standard,
+                )
+            })
+            .collect::<Vec<_>>();
+        for button in [GCButton::DUp, GCButton::DDown, GCButton::DLeft] {
+            let command = DolphinPipeInput::Button(button, want.contains(&button))
+                .into_input_string(
+                    config::Handedness::Standard,
+                    Game::Melee,
+                    config::PipeCapability::Standard,
+                );
+            assert!(
+                commands.contains(&command),
+                "{:?} not in {:?}",
+                command,
+                commands
+            );
+        }
+    }
+
+    // The Mod X pivot-tilt C-stick coordinates apply regardless of C-stick
+    // mode, but an unmodified C-stick cardinal follows the mode.
+    #[test_case(config::CStickMode::Smash, Analog::MAX; "smash")]
+    #[test_case(config::CStickMode::Tilt, P6000; "tilt")]
+    fn tilt_fsmash(c_stick_mode: config::CStickMode, cardinal: Analog) {
+        for x_dir in [POSITIVE, NEGATIVE] {
+            for y_dir in [POSITIVE, NEGATIVE] {
+                let mut main = Main {
+                    c_stick_mode,
+                    ..Default::default()
+                };
+                let got =
+                    main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MX, PRESSED), false);
+                assert!(got.is_empty(), "{:?}", got);
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::A, Axis::Y, y_dir).into(), PRESSED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [Input::Stick(Stick::A, (P0000, P5375.neg_not(y_dir)))]
+                );
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::C, Axis::X, x_dir).into(), PRESSED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [Input::Stick(
+                        Stick::C,
+                        (P8125.neg_not(x_dir), P2875.neg_not(y_dir))
+                    )]
+                );
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::C, Axis::X, x_dir).into(), RELEASED),
+                    false,
+                );
+                assert_eq!(got[..], [Input::Stick(Stick::C, (P0000, P0000))]);
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::A, Axis::Y, y_dir).into(), RELEASED),
+                    false,
+                );
+                assert_eq!(got[..], [Input::Stick(Stick::A, (P0000, P0000))]);
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::C, Axis::X, x_dir).into(), PRESSED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [Input::Stick(Stick::C, (cardinal.neg_not(x_dir), P0000))]
+                );
+            }
+        }
+    }
+
+    // Likewise for C-stick verticals, angled toward the A-stick X direction
+    // with either mod alone.
+    #[test_case(B0xxRaw::MX, P6625, (P4375, P7875), config::CStickMode::Smash, Analog::MAX; "mod_x")]
+    #[test_case(B0xxRaw::MY, P3375, (P2875, P8125), config::CStickMode::Smash, Analog::MAX; "mod_y")]
+    #[test_case(B0xxRaw::MX, P6625, (P4375, P7875), config::CStickMode::Tilt, P6000; "mod_x_tilt_mode")]
+    fn tilt_vertical_smash(
+        modifier: B0xxRaw,
+        a_stick_x: Analog,
+        (angled_x, angled_y): GCStickInput,
+        c_stick_mode: config::CStickMode,
+        cardinal: Analog,
+    ) {
+        for x_dir in [POSITIVE, NEGATIVE] {
+            for y_dir in [POSITIVE, NEGATIVE] {
+                let mut main = Main {
+                    c_stick_mode,
+                    ..Default::default()
+                };
+                let got = main.process_b0xx(B0xxEvent::new_without_time(modifier, PRESSED), false);
+                assert!(got.is_empty(), "{:?}", got);
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::A, Axis::X, x_dir).into(), PRESSED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [Input::Stick(Stick::A, (a_stick_x.neg_not(x_dir), P0000))]
+                );
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::C, Axis::Y, y_dir).into(), PRESSED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [Input::Stick(
+                        Stick::C,
+                        (angled_x.neg_not(x_dir), angled_y.neg_not(y_dir))
+                    )]
+                );
+                // Releasing the A-stick straightens the C-stick.
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::A, Axis::X, x_dir).into(), RELEASED),
+                    false,
+                );
+                assert_eq!(
+                    got[..],
+                    [
+                        Input::Stick(Stick::C, (P0000, cardinal.neg_not(y_dir))),
+                        Input::Stick(Stick::A, (P0000, P0000)),
+                    ]
+                );
+                let got = main.process_b0xx(
+                    B0xxEvent::new_without_time((Stick::C, Axis::Y, y_dir).into(), RELEASED),
+                    false,
+                );
+                assert_eq!(got[..], [Input::Stick(Stick::C, (P0000, P0000))]);
+            }
+        }
+    }
+
+    #[test]
+    fn vertical_smash_needs_one_mod() {
+        let mut main = Main::default();
+        for btn in [B0xxRaw::Right, B0xxRaw::CU] {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+        }
+        assert_eq!(main.output.c_stick, (P0000, Analog::MAX));
+        let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MY, PRESSED), false);
+        assert_eq!(main.output.c_stick, (P2875, P8125));
+        // Both mods select the D-pad layer, but the C-up already held stays on
+        // the C-stick, no longer angled.
+        let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MX, PRESSED), false);
+        assert_eq!(main.output.c_stick, (P0000, Analog::MAX));
+        assert_eq!(main.dpad_held(), []);
+    }
+
+    // Mod X/Y take precedence over the tilt modifier, which applies again once
+    // the mod is released.
+    #[test_case(B0xxRaw::MX, &[B0xxRaw::Right], (P6625, P0000), (P6625, P0000); "mod_x_right")]
+    #[test_case(B0xxRaw::MX, &[B0xxRaw::Up], (P0000, P5375), (P0000, P5375); "mod_x_up")]
+    #[test_case(B0xxRaw::MX, &[B0xxRaw::Right, B0xxRaw::Up], (P7375, P3125), (P5375, P5375); "mod_x_diagonal")]
+    #[test_case(B0xxRaw::MY, &[B0xxRaw::Right], (P3375, P0000), (P6625, P0000); "mod_y_right")]
+    #[test_case(B0xxRaw::MY, &[B0xxRaw::Up], (P0000, P7375), (P0000, P5375); "mod_y_up")]
+    #[test_case(B0xxRaw::MY, &[B0xxRaw::Right, B0xxRaw::Up], (P3125, P7375), (P5375, P5375); "mod_y_diagonal")]
+    fn mod_tilt_precedence(
+        modifier: B0xxRaw,
+        directions: &[B0xxRaw],
+        modified: AStickInput,
+        tilt: AStickInput,
+    ) {
+        let mut buttons = [modifier, B0xxRaw::MT]
+            .into_iter()
+            .chain(directions.iter().copied())
+            .collect::<Vec<_>>();
+        permutohedron::heap_recursive(&mut buttons, |buttons| {
+            let mut main = Main::default();
+            for &btn in buttons.iter() {
+                let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+            }
+            assert_eq!(main.output.a_stick, modified);
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(modifier, RELEASED), false);
+            assert_eq!(main.output.a_stick, tilt);
+        });
+    }
+
+    #[test]
+    fn accidental_side_b() {
+        for dir in [POSITIVE, NEGATIVE] {
+            let left_right = (Stick::A, Axis::X, dir).into();
+            let mut buttons = vec![B0xxRaw::MY, B0xxRaw::B, left_right];
+            permutohedron::heap_recursive(&mut buttons, |buttons| {
+                let mut main = Main::default();
+                let got = press_all(&mut main, buttons, false);
+                assert_eq!(
+                    got[..],
+                    a_stick_change_on_press(buttons, (P6625.neg_not(dir), P0000))
+                );
+            });
+        }
+    }
+
+    // Pressing the opposite direction within the window after B flicks the
+    // stick for the configured duration and then returns the X axis to neutral.
+    #[test_case(Duration::from_millis(50), true; "inside_window")]
+    #[test_case(Duration::from_millis(51), false; "outside_window")]
+    fn b_reverse(opposite_after_b: Duration, flick: bool) {
+        let b_time = Timestamp::default() + Duration::from_secs(10);
+        let opposite_time = b_time + opposite_after_b;
+        for dir in [POSITIVE, NEGATIVE] {
+            let mut main = Main {
+                b_reverse_helper: Some(config::BReverseHelper {
+                    window_ms: 50,
+                    flick_ms: 25,
+                }),
+                ..Default::default()
+            };
+            let steps = [
+                (
+                    (Stick::A, Axis::X, dir).into(),
+                    PRESSED,
+                    Timestamp::default(),
+                ),
+                (B0xxRaw::B, PRESSED, b_time),
+                ((Stick::A, Axis::X, !dir).into(), PRESSED, opposite_time),
+            ];
+            let got = steps
+                .into_iter()
+                .fold(Inputs::new(), |_, (btn, pressed, time)| {
+                    main.process_b0xx(B0xxEvent::new(btn, pressed, time), false)
+                });
+            assert_eq!(
+                got[..],
+                [Input::Stick(Stick::A, (Analog::MAX.neg_not(!dir), P0000))]
+            );
+            if !flick {
+                assert_eq!(main.deadline(), None);
+                continue;
+            }
+            let flick_end = opposite_time + Duration::from_millis(25);
+            assert_eq!(main.deadline(), Some(flick_end));
+            assert!(main
+                .process_deadline(opposite_time + Duration::from_millis(24), false)
+                .is_empty());
+            assert_eq!(
+                main.process_deadline(flick_end, false)[..],
+                [Input::Stick(Stick::A, (P0000, P0000))]
+            );
+            assert_eq!(main.deadline(), None);
+            // The X axis resumes once it transitions.
+            assert_eq!(
+                main.process_b0xx(
+                    B0xxEvent::new((Stick::A, Axis::X, dir).into(), RELEASED, flick_end),
+                    false
+                )[..],
+                [Input::Stick(Stick::A, (Analog::MAX.neg_not(!dir), P0000))]
+            );
+        }
+    }
+
+    /// Processes `events` through `main`, each at its offset in milliseconds,
+    /// and every deadline falling due before each, then those due by `until`
+    /// ms, e.g. `u64::MAX` for all of them. Returns the inputs at their
+    /// offsets. Nothing may fire before its time, and the input state must
+    /// stay consistent throughout.
+    fn play(main: &mut Main, events: &[(u64, B0xxRaw, Pressed)], until: u64) -> Vec<(u64, Input)> {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        let mut got = Vec::new();
+        let process_deadlines = |main: &mut Main, got: &mut Vec<_>, time: Timestamp| {
+            while let Some(deadline) = main.deadline().filter(|&deadline| deadline <= time) {
+                assert!(main
+                    .process_deadline(Timestamp(deadline.0 - Duration::from_nanos(1)), false)
+                    .is_empty());
+                let inputs = main.process_deadline(deadline, false);
+                got.extend(
+                    inputs
+                        .into_iter()
+                        .map(|input| ((deadline - start).as_millis() as u64, input)),
+                );
+                assert_eq!(main.inconsistencies(), Vec::<String>::new());
+            }
+        };
+        for &(ms, btn, pressed) in events {
+            let time = start + Duration::from_millis(ms);
+            process_deadlines(main, &mut got, time);
+            let inputs = main.process_b0xx(B0xxEvent::new(btn, pressed, time), false);
+            got.extend(inputs.into_iter().map(|input| (ms, input)));
+            assert_eq!(main.inconsistencies(), Vec::<String>::new());
+        }
+        process_deadlines(main, &mut got, start + Duration::from_millis(until));
+        got
+    }
+
+    // The turbo button toggles at exact times counted from the key press, and
+    // the key's release always leaves it released.
+    #[test_case(Duration::from_millis(95), &[(0, PRESSED), (10, RELEASED), (40, PRESSED), (50, RELEASED), (80, PRESSED), (90, RELEASED)]; "released_between_presses")]
+    #[test_case(Duration::from_millis(85), &[(0, PRESSED), (10, RELEASED), (40, PRESSED), (50, RELEASED), (80, PRESSED), (85, RELEASED)]; "released_while_pressed")]
+    #[test_case(Duration::from_millis(5), &[(0, PRESSED), (5, RELEASED)]; "tapped")]
+    fn turbo(key_held: Duration, want: &[(u64, Pressed)]) {
+        let mut main = Main {
+            turbo: Some(config::Turbo {
+                button: B0xxRaw::A,
+                rate_hz: 25.,
+                duty: 0.25,
+            }),
+            ..Default::default()
+        };
+        let held = key_held.as_millis() as u64;
+        let events = [(0, B0xxRaw::TB, PRESSED), (held, B0xxRaw::TB, RELEASED)];
+        let got = play(&mut main, &events, 0)
+            .into_iter()
+            .map(|(ms, input)| match input {
+                Input::Button(Button::Pure(ButtonPure::A), pressed) => (ms, pressed),
+                _ => panic!("{:?}", input),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(got, want);
+        assert_eq!(main.deadline(), None);
+        assert!(!main.output.buttons.contains(&GCButton::A));
+    }
+
+    #[test]
+    fn turbo_disabled() {
+        let mut main = Main::default();
+        assert!(press_all(&mut main, &[B0xxRaw::TB], false).is_empty());
+        assert_eq!(main.deadline(), None);
+    }
+
+    // The short-hop key presses X for a fixed time however long it is held,
+    // unless X's own key takes over.
+    #[test_case(&[(0, B0xxRaw::SH, PRESSED), (100, B0xxRaw::SH, RELEASED)], &[(0, PRESSED), (25, RELEASED)]; "held")]
+    #[test_case(&[(0, B0xxRaw::SH, PRESSED), (5, B0xxRaw::SH, RELEASED)], &[(0, PRESSED), (25, RELEASED)]; "tapped")]
+    #[test_case(&[(0, B0xxRaw::SH, PRESSED), (10, B0xxRaw::X, PRESSED), (20, B0xxRaw::SH, RELEASED), (60, B0xxRaw::X, RELEASED)], &[(0, PRESSED), (60, RELEASED)]; "cancelled")]
+    #[test_case(&[(0, B0xxRaw::SH, PRESSED), (5, B0xxRaw::SH, RELEASED), (30, B0xxRaw::SH, PRESSED), (35, B0xxRaw::SH, RELEASED)], &[(0, PRESSED), (25, RELEASED), (30, PRESSED), (55, RELEASED)]; "back_to_back")]
+    #[test_case(&[(0, B0xxRaw::SH, PRESSED), (5, B0xxRaw::SH, RELEASED), (10, B0xxRaw::SH, PRESSED), (15, B0xxRaw::SH, RELEASED)], &[(0, PRESSED), (25, RELEASED)]; "pressed_again")]
+    #[test_case(&[(0, B0xxRaw::X, PRESSED), (10, B0xxRaw::SH, PRESSED), (15, B0xxRaw::SH, RELEASED), (50, B0xxRaw::X, RELEASED)], &[(0, PRESSED), (50, RELEASED)]; "x_held")]
+    fn short_hop(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Pressed)]) {
+        let mut main = Main {
+            short_hop_helper: Some(config::ShortHop { release_ms: 25 }),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX)
+            .into_iter()
+            .map(|(ms, input)| match input {
+                Input::Button(Button::Pure(ButtonPure::X), pressed) => (ms, pressed),
+                _ => panic!("{:?}", input),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(got, want);
+        // Through the frame quantizer, X is written once per change, with no
+        // tap inserted in between.
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let mut quantizer = FrameQuantizer::new(Duration::from_millis(1), t(0));
+        let mut written = Vec::new();
+        for &(ms, pressed) in &got {
+            written.extend(quantizer.tick(t(ms)));
+            quantizer.apply(Input::Button(Button::Pure(ButtonPure::X), pressed));
+        }
+        while let Some(deadline) = quantizer.deadline() {
+            written.extend(quantizer.tick(deadline));
+        }
+        assert_eq!(
+            written,
+            want.iter()
+                .map(|&(_, pressed)| DolphinPipeInput::Button(GCButton::X, pressed))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    const L_PRESSED: Input = Input::Button(Button::Impure(ButtonImpure::L), PRESSED);
+    const L_RELEASED: Input = Input::Button(Button::Impure(ButtonImpure::L), RELEASED);
+    const R_PRESSED: Input = Input::Button(Button::Impure(ButtonImpure::R), PRESSED);
+    const R_RELEASED: Input = Input::Button(Button::Impure(ButtonImpure::R), RELEASED);
+
+    // Negative-edge buttons are pressed for a pulse on release, unless held
+    // too long, while other buttons act on press as usual.
+    #[test_case(&[(0, B0xxRaw::LS, PRESSED), (100, B0xxRaw::LS, RELEASED)], &[(100, Input::Trigger(LS)), (117, Input::Trigger(Trigger::Z))]; "under")]
+    #[test_case(&[(0, B0xxRaw::LS, PRESSED), (200, B0xxRaw::LS, RELEASED)], &[(200, Input::Trigger(LS)), (217, Input::Trigger(Trigger::Z))]; "at")]
+    #[test_case(&[(0, B0xxRaw::LS, PRESSED), (201, B0xxRaw::LS, RELEASED)], &[]; "over")]
+    #[test_case(
+        &[(0, B0xxRaw::R, PRESSED), (50, B0xxRaw::R, RELEASED)],
+        &[(50, R_PRESSED), (67, R_RELEASED)];
+        "digital"
+    )]
+    #[test_case(&[(0, B0xxRaw::LS, PRESSED), (10, B0xxRaw::LS, RELEASED), (15, B0xxRaw::LS, PRESSED), (20, B0xxRaw::LS, RELEASED)], &[(10, Input::Trigger(LS)), (37, Input::Trigger(Trigger::Z))]; "pulse_extended")]
+    #[test_case(&[(0, B0xxRaw::MS, PRESSED), (10, B0xxRaw::LS, PRESSED), (20, B0xxRaw::LS, RELEASED), (50, B0xxRaw::MS, RELEASED)], &[(0, Input::Trigger(MS)), (20, Input::Trigger(LS)), (37, Input::Trigger(Trigger::Z))]; "over_positive_edge")]
+    fn negative_edge(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Input)]) {
+        let mut main = Main {
+            negative_edge: Some(NegativeEdge::new(config::NegativeEdge {
+                buttons: vec![B0xxRaw::LS, B0xxRaw::R],
+                max_hold_ms: 200,
+                pulse_ms: 17,
+            })),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX);
+        assert_eq!(got, want);
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    // A light-press button sets the light value a tick ahead of its press and
+    // drops it after its release, and a release within the tick leaves nothing
+    // behind. Under an analog shield the press is immediate as usual.
+    #[test_case(
+        &[(0, B0xxRaw::L, PRESSED), (100, B0xxRaw::L, RELEASED)],
+        &[
+            (0, Input::Trigger(LS)),
+            (17, L_PRESSED),
+            (100, L_RELEASED),
+            (100, Input::Trigger(Trigger::Z)),
+        ];
+        "sequence"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::L, PRESSED), (5, B0xxRaw::L, RELEASED)],
+        &[(0, Input::Trigger(LS)), (5, Input::Trigger(Trigger::Z))];
+        "tap_release"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::L, PRESSED),
+            (5, B0xxRaw::L, RELEASED),
+            (10, B0xxRaw::L, PRESSED),
+            (40, B0xxRaw::L, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(LS)),
+            (5, Input::Trigger(Trigger::Z)),
+            (10, Input::Trigger(LS)),
+            (27, L_PRESSED),
+            (40, L_RELEASED),
+            (40, Input::Trigger(Trigger::Z)),
+        ];
+        "rapid_taps"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::L, PRESSED),
+            (30, B0xxRaw::R, PRESSED),
+            (60, B0xxRaw::L, RELEASED),
+            (70, B0xxRaw::R, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(LS)),
+            (17, L_PRESSED),
+            (30, R_PRESSED),
+            (60, L_RELEASED),
+            (70, R_RELEASED),
+            (70, Input::Trigger(Trigger::Z)),
+        ];
+        "other_released_last"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::L, PRESSED),
+            (5, B0xxRaw::R, PRESSED),
+            (10, B0xxRaw::L, RELEASED),
+            (50, B0xxRaw::R, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(LS)),
+            (5, R_PRESSED),
+            (50, R_RELEASED),
+            (50, Input::Trigger(Trigger::Z)),
+        ];
+        "tap_release_under_other"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::MS, PRESSED),
+            (10, B0xxRaw::L, PRESSED),
+            (50, B0xxRaw::L, RELEASED),
+            (60, B0xxRaw::MS, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(MS)),
+            (10, Input::Trigger(Trigger::Z)),
+            (10, L_PRESSED),
+            (50, L_RELEASED),
+            (50, Input::Trigger(MS)),
+            (60, Input::Trigger(Trigger::Z)),
+        ];
+        "under_shield"
+    )]
+    fn light_press(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Input)]) {
+        let mut main = Main {
+            light_press: Some(LightPress::new(config::LightPress {
+                buttons: vec![B0xxRaw::L],
+                value: LS,
+                delay_ms: 17,
+            })),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX);
+        assert_eq!(got, want);
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    // A double-tap button presses digital R once held past the window, or
+    // taps it then if released sooner, and holds the analog value instead
+    // when pressed again within the window, never both. The window starts at
+    // the first press, so a press on its end, or a third, starts over.
+    #[test_case(
+        &[(0, B0xxRaw::R, PRESSED), (300, B0xxRaw::R, RELEASED)],
+        &[(150, R_PRESSED), (300, R_RELEASED)];
+        "held"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::R, PRESSED), (50, B0xxRaw::R, RELEASED)],
+        &[(150, R_PRESSED), (150, R_RELEASED)];
+        "tapped"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::R, PRESSED), (150, B0xxRaw::R, RELEASED)],
+        &[(150, R_PRESSED), (150, R_RELEASED)];
+        "released_on_window_end"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::R, PRESSED),
+            (40, B0xxRaw::R, RELEASED),
+            (80, B0xxRaw::R, PRESSED),
+            (300, B0xxRaw::R, RELEASED),
+        ],
+        &[(80, Input::Trigger(Trigger::P70)), (300, Input::Trigger(Trigger::Z))];
+        "double_tap"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::R, PRESSED),
+            (40, B0xxRaw::R, RELEASED),
+            (149, B0xxRaw::R, PRESSED),
+            (200, B0xxRaw::R, RELEASED),
+        ],
+        &[(149, Input::Trigger(Trigger::P70)), (200, Input::Trigger(Trigger::Z))];
+        "second_press_before_window_end"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::R, PRESSED),
+            (40, B0xxRaw::R, RELEASED),
+            (150, B0xxRaw::R, PRESSED),
+            (400, B0xxRaw::R, RELEASED),
+        ],
+        &[(150, R_PRESSED), (150, R_RELEASED), (300, R_PRESSED), (400, R_RELEASED)];
+        "second_press_on_window_end"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::R, PRESSED),
+            (30, B0xxRaw::R, RELEASED),
+            (60, B0xxRaw::R, PRESSED),
+            (90, B0xxRaw::R, RELEASED),
+            (120, B0xxRaw::R, PRESSED),
+            (140, B0xxRaw::R, RELEASED),
+        ],
+        &[
+            (60, Input::Trigger(Trigger::P70)),
+            (90, Input::Trigger(Trigger::Z)),
+            (270, R_PRESSED),
+            (270, R_RELEASED),
+        ];
+        "triple_tap"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::R, PRESSED),
+            (30, B0xxRaw::R, RELEASED),
+            (60, B0xxRaw::R, PRESSED),
+            (90, B0xxRaw::R, RELEASED),
+            (120, B0xxRaw::R, PRESSED),
+            (400, B0xxRaw::R, RELEASED),
+        ],
+        &[
+            (60, Input::Trigger(Trigger::P70)),
+            (90, Input::Trigger(Trigger::Z)),
+            (270, R_PRESSED),
+            (400, R_RELEASED),
+        ];
+        "triple_tap_held"
+    )]
+    fn double_tap(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Input)]) {
+        let mut main = Main {
+            double_tap: Some(DoubleTap::new(config::DoubleTap {
+                buttons: vec![B0xxRaw::R],
+                value: Trigger::P70,
+                window_ms: 150,
+            })),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX);
+        assert_eq!(got, want);
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    // Each shield behavior holds the analog value or digital L/R alone or
+    // both, and composes with the others: analog values as shield tiers, and
+    // digital L/R held by several buttons until the last is released. Only
+    // the standard behavior drops the analog trigger.
+    #[test_case(
+        &[(0, B0xxRaw::L, PRESSED), (100, B0xxRaw::L, RELEASED)],
+        &[(0, Input::Trigger(MS)), (100, Input::Trigger(Trigger::Z))];
+        "analog"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::LS, PRESSED), (100, B0xxRaw::LS, RELEASED)],
+        &[(0, R_PRESSED), (100, R_RELEASED)];
+        "digital"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::L, PRESSED),
+            (10, B0xxRaw::LS, PRESSED),
+            (20, B0xxRaw::LS, RELEASED),
+            (30, B0xxRaw::L, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(MS)),
+            (10, R_PRESSED),
+            (20, R_RELEASED),
+            (30, Input::Trigger(Trigger::Z)),
+        ];
+        "digital_under_analog"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::MS, PRESSED), (100, B0xxRaw::MS, RELEASED)],
+        &[
+            (0, Input::Trigger(Trigger::P110)),
+            (50, L_PRESSED),
+            (100, L_RELEASED),
+            (100, Input::Trigger(Trigger::Z)),
+        ];
+        "analog_then_digital"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::MS, PRESSED), (20, B0xxRaw::MS, RELEASED)],
+        &[(0, Input::Trigger(Trigger::P110)), (20, Input::Trigger(Trigger::Z))];
+        "analog_then_digital_tapped"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::L, PRESSED),
+            (10, B0xxRaw::R, PRESSED),
+            (20, B0xxRaw::R, RELEASED),
+            (30, B0xxRaw::L, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(MS)),
+            (10, Input::Trigger(Trigger::Z)),
+            (10, R_PRESSED),
+            (20, R_RELEASED),
+            (20, Input::Trigger(MS)),
+            (30, Input::Trigger(Trigger::Z)),
+        ];
+        "standard_over_analog"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::MS, PRESSED),
+            (10, B0xxRaw::R, PRESSED),
+            (60, B0xxRaw::R, RELEASED),
+            (100, B0xxRaw::MS, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(Trigger::P110)),
+            (10, Input::Trigger(Trigger::Z)),
+            (10, R_PRESSED),
+            (50, L_PRESSED),
+            (60, R_RELEASED),
+            (60, Input::Trigger(Trigger::P110)),
+            (100, L_RELEASED),
+            (100, Input::Trigger(Trigger::Z)),
+        ];
+        "standard_over_analog_then_digital"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::LS, PRESSED),
+            (10, B0xxRaw::R, PRESSED),
+            (20, B0xxRaw::LS, RELEASED),
+            (30, B0xxRaw::R, RELEASED),
+        ],
+        &[(0, R_PRESSED), (30, R_RELEASED)];
+        "standard_and_digital_share"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::MS, PRESSED),
+            (10, B0xxRaw::L, PRESSED),
+            (20, B0xxRaw::L, RELEASED),
+            (100, B0xxRaw::MS, RELEASED),
+        ],
+        &[
+            (0, Input::Trigger(Trigger::P110)),
+            (10, Input::Trigger(MS)),
+            (20, Input::Trigger(Trigger::Z)),
+            (50, L_PRESSED),
+            (100, L_RELEASED),
+        ];
+        "analog_tiers"
+    )]
+    fn shield_behavior(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Input)]) {
+        let mut main = Main {
+            shield_behavior: config::ShieldBehaviors::try_from(std::collections::BTreeMap::from([
+                (B0xxRaw::L, config::ShieldBehavior::Analog(MS)),
+                (B0xxRaw::LS, config::ShieldBehavior::Digital(B0xxRaw::R)),
+                (
+                    B0xxRaw::MS,
+                    config::ShieldBehavior::AnalogThenDigital {
+                        value: Trigger::P110,
+                        button: B0xxRaw::L,
+                        hold_ms: 50,
+                    },
+                ),
+            ]))
+            .unwrap(),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX);
+        assert_eq!(got, want);
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    // A tap-hold button is tapped on a release before the threshold, which
+    // SOCD then treats as the latest press, so that a direction tapped against
+    // the opposite one leaves the axis neutral. Otherwise it acts as its hold
+    // button from the threshold on.
+    #[test_case(&[(0, B0xxRaw::L, PRESSED), (100, B0xxRaw::L, RELEASED)], &[(100, Input::Button(Button::Impure(ButtonImpure::L), PRESSED)), (100, Input::Button(Button::Impure(ButtonImpure::L), RELEASED))]; "tapped")]
+    #[test_case(&[(0, B0xxRaw::L, PRESSED), (300, B0xxRaw::L, RELEASED)], &[(150, Input::Trigger(LS)), (300, Input::Trigger(Trigger::Z))]; "held")]
+    #[test_case(&[(0, B0xxRaw::L, PRESSED), (150, B0xxRaw::L, RELEASED)], &[(150, Input::Trigger(LS)), (150, Input::Trigger(Trigger::Z))]; "threshold")]
+    #[test_case(&[(0, B0xxRaw::Right, PRESSED), (10, B0xxRaw::Left, PRESSED), (60, B0xxRaw::Left, RELEASED), (100, B0xxRaw::Right, RELEASED)], &[(0, Input::Stick(Stick::A, (Analog::MAX, P0000))), (60, Input::Stick(Stick::A, (-Analog::MAX, P0000))), (60, Input::Stick(Stick::A, (P0000, P0000)))]; "tap_over_opposite")]
+    #[test_case(&[(0, B0xxRaw::Left, PRESSED), (20, B0xxRaw::Right, PRESSED), (60, B0xxRaw::Left, RELEASED), (100, B0xxRaw::Right, RELEASED)], &[(20, Input::Stick(Stick::A, (Analog::MAX, P0000))), (60, Input::Stick(Stick::A, (-Analog::MAX, P0000))), (60, Input::Stick(Stick::A, (P0000, P0000)))]; "tap_after_opposite")]
+    #[test_case(&[(0, B0xxRaw::Right, PRESSED), (10, B0xxRaw::Left, PRESSED), (200, B0xxRaw::Left, RELEASED), (300, B0xxRaw::Right, RELEASED)], &[(0, Input::Stick(Stick::A, (Analog::MAX, P0000))), (160, Input::Button(Button::Impure(ButtonImpure::B), PRESSED)), (200, Input::Button(Button::Impure(ButtonImpure::B), RELEASED)), (300, Input::Stick(Stick::A, (P0000, P0000)))]; "held_over_opposite")]
+    fn tap_hold(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, Input)]) {
+        let tap_holds = [
+            (
+                B0xxRaw::L,
+                config::TapHold {
+                    hold: B0xxRaw::LS,
+                    hold_ms: 150,
+                },
+            ),
+            (
+                B0xxRaw::Left,
+                config::TapHold {
+                    hold: B0xxRaw::B,
+                    hold_ms: 150,
+                },
+            ),
+        ];
+        let mut main = Main {
+            tap_holds: config::TapHolds::try_from(
+                tap_holds
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>(),
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, 0);
+        assert_eq!(main.deadline(), None);
+        assert_eq!(got, want);
+    }
+
+    // A macro plays its steps on time, whatever its key does meanwhile, ends
+    // with what the keys still held make, and is cancelled by a Start reset.
+    #[test_case(config::Repress::Ignore, &[(0, B0xxRaw::M1, PRESSED), (10, B0xxRaw::M1, RELEASED)], &[(0, "PRESS Y"), (50, "PRESS L"), (66, "SET MAIN 0.28125 0.28125"), (3066, "RELEASE L"), (3066, "RELEASE Y"), (3066, "SET MAIN 0.5 0.5")]; "played")]
+    #[test_case(config::Repress::Ignore, &[(0, B0xxRaw::M1, PRESSED), (10, B0xxRaw::M1, RELEASED), (60, B0xxRaw::M1, PRESSED), (70, B0xxRaw::M1, RELEASED)], &[(0, "PRESS Y"), (50, "PRESS L"), (66, "SET MAIN 0.28125 0.28125"), (3066, "RELEASE L"), (3066, "RELEASE Y"), (3066, "SET MAIN 0.5 0.5")]; "ignored")]
+    #[test_case(config::Repress::Restart, &[(0, B0xxRaw::M1, PRESSED), (10, B0xxRaw::M1, RELEASED), (60, B0xxRaw::M1, PRESSED), (70, B0xxRaw::M1, RELEASED)], &[(0, "PRESS Y"), (50, "PRESS L"), (60, "RELEASE L"), (110, "PRESS L"), (126, "SET MAIN 0.28125 0.28125"), (3126, "RELEASE L"), (3126, "RELEASE Y"), (3126, "SET MAIN 0.5 0.5")]; "restarted")]
+    #[test_case(config::Repress::Ignore, &[(0, B0xxRaw::M1, PRESSED), (10, B0xxRaw::M1, RELEASED), (100, B0xxRaw::Start, PRESSED), (2200, B0xxRaw::Start, RELEASED)], &[(0, "PRESS Y"), (50, "PRESS L"), (66, "SET MAIN 0.28125 0.28125"), (100, "PRESS START"), (2100, "RELEASE L"), (2100, "RELEASE Y"), (2100, "RELEASE START"), (2100, "SET MAIN 0.5 0.5")]; "cancelled")]
+    #[test_case(config::Repress::Ignore, &[(0, B0xxRaw::M2, PRESSED), (10, B0xxRaw::M2, RELEASED)], &[]; "unbound")]
+    #[test_case(config::Repress::Ignore, &[(0, B0xxRaw::M1, PRESSED), (10, B0xxRaw::M1, RELEASED), (20, B0xxRaw::A, PRESSED), (30, B0xxRaw::Right, PRESSED)], &[(0, "PRESS Y"), (20, "PRESS A"), (30, "SET MAIN 0.8149606299212598 0.5"), (50, "PRESS L"), (66, "SET MAIN 0.28125 0.28125"), (3066, "RELEASE L"), (3066, "RELEASE Y"), (3066, "SET MAIN 0.8149606299212598 0.5")]; "held_through")]
+    fn macros(repress: config::Repress, events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, &str)]) {
+        use config::MacroStep;
+
+        let steps = vec![
+            MacroStep::Press(GCButton::Y),
+            MacroStep::WaitMs(50),
+            MacroStep::Press(GCButton::L),
+            MacroStep::WaitMs(16),
+            MacroStep::AStick((Analog::new(-56).unwrap(), Analog::new(-56).unwrap())),
+            MacroStep::WaitMs(3000),
+            MacroStep::ReleaseAll,
+        ];
+        let macros = [(B0xxRaw::M1, config::Macro { repress, steps })];
+        let mut main = Main {
+            macros: config::Macros::try_from(
+                macros
+                    .into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>(),
+            )
+            .unwrap(),
+            start_reset: Some(config::StartReset { hold_ms: 2000 }),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, u64::MAX)
+            .into_iter()
+            .map(|(ms, input)| {
+                let command = input.into_pipe_input().into_input_string(
+                    Default::default(),
+                    Game::Melee,
+                    Default::default(),
+                );
+                (ms, command.trim_end().to_owned())
+            })
+            .collect::<Vec<_>>();
+        let got = got
+            .iter()
+            .map(|(ms, command)| (*ms, command.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(got, want);
+        assert_eq!(main.output, main.held_output(false));
+    }
+
+    // The input state sees a negative-edge button only during its pulse.
+    #[test]
+    fn negative_edge_state() {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        let mut main = Main {
+            negative_edge: Some(NegativeEdge::new(config::NegativeEdge {
+                buttons: vec![B0xxRaw::L],
+                max_hold_ms: 200,
+                pulse_ms: 17,
+            })),
+            ..Default::default()
+        };
+        let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::L, PRESSED, start), false);
+        assert!(main.held.contains(&B0xxRaw::L));
+        assert!(!main.state.contains(B0xxState::L));
+        let released = start + Duration::from_millis(50);
+        let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::L, RELEASED, released), false);
+        assert!(main.held.is_empty());
+        assert!(main.state.contains(B0xxState::L));
+        assert_eq!(main.deadline(), Some(released + Duration::from_millis(17)));
+        let _ = main.process_deadline(released + Duration::from_millis(17), false);
+        assert!(!main.state.contains(B0xxState::L));
+        assert_eq!(main.deadline(), None);
+    }
+
+    #[test]
+    fn short_hop_disabled() {
+        let mut main = Main::default();
+        assert!(press_all(&mut main, &[B0xxRaw::SH], false).is_empty());
+        assert_eq!(main.deadline(), None);
+    }
+
+    // The ramp overrides the shields from zero at most once per interval, and
+    // the shield held last takes over again when it is released.
+    #[test_case(&[(10, B0xxRaw::TR, PRESSED), (200, B0xxRaw::TR, RELEASED)], &[(40, 30), (70, 60), (100, 90), (130, 100), (200, 0)]; "alone")]
+    #[test_case(&[(10, B0xxRaw::TR, PRESSED), (50, B0xxRaw::TR, RELEASED)], &[(40, 30), (50, 0)]; "released early")]
+    #[test_case(&[(0, B0xxRaw::LS, PRESSED), (10, B0xxRaw::TR, PRESSED), (50, B0xxRaw::MS, PRESSED), (200, B0xxRaw::TR, RELEASED), (210, B0xxRaw::MS, RELEASED)], &[(0, 49), (10, 0), (40, 30), (70, 60), (100, 90), (130, 100), (200, 94), (210, 49)]; "over shields")]
+    #[test_case(&[(10, B0xxRaw::TR, PRESSED), (20, B0xxRaw::L, PRESSED), (200, B0xxRaw::TR, RELEASED), (210, B0xxRaw::L, RELEASED)], &[(40, 30), (70, 60), (100, 90), (130, 100), (200, 0)]; "over digital L")]
+    fn trigger_ramp(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, u8)]) {
+        let mut main = Main {
+            trigger_ramp: Some(config::TriggerRamp {
+                max: Trigger::P100,
+                ramp_ms: 100,
+                interval_ms: 30,
+            }),
+            ..Default::default()
+        };
+        let got = play(&mut main, events, 0)
+            .into_iter()
+            .filter_map(|(ms, input)| match input {
+                Input::Trigger(trigger) => Some((ms, trigger.get())),
+                Input::Button(Button::Impure(ButtonImpure::L), _) => None,
+                _ => panic!("{:?}", input),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(main.deadline(), None);
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn trigger_ramp_disabled() {
+        let mut main = Main::default();
+        assert!(press_all(&mut main, &[B0xxRaw::TR], false).is_empty());
+        assert_eq!(main.deadline(), None);
+    }
+
+    // Alternating presses closer together than the limit take effect at
+    // exactly the limit's cadence, each held as long as its key, and none are
+    // lost.
+    #[test_case(5, &[(0, Some(NEGATIVE)), (5, None), (20, Some(POSITIVE)), (25, None), (40, Some(NEGATIVE)), (45, None), (60, Some(POSITIVE)), (65, None)]; "mashed")]
+    #[test_case(30, &[(0, Some(NEGATIVE)), (30, None), (60, Some(POSITIVE)), (90, None), (120, Some(NEGATIVE)), (150, None), (180, Some(POSITIVE)), (210, None)]; "slower_than_the_limit")]
+    fn sdi_limit(spacing: u64, want: &[(u64, Option<Direction>)]) {
+        let mut main = Main {
+            sdi_limiter: Some(SdiLimiter::new(config::SdiLimit { interval_ms: 20 })),
+            ..Default::default()
+        };
+        let events = [B0xxRaw::Left, B0xxRaw::Right, B0xxRaw::Left, B0xxRaw::Right]
+            .into_iter()
+            .flat_map(|btn| [(btn, PRESSED), (btn, RELEASED)])
+            .enumerate()
+            .map(|(i, (btn, pressed))| (i as u64 * spacing, btn, pressed))
+            .collect::<Vec<_>>();
+        let got = play(&mut main, &events, u64::MAX)
+            .into_iter()
+            .map(|(ms, input)| match input {
+                Input::Stick(Stick::A, (x, P0000)) => (ms, (x != P0000).then_some(x > P0000)),
+                _ => panic!("{:?}", input),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(got, want);
+    }
+
+    // B pressed within the window after a horizontal direction holds the X
+    // axis neutral until it is released, unless a modifier or a vertical
+    // direction says otherwise.
+    #[test_case(&[], 50, true; "at_window_end")]
+    #[test_case(&[], 51, false; "after_window")]
+    #[test_case(&[B0xxRaw::MX], 0, false; "mod_x")]
+    #[test_case(&[B0xxRaw::MY], 0, false; "mod_y")]
+    #[test_case(&[B0xxRaw::Up], 0, false; "up_b")]
+    fn neutral_b(held: &[B0xxRaw], b_after: u64, locked: bool) {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        let b_time = start + Duration::from_millis(b_after);
+        // Returns the inputs from pressing and releasing B, and the A-stick
+        // output before.
+        let run = |neutral_b_helper| {
+            let mut main = Main {
+                neutral_b_helper,
+                ..Default::default()
+            };
+            let _ = press_all(&mut main, held, false);
+            let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::Right, PRESSED, start), false);
+            let a_stick = main.output.a_stick;
+            let pressed = main.process_b0xx(B0xxEvent::new(B0xxRaw::B, PRESSED, b_time), false);
+            let released = main.process_b0xx(B0xxEvent::new(B0xxRaw::B, RELEASED, b_time), false);
+            (a_stick, pressed, released)
+        };
+        let (a_stick, pressed, released) = run(Some(config::NeutralBHelper { window_ms: 50 }));
+        if locked {
+            let (b_pressed, b_released) = (
+                Input::Button(Button::Impure(ButtonImpure::B), PRESSED),
+                Input::Button(Button::Impure(ButtonImpure::B), RELEASED),
+            );
+            let neutral = Input::Stick(Stick::A, (P0000, a_stick.1));
+            assert_eq!(pressed[..], [neutral, b_pressed]);
+            assert_eq!(released[..], [b_released, Input::Stick(Stick::A, a_stick)]);
+        } else {
+            assert_eq!((a_stick, pressed, released), run(None));
+        }
+    }
+
+    // A press of Start shorter than the hold passes through as usual.
+    #[test_case(None; "disabled")]
+    #[test_case(Some(config::StartReset { hold_ms: 2000 }); "enabled")]
+    fn start_short_press(start_reset: Option<config::StartReset>) {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        let mut main = Main {
+            start_reset,
+            ..Default::default()
+        };
+        let pressed = main.process_b0xx(B0xxEvent::new(B0xxRaw::Start, PRESSED, start), false);
+        assert_eq!(
+            pressed[..],
+            [Input::Button(Button::Pure(ButtonPure::Start), PRESSED)]
+        );
+        assert_eq!(
+            main.deadline(),
+            start_reset.map(|start_reset| start + start_reset.hold())
+        );
+        let released = main.process_b0xx(
+            B0xxEvent::new(B0xxRaw::Start, RELEASED, start + Duration::from_millis(100)),
+            false,
+        );
+        assert_eq!(
+            released[..],
+            [Input::Button(Button::Pure(ButtonPure::Start), RELEASED)]
+        );
+        assert_eq!(main.deadline(), None);
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    // Holding Start resets the output to neutral, and the buttons held through
+    // it do nothing until pressed again.
+    #[test_case(false; "engine")]
+    #[test_case(true; "raw")]
+    fn start_reset(raw: bool) {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        let hold = Duration::from_millis(2000);
+        let mut main = Main {
+            start_reset: Some(config::StartReset { hold_ms: 2000 }),
+            raw: raw.then(Default::default),
+            ..Default::default()
+        };
+        let held = [B0xxRaw::A, B0xxRaw::Right, B0xxRaw::CU, B0xxRaw::LS];
+        for btn in held {
+            let _ = main.process_b0xx(B0xxEvent::new(btn, PRESSED, start), false);
+        }
+        let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::Start, PRESSED, start), false);
+        assert_eq!(main.deadline(), Some(start + hold));
+        assert_eq!(main.process_deadline(start + hold / 2, false)[..], []);
+
+        let reset = main.process_deadline(start + hold, false);
+        assert_eq!(
+            reset[..],
+            [
+                Input::Button(Button::Pure(ButtonPure::A), RELEASED),
+                Input::Button(Button::Pure(ButtonPure::Start), RELEASED),
+                Input::Stick(Stick::C, (P0000, P0000)),
+                Input::Stick(Stick::A, (P0000, P0000)),
+                Input::Trigger(Trigger::Z),
+            ]
+        );
+        assert_eq!(main.output, ControllerSnapshot::default());
+        assert_eq!(main.deadline(), None);
+        for btn in held.into_iter().chain([B0xxRaw::Start]) {
+            let released = main.process_b0xx(B0xxEvent::new(btn, RELEASED, start + hold), false);
+            assert_eq!(released[..], [], "{:?}", btn);
+        }
+        assert_eq!(main.output, ControllerSnapshot::default());
+
+        let pressed = main.process_b0xx(B0xxEvent::new(B0xxRaw::A, PRESSED, start + hold), false);
+        assert_eq!(
+            pressed[..],
+            [Input::Button(Button::Pure(ButtonPure::A), PRESSED)]
+        );
+    }
+
+    #[test]
+    fn raw_engine() {
+        let mut main = Main {
+            raw: Some(Default::default()),
+            ..Default::default()
+        };
+        let steps: &[(B0xxRaw, Pressed, &[Input])] = &[
+            (B0xxRaw::MX, PRESSED, &[]),
+            (B0xxRaw::MY, PRESSED, &[]),
+            (
+                B0xxRaw::Left,
+                PRESSED,
+                &[Input::Stick(Stick::A, (Analog::MIN, P0000))],
+            ),
+            (
+                B0xxRaw::Right,
+                PRESSED,
+                &[Input::Stick(Stick::A, (Analog::MAX, P0000))],
+            ),
+            (
+                B0xxRaw::Up,
+                PRESSED,
+                &[Input::Stick(Stick::A, (Analog::MAX, Analog::MAX))],
+            ),
+            (B0xxRaw::MY, RELEASED, &[]),
+            (
+                B0xxRaw::Right,
+                RELEASED,
+                &[Input::Stick(Stick::A, (Analog::MIN, Analog::MAX))],
+            ),
+            (
+                B0xxRaw::Left,
+                RELEASED,
+                &[Input::Stick(Stick::A, (P0000, Analog::MAX))],
+            ),
+            (
+                B0xxRaw::CD,
+                PRESSED,
+                &[Input::Stick(Stick::C, (P0000, Analog::MIN))],
+            ),
+            (
+                B0xxRaw::L,
+                PRESSED,
+                &[Input::Button(Button::Impure(ButtonImpure::L), PRESSED)],
+            ),
+            (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+            (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+        ];
+        for &(btn, pressed, want) in steps {
+            assert_eq!(
+                main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false)[..],
+                *want,
+                "{:?} {}",
+                btn,
+                pressed
+            );
+        }
+    }
+
+    // Z presses A and the analog trigger in `z_composite` mode, A staying
+    // held while either is, and the trigger staying beneath shields and
+    // digital L/R.
+    #[test]
+    fn z_composite() {
+        let z = Trigger::P30;
+        let a = |pressed| Input::Button(Button::Pure(ButtonPure::A), pressed);
+        let l = |pressed| Input::Button(Button::Impure(ButtonImpure::L), pressed);
+        type Steps<'a> = &'a [(B0xxRaw, Pressed, &'a [Input])];
+        let scenarios: &[(&str, Steps)] = &[
+            (
+                "alone",
+                &[
+                    (B0xxRaw::Z, PRESSED, &[Input::Trigger(z), a(PRESSED)]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+            (
+                "within A",
+                &[
+                    (B0xxRaw::A, PRESSED, &[a(PRESSED)]),
+                    (B0xxRaw::Z, PRESSED, &[Input::Trigger(z)]),
+                    (B0xxRaw::Z, RELEASED, &[Input::Trigger(Trigger::Z)]),
+                    (B0xxRaw::A, RELEASED, &[a(RELEASED)]),
+                ],
+            ),
+            (
+                "overlapping A",
+                &[
+                    (B0xxRaw::A, PRESSED, &[a(PRESSED)]),
+                    (B0xxRaw::Z, PRESSED, &[Input::Trigger(z)]),
+                    (B0xxRaw::A, RELEASED, &[]),
+                    (B0xxRaw::A, PRESSED, &[]),
+                    (B0xxRaw::A, RELEASED, &[]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+            (
+                "shield within",
+                &[
+                    (B0xxRaw::Z, PRESSED, &[Input::Trigger(z), a(PRESSED)]),
+                    (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+                    (B0xxRaw::LS, RELEASED, &[Input::Trigger(z)]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+            (
+                "within shield",
+                &[
+                    (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+                    (B0xxRaw::Z, PRESSED, &[a(PRESSED)]),
+                    (B0xxRaw::Z, RELEASED, &[a(RELEASED)]),
+                    (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+                ],
+            ),
+            (
+                "shield overlapping",
+                &[
+                    (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+                    (B0xxRaw::Z, PRESSED, &[a(PRESSED)]),
+                    (B0xxRaw::LS, RELEASED, &[Input::Trigger(z)]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+            (
+                "digital L within",
+                &[
+                    (B0xxRaw::Z, PRESSED, &[Input::Trigger(z), a(PRESSED)]),
+                    (
+                        B0xxRaw::L,
+                        PRESSED,
+                        &[Input::Trigger(Trigger::Z), l(PRESSED)],
+                    ),
+                    (B0xxRaw::L, RELEASED, &[l(RELEASED), Input::Trigger(z)]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+            (
+                "within digital L",
+                &[
+                    (B0xxRaw::L, PRESSED, &[l(PRESSED)]),
+                    (B0xxRaw::Z, PRESSED, &[a(PRESSED)]),
+                    (B0xxRaw::L, RELEASED, &[l(RELEASED), Input::Trigger(z)]),
+                    (
+                        B0xxRaw::Z,
+                        RELEASED,
+                        &[a(RELEASED), Input::Trigger(Trigger::Z)],
+                    ),
+                ],
+            ),
+        ];
+        for &(scenario, steps) in scenarios {
+            let mut main = Main {
+                z_composite: Some(config::ZComposite { trigger: z }),
+                ..Default::default()
+            };
+            for &(btn, pressed, want) in steps {
+                assert_eq!(
+                    main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false)[..],
+                    *want,
+                    "{}: {:?} {}",
+                    scenario,
+                    btn,
+                    pressed
+                );
+                let mut reconnected = ControllerSnapshot::default();
+                for pipe_input in main.resync() {
+                    reconnected.apply(pipe_input);
+                }
+                assert_eq!(reconnected, main.output, "{}", scenario);
+            }
+        }
+
+        let mut main = Main::default();
+        assert_eq!(
+            main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Z, PRESSED), false)[..],
+            [Input::Button(Button::Pure(ButtonPure::Z), PRESSED)]
+        );
+    }
+
+    #[test]
+    fn last_press() {
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let mut main = Main::default();
+        assert_eq!(main.last_press(B0xxRaw::A), None);
+        for (btn, pressed, time) in [
+            (B0xxRaw::A, PRESSED, t(5)),
+            (B0xxRaw::A, RELEASED, t(10)),
+            (B0xxRaw::B, PRESSED, t(20)),
+        ] {
+            let _ = main.process_b0xx(B0xxEvent::new(btn, pressed, time), false);
+        }
+        assert_eq!(main.last_press(B0xxRaw::A), Some(t(5)));
+        assert_eq!(main.last_press(B0xxRaw::B), Some(t(20)));
+        let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::A, PRESSED, t(30)), false);
+        assert_eq!(main.last_press(B0xxRaw::A), Some(t(30)));
+    }
+
+    #[test]
+    fn held() {
+        let mut main = Main::default();
+        for (btn, pressed) in [
+            (B0xxRaw::MX, PRESSED),
+            (B0xxRaw::Left, PRESSED),
+            (B0xxRaw::LS, PRESSED),
+            (B0xxRaw::MX, RELEASED),
+        ] {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false);
+        }
+        assert_eq!(
+            main.held.iter().copied().collect::<Vec<_>>(),
+            [B0xxRaw::Left, B0xxRaw::LS]
+        );
+    }
+
+    #[test]
+    fn session_stats() {
+        let mut main = Main::default();
+        let t = |secs| Timestamp::default() + Duration::from_secs(secs);
+        for (secs, btn, pressed) in [
+            (0, B0xxRaw::Left, PRESSED),
+            (1, B0xxRaw::Right, PRESSED),
+            (2, B0xxRaw::Right, RELEASED),
+            (3, B0xxRaw::Right, PRESSED),
+            (4, B0xxRaw::Left, RELEASED),
+            (5, B0xxRaw::Right, RELEASED),
+            (60, B0xxRaw::LS, PRESSED),
+            (61, B0xxRaw::MS, PRESSED),
+            (62, B0xxRaw::MS, RELEASED),
+            (63, B0xxRaw::MS, PRESSED),
+            (64, B0xxRaw::LS, RELEASED),
+            (65, B0xxRaw::MS, RELEASED),
+            (185, B0xxRaw::A, PRESSED),
+        ] {
+            let _ = main.process_b0xx(B0xxEvent::new(btn, pressed, t(secs)), false);
+        }
+        let stats = &main.stats;
+        assert_eq!(
+            stats.presses.counted().collect::<Vec<_>>(),
+            [
+                (B0xxRaw::A, 1),
+                (B0xxRaw::Left, 1),
+                (B0xxRaw::Right, 2),
+                (B0xxRaw::LS, 1),
+                (B0xxRaw::MS, 2),
+            ]
+        );
+        assert_eq!(stats.presses_per_minute, [3, 3, 0, 1]);
+        // Right's second press doesn't override Left, which has been a no-op
+        // since Right was first released.
+        assert_eq!(
+            stats.socd_overrides.counted().collect::<Vec<_>>(),
+            [(B0xxRaw::Right, 1)]
+        );
+        assert_eq!(
+            stats.shield_transitions,
+            ShieldTransitions {
+                activated: 1,
+                replaced: 2,
+                released: 1,
+                fell_back: 1,
+                released_inactive: 1,
+            }
+        );
+    }
+
+    // Pressing a direction while its opposite is active counts against the
+    // axis whatever it then resolves to, which the axis state tells apart.
+    #[test_case(config::CStickSocd::SecondInput, DualModeAxisState::Neither(AxisState::Active(POSITIVE, PRESSED)); "second_input")]
+    #[test_case(config::CStickSocd::Neutral, DualModeAxisState::Neither(AxisState::Neutral); "neutral")]
+    fn socd_overrides_by_axis(c_stick: config::CStickSocd, c_x: DualModeAxisState) {
+        let profile = config::Profile {
+            socd: config::Socd {
+                c_stick,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut main = Main::new(&profile);
+        let _ = press_all(
+            &mut main,
+            &[
+                B0xxRaw::Left,
+                B0xxRaw::Right,
+                B0xxRaw::Down,
+                B0xxRaw::Up,
+                B0xxRaw::CL,
+                B0xxRaw::CR,
+            ],
+            false,
+        );
+        assert_eq!(
+            main.stats.socd_overrides_by_axis,
+            AxisCounts {
+                a_stick_x: 1,
+                a_stick_y: 1,
+                c_stick_x: 1,
+                c_stick_y: 0,
+            }
+        );
+        // Axes never overridden are left out of the statistics file.
+        assert_eq!(
+            serde_json::to_string(&main.stats.socd_overrides_by_axis).unwrap(),
+            r#"{"a_stick.x":1,"a_stick.y":1,"c_stick.x":1}"#
+        );
+        assert_eq!(main.a_stick.x, AxisState::Active(POSITIVE, PRESSED));
+        assert_eq!(main.c_stick.x, c_x);
+        // Releasing the overriding direction leaves the axis inactive with
+        // Left still held, which counts nothing.
+        let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Right, RELEASED), false);
+        assert_eq!(main.a_stick.x, AxisState::Null(Some(NEGATIVE)));
+        assert_eq!(main.stats.socd_overrides_by_axis.a_stick_x, 1);
+    }
+
+    #[test_case((P0000, P0000), 5, 5; "neutral")]
+    #[test_case((Analog::MAX, Analog::MAX), 10, 0; "up right")]
+    #[test_case((Analog::MIN, Analog::MIN), 0, 10; "down left")]
+    #[test_case((P7000, P7000), 9, 1; "diagonal")]
+    #[test_case((P0750, P0000), 5, 5; "rounds to neutral")]
+    #[test_case((P1000, P0000), 6, 5; "rounds away")]
+    fn tui_plot(stick: GCStickInput, column: usize, row: usize) {
+        let lines = tui::plot(stick);
+        assert_eq!(lines.len(), 11);
+        for (r, line) in lines.iter().enumerate() {
+            let cells = line.split(' ').collect::<Vec<_>>();
+            for (c, &cell) in cells.iter().enumerate() {
+                let want = if (r, c) == (row, column) {
+                    "o"
+                } else if (r, c) == (5, 5) {
+                    "+"
+                } else {
+                    "."
+                };
+                assert_eq!(cell, want, "{:?}", lines);
+            }
+        }
+    }
+
+    #[test]
+    fn tui_frame() {
+        let mut main = Main::default();
+        let _ = press_all(
+            &mut main,
+            &[B0xxRaw::MX, B0xxRaw::Right, B0xxRaw::LS],
+            false,
+        );
+        let lines = tui::Frame::new(&main, &main.output).render();
+        assert_eq!(lines[0], "   stick:  Left   Down  [Right]  Up ");
+        assert!(
+            lines.contains(&String::from("character: none")),
+            "{:?}",
+            lines
+        );
+        assert!(
+            lines.contains(&String::from("trigger: 72/140")),
+            "{:?}",
+            lines
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.starts_with("A (0.6625, 0.0000)")),
+            "{:?}",
+            lines
+        );
+        assert!(
+            lines.contains(&String::from("A axes: x Active(true, false), y Null(None)")),
+            "{:?}",
+            lines
+        );
+    }
+
+    const WAVEDASH: &[(u64, B0xxRaw, Pressed)] = &[
+        (0, B0xxRaw::Down, PRESSED),
+        (10, B0xxRaw::Y, PRESSED),
+        (20, B0xxRaw::Right, PRESSED),
+        (60, B0xxRaw::R, PRESSED),
+    ];
+
+    #[test_case(WAVEDASH, &[(techniques::Technique::Wavedash, 3)]; "wavedash")]
+    #[test_case(&[
+        (0, B0xxRaw::Y, PRESSED),
+        (10, B0xxRaw::L, PRESSED),
+        (110, B0xxRaw::Down, PRESSED),
+        (120, B0xxRaw::Left, PRESSED),
+    ], &[(techniques::Technique::Wavedash, 7)]; "late wavedash airdodging first")]
+    #[test_case(&[
+        (0, B0xxRaw::Y, PRESSED),
+        (500, B0xxRaw::Down, PRESSED),
+        (510, B0xxRaw::Right, PRESSED),
+        (520, B0xxRaw::R, PRESSED),
+    ], &[]; "airdodge long after jump")]
+    #[test_case(&[
+        (0, B0xxRaw::Right, PRESSED),
+        (100, B0xxRaw::Left, PRESSED),
+        (117, B0xxRaw::Left, RELEASED),
+    ], &[(techniques::Technique::Pivot, 1)]; "pivot")]
+    #[test_case(&[
+        (0, B0xxRaw::Right, PRESSED),
+        (100, B0xxRaw::Left, PRESSED),
+        (150, B0xxRaw::Up, PRESSED),
+        (200, B0xxRaw::Left, RELEASED),
+    ], &[]; "turnaround into diagonal")]
+    #[test_case(&[
+        (0, B0xxRaw::Down, PRESSED),
+        (100, B0xxRaw::Left, PRESSED),
+        (110, B0xxRaw::Down, RELEASED),
+    ], &[(techniques::Technique::DashbackOutOfCrouch, 1)]; "dashback out of crouch")]
+    #[test_case(&[
+        (0, B0xxRaw::Down, PRESSED),
+        (100, B0xxRaw::Down, RELEASED),
+        (150, B0xxRaw::Left, PRESSED),
+    ], &[(techniques::Technique::DashbackOutOfCrouch, 3)]; "dashback through neutral")]
+    #[test_case(&[
+        (0, B0xxRaw::Down, PRESSED),
+        (100, B0xxRaw::Up, PRESSED),
+        (110, B0xxRaw::Down, RELEASED),
+        (120, B0xxRaw::Up, RELEASED),
+        (130, B0xxRaw::Left, PRESSED),
+    ], &[]; "crouch left upward")]
+    fn technique_detectors(
+        script: &[(u64, B0xxRaw, Pressed)],
+        expected: &[(techniques::Technique, u32)],
+    ) {
+        let mut main = Main::default();
+        let mut detectors = techniques::Detectors::new(Default::default());
+        let mut detections = Vec::new();
+        for &(ms, btn, pressed) in script {
+            let time = Timestamp::default() + Duration::from_millis(ms);
+            let inputs = main.process_b0xx(B0xxEvent::new(btn, pressed, time), false);
+            for detection in detectors.process(time, &inputs) {
+                main.stats.technique(&detection);
+                detections.push((detection.technique, detection.frames));
+            }
+        }
+        assert_eq!(detections, expected);
+        assert_eq!(
+            main.stats
+                .techniques
+                .values()
+                .map(|stats| stats.attempts)
+                .sum::<u64>(),
+            expected.len() as u64
+        );
+    }
+
+    #[test]
+    fn technique_detectors_leave_output_alone() {
+        let run = |detect: bool| {
+            let mut main = Main::default();
+            let mut detectors = techniques::Detectors::new(Default::default());
+            WAVEDASH
+                .iter()
+                .flat_map(|&(ms, btn, pressed)| {
+                    let time = Timestamp::default() + Duration::from_millis(ms);
+                    let inputs = main.process_b0xx(B0xxEvent::new(btn, pressed, time), false);
+                    if detect {
+                        let _ = detectors.process(time, &inputs);
+                    }
+                    inputs
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(true), run(false));
+    }
+
+    #[test]
+    fn technique_detection_display() {
+        let detection = |frames| techniques::Detection {
+            technique: techniques::Technique::Wavedash,
+            frames,
+            window: config::FrameWindow { min: 3, max: 4 },
+        };
+        assert_eq!(
+            detection(2).to_string(),
+            "Wavedash: 2 frames, early (3..=4)"
+        );
+        assert!(detection(4).in_window());
+        assert_eq!(detection(5).to_string(), "Wavedash: 5 frames, late (3..=4)");
+    }
+
+    #[test_case(3; "partial")]
+    #[test_case(EVENT_TRACE_LEN; "full")]
+    #[test_case(EVENT_TRACE_LEN * 2 + 50; "wrapped")]
+    fn event_trace_dump(events: usize) {
+        let mut main = Main::default();
+        for i in 0..events {
+            let time = Timestamp::default() + Duration::from_millis(i as u64);
+            let pressed = i % 2 == 0;
+            let _ = main.process_b0xx(B0xxEvent::new(B0xxRaw::A, pressed, time), false);
+        }
+        let lines = main.dump();
+        let kept = events.min(EVENT_TRACE_LEN);
+        assert_eq!(lines.len(), kept + 1);
+        for (line, i) in lines[..kept].iter().zip(events - kept..) {
+            let pressed = i % 2 == 0;
+            assert_eq!(
+                line,
+                &format!(
+                    "{:?} A {}: [{:?}]",
+                    Duration::from_millis(i as u64),
+                    if pressed { "pressed" } else { "released" },
+                    Input::Button(Button::Pure(ButtonPure::A), pressed)
+                )
+            );
+        }
+        assert_eq!(lines[kept], format!("snapshot: {}", main.snapshot()));
+        assert_eq!(main.event_trace.entries.capacity(), EVENT_TRACE_LEN);
+    }
+
+    #[test]
+    fn situation_index_round_trips() {
+        for (i, situation) in coordinates::Situation::all().enumerate() {
+            assert_eq!(situation.index(), i, "{:?}", situation);
+        }
+    }
+
+    /// Compares the table lookup with evaluating the coordinates match per
+    /// event. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn a_stick_table_speed() {
+        let coordinates = config::Coordinates::default();
+        let table = coordinates::AStickTable::new(&coordinates);
+        let situations = coordinates::Situation::all().collect::<Vec<_>>();
+        let time = |f: &dyn Fn(coordinates::Situation) -> AStickInput| {
+            let start = std::time::Instant::now();
+            for _ in 0..1000 {
+                for &situation in &situations {
+                    let _ = std::hint::black_box(f(std::hint::black_box(situation)));
+                }
+            }
+            start.elapsed()
+        };
+        let matched = time(&|situation| coordinates::a_stick(situation, &coordinates));
+        let looked_up = time(&|situation| table.get(situation));
+        println!("match: {:?}, table: {:?}", matched, looked_up);
+    }
+
+    #[test_case("(0.7000, 0.3625) [56, 29] right+up, Mod X, C-down"; "mod x c-down")]
+    #[test_case("(-0.7875, -0.4875) [-63, -39] left+down, Mod X, C-left"; "mod x c-left")]
+    #[test_case("(0.7000, 0.5125) [56, 41] right+up, Mod X, C-up"; "mod x c-up")]
+    #[test_case("(0.6125, -0.5250) [49, -42] right+down, Mod X, C-right"; "mod x c-right")]
+    #[test_case("(0.7375, 0.3125) [59, 25] right+up, Mod X"; "mod x diagonal")]
+    #[test_case("(0.6625, 0.0000) [53, 0] right, tilt"; "tilt cardinal")]
+    #[test_case("(0.0000, 0.0000) [0, 0] neutral"; "neutral")]
+    #[test_case("(0.8125, -0.2875) [65, -23] right, Mod X, A-down"; "angled fsmash")]
+    fn validate_coordinates(line: &str) {
+        let lines = coordinates::dump(&Main::default(), false);
+        assert!(lines.iter().any(|l| l == line), "{:#?}", lines);
+    }
+
+    #[test]
+    fn validate_coordinates_sorted_and_deduplicated() {
+        let lines = coordinates::dump(&Main::default(), false);
+        let c_stick = lines.iter().position(|l| l == "C-stick:").unwrap();
+        assert_eq!(lines[0], "A-stick:");
+        for section in [&lines[1..c_stick], &lines[c_stick + 1..]] {
+            let raw = section
+                .iter()
+                .map(|l| l.split(" [").nth(1).unwrap().split(']').next().unwrap())
+                .map(|raw| {
+                    let (x, y) = raw.split_once(", ").unwrap();
+                    (x.parse::<i8>().unwrap(), y.parse::<i8>().unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert!(raw.windows(2).all(|w| w[0] < w[1]), "{:?}", raw);
+        }
+        // Crouch/walk is off by default, so its diagonal is unreachable.
+        assert!(!lines.iter().any(|l| l.contains("crouch/walk")));
+        assert!(coordinates::dump(&Main::default(), true)
+            .iter()
+            .any(|l| l == "(-0.7125, -0.6875) [-57, -55] left+down, crouch/walk"));
+    }
+
+    /// Collects `entries` into one of the profile's per-button or per-key
+    /// tables.
+    fn table<K: Ord, V, T>(entries: impl IntoIterator<Item = (K, V)>) -> T
+    where
+        T: TryFrom<std::collections::BTreeMap<K, V>>,
+        T::Error: std::fmt::Debug,
+    {
+        T::try_from(entries.into_iter().collect()).unwrap()
+    }
+
+    // Enabling a restricted feature fails its check alone.
+    #[test_case(
+        |p| p.b_reverse = Some(config::BReverseHelper { window_ms: 100, flick_ms: 34 }),
+        "b_reverse";
+        "b_reverse"
+    )]
+    #[test_case(
+        |p| p.turbo = Some(config::Turbo { button: B0xxRaw::A, rate_hz: 30., duty: 0.5 }),
+        "turbo";
+        "turbo"
+    )]
+    #[test_case(|p| p.short_hop = Some(config::ShortHop { release_ms: 25 }), "short_hop"; "short_hop")]
+    #[test_case(
+        |p| p.neutral_b = Some(config::NeutralBHelper { window_ms: 100 }),
+        "neutral_b";
+        "neutral_b"
+    )]
+    #[test_case(
+        |p| p.z_composite = Some(config::ZComposite { trigger: Trigger::P72 }),
+        "z_composite";
+        "z_composite"
+    )]
+    #[test_case(
+        |p| p.trigger_ramp = Some(config::TriggerRamp {
+            max: Trigger::MAX,
+            ramp_ms: 1000,
+            interval_ms: 17,
+        }),
+        "trigger_ramp";
+        "trigger_ramp"
+    )]
+    #[test_case(
+        |p| p.negative_edge = Some(config::NegativeEdge {
+            buttons: vec![B0xxRaw::L],
+            max_hold_ms: 200,
+            pulse_ms: 17,
+        }),
+        "negative_edge";
+        "negative_edge"
+    )]
+    #[test_case(
+        |p| p.light_press = Some(config::LightPress {
+            buttons: vec![B0xxRaw::L],
+            value: Trigger::P72,
+            delay_ms: 17,
+        }),
+        "light_press";
+        "light_press"
+    )]
+    #[test_case(
+        |p| p.double_tap = Some(config::DoubleTap {
+            buttons: vec![B0xxRaw::R],
+            value: Trigger::P70,
+            window_ms: 150,
+        }),
+        "double_tap";
+        "double_tap"
+    )]
+    #[test_case(
+        |p| p.shield_behavior = table([(
+            B0xxRaw::LS,
+            config::ShieldBehavior::AnalogThenDigital {
+                value: Trigger::P72,
+                button: B0xxRaw::L,
+                hold_ms: 100,
+            },
+        )]),
+        "shield_behavior";
+        "shield_behavior"
+    )]
+    #[test_case(
+        |p| p.macros = table([(
+            B0xxRaw::M1,
+            config::Macro { repress: config::Repress::Ignore, steps: vec![] },
+        )]),
+        "macros";
+        "macros"
+    )]
+    #[test_case(
+        |p| p.tap_hold = table([(B0xxRaw::A, config::TapHold { hold: B0xxRaw::B, hold_ms: 200 })]),
+        "tap_hold";
+        "tap_hold"
+    )]
+    #[test_case(
+        |p| p.autorepeat = table([("KEY_J".to_owned(), config::Autorepeat::Press)]),
+        "autorepeat";
+        "autorepeat"
+    )]
+    fn certify_restricted(enable: fn(&mut config::Profile), name: &str) {
+        let name = format!("feature.{}", name);
+        let default = certify::certify(
+            "default",
+            &config::Profile::default(),
+            Engine::B0xx,
+            false,
+            false,
+        );
+        assert_eq!(default.failed(), 0, "{}", default);
+        assert_eq!(
+            default.check(&name).unwrap().outcome,
+            certify::Outcome::Pass
+        );
+        let mut profile = config::Profile::default();
+        enable(&mut profile);
+        let report = certify::certify("default", &profile, Engine::B0xx, false, false);
+        assert_eq!(report.check(&name).unwrap().outcome, certify::Outcome::Fail);
+        assert_eq!(report.failed(), 1, "{}", report);
+        assert_ne!(report.hash, default.hash);
+
+        // --tournament turns it off and says so.
+        let report = certify::certify("default", &profile, Engine::B0xx, false, true);
+        assert_eq!(report.failed(), 0, "{}", report);
+        assert_eq!(
+            report.check(&name).unwrap().detail.split(',').next(),
+            Some("off")
+        );
+        assert_eq!(
+            report.check("tournament").unwrap().detail,
+            format!("on, turned off {}", &name["feature.".len()..])
+        );
+    }
+
+    #[test]
+    fn certify_checks() {
+        let certify = |profile: &config::Profile, engine| {
+            certify::certify("p", profile, engine, false, false)
+        };
+        let outcome = |report: &certify::Report, name: &str| report.check(name).unwrap().outcome;
+
+        // Limiters and resets pass, and the limit is reported.
+        let mut profile = config::Profile {
+            sdi_limit: Some(config::SdiLimit { interval_ms: 50 }),
+            ledgedash_nerf: Some(config::LedgedashNerf {
+                max_reactivations: 1,
+                window_ms: 100,
+            }),
+            start_reset: Some(config::StartReset { hold_ms: 2000 }),
+            ..Default::default()
+        };
+        let report = certify(&profile, Engine::B0xx);
+        assert_eq!(report.failed(), 0, "{}", report);
+        assert_eq!(
+            report.check("rate.a_stick").unwrap().detail,
+            "a change of direction per 50ms at most, by the SDI limit"
+        );
+        assert!(report
+            .to_string()
+            .ends_with(&format!("result: PASS\nhash: {:016x}", report.hash)));
+        assert_eq!(certify(&profile, Engine::B0xx).hash, report.hash);
+
+        // Raw autorepeat only presses again under the raw engine, which fails
+        // on its own.
+        profile.autorepeat = [("KEY_J".to_owned(), config::Autorepeat::Raw)]
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .try_into()
+            .unwrap();
+        let report = certify(&profile, Engine::B0xx);
+        assert_eq!(
+            outcome(&report, "feature.autorepeat"),
+            certify::Outcome::Pass
+        );
+        let report = certify(&profile, Engine::Raw);
+        assert_eq!(outcome(&report, "engine"), certify::Outcome::Fail);
+        assert_eq!(outcome(&report, "coordinates"), certify::Outcome::Fail);
+        assert_eq!(
+            outcome(&report, "feature.autorepeat"),
+            certify::Outcome::Fail
+        );
+
+        // A coordinate override beyond the unit circle fails where it is
+        // output.
+        let mut profile = config::Profile {
+            c_stick_mode: config::CStickMode::Tilt,
+            ..Default::default()
+        };
+        profile.coordinates.c_stick_tilt_diagonal = Some((Analog::MAX, Analog::MAX));
+        let report = certify(&profile, Engine::B0xx);
+        let coordinates = report.check("coordinates").unwrap();
+        assert_eq!(coordinates.outcome, certify::Outcome::Fail);
+        assert!(
+            coordinates.detail.contains("C-stick [80, 80]"),
+            "{}",
+            coordinates.detail
+        );
+        assert_eq!(report.failed(), 1, "{}", report);
+    }
+
+    // Under --tournament the keys of the assists configured output nothing
+    // and time nothing, though the config still has them.
+    #[test]
+    fn tournament_lockout() {
+        let profile = config::Profile {
+            turbo: Some(config::Turbo {
+                button: B0xxRaw::A,
+                rate_hz: 30.,
+                duty: 0.5,
+            }),
+            short_hop: Some(config::ShortHop { release_ms: 25 }),
+            trigger_ramp: Some(config::TriggerRamp {
+                max: Trigger::MAX,
+                ramp_ms: 1000,
+                interval_ms: 17,
+            }),
+            macros: [(
+                B0xxRaw::M1,
+                config::Macro {
+                    repress: config::Repress::Ignore,
+                    steps: vec![
+                        config::MacroStep::Press(GCButton::Y),
+                        config::MacroStep::WaitMs(100),
+                    ],
+                },
+            )]
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .try_into()
+            .unwrap(),
+            ..Default::default()
+        };
+        let (locked, suppressed) = certify::lockout(&profile, Engine::B0xx);
+        assert_eq!(suppressed, ["turbo", "short_hop", "trigger_ramp", "macros"]);
+        assert!(profile.turbo.is_some());
+        for btn in [B0xxRaw::TB, B0xxRaw::SH, B0xxRaw::TR, B0xxRaw::M1] {
+            let mut configured = Main::new(&profile);
+            let inputs = configured.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+            assert!(
+                !inputs.is_empty() || configured.deadline().is_some(),
+                "{:?} does nothing even when configured",
+                btn
+            );
+            let mut main = Main::new(&locked);
+            for pressed in [PRESSED, RELEASED] {
+                assert_eq!(
+                    main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false),
+                    Inputs::new(),
+                    "{:?}",
+                    btn
+                );
+            }
+            assert_eq!(main.deadline(), None, "{:?}", btn);
+        }
+    }
+
+    const C_STICK_MOD_VERTICAL: &[&str] = &["c_stick_mod_x_vertical", "c_stick_mod_y_vertical"];
+
+    // Overrides that no situation can output are flagged, and a partial set
+    // lists the reachable coordinates left at their defaults. A coordinate
+    // present in the profile is overridden even if set to its default.
+    #[test_case(|_| {}, false, Game::Melee, &[], &[]; "defaults")]
+    #[test_case(
+        |profile| profile.coordinates.crouch_walk_diagonal = Some((P7000, P7000)),
+        false,
+        Game::Melee,
+        &["crouch_walk_diagonal"],
+        C_STICK_MOD_VERTICAL;
+        "crouch_walk_disabled"
+    )]
+    #[test_case(
+        |profile| profile.coordinates.crouch_walk_diagonal = Some((P7000, P7000)),
+        true,
+        Game::Melee,
+        &[],
+        C_STICK_MOD_VERTICAL;
+        "crouch_walk_option_select"
+    )]
+    #[test_case(
+        |profile| {
+            profile.crouch_walk.left = true;
+            profile.coordinates.crouch_walk_diagonal = Some((P7000, P7000));
+        },
+        false,
+        Game::PlatformFighter,
+        &["crouch_walk_diagonal"],
+        C_STICK_MOD_VERTICAL;
+        "crouch_walk_outside_melee"
+    )]
+    #[test_case(
+        |profile| {
+            profile.coordinates.crouch_walk_diagonal =
+                Some(config::Coordinates::default().crouch_walk_diagonal);
+        },
+        false,
+        Game::Melee,
+        &["crouch_walk_diagonal"],
+        C_STICK_MOD_VERTICAL;
+        "crouch_walk_default_disabled"
+    )]
+    #[test_case(
+        |profile| {
+            profile.coordinates.c_stick_mod_x_vertical =
+                Some(config::Coordinates::default().c_stick_mod_x_vertical);
+        },
+        false,
+        Game::Melee,
+        &[],
+        &["c_stick_mod_y_vertical"];
+        "c_stick_default"
+    )]
+    #[test_case(
+        |profile| profile.coordinates.c_stick_tilt_diagonal = Some((P3750, P3750)),
+        false,
+        Game::Melee,
+        &["c_stick_tilt_diagonal"],
+        C_STICK_MOD_VERTICAL;
+        "c_stick_smash_mode"
+    )]
+    #[test_case(
+        |profile| {
+            profile.c_stick_mode = config::CStickMode::Tilt;
+            profile.coordinates.c_stick_tilt_diagonal = Some((P3750, P3750));
+        },
+        false,
+        Game::Melee,
+        &[],
+        &["c_stick_tilt_cardinal", "c_stick_mod_x_vertical", "c_stick_mod_y_vertical"];
+        "c_stick_tilt_mode"
+    )]
+    #[test_case(
+        |profile| profile.coordinates.mod_tilt_diagonal = Some((P5000, P5000)),
+        false,
+        Game::Melee,
+        &["mod_tilt_diagonal"],
+        C_STICK_MOD_VERTICAL;
+        "mod_tilt_unbound"
+    )]
+    #[test_case(
+        |profile| {
+            profile.tap_hold = config::TapHolds::try_from(std::collections::BTreeMap::from([(
+                B0xxRaw::A,
+                config::TapHold { hold: B0xxRaw::MT, hold_ms: 200 },
+            )]))
+            .unwrap();
+            profile.coordinates.mod_tilt_diagonal = Some((P5000, P5000));
+        },
+        false,
+        Game::Melee,
+        &[],
+        C_STICK_MOD_VERTICAL;
+        "mod_tilt_held"
+    )]
+    #[test_case(
+        |profile| {
+            profile.stick_coupling.a_stick_angles_c_stick = false;
+            profile.coordinates.c_stick_mod_x_vertical = Some((P3000, P6000));
+        },
+        false,
+        Game::Melee,
+        &["c_stick_mod_x_vertical"],
+        &[];
+        "c_stick_not_angled"
+    )]
+    fn coordinate_overrides(
+        setup: fn(&mut config::Profile),
+        crouch_walk_option_select: bool,
+        game: Game,
+        unreachable: &[&'static str],
+        defaulted: &[&'static str],
+    ) {
+        let mut profile = config::Profile::default();
+        setup(&mut profile);
+        assert_eq!(
+            coordinates::check_overrides(&profile, crouch_walk_option_select, game),
+            coordinates::Overrides {
+                unreachable: unreachable.to_vec(),
+                defaulted: defaulted.to_vec(),
+                deadzoned: Vec::new(),
+            }
+        );
+    }
+
+    #[test_case(P0000, true; "centre")]
+    #[test_case(P2750, true; "edge")]
+    #[test_case(-P2750, true; "negative_edge")]
+    #[test_case(P2875, false; "outside")]
+    #[test_case(-P2875, false; "negative_outside")]
+    fn analog_in_deadzone(a: Analog, inside: bool) {
+        assert_eq!(a.in_deadzone(), inside);
+        assert_eq!(in_deadzone((a, P0000)), inside);
+        assert_eq!(in_deadzone((P0000, a)), inside);
+        assert!(!in_deadzone((a, P2875)));
+    }
+
+    // A-stick coordinates a profile outputs inside Melee's deadzone are
+    // flagged on load, wherever their modifier is bound.
+    #[test_case(|_| {}, Game::Melee, &[]; "defaults")]
+    #[test_case(|profile| profile.coordinates.mod_tilt_diagonal = Some((P1000, P1000)), Game::Melee, &[]; "mod_tilt_unbound")]
+    #[test_case(|profile| {
+        profile.bindings = config::Bindings::try_from(std::collections::HashMap::from([(
+            String::from("KEY_SPACE"),
+            B0xxRaw::MT,
+        )]))
+        .unwrap();
+        profile.coordinates.mod_tilt_diagonal = Some((P1000, P1000));
+    }, Game::Melee, &[(-8, -8), (-8, 8), (8, -8), (8, 8)]; "mod_tilt_bound")]
+    #[test_case(|profile| profile.a_stick_scale = config::Scale::new(0.25).unwrap(), Game::PlatformFighter, &[]; "outside_melee")]
+    fn deadzoned_coordinates(setup: fn(&mut config::Profile), game: Game, deadzoned: &[(i8, i8)]) {
+        let mut profile = config::Profile::default();
+        setup(&mut profile);
+        let overrides = coordinates::check_overrides(&profile, false, game);
+        assert_eq!(
+            overrides
+                .deadzoned
+                .iter()
+                .map(|(character, (x, y))| (character.as_deref(), (x.get(), y.get())))
+                .collect::<Vec<_>>(),
+            deadzoned.iter().map(|&a| (None, a)).collect::<Vec<_>>()
+        );
+    }
+
+    // Each character's coordinates are checked too, naming the character,
+    // for what the profile's own don't already flag.
+    #[test]
+    fn deadzoned_character_coordinates() {
+        let character = |mod_tilt_diagonal| config::CoordinateOverrides {
+            mod_tilt_diagonal: Some(mod_tilt_diagonal),
+            ..Default::default()
+        };
+        let profile = config::Profile {
+            bindings: config::Bindings::try_from(std::collections::HashMap::from([(
+                String::from("KEY_SPACE"),
+                B0xxRaw::MT,
+            )]))
+            .unwrap(),
+            coordinates: config::CoordinateOverrides {
+                mod_tilt_diagonal: Some((P1000, P1000)),
+                ..Default::default()
+            },
+            characters: config::Characters(
+                [
+                    (String::from("falco"), character((P2000, P2000))),
+                    (String::from("fox"), character((P5000, P5000))),
+                    (
+                        String::from("marth"),
+                        config::CoordinateOverrides::default(),
+                    ),
+                ]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let overrides = coordinates::check_overrides(&profile, false, Game::Melee);
+        assert_eq!(
+            overrides
+                .deadzoned
+                .iter()
+                .map(|(character, (x, y))| (character.as_deref(), (x.get(), y.get())))
+                .collect::<Vec<_>>(),
+            [
+                (None, (-8, -8)),
+                (None, (-8, 8)),
+                (None, (8, -8)),
+                (None, (8, 8)),
+                (Some("falco"), (-16, -16)),
+                (Some("falco"), (-16, 16)),
+                (Some("falco"), (16, -16)),
+                (Some("falco"), (16, 16)),
+            ]
+        );
+    }
+
+    // When checked, a coordinate inside the deadzone is recorded the first
+    // time it is output with a direction held, but a centred stick isn't.
+    #[test]
+    fn deadzone_checked_at_runtime() {
+        let mut main = Main {
+            deadzoned: Some(Default::default()),
+            ..Main::new(&config::Profile {
+                coordinates: config::CoordinateOverrides {
+                    mod_tilt_diagonal: Some((P1000, P1000)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let _ = press_all(
+            &mut main,
+            &[B0xxRaw::MT, B0xxRaw::Left, B0xxRaw::Right],
+            false,
+        );
+        assert_eq!(main.deadzoned, Some(Default::default()));
+        let _ = press_all(&mut main, &[B0xxRaw::Up], false);
+        assert_eq!(main.output.a_stick, (P1000, P1000));
+        assert_eq!(main.deadzoned, Some([(P1000, P1000)].into()));
+        for btn in [B0xxRaw::Up, B0xxRaw::Left, B0xxRaw::Right] {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, RELEASED), false);
+        }
+        assert_eq!(main.output.a_stick, (P0000, P0000));
+        assert_eq!(main.deadzoned, Some([(P1000, P1000)].into()));
+    }
+
+    #[test]
+    fn viz_points() {
+        let svg = coordinates::svg(&Main::default(), false);
+        let attribute = |element: &str, name: &str| {
+            let start = element.find(&format!(" {}=\"", name))? + name.len() + 3;
+            let len = element[start..].find('"')?;
+            Some(element[start..start + len].to_string())
+        };
+        let mut counts = std::collections::BTreeMap::<_, usize>::new();
+        for element in svg.split('<').skip(1) {
+            let (tag, class) = (
+                element.split([' ', '>']).next().unwrap(),
+                attribute(element, "class"),
+            );
+            *counts.entry((tag, class)).or_default() += 1;
+        }
+        let count = |tag, class: &str| {
+            counts
+                .get(&(tag, Some(class.to_string())))
+                .copied()
+                .unwrap_or_default()
+        };
+        assert_eq!(count("circle", "unit-circle"), 1);
+        assert_eq!(count("rect", "deadzone"), 1);
+        assert_eq!(
+            coordinates::Category::ALL.map(|category| count("circle", category.class())),
+            [17, 30, 32],
+            "{}",
+            svg
+        );
+        // Mod X and Mod Y each have four C-stick angles in every quadrant.
+        assert_eq!(count("text", "angle"), 32);
+        assert!(svg.contains(">27.4°</text>"), "{}", svg);
+        assert!(svg.contains(
+            r##"cx="0.7000" cy="-0.3625" r="0.012" fill="#d62728"><title>(0.7000, 0.3625) [56, 29] right+up, Mod X, C-down</title>"##
+        ));
+    }
+
+    #[test]
+    fn unit_a_stick_scale_matches_default() {
+        let profile = config::Profile {
+            a_stick_scale: config::Scale::new(1.0).unwrap(),
+            ..Default::default()
+        };
+        for crouch_walk_option_select in [false, true] {
+            assert_eq!(
+                coordinates::dump(&Main::new(&profile), crouch_walk_option_select),
+                coordinates::dump(&Main::default(), crouch_walk_option_select)
+            );
+        }
+    }
+
+    // Only the A-stick is scaled, after the coordinates are chosen.
+    #[test_case(&[B0xxRaw::Right], &[Input::Stick(Stick::A, (P8500, P0000))]; "cardinal")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::Right, B0xxRaw::Up], &[Input::Stick(Stick::A, (P6250, P2625))]; "mod_x_diagonal")]
+    #[test_case(&[B0xxRaw::CR], &[Input::Stick(Stick::C, (Analog::MAX, P0000))]; "c_stick")]
+    #[test_case(&[B0xxRaw::MX, B0xxRaw::MY, B0xxRaw::CU], &[Input::Button(Button::DPad(Axis::Y, POSITIVE), PRESSED)]; "dpad")]
+    #[test_case(&[B0xxRaw::LS], &[Input::Trigger(LS)]; "trigger")]
+    fn a_stick_scale(buttons: &[B0xxRaw], want: &[Input]) {
+        let mut main = Main::new(&config::Profile {
+            a_stick_scale: config::Scale::new(0.85).unwrap(),
+            ..Default::default()
+        });
+        assert_eq!(press_all(&mut main, buttons, false)[..], *want);
+    }
+
+    #[test_case(&[
+        (B0xxRaw::Up, PRESSED),
+        (B0xxRaw::B, PRESSED),
+        (B0xxRaw::Up, RELEASED),
+        (B0xxRaw::B, RELEASED),
+    ], &[]; "all arrive")]
+    #[test_case(&[
+        (B0xxRaw::B, PRESSED),
+        (B0xxRaw::Up, PRESSED),
+        (B0xxRaw::B, RELEASED),
+        (B0xxRaw::Up, RELEASED),
+    ], &[]; "all arrive in another order")]
+    #[test_case(&[
+        (B0xxRaw::Up, PRESSED),
+        (B0xxRaw::A, PRESSED),
+        (B0xxRaw::A, RELEASED),
+        (B0xxRaw::Up, RELEASED),
+    ], &[B0xxRaw::B]; "dropped")]
+    fn ghosting_check(events: &[(B0xxRaw, Pressed)], missing: &[B0xxRaw]) {
+        let mut check = ghosting::Check::new(&[B0xxRaw::Up, B0xxRaw::B]);
+        let (last, events) = events.split_last().unwrap();
+        for &(btn, pressed) in events {
+            assert!(!check.event(btn, pressed), "{:?}", check);
+        }
+        assert!(check.event(last.0, last.1), "{:?}", check);
+        assert_eq!(check.missing(), missing);
+    }
+
+    #[test]
+    fn ghosting_check_waits_for_press() {
+        let mut check = ghosting::Check::new(&[B0xxRaw::Up, B0xxRaw::B]);
+        assert!(!check.event(B0xxRaw::A, PRESSED));
+        assert!(!check.event(B0xxRaw::A, RELEASED));
+        assert!(!check.event(B0xxRaw::Up, RELEASED));
+        assert!(!check.event(B0xxRaw::Up, PRESSED));
+        assert!(check.event(B0xxRaw::Up, RELEASED));
+    }
+
+    /// Synthetic devices: a laptop's internal keyboard, an external one and
+    /// one that reports nothing but its name.
+    fn device(index: usize) -> identify::Descriptor {
+        let (name, phys, uniq) = [
+            (
+                "AT Translated Set 2 keyboard",
+                Some("isa0060/serio0/input0"),
+                None,
+            ),
+            (
+                "Keychron K6",
+                Some("usb-0000:00:14.0-2/input0"),
+                Some("dc:2c:26:0a:1b:2c"),
+            ),
+            ("Virtual Keyboard", None, None),
+        ][index];
+        identify::Descriptor {
+            name: Some(String::from(name)),
+            phys: phys.map(String::from),
+            uniq: uniq.map(String::from),
+        }
+    }
+
+    #[test_case(&[], &[], 0, None; "no filter")]
+    #[test_case(&["AT Translated*"], &[], 0, Some("name \"AT Translated Set 2 keyboard\" matches ignored pattern \"AT Translated*\""); "ignored by name")]
+    #[test_case(&["isa*"], &[], 0, Some("phys \"isa0060/serio0/input0\" matches ignored pattern \"isa*\""); "ignored by phys")]
+    #[test_case(&["dc:2c:*"], &[], 1, Some("uniq \"dc:2c:26:0a:1b:2c\" matches ignored pattern \"dc:2c:*\""); "ignored by uniq")]
+    #[test_case(&["AT Translated*"], &[], 1, None; "other ignored")]
+    #[test_case(&["*/input?"], &[], 2, None; "missing fields never match")]
+    #[test_case(&[], &["usb-*"], 1, None; "allowed")]
+    #[test_case(&[], &["usb-*"], 0, Some("matches no allowed pattern"); "not allowed")]
+    #[test_case(&[], &["usb-*"], 2, Some("matches no allowed pattern"); "not allowed without phys")]
+    #[test_case(&["*K6"], &["usb-*"], 1, Some("name \"Keychron K6\" matches ignored pattern \"*K6\""); "allowed but ignored")]
+    #[test_case(&[], &["Keychron*", "Virtual*"], 2, None; "any allowed pattern")]
+    fn device_exclusion(ignore: &[&str], allow: &[&str], index: usize, want: Option<&str>) {
+        let patterns = |globs: &[&str]| {
+            globs
+                .iter()
+                .map(|glob| glob.parse().unwrap())
+                .collect::<Vec<config::DevicePattern>>()
+        };
+        let filter = config::DeviceFilter {
+            ignore: patterns(ignore),
+            allow: patterns(allow),
+        };
+        assert_eq!(
+            identify::exclusion(&filter, &device(index)).as_deref(),
+            want
+        );
+    }
+
+    // Only a key press picks a device; other events, releases and autorepeat
+    // don't.
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[]; "nothing")]
+    #[test_case(&[(EventCode::EV_KEY(EV_KEY::KEY_A), 0), (EventCode::EV_KEY(EV_KEY::KEY_A), 2)]; "release and autorepeat")]
+    #[test_case(&[(EventCode::EV_REL(EV_REL::REL_X), 1), (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)]; "other events")]
+    fn first_key_press(other: &[(EventCode, i32)]) {
+        let event = |&(code, value): &(EventCode, i32)| {
+            Ok(evdev_rs::InputEvent::new(
+                &evdev_rs::TimeVal::new(0, 0),
+                &code,
+                value,
+            ))
+        };
+        let paths = [
+            std::path::PathBuf::from("/dev/input/event0"),
+            std::path::PathBuf::from("/dev/input/event1"),
+        ];
+        let devices = vec![
+            (
+                paths[0].clone(),
+                futures::stream::iter(other.iter().map(event).collect::<Vec<_>>()),
+            ),
+            (
+                paths[1].clone(),
+                futures::stream::iter(vec![
+                    event(&(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)),
+                    event(&(EventCode::EV_KEY(EV_KEY::KEY_SPACE), 1)),
+                ]),
+            ),
+        ];
+        let got = futures::executor::block_on(identify::first_key_press(devices));
+        assert_eq!(got.ok().as_ref(), Some(&paths[1]));
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn first_key_press_disconnected() {
+        let devices = vec![(
+            std::path::PathBuf::from("/dev/input/event0"),
+            futures::stream::iter(Vec::<std::io::Result<evdev_rs::InputEvent>>::new()),
+        )];
+        assert!(futures::executor::block_on(identify::first_key_press(devices)).is_err());
+    }
+
+    // Identification is retried with the waits doubling up to the cap, until
+    // the keyboard appears or the waits add up to the timeout.
+    #[test_case(2, None, true, &[250, 500]; "found")]
+    #[test_case(7, None, true, &[250, 500, 1000, 2000, 4000, 5000, 5000]; "capped")]
+    #[test_case(0, Some(0), true, &[]; "found at once")]
+    #[test_case(1, Some(0), false, &[]; "no wait")]
+    #[test_case(9, Some(1), false, &[250, 500, 250]; "timed out")]
+    #[test_case(3, Some(1), true, &[250, 500, 250]; "found at the timeout")]
+    fn identify_retry(failures: usize, timeout: Option<u64>, found: bool, waits: &[u64]) {
+        let attempts = std::cell::Cell::new(0);
+        let waited = std::cell::RefCell::new(Vec::new());
+        let got = futures::executor::block_on(identify::retry(
+            || {
+                attempts.set(attempts.get() + 1);
+                let appeared = attempts.get() > failures;
+                async move {
+                    appeared
+                        .then(|| std::path::PathBuf::from("/dev/input/event3"))
+                        .ok_or_else(|| identify::NotFound.into())
+                }
+            },
+            timeout.map(Duration::from_secs),
+            |delay| {
+                waited.borrow_mut().push(delay);
+                futures::future::ready(())
+            },
+        ));
+        let want = if found {
+            Ok(std::path::PathBuf::from("/dev/input/event3"))
+        } else {
+            Err(identify::NotFound.to_string())
+        };
+        assert_eq!(got.map_err(|e| e.to_string()), want);
+        assert_eq!(
+            waited.into_inner(),
+            waits
+                .iter()
+                .copied()
+                .map(Duration::from_millis)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    // Only a keyboard not found is retried, and other errors, e.g. the devices
+    // being unreadable, fail at once.
+    #[test]
+    fn identify_retry_permission_denied() {
+        let attempts = std::cell::Cell::new(0);
+        let got = futures::executor::block_on(identify::retry(
+            || {
+                attempts.set(attempts.get() + 1);
+                futures::future::ready(Err::<std::path::PathBuf, _>(
+                    anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+                        .context("failed to open /dev/input/event3"),
+                ))
+            },
+            None,
+            |_| -> futures::future::Ready<()> { unreachable!("no retry") },
+        ));
+        let e = got.unwrap_err();
+        assert_eq!(
+            e.root_cause()
+                .downcast_ref::<std::io::Error>()
+                .map(std::io::Error::kind),
+            Some(std::io::ErrorKind::PermissionDenied)
+        );
+        assert_eq!(attempts.get(), 1);
+    }
+
+    // Each line splits into the path, key and action, then the quoted name.
+    #[cfg(feature = "linux-input")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_J), 1, Some("AT Translated Set 2 keyboard"), Some(("KEY_J", "pressed", "AT Translated Set 2 keyboard")); "pressed")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_SPACE), 0, None, Some(("KEY_SPACE", "released", "unnamed")); "released unnamed")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_J), 2, Some("kbd"), Some(("KEY_J", "repeated", "kbd")); "repeated")]
+    #[test_case(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0, Some("kbd"), None; "other")]
+    fn probe_line(
+        code: EventCode,
+        value: i32,
+        name: Option<&str>,
+        want: Option<(&str, &str, &str)>,
+    ) {
+        let path = std::path::Path::new("/dev/input/event3");
+        let event = evdev_rs::InputEvent::new(&evdev_rs::TimeVal::new(0, 0), &code, value);
+        let line = identify::probe_line(path, name, &event);
+        let parsed = line.as_deref().map(|line| {
+            let (fields, name) = line.split_once(" \"").unwrap();
+            let fields = fields.split(' ').collect::<Vec<_>>();
+            assert_eq!(fields.len(), 3, "{}", line);
+            assert_eq!(fields[0], "/dev/input/event3");
+            (fields[1], fields[2], name.strip_suffix('"').unwrap())
+        });
+        assert_eq!(parsed, want);
+    }
+
+    // A device failing stops only its own lines.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn probe_disconnected() {
+        let event = |key, value| {
+            Ok(evdev_rs::InputEvent::new(
+                &evdev_rs::TimeVal::new(0, 0),
+                &EventCode::EV_KEY(key),
+                value,
+            ))
+        };
+        let devices = vec![
+            (
+                std::path::PathBuf::from("/dev/input/event0"),
+                Some(String::from("unplugged")),
+                futures::stream::iter(vec![
+                    event(EV_KEY::KEY_J, 1),
+                    Err(std::io::Error::from_raw_os_error(libc::ENODEV)),
+                    event(EV_KEY::KEY_J, 0),
+                ]),
+            ),
+            (
+                std::path::PathBuf::from("/dev/input/event1"),
+                Some(String::from("kbd")),
+                futures::stream::iter(vec![event(EV_KEY::KEY_K, 1), event(EV_KEY::KEY_K, 0)]),
+            ),
+        ];
+        let mut lines = Vec::new();
+        let result = futures::executor::block_on(identify::key_events(
+            devices,
+            Duration::from_secs(30),
+            |line| lines.push(line),
+        ));
+        assert!(result.is_err());
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "/dev/input/event0 KEY_J pressed \"unplugged\"",
+                "/dev/input/event1 KEY_K pressed \"kbd\"",
+                "/dev/input/event1 KEY_K released \"kbd\"",
+            ]
+        );
+    }
+
+    #[cfg(feature = "linux-input")]
+    fn event_dump_remapper() -> Remapper {
+        Remapper {
+            focus: [EventCode::EV_KEY(EV_KEY::KEY_LEFTMETA)].into(),
+            ..Remapper::new(
+                [
+                    ("KEY_J", B0xxRaw::A),
+                    ("SW_LID", B0xxRaw::Start),
+                    ("ABS_HAT0X-", B0xxRaw::CL),
+                ]
+                .into_iter()
+                .map(|(name, btn)| (config::source(name).unwrap(), btn))
+                .collect(),
+            )
+        }
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_J), 1, "1700000000.000123 EV_KEY KEY_J 1 A"; "bound key")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_J), 0, "1700000000.000123 EV_KEY KEY_J 0 A"; "release")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_K), 2, "1700000000.000123 EV_KEY KEY_K 2 -"; "unbound key")]
+    #[test_case(EventCode::EV_KEY(EV_KEY::KEY_LEFTMETA), 1, "1700000000.000123 EV_KEY KEY_LEFTMETA 1 focus"; "focus key")]
+    #[test_case(EventCode::EV_SW(EV_SW::SW_LID), 1, "1700000000.000123 EV_SW SW_LID 1 Start"; "switch")]
+    #[test_case(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), -1, "1700000000.000123 EV_ABS ABS_HAT0X -1 CL"; "hat")]
+    #[test_case(EventCode::EV_ABS(EV_ABS::ABS_HAT0X), 0, "1700000000.000123 EV_ABS ABS_HAT0X 0 -"; "hat centred")]
+    #[test_case(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0, "1700000000.000123 EV_SYN SYN_REPORT 0 -"; "sync")]
+    fn event_dump_line(code: EventCode, value: i32, want: &str) {
+        let event =
+            evdev_rs::InputEvent::new(&evdev_rs::TimeVal::new(1_700_000_000, 123), &code, value);
+        assert_eq!(event_dump::line(&event, &event_dump_remapper()), want);
+    }
+
+    // Exiting stops the dump cleanly after the events so far, where the
+    // keyboard ending is an error.
+    #[cfg(feature = "linux-input")]
+    #[test_case(true; "exit")]
+    #[test_case(false; "disconnected")]
+    fn event_dump_run(exit: bool) {
+        let events =
+            [(EV_KEY::KEY_J, 1), (EV_KEY::KEY_K, 1), (EV_KEY::KEY_J, 0)].map(|(key, value)| {
+                Ok(evdev_rs::InputEvent::new(
+                    &evdev_rs::TimeVal::new(0, 0),
+                    &EventCode::EV_KEY(key),
+                    value,
+                ))
+            });
+        let keyboard = futures::stream::iter(events).chain(futures::stream::iter(
+            (!exit).then(|| Err(std::io::Error::from_raw_os_error(libc::ENODEV))),
+        ));
+        // Ctrl-C arrives once the events have been printed.
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let mut lines = Vec::new();
+        let result = futures::executor::block_on(event_dump::run(
+            keyboard.chain(futures::stream::pending()),
+            receiver,
+            &event_dump_remapper(),
+            |line| {
+                lines.push(line);
+                if exit && lines.len() == 3 {
+                    sender.unbounded_send(()).unwrap();
+                }
+            },
+        ));
+        assert_eq!(result.is_ok(), exit);
+        assert_eq!(
+            lines,
+            [
+                "0.000000 EV_KEY KEY_J 1 A",
+                "0.000000 EV_KEY KEY_K 1 -",
+                "0.000000 EV_KEY KEY_J 0 A",
+            ]
+        );
+    }
+
+    #[test_case(&[
+        (B0xxRaw::Left, PRESSED),
+        (B0xxRaw::Down, PRESSED),
+        (B0xxRaw::Left, PRESSED),
+    ], &[(B0xxRaw::Left, 1)]; "repeated press")]
+    #[test_case(&[
+        (B0xxRaw::Left, PRESSED),
+        (B0xxRaw::Left, RELEASED),
+        (B0xxRaw::Left, PRESSED),
+    ], &[]; "released in between")]
+    #[test_case(&[
+        (B0xxRaw::Left, PRESSED),
+        (B0xxRaw::Right, PRESSED),
+        (B0xxRaw::Left, PRESSED),
+    ], &[]; "overridden")]
+    #[test_case(&[
+        (B0xxRaw::CU, PRESSED),
+        (B0xxRaw::CU, PRESSED),
+        (B0xxRaw::A, PRESSED),
+        (B0xxRaw::A, PRESSED),
+    ], &[(B0xxRaw::CU, 1)]; "c-stick")]
+    fn rollover_suspects(events: &[(B0xxRaw, Pressed)], suspects: &[(B0xxRaw, u64)]) {
+        let mut main = Main::default();
+        for &(btn, pressed) in events {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false);
+        }
+        assert_eq!(
+            main.stats.rollover_suspects.counted().collect::<Vec<_>>(),
+            suspects
+        );
+    }
+
+    #[test_case(LevelFilter::INFO, LevelFilter::DEBUG; "info")]
+    #[test_case(LevelFilter::DEBUG, LevelFilter::TRACE; "debug")]
+    #[test_case(LevelFilter::TRACE, LevelFilter::INFO; "trace")]
+    #[test_case(LevelFilter::WARN, LevelFilter::INFO; "warn")]
+    fn next_log_level_cycles(level: LevelFilter, next: LevelFilter) {
+        assert_eq!(next_log_level(level), next);
+    }
+
+    // Digital L/R and the analog shield go to the opposite triggers when
+    // swapped, and the extended pipe also writes the analog value of the
+    // trigger free of the shield.
+    #[test_case(config::Handedness::Standard, config::PipeCapability::Standard, "L", "R"; "standard")]
+    #[test_case(config::Handedness::Swapped, config::PipeCapability::Standard, "R", "L"; "swapped")]
+    #[test_case(config::Handedness::Standard, config::PipeCapability::Extended, "L", "R"; "standard_extended")]
+    #[test_case(config::Handedness::Swapped, config::PipeCapability::Extended, "R", "L"; "swapped_extended")]
+    fn handedness(
+        handedness: config::Handedness,
+        capability: config::PipeCapability,
+        l: &str,
+        r: &str,
     ) {
-        for x in [POSITIVE, NEGATIVE] {
-            for y in [POSITIVE, NEGATIVE] {
-                let mut buttons = buttons
+        let mut main = Main::default();
+        let commands = [
+            (B0xxRaw::LS, PRESSED),
+            (B0xxRaw::L, PRESSED),
+            (B0xxRaw::R, PRESSED),
+            (B0xxRaw::L, RELEASED),
+            (B0xxRaw::R, RELEASED),
+            (B0xxRaw::LS, RELEASED),
+        ]
+        .into_iter()
+        .flat_map(|(btn, pressed)| {
+            main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false)
+        })
+        .map(|input| {
+            input
+                .into_pipe_input()
+                .into_input_string(handedness, Game::Melee, capability)
+        })
+        .collect::<Vec<_>>();
+        let (r_pressed, r_released) = match capability {
+            config::PipeCapability::Standard => (String::new(), String::new()),
+            config::PipeCapability::Extended => {
+                (format!("SET {} 1\n", r), format!("SET {} 0\n", r))
+            }
+        };
+        assert_eq!(
+            commands,
+            [
+                format!("SET {} 0.3828125\n", l),
+                format!("SET {} 0\n", l),
+                format!("PRESS {}\n", l),
+                format!("PRESS {}\n{}", r, r_pressed),
+                format!("RELEASE {}\n", l),
+                format!("RELEASE {}\n{}", r, r_released),
+                format!("SET {} 0.3828125\n", l),
+                format!("SET {} 0\n", l),
+            ]
+        );
+    }
+
+    // Melee reads 128 steps below the centre and 127 above, while other
+    // platform fighters take the b0xx's range as the whole of the stick's.
+    #[test_case(Game::Melee, "SET MAIN 0.1875 0.5\n", "SET MAIN 0.8149606299212598 0.5\n"; "melee")]
+    #[test_case(Game::PlatformFighter, "SET MAIN 0 0.5\n", "SET MAIN 1 0.5\n"; "platform_fighter")]
+    fn game_stick_values(game: Game, left: &str, right: &str) {
+        for capability in [
+            config::PipeCapability::Standard,
+            config::PipeCapability::Extended,
+        ] {
+            let command = |x| {
+                DolphinPipeInput::Stick(Stick::A, (x, P0000)).into_input_string(
+                    config::Handedness::Standard,
+                    game,
+                    capability,
+                )
+            };
+            assert_eq!(command(-Analog::MAX), left);
+            assert_eq!(command(Analog::MAX), right);
+        }
+        assert_eq!(game.stick_value(P0000), 0.5);
+    }
+
+    // Only Melee refines the Mod X diagonal by the C-stick and applies the
+    // crouch/walk option-select.
+    #[test_case(Game::Melee, (P7000, -P3625), true; "melee")]
+    #[test_case(Game::PlatformFighter, (P7375, -P3125), false; "platform_fighter")]
+    fn game_refinements(game: Game, firefox: AStickInput, crouch_walk: bool) {
+        let mut main = Main {
+            game,
+            ..Default::default()
+        };
+        let _ = press_all(
+            &mut main,
+            &[B0xxRaw::MX, B0xxRaw::Right, B0xxRaw::Down, B0xxRaw::CD],
+            false,
+        );
+        assert_eq!(main.a_stick_coordinates(false), firefox);
+
+        let mut main = Main {
+            game,
+            ..Default::default()
+        };
+        let _ = press_all(&mut main, &[B0xxRaw::Right, B0xxRaw::Down], true);
+        let (x, y) = if crouch_walk {
+            main.coordinates.crouch_walk_diagonal
+        } else {
+            (P7000, P7000)
+        };
+        assert_eq!(main.a_stick_coordinates(true), (x, -y));
+    }
+
+    #[test]
+    fn command_log_analysis() {
+        let frames = frame::FrameClock::new(
+            frame::Rate::new(60.).unwrap(),
+            frame::Epoch::Start,
+            Timestamp::default(),
+        );
+        let log = [
+            (0, Input::Button(Button::Pure(ButtonPure::A), PRESSED)),
+            (1_000, Input::Stick(Stick::A, (Analog::MAX, P0000))),
+            (1_000, Input::Trigger(LS)),
+            (17_000, Input::Stick(Stick::A, (P0000, P0000))),
+            (20_000, Input::Button(Button::Pure(ButtonPure::A), RELEASED)),
+        ]
+        .into_iter()
+        .map(|(micros, input)| {
+            let time = Timestamp::default() + Duration::from_micros(micros);
+            command_log::line(
+                time,
+                frames.frame(time),
+                &input.into_pipe_input().into_input_string(
+                    config::Handedness::Standard,
+                    Game::Melee,
+                    config::PipeCapability::Standard,
+                ),
+            )
+        })
+        .collect::<String>();
+        let analysis = command_log::analyze(&log).unwrap();
+        assert_eq!(
+            analysis
+                .counts
+                .iter()
+                .map(|(command, &n)| (command.as_str(), n))
+                .collect::<Vec<_>>(),
+            [
+                ("PRESS A", 1),
+                ("RELEASE A", 1),
+                ("SET L", 1),
+                ("SET MAIN", 2)
+            ]
+        );
+        assert_eq!(analysis.gaps.count, 4);
+        assert_eq!(analysis.gaps.max, 16_000);
+        // Within the histogram's precision.
+        assert_eq!(
+            analysis.gaps.quantile(0.5),
+            Some(Duration::from_micros(1_023))
+        );
+    }
+
+    // Logs written before frames were numbered still parse.
+    #[test]
+    fn command_log_frames_optional() {
+        let analysis =
+            command_log::analyze("12 0 SET MAIN 0.5 0.5\n13 PRESS A\n14 - RELEASE A\n").unwrap();
+        assert_eq!(
+            analysis
+                .counts
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["PRESS A", "RELEASE A", "SET MAIN"]
+        );
+    }
+
+    #[test_case("12 SET MAIN 0.5 0.5\nPRESS A\n", "line 2"; "missing time")]
+    #[test_case("12 JUMP\n", "line 1"; "unknown command")]
+    #[test_case("12 3 JUMP\n", "line 1"; "unknown command after frame")]
+    #[test_case("-1 PRESS A\n", "line 1"; "negative time")]
+    fn command_log_rejects(log: &str, error: &str) {
+        let e = command_log::analyze(log).unwrap_err().to_string();
+        assert!(e.starts_with(error), "{}", e);
+    }
+
+    #[cfg(feature = "linux-input")]
+    impl Sink for Vec<DolphinPipeInput> {
+        fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+            self.push(pipe_input);
+            Ok(())
+        }
+    }
+
+    /// Fails every write.
+    #[cfg(feature = "linux-input")]
+    struct BrokenPipe;
+
+    #[cfg(feature = "linux-input")]
+    impl Sink for BrokenPipe {
+        fn send(&mut self, _: DolphinPipeInput) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("broken pipe"))
+        }
+    }
+
+    #[cfg(feature = "linux-input")]
+    fn remap_events(
+        events: Vec<anyhow::Result<remap::Event>>,
+    ) -> impl futures::Stream<Item = anyhow::Result<remap::Event>> + Unpin {
+        futures::stream::iter(events)
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run() {
+        let press = |btn, pressed| {
+            Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                btn, pressed,
+            )))
+        };
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(vec![
+                press(B0xxRaw::A, PRESSED),
+                Ok(remap::Event::Snapshot),
+                press(B0xxRaw::Right, PRESSED),
+                press(B0xxRaw::A, RELEASED),
+                Ok(remap::Event::Exit),
+                press(B0xxRaw::B, PRESSED),
+            ]),
+            &mut sink,
+            Default::default(),
+        ))
+        .unwrap();
+        let resync = Main::default().resync();
+        assert_eq!(sink[..resync.len()], resync);
+        assert_eq!(
+            sink[resync.len()..],
+            [
+                DolphinPipeInput::Button(GCButton::A, PRESSED),
+                DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000)),
+                DolphinPipeInput::Button(GCButton::A, RELEASED),
+            ]
+        );
+        assert_eq!(main.held, [B0xxRaw::Right].into());
+    }
+
+    // Pausing, e.g. on a focus key, leaves the controller neutral while the
+    // buttons are still tracked, and resuming catches up with them.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_pause() {
+        let press = |btn, pressed| {
+            Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                btn, pressed,
+            )))
+        };
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(vec![
+                press(B0xxRaw::Right, PRESSED),
+                Ok(remap::Event::Pause),
+                press(B0xxRaw::Right, RELEASED),
+                press(B0xxRaw::A, PRESSED),
+                press(B0xxRaw::Up, PRESSED),
+                Ok(remap::Event::Pause),
+            ]),
+            &mut sink,
+            Default::default(),
+        ))
+        .unwrap();
+        let resync = Main::default().resync();
+        assert_eq!(
+            sink[resync.len()..],
+            [
+                DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000)),
+                DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+                DolphinPipeInput::Button(GCButton::A, PRESSED),
+                DolphinPipeInput::Stick(Stick::A, (P0000, Analog::MAX)),
+            ]
+        );
+    }
+
+    // Waking from idle processes the buttons pressed meanwhile before the
+    // event that woke it.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_wakes_from_idle() {
+        let mut idle = idle::IdleWatch::new(
+            Timestamp::default(),
+            Duration::from_secs(1),
+            Box::new(|now| {
+                Ok(vec![B0xxEvent {
+                    time: now,
+                    btn: B0xxRaw::Right,
+                    pressed: PRESSED,
+                }])
+            }),
+        );
+        assert!(idle.process_deadline(Timestamp::default() + Duration::from_secs(1), true));
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(vec![Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                B0xxRaw::A,
+                PRESSED,
+            )))]),
+            &mut sink,
+            remap::Options {
+                idle: Some(idle),
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let resync = Main::default().resync();
+        assert_eq!(
+            sink[resync.len()..],
+            [
+                DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000)),
+                DolphinPipeInput::Button(GCButton::A, PRESSED),
+            ]
+        );
+        assert_eq!(main.held, [B0xxRaw::A, B0xxRaw::Right].into());
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_errors() {
+        let result = futures::executor::block_on(remap::run(
+            &mut Main::default(),
+            remap_events(vec![Err(anyhow::anyhow!("keyboard disconnected"))]),
+            &mut Vec::new(),
+            Default::default(),
+        ));
+        assert!(
+            matches!(&result, Err(remap::Error::Events(e)) if e.to_string() == "keyboard disconnected"),
+            "{:?}",
+            result
+        );
+        let result = futures::executor::block_on(remap::run(
+            &mut Main::default(),
+            remap_events(Vec::new()),
+            BrokenPipe,
+            Default::default(),
+        ));
+        assert!(matches!(result, Err(remap::Error::Sink(_))), "{:?}", result);
+    }
+
+    /// Fails the writes and reconnects scripted to, in turn, and succeeds
+    /// otherwise.
+    #[cfg(feature = "linux-input")]
+    #[derive(Default)]
+    struct FlakySink {
+        writes: std::collections::VecDeque<bool>,
+        reconnects: std::collections::VecDeque<bool>,
+        written: Vec<DolphinPipeInput>,
+    }
+
+    #[cfg(feature = "linux-input")]
+    impl Sink for FlakySink {
+        fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+            if self.writes.pop_front().unwrap_or(false) {
+                return Err(anyhow::anyhow!("write failed"));
+            }
+            self.written.push(pipe_input);
+            Ok(())
+        }
+
+        fn reconnect(&mut self) -> anyhow::Result<()> {
+            if self.reconnects.pop_front().unwrap_or(false) {
+                return Err(anyhow::anyhow!("reconnect failed"));
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(
+        health::Policy::Fatal,
+        &[false, true],
+        &[],
+        &[true, false],
+        health::Health {
+            status: health::Status::Failed,
+            sent: 1,
+            errors: 1,
+            dropped: 0,
+            reconnects: 0,
+        };
+        "fatal"
+    )]
+    #[test_case(
+        health::Policy::Drop,
+        &[true, true, false],
+        &[],
+        &[true, true, true],
+        health::Health {
+            status: health::Status::Healthy,
+            sent: 1,
+            errors: 2,
+            dropped: 2,
+            reconnects: 0,
+        };
+        "drop_recovered"
+    )]
+    #[test_case(
+        health::Policy::Drop,
+        &[false, true],
+        &[],
+        &[true, true],
+        health::Health {
+            status: health::Status::Failing,
+            sent: 1,
+            errors: 1,
+            dropped: 1,
+            reconnects: 0,
+        };
+        "drop_failing"
+    )]
+    #[test_case(
+        health::Policy::Retry,
+        &[true],
+        &[],
+        &[true],
+        health::Health {
+            status: health::Status::Healthy,
+            sent: 1,
+            errors: 1,
+            dropped: 0,
+            reconnects: 1,
+        };
+        "retry_reconnected"
+    )]
+    #[test_case(
+        health::Policy::Retry,
+        &[true, true],
+        &[true],
+        &[true, true],
+        health::Health {
+            status: health::Status::Healthy,
+            sent: 1,
+            errors: 3,
+            dropped: 1,
+            reconnects: 1,
+        };
+        "retry_reconnect_failed"
+    )]
+    #[test_case(
+        health::Policy::Retry,
+        &[true, true],
+        &[],
+        &[true],
+        health::Health {
+            status: health::Status::Failing,
+            sent: 0,
+            errors: 2,
+            dropped: 1,
+            reconnects: 1,
+        };
+        "retry_resend_failed"
+    )]
+    fn sink_policy(
+        policy: health::Policy,
+        writes: &[bool],
+        reconnects: &[bool],
+        results: &[bool],
+        want: health::Health,
+    ) {
+        let mut flaky = FlakySink {
+            writes: writes.iter().copied().collect(),
+            reconnects: reconnects.iter().copied().collect(),
+            ..Default::default()
+        };
+        let mut sink = health::Monitored::new("flaky", &mut flaky, policy);
+        for (i, &ok) in results.iter().enumerate() {
+            let pipe_input = DolphinPipeInput::Button(GCButton::A, i % 2 == 0);
+            assert_eq!(sink.send(pipe_input).is_ok(), ok, "send {}", i);
+        }
+        assert_eq!(sink.health(), [("flaky", want)].into());
+        assert_eq!(flaky.written.len() as u64, want.sent);
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn reader_queue_drops_oldest() {
+        let (sender, receiver) = pipeline::queue(2, pipeline::Overflow::DropOldest);
+        let stats = receiver.stats();
+        for i in 0..5 {
+            assert!(sender.push(i));
+        }
+        drop(sender);
+        assert_eq!(
+            stats.get(),
+            pipeline::QueueStats {
+                capacity: 2,
+                depth: 2,
+                max_depth: 2,
+                dropped: 3,
+            }
+        );
+        let received: Vec<i32> = futures::executor::block_on(receiver.collect());
+        assert_eq!(received, [3, 4]);
+        assert_eq!(stats.get().depth, 0);
+    }
+
+    // A sink that drops what it fails to write leaves the session running.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_drops() {
+        let mut main = Main::default();
+        let mut sink = health::Monitored::new("pipe", BrokenPipe, health::Policy::Drop);
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(vec![Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                B0xxRaw::A,
+                PRESSED,
+            )))]),
+            &mut sink,
+            Default::default(),
+        ))
+        .unwrap();
+        let resync = Main::default().resync().len() as u64;
+        assert_eq!(
+            sink.health(),
+            [(
+                "pipe",
+                health::Health {
+                    status: health::Status::Failing,
+                    sent: 0,
+                    errors: resync + 1,
+                    dropped: resync + 1,
+                    reconnects: 0,
+                }
+            )]
+            .into()
+        );
+    }
+
+    // A subscriber that never catches up misses events, but the sink gets
+    // every write regardless.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_publishes() {
+        let toggles = [
+            (B0xxRaw::A, PRESSED),
+            (B0xxRaw::Right, PRESSED),
+            (B0xxRaw::A, RELEASED),
+            (B0xxRaw::Right, RELEASED),
+        ];
+        let mut publisher = remap::Publisher::default();
+        let mut prompt = publisher.subscribe(toggles.len());
+        let mut slow = publisher.subscribe(1);
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut Main::default(),
+            remap_events(
+                toggles
                     .iter()
-                    .copied()
-                    .chain(
-                        [(Stick::A, Axis::X, x).into(), (Stick::A, Axis::Y, y).into()].into_iter(),
+                    .map(|&(btn, pressed)| {
+                        Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                            btn, pressed,
+                        )))
+                    })
+                    .collect(),
+            ),
+            &mut sink,
+            remap::Options {
+                publisher,
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let resync = Main::default().resync();
+        let written = [
+            DolphinPipeInput::Button(GCButton::A, PRESSED),
+            DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000)),
+            DolphinPipeInput::Button(GCButton::A, RELEASED),
+            DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+        ];
+        assert_eq!(sink[resync.len()..], written);
+        let drain = |subscriber: &mut remap::Subscriber| {
+            std::iter::from_fn(|| futures::executor::block_on(subscriber.next()))
+                .map(|processed| {
+                    let event = processed.event.expect("no deadlines");
+                    (
+                        (event.btn, event.pressed),
+                        processed
+                            .inputs
+                            .into_iter()
+                            .map(Input::into_pipe_input)
+                            .collect::<Vec<_>>(),
+                        processed.snapshot,
                     )
-                    .collect::<Vec<_>>();
-                let want = if y {
-                    (x_top.neg_not(x), y_top.neg_not(y))
+                })
+                .collect::<Vec<_>>()
+        };
+        let published = drain(&mut prompt);
+        assert_eq!(
+            published
+                .iter()
+                .map(|(toggle, _, _)| *toggle)
+                .collect::<Vec<_>>(),
+            toggles
+        );
+        assert_eq!(
+            published
+                .iter()
+                .flat_map(|(_, pipe_inputs, _)| pipe_inputs.clone())
+                .collect::<Vec<_>>(),
+            written
+        );
+        assert_eq!(
+            published[1].2,
+            ControllerSnapshot {
+                buttons: [GCButton::A].into(),
+                a_stick: (Analog::MAX, P0000),
+                ..Default::default()
+            }
+        );
+        assert_eq!(prompt.dropped(), 0);
+        assert_eq!(drain(&mut slow), published[..1]);
+        assert_eq!(slow.dropped(), toggles.len() as u64 - 1);
+    }
+
+    /// A config with the default profile and `fox`, which has a turbo and
+    /// the character `falco`.
+    #[cfg(feature = "linux-input")]
+    fn control_profiles(rebinds: std::rc::Rc<std::cell::Cell<usize>>) -> remap::Profiles {
+        let fox = config::Profile {
+            turbo: Some(config::Turbo {
+                button: B0xxRaw::A,
+                rate_hz: 20.0,
+                duty: 0.5,
+            }),
+            characters: config::Characters(
+                [(
+                    String::from("falco"),
+                    config::CoordinateOverrides {
+                        mod_tilt_diagonal: Some((P0000, P0000)),
+                        ..Default::default()
+                    },
+                )]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        remap::Profiles {
+            config: config::Config {
+                profile: [(String::from("fox"), fox)].into(),
+                ..Default::default()
+            },
+            disabled: Default::default(),
+            lockout: None,
+            // A's key stays held, and A is bound in every profile.
+            rebind: Box::new(move |_| {
+                rebinds.set(rebinds.get() + 1);
+                [B0xxRaw::A].into()
+            }),
+        }
+    }
+
+    /// The responses to the requests of `control_requests`, with A held
+    /// beforehand.
+    #[cfg(feature = "linux-input")]
+    fn control_responses() -> Vec<control::Response> {
+        let mut pressed = Main::default();
+        let _ = pressed.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::A, PRESSED), false);
+        let state = |profile: &str, held: &[B0xxRaw], paused, disabled: &[control::Feature]| {
+            control::Response::new(Ok(control::State {
+                profile: Some(profile.to_owned()),
+                paused,
+                disabled: disabled.to_vec(),
+                character: None,
+                crouch_walk_option_select: false,
+                socd: None,
+                held: held.to_vec(),
+                output: if held.is_empty() {
+                    Default::default()
                 } else {
-                    (x_bottom.neg_not(x), y_bottom.neg_not(y))
-                };
-                permutohedron::heap_recursive(&mut buttons, |buttons| {
-                    let mut main = Main::default();
-                    let got = buttons
-                        .iter()
-                        .fold(None, |_, &btn| {
-                            main.process_b0xx(
-                                B0xxEvent::new_without_time(btn, PRESSED),
-                                crouch_walk_option_select,
-                            )
-                        })
-                        .expect("final b0xx input resulted in null GC input");
-                    let got = match got {
-                        Input::ModifiedPress(a_stick, btn) => {
-                            assert_eq!(
-                                B0xx::Impure(Impure::Button(btn)),
-                                (*buttons.last().unwrap()).into()
-                            );
-                            a_stick
-                        }
-                        Input::Stick(Stick::A, a_stick) => a_stick,
-                        Input::CStickModifier { a, c: _ } => a,
-                        _ => panic!("unexpected GC input on final b0xx input: {:?}", got),
-                    };
-                    assert_eq!(got, want);
-                });
+                    pressed.output.clone()
+                },
+            }))
+        };
+        let error = |error: &str| control::Response::new(Err(error.to_owned()));
+        vec![
+            state("default", &[B0xxRaw::A], false, &[]),
+            error("profile \"default\" has no Turbo"),
+            error("no profile named \"nope\""),
+            state("fox", &[B0xxRaw::A], false, &[]),
+            state("fox", &[B0xxRaw::A], false, &[control::Feature::Turbo]),
+            state("fox", &[B0xxRaw::A], true, &[control::Feature::Turbo]),
+            state("fox", &[], true, &[control::Feature::Turbo]),
+            state("fox", &[], true, &[]),
+            error("profile has no character \"marth\""),
+            control::Response::new(Ok(control::State {
+                profile: Some(String::from("fox")),
+                paused: true,
+                disabled: Vec::new(),
+                character: Some(String::from("falco")),
+                crouch_walk_option_select: false,
+                socd: None,
+                held: Vec::new(),
+                output: Default::default(),
+            })),
+        ]
+    }
+
+    #[cfg(feature = "linux-input")]
+    fn control_requests() -> Vec<control::Request> {
+        let toggle = |feature| control::Request::Toggle { feature };
+        vec![
+            control::Request::GetState,
+            toggle(control::Feature::Turbo),
+            control::Request::SetProfile {
+                profile: String::from("nope"),
+            },
+            control::Request::SetProfile {
+                profile: String::from("fox"),
+            },
+            toggle(control::Feature::Turbo),
+            toggle(control::Feature::Pause),
+            control::Request::Reset,
+            toggle(control::Feature::Turbo),
+            control::Request::SetCharacter {
+                character: Some(String::from("marth")),
+            },
+            control::Request::SetCharacter {
+                character: Some(String::from("falco")),
+            },
+        ]
+    }
+
+    // Control requests go through `run` in order with the button events,
+    // and a button held through a profile switch stays held.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn remap_run_controls() {
+        let mut replies = Vec::new();
+        let mut events = vec![Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+            B0xxRaw::A,
+            PRESSED,
+        )))];
+        for request in control_requests() {
+            let (reply, response) = futures::channel::oneshot::channel();
+            events.push(Ok(remap::Event::Control(request, reply)));
+            replies.push(response);
+        }
+        let rebinds = std::rc::Rc::default();
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(events),
+            &mut sink,
+            remap::Options {
+                profiles: Some(control_profiles(std::rc::Rc::clone(&rebinds))),
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let responses = replies
+            .into_iter()
+            .map(|response| futures::executor::block_on(response).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses, control_responses());
+        assert_eq!(rebinds.get(), 1);
+        // A stayed pressed through the switches until pausing released it,
+        // and nothing after was written.
+        let resync = Main::default().resync();
+        assert_eq!(
+            sink[resync.len()..],
+            [
+                DolphinPipeInput::Button(GCButton::A, PRESSED),
+                DolphinPipeInput::Button(GCButton::A, RELEASED),
+            ]
+        );
+        assert!(main.turbo.is_some());
+        assert_eq!(main.character.as_deref(), Some("falco"));
+        assert_eq!(main.coordinates.mod_tilt_diagonal, (P0000, P0000));
+    }
+
+    // Selecting a character's coordinates with a modifier and a direction
+    // held writes only the A-stick coordinate that changed, rather than
+    // releasing everything and pressing it again.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn character_switch_writes_difference() {
+        let fox = config::Profile {
+            characters: config::Characters(
+                [(
+                    String::from("falco"),
+                    config::CoordinateOverrides {
+                        mod_tilt_diagonal: Some((P5000, P5000)),
+                        ..Default::default()
+                    },
+                )]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let held = [B0xxRaw::MT, B0xxRaw::Right, B0xxRaw::Up];
+        let mut events = held
+            .iter()
+            .map(|&btn| {
+                Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                    btn, PRESSED,
+                )))
+            })
+            .collect::<Vec<_>>();
+        let (reply, response) = futures::channel::oneshot::channel();
+        events.push(Ok(remap::Event::Control(
+            control::Request::SetCharacter {
+                character: Some(String::from("falco")),
+            },
+            reply,
+        )));
+        let mut main = Main::new(&fox);
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(events),
+            &mut sink,
+            remap::Options {
+                profiles: Some(remap::Profiles {
+                    config: config::Config {
+                        profile: [(String::from("fox"), fox.clone())].into(),
+                        ..Default::default()
+                    },
+                    disabled: Default::default(),
+                    lockout: None,
+                    rebind: Box::new(|_| unreachable!("no profile switched to")),
+                }),
+                runtime: remap::RuntimeOptions {
+                    profile: String::from("fox"),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let response = futures::executor::block_on(response).unwrap();
+        assert_eq!(response.error, None);
+        let mut before = Main::new(&fox);
+        let mut want = before.resync();
+        for btn in held {
+            want.extend(
+                before
+                    .process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
+                    .into_iter()
+                    .map(Input::into_pipe_input),
+            );
+        }
+        assert_eq!(before.output.a_stick, (P5375, P5375));
+        want.push(DolphinPipeInput::Stick(Stick::A, (P5000, P5000)));
+        assert_eq!(sink, want);
+        assert_eq!(main.held, held.into());
+        assert_eq!(main.output.a_stick, (P5000, P5000));
+    }
+
+    // Opposite directions held through a character switch are pressed again
+    // in the order they were, so that the second still wins and nothing is
+    // written.
+    #[cfg(feature = "linux-input")]
+    #[test_case(B0xxRaw::Right, B0xxRaw::Left; "right_then_left")]
+    #[test_case(B0xxRaw::Left, B0xxRaw::Right; "left_then_right")]
+    #[test_case(B0xxRaw::Up, B0xxRaw::Down; "up_then_down")]
+    #[test_case(B0xxRaw::Down, B0xxRaw::Up; "down_then_up")]
+    fn character_switch_keeps_press_order(first: B0xxRaw, second: B0xxRaw) {
+        let mut events = [first, second]
+            .into_iter()
+            .map(|btn| {
+                Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                    btn, PRESSED,
+                )))
+            })
+            .collect::<Vec<_>>();
+        let (reply, response) = futures::channel::oneshot::channel();
+        events.push(Ok(remap::Event::Control(
+            control::Request::SetCharacter {
+                character: Some(String::from("falco")),
+            },
+            reply,
+        )));
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(events),
+            &mut sink,
+            remap::Options {
+                profiles: Some(control_profiles(std::rc::Rc::default())),
+                runtime: remap::RuntimeOptions {
+                    profile: String::from("fox"),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let response = futures::executor::block_on(response).unwrap();
+        assert_eq!(response.error, None);
+        let mut before = Main::default();
+        let mut want = before.resync();
+        for btn in [first, second] {
+            want.extend(
+                before
+                    .process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
+                    .into_iter()
+                    .map(Input::into_pipe_input),
+            );
+        }
+        assert_eq!(sink, want);
+        assert_eq!(main.output, before.output);
+        assert_eq!(main.held_in_press_order(), [first, second]);
+    }
+
+    // Under `--tournament` the keys are bound to the profile switched to as
+    // locked out, so that its autorepeat is still dropped.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn profile_switch_keeps_lockout() {
+        let repeating = config::Profile {
+            bindings: config::Bindings::try_from(std::collections::HashMap::from([(
+                String::from("KEY_J"),
+                B0xxRaw::A,
+            )]))
+            .unwrap(),
+            autorepeat: [(String::from("KEY_J"), config::Autorepeat::Press)]
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>()
+                .try_into()
+                .unwrap(),
+            ..Default::default()
+        };
+        let remapper = std::rc::Rc::new(std::cell::RefCell::new(Remapper::default()));
+        let (reply, response) = futures::channel::oneshot::channel();
+        let events = vec![Ok(remap::Event::Control(
+            control::Request::SetProfile {
+                profile: String::from("repeating"),
+            },
+            reply,
+        ))];
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(events),
+            &mut sink,
+            remap::Options {
+                profiles: Some(remap::Profiles {
+                    config: config::Config {
+                        profile: [(String::from("repeating"), repeating)].into(),
+                        ..Default::default()
+                    },
+                    disabled: Default::default(),
+                    lockout: Some(Engine::B0xx),
+                    rebind: Box::new({
+                        let remapper = std::rc::Rc::clone(&remapper);
+                        move |profile| remapper.borrow_mut().rebind(profile)
+                    }),
+                }),
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let response = futures::executor::block_on(response).unwrap();
+        assert_eq!(response.error, None);
+        let mut remapper = remapper.borrow_mut();
+        let key_j = |value| {
+            evdev_rs::InputEvent::new(
+                &evdev_rs::TimeVal::new(0, 0),
+                &EventCode::EV_KEY(EV_KEY::KEY_J),
+                value,
+            )
+        };
+        assert_eq!(remapper.evdev_to_b0xx(key_j(1)).len(), 1);
+        assert!(remapper.evdev_to_b0xx(key_j(2)).is_empty());
+    }
+
+    // A command queued before a button event is visible when the event is
+    // processed: turning the crouch-walk option select on between Right and
+    // Down angles the down diagonal, and one merged in by `prioritize` goes
+    // ahead of the events already waiting.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn runtime_options_apply_from_next_event() {
+        let press = |btn| {
+            Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                btn, PRESSED,
+            )))
+        };
+        let turn_on = || {
+            let (reply, response) = futures::channel::oneshot::channel();
+            let request = control::Request::SetCrouchWalkOptionSelect { on: true };
+            (Ok(remap::Event::Control(request, reply)), response)
+        };
+        let written = |events| {
+            let mut main = Main::default();
+            let mut sink = Vec::new();
+            futures::executor::block_on(remap::run(
+                &mut main,
+                events,
+                &mut sink,
+                Default::default(),
+            ))
+            .unwrap();
+            sink
+        };
+        let mut want = Main::default();
+        let mut want_sink = want.resync();
+        for (btn, crouch_walk_option_select) in [(B0xxRaw::Right, false), (B0xxRaw::Down, true)] {
+            want_sink.extend(
+                want.process_b0xx(
+                    B0xxEvent::new_without_time(btn, PRESSED),
+                    crouch_walk_option_select,
+                )
+                .into_iter()
+                .map(Input::into_pipe_input),
+            );
+        }
+        let mut off = Main::default();
+        for btn in [B0xxRaw::Right, B0xxRaw::Down] {
+            let _ = off.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+        }
+        assert_ne!(want.output.a_stick, off.output.a_stick);
+
+        let (control, response) = turn_on();
+        let events = remap::prioritize(
+            remap_events(Vec::new()),
+            remap_events(vec![press(B0xxRaw::Right), control, press(B0xxRaw::Down)]),
+        );
+        assert_eq!(written(events), want_sink);
+        let state = futures::executor::block_on(response)
+            .unwrap()
+            .state
+            .unwrap();
+        assert!(state.crouch_walk_option_select);
+
+        let (control, response) = turn_on();
+        let events = remap::prioritize(
+            remap_events(vec![control]),
+            remap_events(vec![press(B0xxRaw::Down), press(B0xxRaw::Right)]),
+        );
+        let mut want = Main::default();
+        let mut want_sink = want.resync();
+        for btn in [B0xxRaw::Down, B0xxRaw::Right] {
+            want_sink.extend(
+                want.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), true)
+                    .into_iter()
+                    .map(Input::into_pipe_input),
+            );
+        }
+        assert_eq!(written(events), want_sink);
+        assert!(futures::executor::block_on(response)
+            .unwrap()
+            .error
+            .is_none());
+    }
+
+    #[cfg(feature = "linux-input")]
+    const UP_PRIORITY: config::Socd = config::Socd {
+        y: config::YSocd::UpPriority,
+        c_stick: config::CStickSocd::SecondInput,
+    };
+
+    /// A `set-socd` request with `socd` as is, or none for no field.
+    #[cfg(feature = "linux-input")]
+    fn set_socd_line(socd: Option<&str>) -> String {
+        let field = socd
+            .map(|socd| format!(r#", "socd": {}"#, socd))
+            .unwrap_or_default();
+        format!(r#"{{"version": 1, "command": "set-socd"{}}}"#, field)
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(Some(r#"{"y": "up_priority"}"#), Some(UP_PRIORITY); "up_priority")]
+    #[test_case(Some("{}"), Some(config::Socd::default()); "defaults")]
+    #[test_case(Some("null"), None; "null")]
+    #[test_case(None, None; "missing")]
+    fn control_parses_set_socd(field: Option<&str>, socd: Option<config::Socd>) {
+        assert_eq!(
+            control::parse(&set_socd_line(field)),
+            Ok(control::Request::SetSocd { socd })
+        );
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(r#"{"x": "up_priority"}"#; "unknown_axis")]
+    #[test_case(r#"{"y": "down_priority"}"#; "unknown_resolution")]
+    #[test_case(r#""up_priority""#; "not_a_table")]
+    fn control_rejects_set_socd(field: &str) {
+        assert!(control::parse(&set_socd_line(Some(field))).is_err());
+    }
+
+    // Overriding the SOCD resolution with Up and Down held resolves them
+    // again under the override, and `null` restores the profile's own.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn set_socd_resolves_held_directions() {
+        let mut events = [B0xxRaw::Up, B0xxRaw::Down]
+            .into_iter()
+            .map(|btn| {
+                Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                    btn, PRESSED,
+                )))
+            })
+            .collect::<Vec<_>>();
+        let mut replies = Vec::new();
+        for socd in [Some(UP_PRIORITY), None] {
+            let (reply, response) = futures::channel::oneshot::channel();
+            events.push(Ok(remap::Event::Control(
+                control::Request::SetSocd { socd },
+                reply,
+            )));
+            replies.push(response);
+        }
+        let mut main = Main::default();
+        let mut sink = Vec::new();
+        futures::executor::block_on(remap::run(
+            &mut main,
+            remap_events(events),
+            &mut sink,
+            remap::Options {
+                profiles: Some(control_profiles(Default::default())),
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let states = replies
+            .into_iter()
+            .map(|response| {
+                futures::executor::block_on(response)
+                    .unwrap()
+                    .state
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(states[0].socd, Some(UP_PRIORITY));
+        assert_eq!(states[0].output.a_stick, (P0000, Analog::MAX));
+        assert_eq!(states[1].socd, None);
+        assert_eq!(states[1].output.a_stick, (P0000, Analog::MIN));
+        let resync = Main::default().resync();
+        assert_eq!(
+            sink[resync.len()..],
+            [
+                DolphinPipeInput::Stick(Stick::A, (P0000, Analog::MAX)),
+                DolphinPipeInput::Stick(Stick::A, (P0000, Analog::MIN)),
+                DolphinPipeInput::Stick(Stick::A, (P0000, Analog::MAX)),
+                DolphinPipeInput::Stick(Stick::A, (P0000, Analog::MIN)),
+            ]
+        );
+        assert_eq!(main.held_in_press_order(), [B0xxRaw::Up, B0xxRaw::Down]);
+    }
+
+    // A client on the socket gets a line of JSON in response to each line of
+    // its requests, and a request of another protocol version is refused.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn control_socket_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("tuxb0xx-control-{}.sock", std::process::id()));
+        let (sender, events) = futures::channel::mpsc::unbounded();
+        control::serve(&path, sender.clone()).unwrap();
+        let lines = [
+            r#"{"version": 1, "command": "get-state"}"#,
+            r#"{"version": 1, "command": "toggle", "feature": "turbo"}"#,
+            r#"{"version": 1, "command": "set-profile", "profile": "nope"}"#,
+            r#"{"version": 1, "command": "set-profile", "profile": "fox"}"#,
+            r#"{"version": 1, "command": "toggle", "feature": "turbo"}"#,
+            r#"{"version": 1, "command": "toggle", "feature": "pause"}"#,
+            r#"{"version": 1, "command": "reset"}"#,
+            r#"{"version": 1, "command": "toggle", "feature": "turbo"}"#,
+            r#"{"version": 1, "command": "set-character", "character": "marth"}"#,
+            r#"{"version": 1, "command": "set-character", "character": "falco"}"#,
+            r#"{"version": 2, "command": "get-state"}"#,
+        ];
+        let client = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                use std::io::{BufRead as _, Write as _};
+
+                // Queued ahead of the requests, in the same channel.
+                assert!(sender
+                    .unbounded_send(Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                        B0xxRaw::A,
+                        PRESSED,
+                    ))))
+                    .is_ok());
+                let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let responses = lines
+                    .iter()
+                    .map(|line| {
+                        writeln!(stream, "{}", line).unwrap();
+                        let mut response = String::new();
+                        let _: usize = reader.read_line(&mut response).unwrap();
+                        response.trim_end().to_owned()
+                    })
+                    .collect::<Vec<_>>();
+                assert!(sender.unbounded_send(Ok(remap::Event::Exit)).is_ok());
+                responses
+            }
+        });
+        futures::executor::block_on(remap::run(
+            &mut Main::default(),
+            events,
+            Vec::new(),
+            remap::Options {
+                profiles: Some(control_profiles(Default::default())),
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        let responses = client.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let want = control_responses()
+            .into_iter()
+            .chain([control::Response::new(Err(String::from(
+                "unsupported protocol version 2, expected 1",
+            )))])
+            .map(|response| serde_json::to_string(&response).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses, want);
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[], b"00000000000000000000\n"; "none")]
+    #[test_case(&[B0xxRaw::Start, B0xxRaw::A], b"10001000000000000000\n"; "start_a")]
+    #[test_case(&[B0xxRaw::Up, B0xxRaw::Left, B0xxRaw::MY], b"00000000100101000000\n"; "directions")]
+    #[test_case(&[B0xxRaw::CL, B0xxRaw::CD, B0xxRaw::MS], b"00000000000000100101\n"; "c_stick_and_shield")]
+    #[test_case(&[B0xxRaw::MT, B0xxRaw::DP, B0xxRaw::DU, B0xxRaw::TB], b"00000000000000000000\n"; "not_shown")]
+    fn viewer_frame(held: &[B0xxRaw], frame: &[u8; 21]) {
+        assert_eq!(&viewer::frame(&held.iter().copied().collect()), frame);
+    }
+
+    // A viewer gets the frame of the buttons held on connecting and on each
+    // change until `run` ends, and another disconnecting doesn't stop it.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn viewer_serves_frames() {
+        use std::io::Read as _;
+
+        let mut publisher = remap::Publisher::default();
+        let address = viewer::serve(0, publisher.subscribe(64)).unwrap();
+        let connect = || {
+            let mut stream = std::net::TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut frame = [0; 21];
+            stream.read_exact(&mut frame).unwrap();
+            assert_eq!(&frame, b"00000000000000000000\n");
+            stream
+        };
+        let mut viewer = connect();
+        drop(connect());
+        let press = |btn, pressed| {
+            Ok(remap::Event::B0xx(B0xxEvent::new_without_time(
+                btn, pressed,
+            )))
+        };
+        futures::executor::block_on(remap::run(
+            &mut Main::default(),
+            remap_events(vec![
+                press(B0xxRaw::A, PRESSED),
+                press(B0xxRaw::MT, PRESSED),
+                press(B0xxRaw::A, RELEASED),
+            ]),
+            Vec::new(),
+            remap::Options {
+                publisher,
+                ..Default::default()
+            },
+        ))
+        .unwrap();
+        // Mod Tilt isn't shown, so pressing it sends nothing.
+        let mut frames = Vec::new();
+        let _: usize = viewer.read_to_end(&mut frames).unwrap();
+        assert_eq!(frames, b"00001000000000000000\n00000000000000000000\n");
+    }
+
+    // A viewer that stops reading fails a send once the socket buffers fill,
+    // rather than blocking the others.
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn viewer_stalled() {
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let _stalled = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let frame = viewer::frame(&Default::default());
+        // Far more than any socket buffers hold.
+        let sent = (0..10_000_000)
+            .take_while(|_| viewer::send(&stream, &frame))
+            .count();
+        assert!(sent < 10_000_000);
+    }
+
+    // Start+L+R+A resets once held for a second, and Start+A pauses once held
+    // for half a second, with button toggles at the given milliseconds.
+    #[test_case(
+        &[(0, B0xxRaw::Start, PRESSED), (100, B0xxRaw::A, PRESSED)],
+        &[(600, config::ComboAction::Pause)];
+        "held"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::Start, PRESSED), (100, B0xxRaw::A, PRESSED), (599, B0xxRaw::A, RELEASED)],
+        &[];
+        "released_early"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::Start, PRESSED), (100, B0xxRaw::A, PRESSED), (600, B0xxRaw::A, RELEASED)],
+        &[(600, config::ComboAction::Pause)];
+        "released_on_time"
+    )]
+    #[test_case(
+        &[(0, B0xxRaw::Start, PRESSED), (0, B0xxRaw::A, PRESSED)],
+        &[(500, config::ComboAction::Pause)];
+        "held_on"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::Start, PRESSED),
+            (0, B0xxRaw::A, PRESSED),
+            (700, B0xxRaw::A, RELEASED),
+            (800, B0xxRaw::A, PRESSED),
+        ],
+        &[(500, config::ComboAction::Pause), (1300, config::ComboAction::Pause)];
+        "pressed_again"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::Start, PRESSED),
+            (0, B0xxRaw::A, PRESSED),
+            (300, B0xxRaw::Start, RELEASED),
+            (400, B0xxRaw::Start, PRESSED),
+        ],
+        &[(900, config::ComboAction::Pause)];
+        "partial_release"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::Start, PRESSED),
+            (0, B0xxRaw::L, PRESSED),
+            (0, B0xxRaw::R, PRESSED),
+            (0, B0xxRaw::A, PRESSED),
+        ],
+        &[(1000, config::ComboAction::Reset)];
+        "bigger_combo"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::Start, PRESSED),
+            (0, B0xxRaw::L, PRESSED),
+            (0, B0xxRaw::R, PRESSED),
+            (0, B0xxRaw::A, PRESSED),
+            (1200, B0xxRaw::L, RELEASED),
+        ],
+        &[(1000, config::ComboAction::Reset)];
+        "bigger_combo_released_after"
+    )]
+    #[test_case(
+        &[
+            (0, B0xxRaw::Start, PRESSED),
+            (0, B0xxRaw::L, PRESSED),
+            (0, B0xxRaw::R, PRESSED),
+            (0, B0xxRaw::A, PRESSED),
+            (700, B0xxRaw::L, RELEASED),
+        ],
+        &[(700, config::ComboAction::Pause)];
+        "bigger_combo_released_early"
+    )]
+    #[test_case(&[(0, B0xxRaw::Start, PRESSED), (0, B0xxRaw::B, PRESSED)], &[]; "no_combo")]
+    fn combo_detector(events: &[(u64, B0xxRaw, Pressed)], want: &[(u64, config::ComboAction)]) {
+        let combo = |buttons: &[B0xxRaw], hold_ms, action| config::Combo {
+            buttons: buttons.iter().copied().collect(),
+            hold_ms,
+            action,
+        };
+        let mut detector = combo::Detector::new(
+            config::Combos::try_from(vec![
+                combo(
+                    &[B0xxRaw::Start, B0xxRaw::A],
+                    500,
+                    config::ComboAction::Pause,
+                ),
+                combo(
+                    &[B0xxRaw::Start, B0xxRaw::L, B0xxRaw::R, B0xxRaw::A],
+                    1000,
+                    config::ComboAction::Reset,
+                ),
+            ])
+            .unwrap(),
+        );
+        let at = |ms| Timestamp::default() + std::time::Duration::from_millis(ms);
+        let mut fired = Vec::new();
+        // A deadline already past when it is armed is due at once, as the
+        // timer in `remap::run` fires at once for it.
+        let mut process_deadlines = |detector: &mut combo::Detector, last: u64, until: u64| {
+            while let Some(deadline) = detector
+                .deadline()
+                .filter(|&deadline| deadline <= at(until))
+            {
+                let now = deadline.max(at(last));
+                if let Some(action) = detector.process_deadline(now) {
+                    fired.push(((now - Timestamp::default()).as_millis() as u64, action));
+                }
+            }
+        };
+        let mut now = 0;
+        for &(ms, btn, pressed) in events {
+            process_deadlines(&mut detector, now, ms);
+            now = ms;
+            detector.process(&B0xxEvent::new(btn, pressed, at(now)));
+            process_deadlines(&mut detector, now, now);
+        }
+        process_deadlines(&mut detector, now, now + 5000);
+        assert_eq!(fired, want);
+    }
+
+    /// Devices as scripted, for `grab::conflict`.
+    #[cfg(feature = "linux-input")]
+    struct FakeDevices {
+        probe: Option<i32>,
+        holders: Option<Vec<grab::Holder>>,
+    }
+
+    #[cfg(feature = "linux-input")]
+    impl grab::Devices for FakeDevices {
+        fn probe_grab(&self, _: &std::path::Path) -> std::io::Result<()> {
+            match self.probe {
+                Some(errno) => Err(std::io::Error::from_raw_os_error(errno)),
+                None => Ok(()),
+            }
+        }
+
+        fn holders(&self, _: &std::path::Path) -> std::io::Result<Vec<grab::Holder>> {
+            self.holders
+                .clone()
+                .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EACCES))
+        }
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test_case(None, Some(&[]), None; "free")]
+    #[test_case(Some(libc::EACCES), Some(&[]), None; "unprobed")]
+    #[test_case(Some(libc::EBUSY), Some(&[(1234, "kmonad"), (99, "Xwayland")]), Some("it is open in kmonad (pid 1234), Xwayland (pid 99)"); "named")]
+    #[test_case(Some(libc::EBUSY), Some(&[]), Some("no other process visibly has it open"); "unseen")]
+    #[test_case(Some(libc::EBUSY), None, Some("failed to list the processes with it open"); "unlisted")]
+    fn grab_conflict(probe: Option<i32>, holders: Option<&[(u32, &str)]>, want: Option<&str>) {
+        let devices = FakeDevices {
+            probe,
+            holders: holders.map(|holders| {
+                holders
+                    .iter()
+                    .map(|&(pid, name)| grab::Holder {
+                        pid,
+                        name: name.to_owned(),
+                    })
+                    .collect()
+            }),
+        };
+        let path = std::path::Path::new("/dev/input/event3");
+        let warning = grab::conflict(&devices, path);
+        match (&warning, want) {
+            (Some(warning), Some(want)) => {
+                assert!(
+                    warning.starts_with("/dev/input/event3 is grabbed by another process"),
+                    "{}",
+                    warning
+                );
+                assert!(warning.contains(want), "{}", warning);
             }
+            (None, None) => {}
+            _ => panic!("{:?}, want {:?}", warning, want),
         }
     }
 
+    // The hint is given once, at the deadline, unless a button event came
+    // first.
+    #[cfg(feature = "linux-input")]
+    #[test_case(false, &[9_999], &[]; "early")]
+    #[test_case(false, &[9_999, 10_000, 20_000], &[10_000]; "silent")]
+    #[test_case(true, &[10_000], &[]; "event")]
+    fn silence_watch(event: bool, checks: &[u64], want: &[u64]) {
+        let at = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let mut watch = grab::SilenceWatch::new(at(0), Duration::from_secs(10));
+        assert_eq!(watch.deadline(), Some(at(10_000)));
+        if event {
+            watch.event();
+            assert_eq!(watch.deadline(), None);
+        }
+        let hinted = checks
+            .iter()
+            .copied()
+            .filter(|&ms| watch.process_deadline(at(ms)).is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(hinted, want);
+    }
+
+    // The watch goes idle at the deadline only if quiet, and wakes on the
+    // next button event with the resynced events.
+    #[cfg(feature = "linux-input")]
+    #[test_case(&[], &[9_999, 10_000], &[10_000]; "quiet")]
+    #[test_case(&[], &[10_000, 20_000], &[10_000]; "once")]
+    #[test_case(&[5_000], &[10_000, 15_000], &[15_000]; "event")]
+    #[test_case(&[], &[-10_000, 20_000], &[20_000]; "busy")]
+    fn idle_watch(events: &[u64], checks: &[i64], want: &[u64]) {
+        let at = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let resyncs = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut watch = idle::IdleWatch::new(
+            at(0),
+            Duration::from_secs(10),
+            Box::new({
+                let resyncs = std::rc::Rc::clone(&resyncs);
+                move |now| {
+                    resyncs.set(resyncs.get() + 1);
+                    Ok(vec![B0xxEvent {
+                        time: now,
+                        btn: B0xxRaw::A,
+                        pressed: PRESSED,
+                    }])
+                }
+            }),
+        );
+        for &ms in events {
+            assert!(watch.event(at(ms)).is_none());
+        }
+        // A negative check is made while something is held.
+        let idled = checks
+            .iter()
+            .map(|&ms| (ms.unsigned_abs(), ms >= 0))
+            .filter(|&(ms, quiet)| watch.process_deadline(at(ms), quiet))
+            .map(|(ms, _)| ms)
+            .collect::<Vec<_>>();
+        assert_eq!(idled, want);
+        assert_eq!(watch.is_idle(), watch.deadline().is_none());
+        assert!(watch.is_idle());
+        assert_eq!(resyncs.get(), 0);
+        let woken = watch.event(at(30_000)).unwrap();
+        assert_eq!(
+            woken.iter().map(|e| (e.btn, e.pressed)).collect::<Vec<_>>(),
+            [(B0xxRaw::A, PRESSED)]
+        );
+        assert_eq!(resyncs.get(), 1);
+        assert_eq!(watch.deadline(), Some(at(40_000)));
+        assert!(watch.event(at(31_000)).is_none());
+    }
+
     #[test]
-    fn c_stick_diagonals() {
-        for x in [POSITIVE, NEGATIVE] {
-            for y in [POSITIVE, NEGATIVE] {
-                let mut buttons = [(Stick::C, Axis::X, x).into(), (Stick::C, Axis::Y, y).into()];
-                let c_stick = (P5250.neg_not(x), P8500.neg_not(y));
-                permutohedron::heap_recursive(&mut buttons, |buttons| {
-                    let mut main = Main::default();
-                    let got = buttons
-                        .iter()
-                        .fold(None, |_, &btn| {
-                            main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
-                        })
-                        .expect("final b0xx input resulted in null GC input");
-                    assert_eq!(got, Input::Stick(Stick::C, c_stick));
-                });
+    fn inconsistencies_healed() {
+        let press = |buttons: &[B0xxRaw]| {
+            let mut main = Main::default();
+            for &btn in buttons {
+                let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
             }
+            assert_eq!(main.inconsistencies(), Vec::<String>::new());
+            main
+        };
+        let heal = |main: &mut Main| {
+            let healed = main
+                .heal(Timestamp::default(), false)
+                .into_iter()
+                .map(Input::into_pipe_input)
+                .collect::<Vec<_>>();
+            assert_eq!(main.inconsistencies(), Vec::<String>::new());
+            healed
+        };
+
+        // A lost modifier release.
+        let mut main = press(&[B0xxRaw::MX, B0xxRaw::Right, B0xxRaw::A]);
+        let _ = main.held.remove(&B0xxRaw::MX);
+        let inconsistencies = main.inconsistencies();
+        assert_eq!(inconsistencies.len(), 1, "{:?}", inconsistencies);
+        assert!(
+            inconsistencies[0].ends_with("but MX is not held"),
+            "{:?}",
+            inconsistencies
+        );
+        assert_eq!(
+            heal(&mut main),
+            [DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000))]
+        );
+
+        // A direction swapped behind the axis state's back.
+        let mut main = press(&[B0xxRaw::Left]);
+        let _ = main.held.remove(&B0xxRaw::Left);
+        let _ = main.held.insert(B0xxRaw::Right);
+        let inconsistencies = main.inconsistencies();
+        assert_eq!(inconsistencies.len(), 1, "{:?}", inconsistencies);
+        assert!(
+            inconsistencies[0].starts_with("A-stick X is "),
+            "{:?}",
+            inconsistencies
+        );
+        assert!(
+            inconsistencies[0].ends_with("but [Right] are held"),
+            "{:?}",
+            inconsistencies
+        );
+        assert_eq!(
+            heal(&mut main),
+            [DolphinPipeInput::Stick(Stick::A, (Analog::MAX, P0000))]
+        );
+
+        // An output left pressed with nothing held.
+        let mut main = press(&[B0xxRaw::A]);
+        main.held.clear();
+        let inconsistencies = main.inconsistencies();
+        assert_eq!(inconsistencies.len(), 1, "{:?}", inconsistencies);
+        assert!(
+            inconsistencies[0].starts_with("nothing is held"),
+            "{:?}",
+            inconsistencies
+        );
+        assert_eq!(
+            heal(&mut main),
+            [DolphinPipeInput::Button(GCButton::A, RELEASED)]
+        );
+        assert_eq!(main.output, ControllerSnapshot::default());
+    }
+
+    #[cfg(feature = "linux-input")]
+    fn held_main(buttons: &[B0xxRaw]) -> Main {
+        let mut main = Main::default();
+        for &btn in buttons {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
         }
+        main
     }
 
-    #[test_case(&[], Stick::A, Analog::MAX, Analog::MAX; "a_stick")]
-    #[test_case(&[B0xxRaw::MX], Stick::A, P6625, P5375; "a_stick_mod_x")]
-    #[test_case(&[B0xxRaw::MY], Stick::A, P3375, P7375; "a_stick_mod_y")]
-    #[test_case(&[], Stick::C, Analog::MAX, Analog::MAX; "c_stick")]
-    fn cardinals(buttons: &[B0xxRaw], stick: Stick, x_positive: Analog, y_positive: Analog) {
-        for axis in [Axis::X, Axis::Y] {
-            for dir in [POSITIVE, NEGATIVE] {
-                let mut buttons = buttons
-                    .iter()
-                    .copied()
-                    .chain(std::iter::once((stick, axis, dir).into()))
-                    .collect::<Vec<_>>();
-                let want = match axis {
-                    Axis::X => (x_positive.neg_not(dir), P0000),
-                    Axis::Y => (P0000, y_positive.neg_not(dir)),
-                };
-                permutohedron::heap_recursive(&mut buttons, |buttons| {
-                    let mut main = Main::default();
-                    let got = buttons
-                        .iter()
-                        .fold(None, |_, &btn| {
-                            main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
-                        })
-                        .expect("final b0xx input resulted in null GC input");
-                    assert_eq!(got, Input::Stick(stick, want));
-                });
+    #[cfg(feature = "linux-input")]
+    fn state_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tuxb0xx-{}-{}/state.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    // The state file goes in $XDG_CONFIG_HOME, or else in ~/.config, ignoring
+    // relative paths.
+    #[cfg(feature = "linux-input")]
+    #[test_case(Some("/xdg"), Some("/h"), Some("/xdg/tuxb0xx/state.json"); "xdg")]
+    #[test_case(None, Some("/h"), Some("/h/.config/tuxb0xx/state.json"); "home")]
+    #[test_case(Some(""), Some("/h"), Some("/h/.config/tuxb0xx/state.json"); "xdg_empty")]
+    #[test_case(Some("xdg"), Some("/h"), Some("/h/.config/tuxb0xx/state.json"); "xdg_relative")]
+    #[test_case(None, None, None; "neither")]
+    fn default_state_path(xdg: Option<&str>, home: Option<&str>, want: Option<&str>) {
+        let var = |name: &str| match name {
+            "XDG_CONFIG_HOME" => xdg.map(std::ffi::OsString::from),
+            "HOME" => home.map(std::ffi::OsString::from),
+            _ => None,
+        };
+        assert_eq!(
+            super::default_state_path(var),
+            want.map(std::path::PathBuf::from)
+        );
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn state_neutralize() {
+        let main = held_main(&[B0xxRaw::A, B0xxRaw::Right, B0xxRaw::CU]);
+        let state = recovery::State {
+            clean: false,
+            held: main.held.clone(),
+            output: main.output.clone(),
+        };
+        assert_eq!(
+            state.neutralize(),
+            [
+                DolphinPipeInput::Button(GCButton::A, RELEASED),
+                DolphinPipeInput::Stick(Stick::C, (P0000, P0000)),
+                DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn state_file_recovery() {
+        let path = state_path("recovery");
+        assert_eq!(recovery::recover(&path), [], "no state file");
+        let main = held_main(&[B0xxRaw::A, B0xxRaw::Right]);
+        let mut state_file = recovery::StateFile::new(path.clone(), Duration::from_secs(3600));
+        state_file.save(&main);
+        // Dropped without closing, as when the process dies.
+        drop(state_file);
+        let state = recovery::read(&path).unwrap().unwrap();
+        assert!(!state.clean);
+        assert_eq!(state.held, [B0xxRaw::A, B0xxRaw::Right].into());
+        assert_eq!(
+            recovery::recover(&path),
+            [
+                DolphinPipeInput::Button(GCButton::A, RELEASED),
+                DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+            ]
+        );
+
+        let mut state_file = recovery::StateFile::new(path.clone(), Duration::from_secs(3600));
+        state_file.save(&main);
+        state_file.close(&main);
+        assert!(recovery::read(&path).unwrap().unwrap().clean);
+        assert_eq!(recovery::recover(&path), [], "clean shutdown");
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[cfg(feature = "linux-input")]
+    #[test]
+    fn state_file_rate_limited() {
+        let path = state_path("rate-limited");
+        let mut state_file = recovery::StateFile::new(path.clone(), Duration::from_secs(3600));
+        state_file.save(&held_main(&[B0xxRaw::A]));
+        // The first state is saved immediately.
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let saved = loop {
+            if let Some(state) = recovery::read(&path).unwrap() {
+                break state;
             }
+            assert!(std::time::Instant::now() < deadline, "never saved");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(saved.held, [B0xxRaw::A].into());
+        // Later ones wait out the period, or the shutdown.
+        state_file.save(&held_main(&[B0xxRaw::B]));
+        state_file.save(&held_main(&[B0xxRaw::X]));
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(recovery::read(&path).unwrap().unwrap(), saved);
+        drop(state_file);
+        assert_eq!(
+            recovery::read(&path).unwrap().unwrap().held,
+            [B0xxRaw::X].into()
+        );
+    }
+
+    // A poor man's fuzzer for when cargo-fuzz isn't at hand.
+    #[test]
+    fn fuzz_pseudo_random() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u8
+        };
+        for _ in 0..2000 {
+            let data = (0..129).map(|_| next()).collect::<Vec<_>>();
+            fuzz::run(&data);
         }
     }
 
     #[test]
-    fn dpad() {
-        for axis in [Axis::X, Axis::Y] {
-            for dir in [POSITIVE, NEGATIVE] {
-                let mut main = Main::default();
-                let got =
-                    main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MX, PRESSED), false);
-                assert_eq!(got, None);
-                let got =
-                    main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MY, PRESSED), false);
-                assert_eq!(got, None);
-                let got = main.process_b0xx(
-                    B0xxEvent::new_without_time((Stick::C, axis, dir).into(), PRESSED),
-                    false,
-                );
-                assert_eq!(got, Some(Input::Button(Button::DPad(axis, dir), PRESSED)));
-            }
+    fn delay_queue() {
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let a = Input::Button(Button::Pure(ButtonPure::A), PRESSED);
+        let stick = Input::Stick(Stick::A, (P6625, P0000));
+        let mut queue = DelayQueue::new(Duration::from_millis(100));
+        assert_eq!(queue.deadline(), None);
+        queue.push(t(10), a);
+        queue.push(t(10), stick);
+        queue.push(t(20), a);
+        assert_eq!(queue.deadline(), Some(t(110)));
+        assert_eq!(queue.pop_due(t(109)), None);
+        assert_eq!(queue.pop_due(t(110)), Some(a));
+        assert_eq!(queue.pop_due(t(110)), Some(stick));
+        assert_eq!(queue.pop_due(t(110)), None);
+        assert_eq!(queue.deadline(), Some(t(120)));
+        assert_eq!(queue.pop_due(t(150)), Some(a));
+        assert_eq!(queue.deadline(), None);
+    }
+
+    #[test]
+    fn delay_queue_preserves_order() {
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let a = Input::Button(Button::Pure(ButtonPure::A), PRESSED);
+        let b = Input::Button(Button::Impure(ButtonImpure::B), PRESSED);
+        let mut queue = DelayQueue::new(Duration::from_millis(100));
+        queue.push(t(20), a);
+        queue.push(t(10), b);
+        assert_eq!(queue.pop_due(t(110)), None);
+        assert_eq!(queue.pop_due(t(120)), Some(a));
+        assert_eq!(queue.pop_due(t(120)), Some(b));
+    }
+
+    fn snapshot_of(pipe_inputs: &[DolphinPipeInput]) -> ControllerSnapshot {
+        let mut snapshot = ControllerSnapshot::default();
+        for &pipe_input in pipe_inputs {
+            snapshot.apply(pipe_input);
+        }
+        snapshot
+    }
+
+    #[test_case(&[], &[], &[]; "neutral")]
+    #[test_case(
+        &[DolphinPipeInput::Button(GCButton::A, PRESSED), DolphinPipeInput::Stick(Stick::C, (P0000, P6000))],
+        &[DolphinPipeInput::Button(GCButton::A, PRESSED), DolphinPipeInput::Stick(Stick::C, (P0000, P6000))],
+        &[];
+        "unchanged"
+    )]
+    #[test_case(
+        &[],
+        &[DolphinPipeInput::Button(GCButton::Z, PRESSED), DolphinPipeInput::Button(GCButton::A, PRESSED)],
+        &[DolphinPipeInput::Button(GCButton::A, PRESSED), DolphinPipeInput::Button(GCButton::Z, PRESSED)];
+        "presses in button order"
+    )]
+    #[test_case(
+        &[DolphinPipeInput::Button(GCButton::R, PRESSED)],
+        &[DolphinPipeInput::Button(GCButton::A, PRESSED)],
+        &[DolphinPipeInput::Button(GCButton::R, RELEASED), DolphinPipeInput::Button(GCButton::A, PRESSED)];
+        "releases before presses"
+    )]
+    #[test_case(
+        &[DolphinPipeInput::Button(GCButton::B, PRESSED), DolphinPipeInput::Stick(Stick::A, (P5000, P0000))],
+        &[
+            DolphinPipeInput::Trigger(LS),
+            DolphinPipeInput::Stick(Stick::C, (P0000, -P6000)),
+            DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+            DolphinPipeInput::Button(GCButton::X, PRESSED),
+        ],
+        &[
+            DolphinPipeInput::Button(GCButton::B, RELEASED),
+            DolphinPipeInput::Button(GCButton::X, PRESSED),
+            DolphinPipeInput::Stick(Stick::C, (P0000, -P6000)),
+            DolphinPipeInput::Stick(Stick::A, (P0000, P0000)),
+            DolphinPipeInput::Trigger(LS),
+        ];
+        "every field"
+    )]
+    #[test_case(
+        &[DolphinPipeInput::Stick(Stick::A, (P5000, P0000)), DolphinPipeInput::Stick(Stick::C, (P6000, P0000))],
+        &[DolphinPipeInput::Stick(Stick::A, (P5000, P0000)), DolphinPipeInput::Stick(Stick::C, (-P6000, P0000))],
+        &[DolphinPipeInput::Stick(Stick::C, (-P6000, P0000))];
+        "only the stick that moved"
+    )]
+    fn snapshot_diff(
+        old: &[DolphinPipeInput],
+        new: &[DolphinPipeInput],
+        want: &[DolphinPipeInput],
+    ) {
+        let (old, new) = (snapshot_of(old), snapshot_of(new));
+        let got = diff(&old, &new);
+        assert_eq!(got, want);
+        // Applying the diff to the old snapshot gives the new one.
+        let mut patched = old.clone();
+        for &pipe_input in &got {
+            patched.apply(pipe_input);
         }
+        assert_eq!(patched, new);
+        // The way back changes the same fields.
+        assert_eq!(diff(&new, &old).len(), got.len());
     }
 
-    // When a C-stick button is acting as dpad, and one of the modifiers is
-    // released, diagonals should not be modified.
     #[test]
-    fn dpad_not_modify() {
-        for ((c_axis, c_dir), (x_dir, y_dir)) in CARDINALS.into_iter().cartesian_product(DIAGONALS)
+    fn frame_quantizer_coalesces() {
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let mut quantizer = FrameQuantizer::new(Duration::from_millis(10), t(0));
+        assert_eq!(quantizer.deadline(), None);
+        quantizer.apply(Input::Stick(Stick::A, (P5000, P0000)));
+        quantizer.apply(Input::Stick(Stick::A, (P6625, P0000)));
+        quantizer.apply(Input::Button(Button::Impure(ButtonImpure::B), PRESSED));
+        assert_eq!(quantizer.deadline(), Some(t(10)));
+        assert_eq!(quantizer.tick(t(9)), []);
+        assert_eq!(
+            quantizer.tick(t(10)),
+            [
+                DolphinPipeInput::Button(GCButton::B, PRESSED),
+                DolphinPipeInput::Stick(Stick::A, (P6625, P0000)),
+            ]
+        );
+        assert_eq!(quantizer.deadline(), None);
+        quantizer.apply(Input::Stick(Stick::A, (P0000, P0000)));
+        quantizer.apply(Input::Stick(Stick::A, (P6625, P0000)));
+        assert_eq!(quantizer.tick(t(20)), []);
+        assert_eq!(quantizer.deadline(), None);
+    }
+
+    #[test_case(&[], true; "neither")]
+    #[test_case(&[DolphinPipeInput::Stick(Stick::A, (P5000, P0000))], true; "a_stick")]
+    #[test_case(&[DolphinPipeInput::Stick(Stick::C, (P5000, P0000)), DolphinPipeInput::Button(GCButton::A, PRESSED), DolphinPipeInput::Stick(Stick::A, (P5000, P0000))], true; "c_stick_first")]
+    #[test_case(&[DolphinPipeInput::Stick(Stick::A, (P5000, P0000)), DolphinPipeInput::Button(GCButton::A, PRESSED), DolphinPipeInput::Stick(Stick::C, (P5000, P0000))], false; "a_stick_first")]
+    fn sticks_ordered(pipe_inputs: &[DolphinPipeInput], want: bool) {
+        assert_eq!(super::sticks_ordered(pipe_inputs.iter().copied()), want);
+    }
+
+    /// Every path writing both sticks for one change writes the C-stick first,
+    /// whatever order they changed in.
+    #[test]
+    fn sticks_written_c_first() {
+        let mut main = Main::default();
+        for btn in [B0xxRaw::MX, B0xxRaw::Right, B0xxRaw::CU] {
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false);
+        }
+        // Releasing the A-stick straightens the C-stick.
+        let released =
+            main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Right, RELEASED), false);
+        assert!(
+            matches!(
+                released[..],
+                [Input::Stick(Stick::C, _), Input::Stick(Stick::A, _)]
+            ),
+            "{:?}",
+            released
+        );
+        assert!(super::sticks_ordered(main.resync()));
+        assert!(super::sticks_ordered(diff(
+            &main.output,
+            &ControllerSnapshot::default()
+        )));
+
+        // The A-stick changing first in a frame doesn't put it first.
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let mut quantizer = FrameQuantizer::new(Duration::from_millis(10), t(0));
+        quantizer.apply(Input::Stick(Stick::A, (P6625, P0000)));
+        quantizer.apply(Input::Stick(Stick::C, (P4375, P7875)));
+        assert_eq!(
+            quantizer.tick(t(10)),
+            [
+                DolphinPipeInput::Stick(Stick::C, (P4375, P7875)),
+                DolphinPipeInput::Stick(Stick::A, (P6625, P0000)),
+            ]
+        );
+    }
+
+    #[test_case(PRESSED ; "tap")]
+    #[test_case(RELEASED ; "release and repress")]
+    fn frame_quantizer_keeps_taps(pressed: Pressed) {
+        let t = |ms| Timestamp::default() + Duration::from_millis(ms);
+        let a = Button::Pure(ButtonPure::A);
+        let mut quantizer = FrameQuantizer::new(Duration::from_millis(10), t(0));
+        if !pressed {
+            quantizer.apply(Input::Button(a, PRESSED));
+        }
+        let _ = quantizer.tick(t(10));
+        quantizer.apply(Input::Button(a, pressed));
+        quantizer.apply(Input::Button(a, !pressed));
+        assert_eq!(quantizer.deadline(), Some(t(20)));
+        assert_eq!(
+            quantizer.tick(t(21)),
+            [DolphinPipeInput::Button(GCButton::A, pressed)]
+        );
+        assert_eq!(quantizer.deadline(), Some(t(30)));
+        assert_eq!(
+            quantizer.tick(t(30)),
+            [DolphinPipeInput::Button(GCButton::A, !pressed)]
+        );
+        assert_eq!(quantizer.deadline(), None);
+    }
+
+    #[test_case(0, 0; "zero")]
+    #[test_case(31, 31; "last exact")]
+    #[test_case(32, 32; "first shared")]
+    #[test_case(33, 32; "shared")]
+    #[test_case(34, 33; "second shared")]
+    #[test_case(63, 47; "end of first doubling")]
+    #[test_case(64, 48; "start of second doubling")]
+    #[test_case(67, 48; "four wide")]
+    #[test_case(68, 49; "next four wide")]
+    #[test_case(u64::MAX, LatencyHistogram::BUCKETS - 1; "max")]
+    fn latency_histogram_bucket(value: u64, bucket: usize) {
+        assert_eq!(LatencyHistogram::bucket(value), bucket);
+        assert!(LatencyHistogram::bucket_start(bucket) <= value);
+        assert!(value <= LatencyHistogram::bucket_end(bucket));
+    }
+
+    #[test]
+    fn latency_histogram_buckets_are_contiguous() {
+        assert_eq!(LatencyHistogram::bucket_start(0), 0);
+        for bucket in 1..LatencyHistogram::BUCKETS {
+            let start = LatencyHistogram::bucket_start(bucket);
+            assert_eq!(
+                LatencyHistogram::bucket_end(bucket - 1) + 1,
+                start,
+                "{}",
+                bucket
+            );
+            assert_eq!(LatencyHistogram::bucket(start), bucket);
+            assert_eq!(LatencyHistogram::bucket(start - 1), bucket - 1);
+        }
+    }
+
+    #[test]
+    fn latency_histogram_quantiles() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        // 50 and 99 share buckets with 51 and 96..=99 respectively.
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(51)));
+        assert_eq!(histogram.quantile(0.99), Some(Duration::from_micros(99)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_micros(100)));
+        assert_eq!(histogram.quantile(0.0), Some(Duration::from_micros(1)));
+    }
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    }
+
+    /// Counts the allocations made on each thread, so that tests running in
+    /// parallel don't count each other's.
+    struct CountingAllocator;
+
+    // SAFETY: Every call is passed on to `System` as is.
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            // SAFETY: The caller upholds `alloc`'s contract.
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            // SAFETY: The caller upholds `dealloc`'s contract.
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(
+            &self,
+            ptr: *mut u8,
+            layout: std::alloc::Layout,
+            new_size: usize,
+        ) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            // SAFETY: The caller upholds `realloc`'s contract.
+            unsafe { std::alloc::System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Returns how many allocations and reallocations `f` made on this thread.
+    fn allocations(f: impl FnOnce()) -> u64 {
+        let before = ALLOCATIONS.with(std::cell::Cell::get);
+        f();
+        ALLOCATIONS.with(std::cell::Cell::get) - before
+    }
+
+    /// Once warmed up, processing events and formatting the resulting pipe
+    /// commands allocates nothing, whatever the event.
+    #[test]
+    fn hot_path_allocation_free() {
+        use std::alloc::GlobalAlloc as _;
+
+        let cycle = [
+            (B0xxRaw::Right, PRESSED),
+            (B0xxRaw::Up, PRESSED),
+            (B0xxRaw::MX, PRESSED),
+            (B0xxRaw::A, PRESSED),
+            (B0xxRaw::A, RELEASED),
+            (B0xxRaw::CU, PRESSED),
+            (B0xxRaw::CU, RELEASED),
+            (B0xxRaw::MX, RELEASED),
+            (B0xxRaw::Left, PRESSED),
+            (B0xxRaw::Right, RELEASED),
+            (B0xxRaw::LS, PRESSED),
+            (B0xxRaw::MS, PRESSED),
+            (B0xxRaw::LS, RELEASED),
+            (B0xxRaw::MS, RELEASED),
+            (B0xxRaw::B, PRESSED),
+            (B0xxRaw::B, RELEASED),
+            (B0xxRaw::Up, RELEASED),
+            (B0xxRaw::Left, RELEASED),
+            (B0xxRaw::Z, PRESSED),
+            (B0xxRaw::Z, RELEASED),
+        ];
+        let mut main = Main::default();
+        let mut command = String::new();
+        let mut i = 0;
+        let mut burst = |main: &mut Main, command: &mut String, n| {
+            for _ in 0..n {
+                let (btn, pressed) = cycle[i % cycle.len()];
+                let now = Timestamp::default() + Duration::from_millis(i as u64);
+                i += 1;
+                for input in main.process_b0xx(B0xxEvent::new(btn, pressed, now), false) {
+                    command.clear();
+                    let _ = input.into_pipe_input().write_command(
+                        command,
+                        config::Handedness::Standard,
+                        Game::Melee,
+                        config::PipeCapability::Standard,
+                    );
+                }
+            }
+        };
+        burst(&mut main, &mut command, cycle.len());
+        assert_eq!(allocations(|| burst(&mut main, &mut command, 1000)), 0);
+        // Nor does the loop around them, writing to a pipe and saving the
+        // state.
+        #[cfg(feature = "linux-input")]
         {
+            use std::future::Future as _;
+            use std::task::Poll;
+
+            // Events left to read before the stream waits.
+            let budget = std::cell::Cell::new(cycle.len());
+            let mut next = 0;
+            let events = futures::stream::poll_fn(|_| {
+                if budget.get() == 0 {
+                    return Poll::Pending;
+                }
+                budget.set(budget.get() - 1);
+                let (btn, pressed) = cycle[next % cycle.len()];
+                next += 1;
+                Poll::Ready(Some(Ok(remap::Event::B0xx(B0xxEvent::new(
+                    btn,
+                    pressed,
+                    Timestamp::now(),
+                )))))
+            });
+            let sink = OutputSink {
+                file: std::io::sink(),
+                reopen: None,
+                command_log: None,
+                handedness: config::Handedness::Standard,
+                game: Game::Melee,
+                capability: config::PipeCapability::Standard,
+                command: String::new(),
+                written: Default::default(),
+            };
+            // With the state file on, as it is by default.
+            let options = remap::Options {
+                state_file: Some(recovery::StateFile::new(
+                    state_path("allocation-free"),
+                    recovery::SAVE_PERIOD,
+                )),
+                ..Default::default()
+            };
             let mut main = Main::default();
-            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MX, PRESSED), false);
-            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MY, PRESSED), false);
-            let _ = main.process_b0xx(
-                B0xxEvent::new_without_time((Stick::C, c_axis, c_dir).into(), PRESSED),
-                false,
-            );
-            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MY, RELEASED), false);
-            let _ = main.process_b0xx(
-                B0xxEvent::new_without_time((Stick::A, Axis::X, x_dir).into(), PRESSED),
-                false,
-            );
-            let got = main.process_b0xx(
-                B0xxEvent::new_without_time((Stick::A, Axis::Y, y_dir).into(), PRESSED),
-                false,
+            let mut run = Box::pin(remap::run(&mut main, events, sink, options));
+            let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+            assert!(run.as_mut().poll(&mut cx).is_pending());
+            budget.set(1000);
+            assert_eq!(
+                allocations(|| assert!(run.as_mut().poll(&mut cx).is_pending())),
+                0
             );
-            let want = (P7375.neg_not(x_dir), P3125.neg_not(y_dir));
-            assert_eq!(got, Some(Input::Stick(Stick::A, want)),);
+            assert_eq!(budget.get(), 0);
         }
+        // The allocator counts what it should.
+        assert_eq!(
+            allocations(|| {
+                let layout = std::alloc::Layout::new::<u64>();
+                // SAFETY: `layout` isn't zero-sized, and is what the pointer
+                // was allocated with.
+                unsafe { ALLOCATOR.dealloc(ALLOCATOR.alloc(layout), layout) }
+            }),
+            1
+        );
     }
 
     #[test]
-    fn tilt_fsmash() {
-        for x_dir in [POSITIVE, NEGATIVE] {
-            for y_dir in [POSITIVE, NEGATIVE] {
-                let mut main = Main::default();
-                let got =
-                    main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::MX, PRESSED), false);
-                assert_eq!(got, None);
-                let got = main.process_b0xx(
-                    B0xxEvent::new_without_time((Stick::A, Axis::Y, y_dir).into(), PRESSED),
-                    false,
-                );
-                assert_eq!(
-                    got,
-                    Some(Input::Stick(Stick::A, (P0000, P5375.neg_not(y_dir))))
-                );
-                let got = main.process_b0xx(
-                    B0xxEvent::new_without_time((Stick::C, Axis::X, x_dir).into(), PRESSED),
-                    false,
-                );
-                assert_eq!(
-                    got,
-                    Some(Input::Stick(
-                        Stick::C,
-                        (P8125.neg_not(x_dir), P2875.neg_not(y_dir))
-                    ))
-                );
-                let got = main.process_b0xx(
-                    B0xxEvent::new_without_time((Stick::C, Axis::X, x_dir).into(), RELEASED),
-                    false,
-                );
-                assert_eq!(got, Some(Input::Stick(Stick::C, (P0000, P0000))));
-            }
+    fn b0xx_raw_all() {
+        for (i, btn) in B0xxRaw::ALL.into_iter().enumerate() {
+            assert_eq!(btn as usize, i);
         }
+        assert_eq!(B0xxRaw::ALL.last(), Some(&B0xxRaw::M4));
     }
 
     #[test]
-    fn accidental_side_b() {
-        for dir in [POSITIVE, NEGATIVE] {
-            let left_right = (Stick::A, Axis::X, dir).into();
-            let mut buttons = vec![B0xxRaw::MY, B0xxRaw::B, left_right];
-            permutohedron::heap_recursive(&mut buttons, |buttons| {
-                let mut main = Main::default();
-                let got = buttons
-                    .iter()
-                    .fold(None, |_, &btn| {
-                        main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
-                    })
-                    .expect("final b0xx input resulted in null GC input");
-                let want = match *buttons.last().unwrap() {
-                    B0xxRaw::B => {
-                        Input::ModifiedPress((P6625.neg_not(dir), P0000), ButtonImpure::B)
-                    }
-                    _ => Input::Stick(Stick::A, (P6625.neg_not(dir), P0000)),
-                };
-                assert_eq!(got, want);
-            });
+    fn hold_durations() {
+        let mut main = Main::default();
+        let t = |micros| Timestamp::default() + Duration::from_micros(micros);
+        for (micros, btn, pressed) in [
+            (0, B0xxRaw::LS, PRESSED),
+            (3_200, B0xxRaw::LS, RELEASED),
+            (10_000, B0xxRaw::LS, PRESSED),
+            (14_000, B0xxRaw::LS, RELEASED),
+            // Releasing again isn't another hold.
+            (15_000, B0xxRaw::LS, RELEASED),
+            (20_000, B0xxRaw::LS, PRESSED),
+            (24_700, B0xxRaw::X, PRESSED),
+            (24_700, B0xxRaw::LS, RELEASED),
+            (30_000, B0xxRaw::LS, PRESSED),
+            (36_100, B0xxRaw::LS, RELEASED),
+            (40_000, B0xxRaw::LS, PRESSED),
+            (124_700, B0xxRaw::X, RELEASED),
+            (490_000, B0xxRaw::LS, RELEASED),
+            // Held since, and not counted.
+            (500_000, B0xxRaw::A, PRESSED),
+        ] {
+            let _ = main.process_b0xx(B0xxEvent::new(btn, pressed, t(micros)), false);
         }
+        let holds = &main.stats.hold_durations;
+        assert_eq!(
+            holds.held().collect::<Vec<_>>(),
+            [(B0xxRaw::X, 1), (B0xxRaw::LS, 5)]
+        );
+        // 3.2, 4, 4.7 and 6.1ms end at 3.5, 4.5, 5 and 6.5ms, and 450ms
+        // overflows.
+        assert_eq!(
+            holds.quantile(B0xxRaw::LS, 0.1),
+            Some(Duration::from_micros(3_500))
+        );
+        assert_eq!(
+            holds.quantile(B0xxRaw::LS, 0.5),
+            Some(Duration::from_micros(5_000))
+        );
+        assert_eq!(
+            holds.quantile(B0xxRaw::LS, 0.8),
+            Some(Duration::from_micros(6_500))
+        );
+        assert_eq!(holds.quantile(B0xxRaw::LS, 0.9), None);
+        assert_eq!(holds.quantile(B0xxRaw::A, 0.5), None);
+        assert_eq!(
+            holds.to_string(),
+            "X: 1 holds, p10 100.5ms, p50 100.5ms, p90 100.5ms, p99 100.5ms\n\
+             LS: 5 holds, p10 3.5ms, p50 5ms, p90 >400ms, p99 >400ms"
+        );
+    }
+
+    #[test]
+    fn timestamp_sub_saturates() {
+        let earlier = Timestamp::default() + Duration::from_millis(5);
+        let later = earlier + Duration::from_millis(10);
+        assert_eq!(later - earlier, Duration::from_millis(10));
+        assert_eq!(earlier - later, Duration::ZERO);
     }
 
     #[test]
@@ -1374,18 +11066,355 @@ mod tests {
             let mut buttons = [B0xxRaw::Left, B0xxRaw::Right, modifier];
             permutohedron::heap_recursive(&mut buttons, |buttons| {
                 let mut main = Main::default();
-                let got = buttons.iter().fold(None, |_, &btn| {
-                    main.process_b0xx(B0xxEvent::new_without_time(btn, PRESSED), false)
-                });
+                let got = press_all(&mut main, buttons, false);
                 let want = match (*buttons.last().unwrap()).into() {
-                    B0xx::Impure(Impure::ModX) | B0xx::Impure(Impure::ModY) => None,
+                    B0xx::Impure(Impure::ModX) | B0xx::Impure(Impure::ModY) => vec![],
                     B0xx::Impure(Impure::Stick(Stick::A, Axis::X, dir)) => {
-                        Some(Input::Stick(Stick::A, (Analog::MAX.neg_not(dir), P0000)))
+                        vec![Input::Stick(Stick::A, (Analog::MAX.neg_not(dir), P0000))]
                     }
                     btn => panic!("unexpected button: {:?}", btn),
                 };
-                assert_eq!(got, want);
+                assert_eq!(got[..], want);
             })
         }
     }
+
+    // Rolling between left and right, each press overriding the other still
+    // held before it is released, is nerfed from the third override within
+    // 100ms, with or without a modifier.
+    #[test_case(40, &[false, false, true, true]; "rapid")]
+    #[test_case(60, &[false, false, false, false]; "outside_window")]
+    fn ledgedash_nerf(spacing: u64, nerfed: &[bool]) {
+        let start = Timestamp::default() + Duration::from_secs(10);
+        for modifier in [None, Some(B0xxRaw::MX), Some(B0xxRaw::MY)] {
+            let mut main = Main {
+                ledgedash_nerf: Some(LedgedashNerf::new(config::LedgedashNerf {
+                    max_reactivations: 2,
+                    window_ms: 100,
+                })),
+                ..Default::default()
+            };
+            for btn in modifier.into_iter().chain([B0xxRaw::Left]) {
+                let _ = main.process_b0xx(B0xxEvent::new(btn, PRESSED, start), false);
+            }
+            let mut held = B0xxRaw::Left;
+            for (i, &nerfed) in nerfed.iter().enumerate() {
+                let time = start + Duration::from_millis((i as u64 + 1) * spacing);
+                let other = if held == B0xxRaw::Left {
+                    B0xxRaw::Right
+                } else {
+                    B0xxRaw::Left
+                };
+                let want = Input::Stick(
+                    Stick::A,
+                    (Analog::MAX.neg_not(other == B0xxRaw::Right), P0000),
+                );
+                let got = main.process_b0xx(B0xxEvent::new(other, PRESSED, time), false);
+                if nerfed {
+                    assert_eq!(
+                        got[..],
+                        [Input::Stick(Stick::A, (P0000, P0000))],
+                        "{:?} {}",
+                        modifier,
+                        i
+                    );
+                    let deadline = time + LedgedashNerf::NEUTRAL;
+                    assert_eq!(main.deadline(), Some(deadline));
+                    assert_eq!(main.process_deadline(deadline, false)[..], [want]);
+                } else {
+                    assert_eq!(got[..], [want], "{:?} {}", modifier, i);
+                    assert_eq!(main.deadline(), None);
+                }
+                let release = time + Duration::from_millis(spacing / 2);
+                let _ = main.process_b0xx(B0xxEvent::new(held, RELEASED, release), false);
+                held = other;
+            }
+        }
+    }
+
+    #[test_case(&[(NEGATIVE, PRESSED), (POSITIVE, PRESSED)], AxisState::Active(POSITIVE, PRESSED); "up_after_down")]
+    #[test_case(&[(POSITIVE, PRESSED), (NEGATIVE, PRESSED)], AxisState::Active(POSITIVE, PRESSED); "down_after_up")]
+    #[test_case(&[(NEGATIVE, PRESSED), (POSITIVE, PRESSED), (POSITIVE, RELEASED)], AxisState::Active(NEGATIVE, RELEASED); "up_released_last")]
+    #[test_case(&[(POSITIVE, PRESSED), (NEGATIVE, PRESSED), (POSITIVE, RELEASED)], AxisState::Active(NEGATIVE, RELEASED); "up_released_first")]
+    #[test_case(&[(POSITIVE, PRESSED), (NEGATIVE, PRESSED), (NEGATIVE, RELEASED)], AxisState::Active(POSITIVE, RELEASED); "down_released")]
+    #[test_case(&[(NEGATIVE, PRESSED), (NEGATIVE, RELEASED)], AxisState::Null(None); "down_alone")]
+    #[test_case(&[(NEGATIVE, PRESSED), (NEGATIVE, PRESSED)], AxisState::Active(NEGATIVE, RELEASED); "down_pressed_twice")]
+    fn up_priority_transition(events: &[(Direction, Pressed)], expected: AxisState) {
+        let mut state = AxisState::default();
+        for &(dir, pressed) in events {
+            state.transition_prioritized(POSITIVE, dir, pressed);
+        }
+        assert_eq!(state, expected);
+    }
+
+    // Up wins over down whatever the press order, and down takes over once up
+    // is released, at Mod Y's vertical coordinates throughout.
+    #[test]
+    fn up_priority_mod_y() {
+        let profile = config::Profile {
+            socd: config::Socd {
+                y: config::YSocd::UpPriority,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut buttons = [B0xxRaw::MY, B0xxRaw::Up, B0xxRaw::Down];
+        permutohedron::heap_recursive(&mut buttons, |buttons| {
+            let mut main = Main {
+                a_stick_reference: Some((
+                    AxisReference::default(),
+                    AxisReference::with_priority(Some(POSITIVE)),
+                )),
+                ..Main::new(&profile)
+            };
+            let _ = press_all(&mut main, buttons, false);
+            assert_eq!(main.output.a_stick, (P0000, P7375));
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Up, RELEASED), false);
+            assert_eq!(main.output.a_stick, (P0000, -P7375));
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Up, PRESSED), false);
+            assert_eq!(main.output.a_stick, (P0000, P7375));
+            let _ = main.process_b0xx(B0xxEvent::new_without_time(B0xxRaw::Down, RELEASED), false);
+            assert_eq!(main.output.a_stick, (P0000, P7375));
+            let (_, reference) = main.a_stick_reference.unwrap();
+            assert!(!reference.diverges(main.a_stick.y), "{:?}", reference);
+        });
+    }
+
+    // Mashing opposite C directions never gives a stray reverse smash with
+    // neutral SOCD, which hands the stick back to whichever is still held.
+    #[test_case(config::CStickSocd::SecondInput, &["SET C 0.1875 0.5", "SET C 0.8149606299212598 0.5", "SET C 0.1875 0.5", "SET C 0.5 0.5"]; "second_input")]
+    #[test_case(config::CStickSocd::Neutral, &["SET C 0.1875 0.5", "SET C 0.5 0.5", "SET C 0.8149606299212598 0.5", "SET C 0.5 0.5", "SET C 0.1875 0.5", "SET C 0.5 0.5"]; "neutral")]
+    fn c_stick_socd(c_stick: config::CStickSocd, want: &[&str]) {
+        let profile = config::Profile {
+            socd: config::Socd {
+                c_stick,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut main = Main::new(&profile);
+        let mut got = Vec::new();
+        for (btn, pressed) in [
+            (B0xxRaw::CL, PRESSED),
+            (B0xxRaw::CR, PRESSED),
+            (B0xxRaw::CL, RELEASED),
+            (B0xxRaw::CL, PRESSED),
+            (B0xxRaw::CR, RELEASED),
+            (B0xxRaw::CL, RELEASED),
+        ] {
+            let inputs = main.process_b0xx(B0xxEvent::new_without_time(btn, pressed), false);
+            got.extend(inputs.into_iter().map(|input| {
+                input
+                    .into_pipe_input()
+                    .into_input_string(Default::default(), Game::Melee, Default::default())
+                    .trim_end()
+                    .to_owned()
+            }));
+        }
+        assert_eq!(got, want);
+        assert_eq!(main.c_stick, CStickState::default());
+    }
+
+    /// Exhaustively checks the axis state machines against a model of the
+    /// physical buttons.
+    mod axis_model {
+        use super::*;
+
+        const MAX_LEN: usize = 6;
+
+        /// Adapts an axis state machine to the model check.
+        trait AxisMachine: Copy + Default + std::fmt::Debug + std::panic::UnwindSafe {
+            const ALT: bool;
+
+            /// Returns whether alt mode was released.
+            fn transition(
+                &mut self,
+                dir: Direction,
+                pressed: Pressed,
+                alt_on_pressed: bool,
+            ) -> bool;
+            fn active(self) -> Option<Direction>;
+            fn active_unique(self) -> Option<Direction>;
+        }
+
+        impl AxisMachine for DualModeAxisState {
+            const ALT: bool = true;
+
+            fn transition(
+                &mut self,
+                dir: Direction,
+                pressed: Pressed,
+                alt_on_pressed: bool,
+            ) -> bool {
+                DualModeAxisState::transition(
+                    self,
+                    dir,
+                    pressed,
+                    alt_on_pressed,
+                    config::CStickSocd::SecondInput,
+                )
+            }
+
+            fn active(self) -> Option<Direction> {
+                DualModeAxisState::active(self)
+            }
+
+            fn active_unique(self) -> Option<Direction> {
+                DualModeAxisState::active_unique(self)
+            }
+        }
+
+        impl AxisMachine for AxisState {
+            const ALT: bool = false;
+
+            fn transition(&mut self, dir: Direction, pressed: Pressed, _: bool) -> bool {
+                AxisState::transition(self, dir, pressed);
+                false
+            }
+
+            fn active(self) -> Option<Direction> {
+                AxisState::active(self)
+            }
+
+            fn active_unique(self) -> Option<Direction> {
+                AxisState::active_unique(self)
+            }
+        }
+
+        /// `AxisState` with up priority.
+        #[derive(Clone, Copy, Debug, Default)]
+        struct UpPriority(AxisState);
+
+        impl AxisMachine for UpPriority {
+            const ALT: bool = false;
+
+            fn transition(&mut self, dir: Direction, pressed: Pressed, _: bool) -> bool {
+                self.0.transition_prioritized(POSITIVE, dir, pressed);
+                false
+            }
+
+            fn active(self) -> Option<Direction> {
+                self.0.active()
+            }
+
+            fn active_unique(self) -> Option<Direction> {
+                self.0.active_unique()
+            }
+        }
+
+        /// Whether each direction, indexed by `Direction as usize`, is held
+        /// and if so whether it was pressed in alt mode.
+        type Buttons = [Option<bool>; 2];
+
+        fn check<M: AxisMachine>(
+            machine: M,
+            buttons: Buttons,
+            events: &mut Vec<(Direction, Pressed, bool)>,
+        ) {
+            if events.len() == MAX_LEN {
+                return;
+            }
+            for dir in [POSITIVE, NEGATIVE] {
+                let held = buttons[dir as usize];
+                let alts: &[bool] = match held {
+                    // Whether a release is in alt mode is irrelevant.
+                    Some(_) => &[false],
+                    None if M::ALT => &[false, true],
+                    None => &[false],
+                };
+                for &alt_on_pressed in alts {
+                    let pressed = held.is_none();
+                    events.push((dir, pressed, alt_on_pressed));
+                    let mut next = machine;
+                    let alt_released = std::panic::catch_unwind(move || {
+                        let alt_released = next.transition(dir, pressed, alt_on_pressed);
+                        (next, alt_released)
+                    });
+                    let (next, alt_released) = match alt_released {
+                        Ok(result) => result,
+                        Err(_) => panic!("panicked after {:?}", events),
+                    };
+                    let mut buttons = buttons;
+                    buttons[dir as usize] = pressed.then_some(alt_on_pressed);
+                    assert_eq!(
+                        alt_released,
+                        held == Some(true),
+                        "alt released after {:?}",
+                        events
+                    );
+                    let normal = |dir: Direction| buttons[dir as usize] == Some(false);
+                    if let Some(active) = next.active() {
+                        assert!(normal(active), "{:?} active after {:?}", next, events);
+                    }
+                    if let Some(active) = next.active_unique() {
+                        assert!(
+                            normal(active) && !normal(!active),
+                            "{:?} uniquely active after {:?}",
+                            next,
+                            events
+                        );
+                    }
+                    check(next, buttons, events);
+                    let _ = events.pop();
+                }
+            }
+        }
+
+        #[test]
+        fn dual_mode_axis_state() {
+            check(DualModeAxisState::default(), [None; 2], &mut Vec::new());
+        }
+
+        #[test]
+        fn axis_state() {
+            check(AxisState::default(), [None; 2], &mut Vec::new());
+        }
+
+        #[test]
+        fn up_priority_axis_state() {
+            check(UpPriority::default(), [None; 2], &mut Vec::new());
+        }
+
+        proptest::proptest! {
+            // Each element toggles a direction, as presses and releases
+            // alternate on a real keyboard.
+            #[test]
+            fn axis_state_matches_reference(
+                toggles in proptest::collection::vec(proptest::prelude::any::<bool>(), 0..64),
+            ) {
+                let mut state = AxisState::default();
+                let mut reference = AxisReference::default();
+                for dir in toggles {
+                    let pressed = !reference.held[dir as usize];
+                    state.transition(dir, pressed);
+                    reference.transition(dir, pressed);
+                    proptest::prop_assert!(
+                        !reference.diverges(state),
+                        "{:?} vs {:?}",
+                        state,
+                        reference
+                    );
+                }
+            }
+
+            #[test]
+            fn up_priority_matches_reference(
+                toggles in proptest::collection::vec(proptest::prelude::any::<bool>(), 0..64),
+            ) {
+                let mut state = AxisState::default();
+                let mut reference = AxisReference::with_priority(Some(POSITIVE));
+                for dir in toggles {
+                    let pressed = !reference.held[dir as usize];
+                    state.transition_prioritized(POSITIVE, dir, pressed);
+                    reference.transition(dir, pressed);
+                    proptest::prop_assert!(
+                        !reference.diverges(state),
+                        "{:?} vs {:?}",
+                        state,
+                        reference
+                    );
+                }
+            }
+        }
+    }
 }
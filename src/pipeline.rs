@@ -0,0 +1,252 @@
+//! Reading the keyboard on a thread of its own, with `--reader-queue`.
+//!
+//! By default the keyboard is read on the thread that processes its events
+//! and writes the output, so a slow write, e.g. to a subscriber or a pipe
+//! Dolphin is slow to drain, delays reading it. Should the kernel's buffer for
+//! the device fill meanwhile, it drops events and reports SYN_DROPPED.
+//! `spawn_reader` instead reads the device on a thread that pushes every
+//! event into a bounded `queue`, which `run` drains as its stream of events.
+//! Remapping stays on the processing thread, as the bindings follow the
+//! profile switched to. A full queue either holds the reader back, losing
+//! nothing, or drops its oldest event with a warning, per `Overflow`. A
+//! dropped event may be a release, so `run` reads back the keys down after a
+//! drop.
+
+use super::*;
+
+/// What the reader does with an event when the queue is full.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Overflow {
+    /// Waits for room, leaving the events in the kernel's buffer meanwhile.
+    #[default]
+    Block,
+    /// Drops the oldest event queued to make room.
+    DropOldest,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            _ => Err(format!("unknown queue overflow {:?}", s)),
+        }
+    }
+}
+
+/// How full the queue is and has been, as the session statistics report it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub(crate) struct QueueStats {
+    pub(crate) capacity: usize,
+    /// Events queued as of the stats.
+    pub(crate) depth: usize,
+    /// The most events ever queued at once.
+    pub(crate) max_depth: usize,
+    /// Events dropped for a full queue, under `Overflow::DropOldest`.
+    pub(crate) dropped: u64,
+}
+
+#[cfg(feature = "linux-input")]
+struct State<T> {
+    queue: std::collections::VecDeque<T>,
+    overflow: Overflow,
+    stats: QueueStats,
+    /// The sender is gone, so the queue ends once drained.
+    sent: bool,
+    /// The receiver is gone, so nothing more is pushed.
+    received: bool,
+}
+
+#[cfg(feature = "linux-input")]
+struct Shared<T> {
+    state: std::sync::Mutex<State<T>>,
+    /// Signalled when an event is popped or the receiver goes.
+    room: std::sync::Condvar,
+    /// Woken when an event is pushed or the sender goes.
+    waker: futures::task::AtomicWaker,
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> Shared<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.state.lock().expect("queue lock poisoned")
+    }
+}
+
+#[cfg(feature = "linux-input")]
+/// Returns the ends of a queue of up to `capacity` events, at least one.
+pub(crate) fn queue<T>(capacity: usize, overflow: Overflow) -> (Sender<T>, Receiver<T>) {
+    let capacity = capacity.max(1);
+    let shared = std::sync::Arc::new(Shared {
+        state: std::sync::Mutex::new(State {
+            queue: std::collections::VecDeque::with_capacity(capacity),
+            overflow,
+            stats: QueueStats {
+                capacity,
+                ..Default::default()
+            },
+            sent: false,
+            received: false,
+        }),
+        room: std::sync::Condvar::new(),
+        waker: Default::default(),
+    });
+    (
+        Sender {
+            shared: std::sync::Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(feature = "linux-input")]
+/// The end of a queue that the reader pushes events into.
+pub(crate) struct Sender<T> {
+    shared: std::sync::Arc<Shared<T>>,
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> Sender<T> {
+    /// Queues `event`, waiting for room or dropping the oldest event queued
+    /// if the queue is full. Returns false once the receiver is gone.
+    pub(crate) fn push(&self, event: T) -> bool {
+        let mut state = self.shared.lock();
+        while state.queue.len() >= state.stats.capacity && !state.received {
+            match state.overflow {
+                Overflow::Block => {
+                    state = self.shared.room.wait(state).expect("queue lock poisoned");
+                }
+                Overflow::DropOldest => {
+                    let _: Option<T> = state.queue.pop_front();
+                    state.stats.dropped += 1;
+                    warn!(
+                        dropped = state.stats.dropped,
+                        "keyboard event queue full, dropped the oldest event"
+                    );
+                }
+            }
+        }
+        if state.received {
+            return false;
+        }
+        state.queue.push_back(event);
+        state.stats.max_depth = state.stats.max_depth.max(state.queue.len());
+        drop(state);
+        self.shared.waker.wake();
+        true
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.lock().sent = true;
+        self.shared.waker.wake();
+    }
+}
+
+#[cfg(feature = "linux-input")]
+/// The end of a queue that `run` drains, as a stream ending once the sender
+/// is gone and every event it queued is taken.
+pub(crate) struct Receiver<T> {
+    shared: std::sync::Arc<Shared<T>>,
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> Receiver<T> {
+    /// Returns a handle to the statistics of the queue, which outlives the
+    /// receiver being moved into `run`.
+    pub(crate) fn stats(&self) -> Stats
+    where
+        T: 'static,
+    {
+        let shared = std::sync::Arc::clone(&self.shared);
+        Stats(Box::new(move || {
+            let state = shared.lock();
+            QueueStats {
+                depth: state.queue.len(),
+                ..state.stats
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> futures::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<T>> {
+        // Registered before looking, so that a push in between still wakes
+        // the task.
+        self.shared.waker.register(cx.waker());
+        let mut state = self.shared.lock();
+        match state.queue.pop_front() {
+            Some(event) => {
+                drop(state);
+                self.shared.room.notify_one();
+                std::task::Poll::Ready(Some(event))
+            }
+            None if state.sent => std::task::Poll::Ready(None),
+            None => std::task::Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "linux-input")]
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().received = true;
+        self.shared.room.notify_one();
+    }
+}
+
+#[cfg(feature = "linux-input")]
+/// Reads the statistics of a queue, whatever its events.
+pub(crate) struct Stats(Box<dyn Fn() -> QueueStats>);
+
+#[cfg(feature = "linux-input")]
+impl Stats {
+    pub(crate) fn get(&self) -> QueueStats {
+        (self.0)()
+    }
+}
+
+/// Opens the keyboard with `open` on a thread of its own, e.g. the device at
+/// a path, and returns the stream of its events, ending with the first error,
+/// through a queue of up to `capacity`.
+#[cfg(feature = "linux-input")]
+pub(crate) fn spawn_reader<S>(
+    open: impl FnOnce() -> anyhow::Result<S> + Send + 'static,
+    capacity: usize,
+    overflow: Overflow,
+) -> Receiver<anyhow::Result<evdev_rs::InputEvent>>
+where
+    S: futures::Stream<Item = std::io::Result<evdev_rs::InputEvent>>,
+{
+    let (sender, receiver) = queue(capacity, overflow);
+    let _: std::thread::JoinHandle<()> = std::thread::spawn(move || {
+        futures::executor::block_on(async {
+            let device = match open() {
+                Ok(device) => device,
+                Err(e) => {
+                    let _: bool = sender.push(Err(e));
+                    return;
+                }
+            };
+            futures::pin_mut!(device);
+            while let Some(r) = device.next().await {
+                let r = r.context("failed to read keyboard event");
+                let failed = r.is_err();
+                if !sender.push(r) || failed {
+                    return;
+                }
+            }
+        })
+    });
+    receiver
+}
@@ -0,0 +1,235 @@
+//! A state file for noticing that the last session never shut down, e.g.
+//! because it was killed or crashed, possibly with buttons held in Dolphin.
+//!
+//! While running, `StateFile` saves the held buttons and what was last output,
+//! marked dirty, from a thread of its own and at most once per period. A
+//! clean shutdown saves them marked clean. On the next start, `recover` reads
+//! the file and releases whatever a dirty session left pressed.
+
+use super::*;
+
+/// How often the state is saved at most while it keeps changing.
+pub(crate) const SAVE_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// What is saved.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct State {
+    /// Whether the session shut down, rather than still running or having
+    /// died.
+    pub(crate) clean: bool,
+    pub(crate) held: std::collections::BTreeSet<B0xxRaw>,
+    pub(crate) output: ControllerSnapshot,
+}
+
+impl State {
+    /// Returns the commands taking Dolphin from `output` back to neutral.
+    pub(crate) fn neutralize(&self) -> Vec<DolphinPipeInput> {
+        diff(&self.output, &ControllerSnapshot::default())
+    }
+}
+
+/// Reads the state saved at `path`, if any.
+pub(crate) fn read(path: &std::path::Path) -> anyhow::Result<Option<State>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context(format!("failed to read state file {}", path.display())))
+        }
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("failed to parse state file {}", path.display()))
+}
+
+/// Writes `state` to `path` through a temporary file renamed over it, so that
+/// dying midway never leaves it truncated.
+pub(crate) fn write(path: &std::path::Path, state: &State) -> anyhow::Result<()> {
+    let json = serde_json::to_string(state).context("failed to serialize state")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, json)
+        .with_context(|| format!("failed to write {}", temporary.display()))?;
+    std::fs::rename(&temporary, path).with_context(|| {
+        format!(
+            "failed to rename {} to {}",
+            temporary.display(),
+            path.display()
+        )
+    })
+}
+
+/// What `State` records, in a fixed size so that the running session can
+/// hand it to the saving thread without allocating.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Snapshot {
+    clean: bool,
+    /// One bit per held button, by `B0xxRaw` discriminant.
+    held: u64,
+    /// One bit per output button, by `GCButton` discriminant.
+    buttons: u16,
+    a_stick: AStickInput,
+    c_stick: CStickInput,
+    trigger: Trigger,
+}
+
+impl Snapshot {
+    fn new(main: &Main, clean: bool) -> Self {
+        Self {
+            clean,
+            held: main
+                .held
+                .iter()
+                .fold(0, |bits, &btn| bits | 1 << btn as u32),
+            buttons: main
+                .output
+                .buttons
+                .iter()
+                .fold(0, |bits, &button| bits | 1 << button as u32),
+            a_stick: main.output.a_stick,
+            c_stick: main.output.c_stick,
+            trigger: main.output.trigger,
+        }
+    }
+}
+
+impl From<Snapshot> for State {
+    fn from(snapshot: Snapshot) -> Self {
+        Self {
+            clean: snapshot.clean,
+            held: B0xxRaw::ALL
+                .into_iter()
+                .filter(|&btn| snapshot.held & 1 << btn as u32 != 0)
+                .collect(),
+            output: ControllerSnapshot {
+                buttons: GCButton::ALL
+                    .into_iter()
+                    .filter(|&button| snapshot.buttons & 1 << button as u32 != 0)
+                    .collect(),
+                a_stick: snapshot.a_stick,
+                c_stick: snapshot.c_stick,
+                trigger: snapshot.trigger,
+            },
+        }
+    }
+}
+
+/// Returns the commands releasing whatever the session that saved `path`
+/// left pressed if it never shut down, logging what it held.
+pub(crate) fn recover(path: &std::path::Path) -> Vec<DolphinPipeInput> {
+    match read(path) {
+        Ok(Some(state)) if !state.clean => {
+            warn!(
+                "the last session did not shut down, with {:?} held and {:?} output; \
+                 returning to neutral",
+                state.held, state.output
+            );
+            state.neutralize()
+        }
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            warn!("{:#}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The latest state not yet saved, and whether the sender is gone.
+type Latest = std::sync::Arc<(
+    std::sync::Mutex<(Option<Snapshot>, bool)>,
+    std::sync::Condvar,
+)>;
+
+/// Saves the states it is given to a file in the background.
+pub(crate) struct StateFile {
+    /// The last state sent, to skip sending it again unchanged.
+    sent: Option<Snapshot>,
+    latest: Latest,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl StateFile {
+    /// Saves the first state immediately and each later one no sooner than
+    /// `period` after the previous save, skipping any replaced meanwhile.
+    pub(crate) fn new(path: std::path::PathBuf, period: std::time::Duration) -> Self {
+        let latest = Latest::default();
+        let thread = {
+            let latest = latest.clone();
+            std::thread::spawn(move || {
+                let mut next_save = std::time::Instant::now();
+                loop {
+                    let (snapshot, closed) = {
+                        let (lock, condvar) = &*latest;
+                        let guard = condvar
+                            .wait_while(
+                                lock.lock().expect("state file lock poisoned"),
+                                |(snapshot, closed)| snapshot.is_none() && !*closed,
+                            )
+                            .expect("state file lock poisoned");
+                        // The last state is saved regardless of the period, as
+                        // it is the one shutting down.
+                        let (mut guard, _) = condvar
+                            .wait_timeout_while(
+                                guard,
+                                next_save.saturating_duration_since(std::time::Instant::now()),
+                                |(_, closed)| !*closed,
+                            )
+                            .expect("state file lock poisoned");
+                        (guard.0.take(), guard.1)
+                    };
+                    if let Some(snapshot) = snapshot {
+                        if let Err(e) = write(&path, &snapshot.into()) {
+                            warn!("{:#}", e);
+                        }
+                        next_save = std::time::Instant::now() + period;
+                    }
+                    if closed {
+                        break;
+                    }
+                }
+            })
+        };
+        Self {
+            sent: None,
+            latest,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues the state of a session still running.
+    pub(crate) fn save(&mut self, main: &Main) {
+        let snapshot = Snapshot::new(main, false);
+        if self.sent != Some(snapshot) {
+            self.send(snapshot);
+        }
+    }
+
+    /// Saves the state of a session shutting down cleanly and waits for it to
+    /// be written.
+    pub(crate) fn close(mut self, main: &Main) {
+        self.send(Snapshot::new(main, true));
+    }
+
+    fn send(&mut self, snapshot: Snapshot) {
+        let (lock, condvar) = &*self.latest;
+        lock.lock().expect("state file lock poisoned").0 = Some(snapshot);
+        condvar.notify_one();
+        self.sent = Some(snapshot);
+    }
+}
+
+impl Drop for StateFile {
+    /// Writes out the last state queued before returning.
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.latest;
+        lock.lock().expect("state file lock poisoned").1 = true;
+        condvar.notify_one();
+        if let Some(Err(_)) = self.thread.take().map(std::thread::JoinHandle::join) {
+            warn!("state file thread panicked");
+        }
+    }
+}
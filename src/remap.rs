@@ -0,0 +1,918 @@
+//! The remapping loop, apart from finding the keyboard and opening the pipe.
+//!
+//! `run` takes its events as a stream and writes to any `Sink`, so that a
+//! bigger tool, e.g. a trainer, can embed the engine, and tests can drive it
+//! in memory. The binary feeds it the keyboard and signals, and the pipe.
+//!
+//! An embedder that only observes, e.g. an overlay, subscribes to
+//! `Options::publisher` instead of implementing a `Sink`. Subscribers that
+//! fall behind miss events rather than hold up the sink.
+//!
+//! Combos and the control socket run the same internal commands, through
+//! `execute`.
+
+use super::*;
+
+/// What drives `run` besides deadlines.
+pub(crate) enum Event {
+    /// A button pressed or released.
+    B0xx(B0xxEvent),
+    /// Logs a snapshot of the input state, e.g. on `--dump-key`.
+    Snapshot,
+    /// Writes the session statistics to `Options::stats`, e.g. on SIGUSR1.
+    WriteStats,
+    /// Pauses or resumes as the pause combo does, e.g. on a focus key.
+    Pause,
+    /// A request from the control socket, along with where its response
+    /// goes.
+    Control(
+        control::Request,
+        futures::channel::oneshot::Sender<control::Response>,
+    ),
+    /// Ends `run` cleanly, as does the end of the events.
+    Exit,
+}
+
+/// Why `run` failed.
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The events yielded an error, e.g. the keyboard was disconnected.
+    Events(anyhow::Error),
+    /// Writing to the sink failed.
+    Sink(anyhow::Error),
+}
+
+/// What `run` made of an event, as published to subscribers.
+#[derive(Clone, Debug)]
+pub(crate) struct ProcessedEvent {
+    /// The button event, or none for a deadline.
+    pub(crate) event: Option<B0xxEvent>,
+    /// The inputs sent to the sink, before any `--delay` or `--quantize`.
+    pub(crate) inputs: Inputs,
+    /// The controller state after the inputs.
+    pub(crate) snapshot: ControllerSnapshot,
+    /// Every button held after the event, however interpreted.
+    pub(crate) held: std::collections::BTreeSet<B0xxRaw>,
+    /// How each axis resolved the directions held, e.g. whether the opposite
+    /// of the active direction is held too.
+    pub(crate) a_stick: StickState,
+    pub(crate) c_stick: CStickState,
+}
+
+/// Hands every `ProcessedEvent` to the subscribers, never waiting on them.
+#[derive(Default)]
+pub(crate) struct Publisher {
+    subscribers: Vec<(
+        futures::channel::mpsc::Sender<ProcessedEvent>,
+        std::sync::Arc<std::sync::atomic::AtomicU64>,
+    )>,
+}
+
+impl Publisher {
+    /// Returns a subscriber that buffers up to `capacity` events, at least
+    /// one, and misses those published while its buffer is full.
+    pub(crate) fn subscribe(&mut self, capacity: usize) -> Subscriber {
+        let (sender, receiver) = futures::channel::mpsc::channel(capacity.saturating_sub(1));
+        let dropped = std::sync::Arc::default();
+        self.subscribers
+            .push((sender, std::sync::Arc::clone(&dropped)));
+        Subscriber { receiver, dropped }
+    }
+
+    /// Publishes the event `processed` returns, unless nobody is subscribed.
+    /// Subscribers that went away are forgotten.
+    fn publish(&mut self, processed: impl FnOnce() -> ProcessedEvent) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        let processed = processed();
+        self.subscribers.retain_mut(
+            |(sender, dropped)| match sender.try_send(processed.clone()) {
+                Ok(()) => true,
+                Err(e) if e.is_full() => {
+                    let _: u64 = dropped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    true
+                }
+                Err(_) => false,
+            },
+        );
+    }
+}
+
+/// The events published after `Publisher::subscribe`, ending with `run`.
+pub(crate) struct Subscriber {
+    receiver: futures::channel::mpsc::Receiver<ProcessedEvent>,
+    dropped: std::sync::Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl Subscriber {
+    /// Returns how many events were missed so far for a full buffer.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+impl futures::Stream for Subscriber {
+    type Item = ProcessedEvent;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<ProcessedEvent>> {
+        futures::Stream::poll_next(std::pin::Pin::new(&mut self.receiver), cx)
+    }
+}
+
+/// The settings that change while running. `run` owns them, and only
+/// `execute` changes them, between events, whether the command came from a
+/// combo, a signal or the control socket. Nothing reading them takes a lock,
+/// and a command queued ahead of an event, e.g. in the same channel or
+/// through `prioritize`, is applied before the event is processed.
+#[derive(Clone, Debug)]
+pub(crate) struct RuntimeOptions {
+    /// The name of the profile in use, when there are profiles to switch
+    /// between.
+    pub(crate) profile: String,
+    /// The character of the profile in use whose coordinates are selected.
+    pub(crate) character: Option<String>,
+    pub(crate) crouch_walk_option_select: bool,
+    /// Replaces the SOCD resolution of the profile in use, if present.
+    pub(crate) socd: Option<config::Socd>,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            profile: String::from(config::DEFAULT_PROFILE),
+            character: None,
+            crouch_walk_option_select: false,
+            socd: None,
+        }
+    }
+}
+
+/// Merges the commands for `run`, e.g. from the control socket, with the
+/// button events, taking whatever commands are queued before the next event
+/// so that they apply to it.
+pub(crate) fn prioritize(
+    commands: impl futures::Stream<Item = anyhow::Result<Event>> + Unpin,
+    events: impl futures::Stream<Item = anyhow::Result<Event>> + Unpin,
+) -> impl futures::Stream<Item = anyhow::Result<Event>> + Unpin {
+    futures::stream::select_with_strategy(commands, events, |_: &mut ()| {
+        futures::stream::PollNext::Left
+    })
+}
+
+/// What switching profiles and toggling their features needs besides
+/// `Main` and the `RuntimeOptions`.
+pub(crate) struct Profiles {
+    pub(crate) config: config::Config,
+    /// The features of the profile in use turned off.
+    pub(crate) disabled: std::collections::BTreeSet<control::Feature>,
+    /// Under `--tournament`, the engine under which the restricted features
+    /// of every profile switched to are turned off.
+    pub(crate) lockout: Option<Engine>,
+    /// Binds the keys anew to a profile switched to, e.g. in the `Remapper`
+    /// feeding `run`, and returns the buttons the keys held press under it,
+    /// in the order they were pressed.
+    pub(crate) rebind: Rebind,
+}
+
+/// Binds the keys to a profile, returning the buttons the keys held press.
+pub(crate) type Rebind = Box<dyn FnMut(&config::Profile) -> Vec<B0xxRaw>>;
+
+impl Profiles {
+    /// Returns the profile in use without the features turned off, with the
+    /// character's coordinates and the SOCD resolution selected, and locked
+    /// out under `--tournament`.
+    fn effective(&self, runtime: &RuntimeOptions) -> Result<config::Profile, String> {
+        let mut profile = self
+            .config
+            .profile(&runtime.profile)
+            .map_err(|e| e.to_string())?;
+        for &feature in &self.disabled {
+            let _: bool = feature.remove(&mut profile);
+        }
+        profile.coordinates = profile
+            .coordinates_for(runtime.character.as_deref())?
+            .into();
+        if let Some(socd) = runtime.socd {
+            profile.socd = socd;
+        }
+        if let Some(engine) = self.lockout {
+            let (locked, suppressed) = certify::lockout(&profile, engine);
+            for name in suppressed {
+                info!(
+                    "--tournament turned off {} in profile {:?}",
+                    name, runtime.profile
+                );
+            }
+            profile = locked;
+        }
+        Ok(profile)
+    }
+
+    /// Switches `main` to the effective profile with `held` pressed again in
+    /// order, returning the change in output.
+    fn switch(
+        &self,
+        now: Timestamp,
+        main: &mut Main,
+        held: Vec<B0xxRaw>,
+        runtime: &RuntimeOptions,
+    ) -> Result<Inputs, String> {
+        let profile = self.effective(runtime)?;
+        Ok(switch_to(now, main, &profile, held, runtime))
+    }
+}
+
+/// Switches `main` to `profile`, effective under `runtime`, with `held`
+/// pressed again in order, returning the change in output.
+fn switch_to(
+    now: Timestamp,
+    main: &mut Main,
+    profile: &config::Profile,
+    held: Vec<B0xxRaw>,
+    runtime: &RuntimeOptions,
+) -> Inputs {
+    let inputs = main.switch_profile(now, profile, held, runtime.crouch_walk_option_select);
+    main.character = runtime.character.clone();
+    inputs
+}
+
+/// An internal command, from a combo or the control socket alike.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Command {
+    Reset,
+    /// Pauses the output, or resumes it.
+    Pause,
+    Snapshot,
+    WriteStats,
+    /// Changes nothing.
+    GetState,
+    SetProfile(String),
+    /// Turns a feature of the profile off, or back on.
+    Toggle(control::Feature),
+    /// Selects a character's coordinates, or the profile's own.
+    SetCharacter(Option<String>),
+    /// Selects the next character's coordinates, in name order, then the
+    /// profile's own.
+    NextCharacter,
+    /// Turns the crouch-walk option select on or off, from the next event.
+    SetCrouchWalkOptionSelect(bool),
+    /// Replaces the profile's SOCD resolution, or restores it.
+    SetSocd(Option<config::Socd>),
+}
+
+impl From<config::ComboAction> for Command {
+    fn from(action: config::ComboAction) -> Self {
+        match action {
+            config::ComboAction::Reset => Self::Reset,
+            config::ComboAction::Pause => Self::Pause,
+            config::ComboAction::Snapshot => Self::Snapshot,
+            config::ComboAction::WriteStats => Self::WriteStats,
+            config::ComboAction::NextCharacter => Self::NextCharacter,
+            config::ComboAction::SetProfile(profile) => Self::SetProfile(profile),
+        }
+    }
+}
+
+impl From<control::Request> for Command {
+    fn from(request: control::Request) -> Self {
+        match request {
+            control::Request::GetState => Self::GetState,
+            control::Request::SetProfile { profile } => Self::SetProfile(profile),
+            control::Request::Toggle {
+                feature: control::Feature::Pause,
+            } => Self::Pause,
+            control::Request::Toggle { feature } => Self::Toggle(feature),
+            control::Request::Reset => Self::Reset,
+            control::Request::SetCharacter { character } => Self::SetCharacter(character),
+            control::Request::SetCrouchWalkOptionSelect { on } => {
+                Self::SetCrouchWalkOptionSelect(on)
+            }
+            control::Request::SetSocd { socd } => Self::SetSocd(socd),
+        }
+    }
+}
+
+/// What internal commands change in `run`, besides `Main` and the output.
+struct Controls {
+    /// Nothing is written while paused, though the inputs are processed all
+    /// the same.
+    paused: bool,
+    runtime: RuntimeOptions,
+    profiles: Option<Profiles>,
+    combos: combo::Detector,
+    detectors: Option<techniques::Detectors>,
+    reader_queue: Option<pipeline::Stats>,
+}
+
+impl Controls {
+    fn state(&self, main: &Main) -> control::State {
+        control::State {
+            profile: self.profiles.as_ref().map(|_| self.runtime.profile.clone()),
+            paused: self.paused,
+            crouch_walk_option_select: self.runtime.crouch_walk_option_select,
+            socd: self.runtime.socd,
+            disabled: self
+                .profiles
+                .as_ref()
+                .map(|profiles| profiles.disabled.iter().copied().collect())
+                .unwrap_or_default(),
+            character: main.character.clone(),
+            held: main.held.iter().copied().collect(),
+            output: main.output.clone(),
+        }
+    }
+}
+
+/// Everything `run` needs besides `Main`, the events and the sink.
+#[derive(Default)]
+pub(crate) struct Options {
+    /// The settings commands change, as they start out.
+    pub(crate) runtime: RuntimeOptions,
+    /// Holds every input back by this long, unless zero.
+    pub(crate) delay: std::time::Duration,
+    /// Writes at most once per this frame period, if present.
+    pub(crate) quantize: Option<std::time::Duration>,
+    pub(crate) detectors: Option<techniques::Detectors>,
+    /// Logs latency percentiles this often, or only at the end if zero.
+    pub(crate) latency_report: Option<std::time::Duration>,
+    /// Where `Event::WriteStats` and the end write the session statistics.
+    pub(crate) stats: Option<std::path::PathBuf>,
+    pub(crate) state_file: Option<recovery::StateFile>,
+    /// Checks `Main` for contradictions this often, if present.
+    pub(crate) consistency_check: Option<std::time::Duration>,
+    /// Rebuilds the state when the check finds a contradiction, rather than
+    /// only logging it.
+    pub(crate) heal: bool,
+    /// Runs internal commands when buttons are held together.
+    pub(crate) combos: combo::Detector,
+    /// Hints at another process's grab of the keyboard, if present.
+    pub(crate) silence: Option<grab::SilenceWatch>,
+    /// Pauses the periodic timers while no button event arrives, if present.
+    pub(crate) idle: Option<idle::IdleWatch>,
+    /// Where to subscribe to the events processed.
+    pub(crate) publisher: Publisher,
+    /// Lets the control socket switch profiles and toggle their features, if
+    /// present.
+    pub(crate) profiles: Option<Profiles>,
+    /// Reports on the queue the keyboard is read through, if present.
+    pub(crate) reader_queue: Option<pipeline::Stats>,
+    /// Numbers the frames in the logs, if present.
+    pub(crate) frames: Option<frame::FrameClock>,
+    #[cfg(feature = "tui")]
+    pub(crate) tui: Option<tui::Tui>,
+}
+
+/// Resyncs the sink, then feeds `events` through `main` into `sink` and
+/// processes deadlines until the events end or fail, and finally releases
+/// the turbo button and writes out the reports.
+pub(crate) async fn run<S: Sink>(
+    main: &mut Main,
+    events: impl futures::Stream<Item = anyhow::Result<Event>> + Unpin,
+    sink: S,
+    options: Options,
+) -> Result<(), Error> {
+    let Options {
+        runtime,
+        delay,
+        quantize,
+        detectors,
+        latency_report,
+        stats,
+        mut state_file,
+        consistency_check,
+        heal,
+        combos,
+        mut silence,
+        mut idle,
+        mut publisher,
+        profiles,
+        reader_queue,
+        frames,
+        #[cfg(feature = "tui")]
+        mut tui,
+    } = options;
+    let mut output = Output {
+        sink,
+        // A zero delay bypasses the queue entirely.
+        delay_queue: (!delay.is_zero()).then(|| DelayQueue::new(delay)),
+        quantizer: quantize.map(|period| FrameQuantizer::new(period, Timestamp::now())),
+    };
+    // Dolphin keeps whatever an earlier writer left pressed, e.g. D_Up after a
+    // crash with the D-pad held, until told otherwise.
+    for pipe_input in main.resync() {
+        output.sink.send(pipe_input).map_err(Error::Sink)?;
+    }
+    let mut latency = latency_report.map(|_| LatencyHistogram::default());
+    let mut next_report = latency_report
+        .filter(|period| !period.is_zero())
+        .map(|period| Timestamp::now() + period);
+    let mut next_check = consistency_check.map(|period| Timestamp::now() + period);
+    let mut controls = Controls {
+        paused: false,
+        runtime,
+        profiles,
+        combos,
+        detectors,
+        reader_queue,
+    };
+    let mut events = events.fuse();
+    let result: Result<(), Error> = async {
+        loop {
+            // Marks the session dirty from the start, until `close`.
+            if let Some(state_file) = &mut state_file {
+                state_file.save(main);
+            }
+            let deadline = main
+                .deadline()
+                .into_iter()
+                .chain(output.deadline())
+                .chain(next_report)
+                .chain(next_check)
+                .chain(controls.combos.deadline())
+                .chain(silence.as_ref().and_then(grab::SilenceWatch::deadline))
+                .chain(idle.as_ref().and_then(idle::IdleWatch::deadline))
+                .min();
+            let timer = async {
+                match deadline {
+                    Some(deadline) => {
+                        let _: std::time::Instant =
+                            async_io::Timer::after(deadline - Timestamp::now()).await;
+                    }
+                    None => futures::future::pending().await,
+                }
+            };
+            futures::pin_mut!(timer);
+            futures::select! {
+                event = events.next() => match event {
+                    None | Some(Ok(Event::Exit)) => return Ok(()),
+                    Some(Err(e)) => return Err(Error::Events(e)),
+                    Some(Ok(Event::Snapshot)) => warn!("snapshot: {}", main.snapshot()),
+                    Some(Ok(Event::Pause)) => {
+                        let _ = execute(Command::Pause, Timestamp::now(), main, &mut output, &mut controls, stats.as_deref())?;
+                    }
+                    Some(Ok(Event::WriteStats)) => {
+                        main.stats.sinks = output.sink.health();
+                        main.stats.reader_queue =
+                            controls.reader_queue.as_ref().map(pipeline::Stats::get);
+                        write_stats(&main.stats, stats.as_deref());
+                    }
+                    Some(Ok(Event::Control(request, reply))) => {
+                        let now = Timestamp::now();
+                        let result =
+                            execute(request.into(), now, main, &mut output, &mut controls, stats.as_deref())?;
+                        // The client may have gone meanwhile.
+                        let _ = reply.send(control::Response::new(
+                            result.map(|()| controls.state(main)),
+                        ));
+                    }
+                    Some(Ok(Event::B0xx(e))) => {
+                        if let Some(silence) = &mut silence {
+                            silence.event();
+                        }
+                        // Waking processes the buttons pressed or released while
+                        // idle first, and restarts the periodic timers.
+                        let resync = idle.as_mut().and_then(|idle| idle.event(Timestamp::now()));
+                        if resync.is_some() {
+                            let now = Timestamp::now();
+                            next_report = latency_report
+                                .filter(|period| !period.is_zero())
+                                .map(|period| now + period);
+                            next_check = consistency_check.map(|period| now + period);
+                        }
+                        for e in resync.into_iter().flatten().chain([e]) {
+                            let time = e.time;
+                            let now = Timestamp::now();
+                            let frame = frames.as_ref().and_then(|frames| {
+                                frames.start(time);
+                                frames.frame(now)
+                            });
+                            let _span = tracing::debug_span!("frame", frame).entered();
+                            let inputs = main.process_b0xx(e, controls.runtime.crouch_walk_option_select);
+                            if !controls.paused {
+                                for &input in &inputs {
+                                    output.send(now, input).map_err(Error::Sink)?;
+                                }
+                            }
+                            publisher.publish(|| ProcessedEvent {
+                                event: Some(e),
+                                inputs: inputs.clone(),
+                                snapshot: main.output.clone(),
+                                held: main.held.clone(),
+                                a_stick: main.a_stick,
+                                c_stick: main.c_stick,
+                            });
+                            controls.combos.process(&e);
+                            #[cfg(feature = "tui")]
+                            if let Some(tui) = &mut tui {
+                                tui.show(main, &inputs);
+                            }
+                            if let Some(detectors) = &mut controls.detectors {
+                                for detection in detectors.process(time, &inputs) {
+                                    info!(frame, "{}", detection);
+                                    main.stats.technique(&detection);
+                                }
+                            }
+                            // Inputs held back by --delay or --quantize are written
+                            // later by design, so only immediate writes are measured.
+                            if let Some(latency) = &mut latency {
+                                if !inputs.is_empty() && output.writes_immediately() {
+                                    latency.record(Timestamp::now() - time);
+                                }
+                            }
+                        }
+                    }
+                },
+                () = timer.fuse() => {
+                    let now = Timestamp::now();
+                    let frame = frames.as_ref().and_then(|frames| frames.frame(now));
+                    let _span = tracing::debug_span!("frame", frame).entered();
+                    let inputs = main.process_deadline(now, controls.runtime.crouch_walk_option_select);
+                    if !controls.paused {
+                        for &input in &inputs {
+                            output.send(now, input).map_err(Error::Sink)?;
+                        }
+                    }
+                    publisher.publish(|| ProcessedEvent {
+                        event: None,
+                        inputs: inputs.clone(),
+                        snapshot: main.output.clone(),
+                        held: main.held.clone(),
+                        a_stick: main.a_stick,
+                        c_stick: main.c_stick,
+                    });
+                    #[cfg(feature = "tui")]
+                    if let Some(tui) = &mut tui {
+                        tui.show(main, &inputs);
+                    }
+                    if let Some(detectors) = &mut controls.detectors {
+                        for detection in detectors.process(now, &inputs) {
+                            info!(frame, "{}", detection);
+                            main.stats.technique(&detection);
+                        }
+                    }
+                    output.process_deadline(now).map_err(Error::Sink)?;
+                    if let (Some(period), Some(report), Some(latency)) =
+                        (latency_report, next_report, &latency)
+                    {
+                        if now >= report {
+                            info!("latency: {}", latency);
+                            next_report = Some(report + period);
+                        }
+                    }
+                    if let (Some(period), Some(check)) = (consistency_check, next_check) {
+                        if now >= check {
+                            let inconsistencies = main.inconsistencies();
+                            for inconsistency in &inconsistencies {
+                                warn!("inconsistent state: {}", inconsistency);
+                            }
+                            if !inconsistencies.is_empty() {
+                                warn!("snapshot: {}", main.snapshot());
+                                if heal {
+                                    let inputs = main.heal(now, controls.runtime.crouch_walk_option_select);
+                                    if !controls.paused {
+                                        for input in inputs {
+                                            output.send(now, input).map_err(Error::Sink)?;
+                                        }
+                                    }
+                                }
+                            }
+                            next_check = Some(now + period);
+                        }
+                    }
+                    if let Some(hint) = silence.as_mut().and_then(|silence| silence.process_deadline(now)) {
+                        warn!("{}", hint);
+                    }
+                    if let Some(idle) = &mut idle {
+                        let quiet = main.held.is_empty()
+                            && main.deadline().is_none()
+                            && output.deadline().is_none()
+                            && controls.combos.deadline().is_none();
+                        if idle.process_deadline(now, quiet) {
+                            next_report = None;
+                            next_check = None;
+                        }
+                    }
+                    if let Some(action) = controls.combos.process_deadline(now) {
+                        let command = action.clone().into();
+                        if let Err(e) =
+                            execute(command, now, main, &mut output, &mut controls, stats.as_deref())?
+                        {
+                            warn!("combo {:?} failed: {}", action, e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    .await;
+    // Leave Dolphin with the turbo button released rather than pressed.
+    for input in main.stop_turbo() {
+        if let Err(e) = output.sink.send_input(input) {
+            warn!("failed to release the turbo button: {:#}", e);
+        }
+    }
+    // Only a clean shutdown is marked clean; otherwise dropping the state file
+    // writes out the last state saved, still dirty.
+    if let (Some(state_file), Ok(())) = (state_file, &result) {
+        state_file.close(main);
+    }
+    if let Some(latency) = &latency {
+        info!("latency: {}", latency);
+    }
+    if stats.is_some() {
+        main.stats.sinks = output.sink.health();
+        main.stats.reader_queue = controls.reader_queue.as_ref().map(pipeline::Stats::get);
+        write_stats(&main.stats, stats.as_deref());
+    }
+    result
+}
+
+fn write_stats(session: &SessionStats, path: Option<&std::path::Path>) {
+    match path {
+        Some(path) => match session.write(path) {
+            Ok(()) => {
+                info!("wrote stats to {}", path.display());
+                if session.hold_durations.held().next().is_some() {
+                    info!("hold durations:\n{}", session.hold_durations);
+                }
+            }
+            Err(e) => warn!("{:#}", e),
+        },
+        None => warn!("no --stats path to write stats to"),
+    }
+}
+
+/// Runs an internal command, failing only if writing to the sink does. The
+/// inner result is whether the command itself went through.
+fn execute<S: Sink>(
+    command: Command,
+    now: Timestamp,
+    main: &mut Main,
+    output: &mut Output<S>,
+    controls: &mut Controls,
+    stats: Option<&std::path::Path>,
+) -> Result<Result<(), String>, Error> {
+    let inputs = match command {
+        Command::GetState => return Ok(Ok(())),
+        Command::Snapshot => {
+            warn!("snapshot: {}", main.snapshot());
+            return Ok(Ok(()));
+        }
+        Command::WriteStats => {
+            main.stats.sinks = output.sink.health();
+            main.stats.reader_queue = controls.reader_queue.as_ref().map(pipeline::Stats::get);
+            write_stats(&main.stats, stats);
+            return Ok(Ok(()));
+        }
+        Command::Pause => {
+            controls.paused = !controls.paused;
+            // The controller is left neutral while paused, and catches up
+            // with the inputs on resuming.
+            let neutral = ControllerSnapshot::default();
+            let pipe_inputs = if controls.paused {
+                info!("paused");
+                diff(&main.output, &neutral)
+            } else {
+                info!("resumed");
+                diff(&neutral, &main.output)
+            };
+            for pipe_input in pipe_inputs {
+                output
+                    .send(now, Input::from(pipe_input))
+                    .map_err(Error::Sink)?;
+            }
+            return Ok(Ok(()));
+        }
+        Command::Reset => main.reset_at(now),
+        Command::SetProfile(name) => {
+            let Some(profiles) = &mut controls.profiles else {
+                return Ok(Err("no config to switch profiles in".to_owned()));
+            };
+            let runtime = RuntimeOptions {
+                profile: name,
+                character: None,
+                ..controls.runtime.clone()
+            };
+            let disabled = std::mem::take(&mut profiles.disabled);
+            // The keys are bound to the profile as it runs, e.g. without the
+            // autorepeat `--tournament` turns off.
+            let profile = match profiles.effective(&runtime) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    profiles.disabled = disabled;
+                    return Ok(Err(e));
+                }
+            };
+            info!("switching to profile {:?}", runtime.profile);
+            let held = (profiles.rebind)(&profile);
+            controls.combos = combo::Detector::new(profile.combos.clone());
+            controls.detectors = profile.techniques.map(techniques::Detectors::new);
+            let inputs = switch_to(now, main, &profile, held, &runtime);
+            controls.runtime = runtime;
+            inputs
+        }
+        Command::SetCharacter(character) => {
+            let Some(profiles) = &mut controls.profiles else {
+                return Ok(Err("no config to select characters in".to_owned()));
+            };
+            let previous = std::mem::replace(&mut controls.runtime.character, character);
+            let held = main.held_in_press_order();
+            match profiles.switch(now, main, held, &controls.runtime) {
+                Ok(inputs) => {
+                    info!("switched to character {:?}", controls.runtime.character);
+                    inputs
+                }
+                Err(e) => {
+                    controls.runtime.character = previous;
+                    return Ok(Err(e));
+                }
+            }
+        }
+        Command::NextCharacter => {
+            let Some(profiles) = &mut controls.profiles else {
+                return Ok(Err("no config to select characters in".to_owned()));
+            };
+            let profile = match profiles.config.profile(&controls.runtime.profile) {
+                Ok(profile) => profile,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            if profile.characters.is_empty() {
+                return Ok(Err(format!(
+                    "profile {:?} has no characters",
+                    controls.runtime.profile
+                )));
+            }
+            controls.runtime.character = profile
+                .characters
+                .next(controls.runtime.character.as_deref())
+                .map(String::from);
+            let held = main.held_in_press_order();
+            match profiles.switch(now, main, held, &controls.runtime) {
+                Ok(inputs) => {
+                    info!("switched to character {:?}", controls.runtime.character);
+                    inputs
+                }
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        Command::Toggle(feature) => {
+            let Some(profiles) = &mut controls.profiles else {
+                return Ok(Err("no config to toggle features in".to_owned()));
+            };
+            let mut profile = match profiles.config.profile(&controls.runtime.profile) {
+                Ok(profile) => profile,
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+            if !feature.remove(&mut profile) {
+                return Ok(Err(format!(
+                    "profile {:?} has no {:?}",
+                    controls.runtime.profile, feature
+                )));
+            }
+            let on = profiles.disabled.remove(&feature);
+            if !on {
+                let _ = profiles.disabled.insert(feature);
+            }
+            info!("{:?} is now {}", feature, if on { "on" } else { "off" });
+            let held = main.held_in_press_order();
+            match profiles.switch(now, main, held, &controls.runtime) {
+                Ok(inputs) => inputs,
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+        Command::SetCrouchWalkOptionSelect(on) => {
+            controls.runtime.crouch_walk_option_select = on;
+            info!(
+                "crouch-walk option select is now {}",
+                if on { "on" } else { "off" }
+            );
+            Default::default()
+        }
+        Command::SetSocd(socd) => {
+            let Some(profiles) = &mut controls.profiles else {
+                return Ok(Err(
+                    "no config to override the SOCD resolution of".to_owned()
+                ));
+            };
+            let previous = std::mem::replace(&mut controls.runtime.socd, socd);
+            let held = main.held_in_press_order();
+            match profiles.switch(now, main, held, &controls.runtime) {
+                Ok(inputs) => {
+                    info!(
+                        "SOCD resolution override is now {:?}",
+                        controls.runtime.socd
+                    );
+                    inputs
+                }
+                Err(e) => {
+                    controls.runtime.socd = previous;
+                    return Ok(Err(e));
+                }
+            }
+        }
+    };
+    if !controls.paused {
+        for &input in &inputs {
+            output.send(now, input).map_err(Error::Sink)?;
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Feeds `run` button toggles read from stdin, one `<button> <0|1>` per line,
+/// e.g. `A 1`, writing nothing anywhere, and prints a summary of every event
+/// published meanwhile. See `examples/subscribe.rs`.
+#[allow(dead_code)] // Only the example calls it, compiling the crate in.
+pub(crate) fn subscribe_example() -> anyhow::Result<()> {
+    let (sender, events) = futures::channel::mpsc::unbounded();
+    let _: std::thread::JoinHandle<()> = std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let event = line
+                .context("failed to read stdin")
+                .and_then(|line| parse_toggle(&line))
+                .map(Event::B0xx);
+            if sender.unbounded_send(event).is_err() {
+                break;
+            }
+        }
+    });
+    let mut publisher = Publisher::default();
+    let mut subscriber = publisher.subscribe(64);
+    let sink = OutputSink {
+        file: std::io::sink(),
+        reopen: None,
+        command_log: None,
+        handedness: Default::default(),
+        game: Game::Melee,
+        capability: Default::default(),
+        command: String::new(),
+        written: Default::default(),
+    };
+    let print = async {
+        while let Some(ProcessedEvent {
+            event,
+            inputs,
+            snapshot,
+            held,
+            a_stick: a_axes,
+            c_stick: c_axes,
+        }) = subscriber.next().await
+        {
+            let ControllerSnapshot {
+                buttons,
+                a_stick,
+                c_stick,
+                trigger,
+            } = snapshot;
+            match event {
+                Some(B0xxEvent { btn, pressed, .. }) => print!("{:?} {}", btn, u8::from(pressed)),
+                None => print!("deadline"),
+            }
+            println!(
+                ": {} inputs, held {:?}, buttons {:?}, A {:?} {:?}, C {:?} {:?}, trigger {:?}, {} missed",
+                inputs.len(),
+                held,
+                buttons,
+                a_stick,
+                a_axes,
+                c_stick,
+                c_axes,
+                trigger,
+                subscriber.dropped(),
+            );
+        }
+    };
+    let mut main = Main::default();
+    let options = Options {
+        publisher,
+        ..Default::default()
+    };
+    let (result, ()) = futures::executor::block_on(futures::future::join(
+        run(&mut main, events, sink, options),
+        print,
+    ));
+    result.map_err(|(Error::Events(e) | Error::Sink(e))| e)
+}
+
+/// Parses a line of `subscribe_example`'s input.
+fn parse_toggle(line: &str) -> anyhow::Result<B0xxEvent> {
+    let (btn, pressed) = line
+        .split_once(' ')
+        .with_context(|| format!("expected `<button> <0|1>`, got {:?}", line))?;
+    let btn = serde_json::from_str(&format!("{:?}", btn))
+        .with_context(|| format!("unknown button {:?}", btn))?;
+    let pressed = match pressed.trim() {
+        "0" => RELEASED,
+        "1" => PRESSED,
+        pressed => anyhow::bail!("expected 0 or 1, got {:?}", pressed),
+    };
+    Ok(B0xxEvent {
+        time: Timestamp::now(),
+        btn,
+        pressed,
+    })
+}
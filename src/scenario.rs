@@ -0,0 +1,524 @@
+//! Golden tests of whole sessions.
+//!
+//! Each `tests/scenarios/<name>.json` holds a profile and timestamped button
+//! toggles. They are turned into key events for the profile's bindings and
+//! go through `Remapper`, `Main` and `Output` as in `run`, on a simulated
+//! clock, into a buffer instead of the pipe. The bytes written must match
+//! `<name>.golden`, which is rewritten instead when `BLESS` is set.
+//!
+//! The same sessions also run through `remap::run` on the real clock into a
+//! FIFO, read on another thread as Dolphin would, to cover the pipe I/O the
+//! buffer stands in for. One of them has its reader go away halfway and
+//! another take its place, which the sink must reconnect to and bring up to
+//! date without losing a command.
+//!
+//! Key events also go through `pipeline::spawn_reader`'s thread and queue
+//! into a slow sink, which must hold the reader back rather than lose them.
+
+use super::*;
+
+/// A session to replay.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    /// As in a config file, with anything left out taking its default.
+    #[serde(default)]
+    profile: config::Profile,
+    #[serde(default)]
+    crouch_walk_option_select: bool,
+    /// Milliseconds since the start, the button and whether it is pressed.
+    events: Vec<(u64, B0xxRaw, Pressed)>,
+}
+
+/// Returns the key event toggling `btn`, on the first key bound to it.
+fn key_event(bindings: &config::Bindings, btn: B0xxRaw, pressed: Pressed) -> evdev_rs::InputEvent {
+    let key = *bindings
+        .keys(btn)
+        .first()
+        .unwrap_or_else(|| panic!("nothing is bound to {:?}", btn));
+    let (code, value) = match config::source(key).expect("validated key name") {
+        config::Source::Code(code) => (code, pressed.into()),
+        config::Source::Hat(code, dir) => (
+            code,
+            match (pressed, dir) {
+                (RELEASED, _) => 0,
+                (PRESSED, POSITIVE) => 1,
+                (PRESSED, NEGATIVE) => -1,
+            },
+        ),
+    };
+    evdev_rs::InputEvent::new(&evdev_rs::TimeVal::new(0, 0), &code, value)
+}
+
+/// Returns `Main` as `run` sets it up for `profile`, with the invariants
+/// checked.
+fn new_main(profile: &config::Profile) -> Main {
+    Main {
+        a_stick_reference: Some((
+            AxisReference::default(),
+            AxisReference::with_priority(profile.socd.y.priority()),
+        )),
+        ..Main::new(profile)
+    }
+}
+
+/// Returns the button events of `scenario` in order, each with its
+/// milliseconds since the start.
+fn b0xx_events(scenario: &Scenario) -> Vec<(u64, B0xxEvent)> {
+    let profile = &scenario.profile;
+    let mut remapper = Remapper::new(profile.bindings.sources());
+    scenario
+        .events
+        .iter()
+        .flat_map(|&(ms, btn, pressed)| {
+            remapper
+                .evdev_to_b0xx(key_event(&profile.bindings, btn, pressed))
+                .into_iter()
+                .map(move |e| (ms, e))
+        })
+        .collect()
+}
+
+/// Returns an `OutputSink` for `profile` writing to `file`.
+fn output_sink<W>(profile: &config::Profile, file: W) -> OutputSink<W> {
+    OutputSink {
+        file,
+        reopen: None,
+        command_log: None,
+        handedness: profile.handedness,
+        game: Game::Melee,
+        capability: Default::default(),
+        command: String::new(),
+        written: Default::default(),
+    }
+}
+
+/// Returns everything written to the pipe in `scenario`, starting with the
+/// resync burst.
+fn replay(scenario: &Scenario) -> String {
+    let start = Timestamp::default();
+    let mut main = new_main(&scenario.profile);
+    let mut output = Output {
+        sink: output_sink(&scenario.profile, Vec::new()),
+        delay_queue: None,
+        quantizer: None,
+    };
+    for pipe_input in main.resync() {
+        output.sink.send(pipe_input).expect("writes to a buffer");
+    }
+    let crouch_walk_option_select = scenario.crouch_walk_option_select;
+    let process_deadlines = |main: &mut Main, output: &mut Output<OutputSink<Vec<u8>>>, until| {
+        while let Some(deadline) = main.deadline().filter(|&deadline| deadline <= until) {
+            for input in main.process_deadline(deadline, crouch_walk_option_select) {
+                output.send(deadline, input).expect("writes to a buffer");
+            }
+        }
+    };
+    for (ms, e) in b0xx_events(scenario) {
+        let time = start + std::time::Duration::from_millis(ms);
+        process_deadlines(&mut main, &mut output, time);
+        // Key events are stamped with the real time, so the simulated time
+        // stands in.
+        let e = B0xxEvent { time, ..e };
+        for input in main.process_b0xx(e, crouch_walk_option_select) {
+            output.send(time, input).expect("writes to a buffer");
+        }
+    }
+    process_deadlines(&mut main, &mut output, Timestamp(std::time::Duration::MAX));
+    String::from_utf8(output.sink.file).expect("commands are ASCII")
+}
+
+/// Describes the first line at which `got` and `want` differ.
+fn first_difference(got: &str, want: &str) -> String {
+    let (mut got_lines, mut want_lines) = (got.lines(), want.lines());
+    for line in 1.. {
+        match (got_lines.next(), want_lines.next()) {
+            (Some(got), Some(want)) if got == want => {}
+            (None, None) => break,
+            (got, want) => {
+                return format!(
+                    "line {}: got {:?}, want {:?}",
+                    line,
+                    got.unwrap_or("end of output"),
+                    want.unwrap_or("end of output")
+                )
+            }
+        }
+    }
+    String::from("only in line endings")
+}
+
+/// Returns the paths of every scenario, in name order.
+fn scenario_paths() -> Vec<std::path::PathBuf> {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let mut paths = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+        .map(|entry| entry.expect("readable directory entry").path())
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scenarios in {}", dir.display());
+    paths
+}
+
+fn load(path: &std::path::Path) -> Scenario {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path.display(), e))
+}
+
+/// The scenario whose reader goes away halfway.
+const RECONNECT_SCENARIO: &str = "shield_tiers";
+
+/// How long a scenario may take through a FIFO, well beyond its last event.
+const FIFO_DEADLINE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Returns the FIFO the scenario named `name` is replayed into.
+fn fifo_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("tuxb0xx-scenario-{}-{}", std::process::id(), name))
+}
+
+fn mkfifo(path: &std::path::Path) {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .expect("temporary directory contains no NUL byte");
+    // SAFETY: `c_path` is NUL-terminated and outlives the call.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        panic!(
+            "failed to create FIFO {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Opens the FIFO of `RECONNECT_SCENARIO` again, as `run` does the pipe.
+fn reopen_fifo() -> std::io::Result<std::fs::File> {
+    reopen_pipe(&fifo_path(RECONNECT_SCENARIO))
+}
+
+/// Reads the FIFO at `path` until every writer closed it, returning what was
+/// read from each reader. On a message from `disconnect`, reads what is
+/// written so far, replaces the FIFO with a new one, opens it and closes the
+/// old one, then acknowledges and goes on reading the new one as the next
+/// reader.
+fn read_fifo(
+    path: &std::path::Path,
+    disconnect: Option<(
+        std::sync::mpsc::Receiver<()>,
+        std::sync::mpsc::SyncSender<()>,
+    )>,
+) -> std::io::Result<Vec<String>> {
+    use std::io::Read as _;
+    use std::os::unix::fs::OpenOptionsExt as _;
+    use std::os::unix::io::AsRawFd as _;
+
+    let text = |read| String::from_utf8(read).expect("commands are ASCII");
+    let mut reads = Vec::new();
+    let mut read = Vec::new();
+    let mut file = std::fs::File::open(path)?;
+    if let Some((disconnect, disconnected)) = disconnect {
+        disconnect.recv().expect("the writer asks to disconnect");
+        // The writer waits for the acknowledgement, so whatever it wrote is
+        // in the FIFO already.
+        // SAFETY: `file` is open for as long as the call.
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        match file.read_to_end(&mut read) {
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            result => {
+                let _: usize = result?;
+            }
+        }
+        reads.push(text(std::mem::take(&mut read)));
+        std::fs::remove_file(path)?;
+        mkfifo(path);
+        // Opened without waiting for a writer, so that the writer's reopening,
+        // which doesn't wait for a reader, finds one.
+        let next = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        drop(file);
+        disconnected
+            .send(())
+            .expect("the writer waits for the acknowledgement");
+        // The FIFO reads as closed until the writer reopens it, which
+        // always writes something.
+        loop {
+            match (&next).read_to_end(&mut read) {
+                Ok(_) if !read.is_empty() => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    } else {
+        let _: usize = file.read_to_end(&mut read)?;
+    }
+    reads.push(text(read));
+    Ok(reads)
+}
+
+/// Runs `scenario` through `remap::run` on the real clock into the FIFO at
+/// `path`, pausing halfway for `disconnect` if given, and returns the health
+/// of the sink.
+fn write_fifo(
+    path: &std::path::Path,
+    scenario: &Scenario,
+    disconnect: Option<(
+        std::sync::mpsc::SyncSender<()>,
+        std::sync::mpsc::Receiver<()>,
+    )>,
+) -> health::Health {
+    let profile = &scenario.profile;
+    let file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .unwrap_or_else(|e| panic!("failed to open FIFO {}: {}", path.display(), e));
+    let mut sink = health::Monitored::new(
+        "fifo",
+        OutputSink {
+            reopen: Some(reopen_fifo),
+            ..output_sink(profile, file)
+        },
+        health::Policy::Retry,
+    );
+    let events = b0xx_events(scenario);
+    let halfway = events.len() / 2;
+    let start = Timestamp::now();
+    let events = futures::stream::iter(events.into_iter().enumerate()).then(move |(i, (ms, e))| {
+        if let Some((disconnect, disconnected)) = disconnect.as_ref().filter(|_| i == halfway) {
+            disconnect.send(()).expect("the reader waits to disconnect");
+            disconnected.recv().expect("the reader disconnects");
+        }
+        async move {
+            let _: std::time::Instant = async_io::Timer::after(
+                start + std::time::Duration::from_millis(ms) - Timestamp::now(),
+            )
+            .await;
+            Ok::<_, anyhow::Error>(remap::Event::B0xx(B0xxEvent {
+                time: Timestamp::now(),
+                ..e
+            }))
+        }
+    });
+    futures::executor::block_on(remap::run(
+        &mut new_main(profile),
+        Box::pin(events),
+        &mut sink,
+        remap::Options {
+            runtime: remap::RuntimeOptions {
+                crouch_walk_option_select: scenario.crouch_walk_option_select,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ))
+    .expect("the session runs to the end");
+    sink.health()["fifo"]
+}
+
+/// Returns what each reader reads from a FIFO that the scenario at `path` is
+/// replayed into, and the health of the sink writing to it. With `reconnect`,
+/// the first reader goes away halfway.
+fn replay_through_fifo(path: &std::path::Path, reconnect: bool) -> (Vec<String>, health::Health) {
+    let name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .expect("scenario names are UTF-8");
+    let fifo = fifo_path(name);
+    mkfifo(&fifo);
+    let (disconnect, disconnected) = if reconnect {
+        let (disconnect_sender, disconnect_receiver) = std::sync::mpsc::sync_channel(0);
+        let (disconnected_sender, disconnected_receiver) = std::sync::mpsc::sync_channel(0);
+        (
+            Some((disconnect_receiver, disconnected_sender)),
+            Some((disconnect_sender, disconnected_receiver)),
+        )
+    } else {
+        (None, None)
+    };
+    // Opening either end of a FIFO blocks until the other is opened, and a
+    // stuck session must fail the test rather than hang it, so both ends run
+    // apart from the test.
+    let (read_sender, read_receiver) = std::sync::mpsc::channel();
+    {
+        let fifo = fifo.clone();
+        let _ = std::thread::spawn(move || read_sender.send(read_fifo(&fifo, disconnect)));
+    }
+    let writer = {
+        let (fifo, path) = (fifo.clone(), path.to_owned());
+        std::thread::spawn(move || write_fifo(&fifo, &load(&path), disconnected))
+    };
+    let read = read_receiver
+        .recv_timeout(FIFO_DEADLINE)
+        .unwrap_or_else(|e| panic!("{}: nothing read within {:?}: {}", name, FIFO_DEADLINE, e))
+        .unwrap_or_else(|e| panic!("{}: failed to read FIFO {}: {}", name, fifo.display(), e));
+    let health = writer.join().expect("FIFO writer panicked");
+    std::fs::remove_file(&fifo)
+        .unwrap_or_else(|e| panic!("failed to remove FIFO {}: {}", fifo.display(), e));
+    (read, health)
+}
+
+#[test]
+fn scenarios() {
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+    for path in scenario_paths() {
+        let got = replay(&load(&path));
+        let golden = path.with_extension("golden");
+        if bless {
+            std::fs::write(&golden, &got)
+                .unwrap_or_else(|e| panic!("failed to write {}: {}", golden.display(), e));
+            continue;
+        }
+        let want = std::fs::read_to_string(&golden)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", golden.display(), e));
+        if got != want {
+            failures.push(format!(
+                "{}: {}",
+                path.display(),
+                first_difference(&got, &want)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "output differs from the golden files, rerun with BLESS=1 to accept it:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn scenarios_through_fifo() {
+    let mut failures = Vec::new();
+    for path in scenario_paths() {
+        let (got, health) = replay_through_fifo(&path, false);
+        let got = got.concat();
+        assert_eq!(health.errors, 0, "{}: {:?}", path.display(), health);
+        let golden = path.with_extension("golden");
+        let want = std::fs::read_to_string(&golden)
+            .unwrap_or_else(|e| panic!("failed to read {}: {}", golden.display(), e));
+        if got != want {
+            failures.push(format!(
+                "{}: {}",
+                path.display(),
+                first_difference(&got, &want)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "output through a FIFO differs from the golden files:\n{}",
+        failures.join("\n")
+    );
+}
+
+/// Returns what `commands` leave each button, stick and trigger at, by the
+/// name the pipe knows it by, leaving out those left neutral.
+fn pipe_state(commands: &str) -> std::collections::BTreeMap<&str, &str> {
+    let mut state = std::collections::BTreeMap::new();
+    for line in commands.lines() {
+        let (name, value) = match line.split_once(' ') {
+            Some(("SET", set)) => set.split_once(' ').expect("SET has a value"),
+            Some((verb, name)) => (name, verb),
+            None => panic!("unknown command {:?}", line),
+        };
+        let _ = state.insert(name, value);
+    }
+    state.retain(|_, value| !["RELEASE", "0.5 0.5", "0"].contains(value));
+    state
+}
+
+// The sink reconnects to the reader taking over, writes it everything not
+// neutral and then the command the first one missed.
+#[test]
+fn scenario_reconnects() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/scenarios")
+        .join(RECONNECT_SCENARIO)
+        .with_extension("json");
+    let (got, health) = replay_through_fifo(&path, true);
+    assert_eq!(health.reconnects, 1, "{:?}", health);
+    assert_eq!(health.dropped, 0, "{:?}", health);
+    let golden = path.with_extension("golden");
+    let want = std::fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", golden.display(), e));
+    let [first, second] = &got[..] else {
+        panic!("read by {} readers", got.len());
+    };
+    let (before, rest) = want.split_at(first.len());
+    assert!(first == before, "{}", first_difference(first, before));
+    let resync = second
+        .strip_suffix(rest)
+        .unwrap_or_else(|| panic!("the second reader missed commands:\n{}", second));
+    assert!(!resync.is_empty());
+    assert_eq!(pipe_state(resync), pipe_state(first));
+}
+
+/// Writes slower than the keyboard can be read, as through a pipe Dolphin is
+/// slow to drain.
+struct SlowSink {
+    written: Vec<DolphinPipeInput>,
+}
+
+impl Sink for SlowSink {
+    fn send(&mut self, pipe_input: DolphinPipeInput) -> anyhow::Result<()> {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        self.written.push(pipe_input);
+        Ok(())
+    }
+}
+
+// The keyboard read by `spawn_reader` on a thread of its own is held back by
+// a full queue rather than losing key events, however slow the writes.
+#[test]
+fn reader_queue_blocks() {
+    const CAPACITY: usize = 4;
+    const TOGGLES: usize = 200;
+    let profile = config::Profile::default();
+    let keys = (0..TOGGLES)
+        .map(|i| Ok(key_event(&profile.bindings, B0xxRaw::A, i % 2 == 0)))
+        .collect::<Vec<_>>();
+    let reader = pipeline::spawn_reader(
+        move || Ok(futures::stream::iter(keys)),
+        CAPACITY,
+        pipeline::Overflow::Block,
+    );
+    let stats = reader.stats();
+    let mut remapper = Remapper::new(profile.bindings.sources());
+    let events = reader.flat_map(move |r| {
+        let event = r.expect("the keys are read");
+        futures::stream::iter(
+            remapper
+                .evdev_to_b0xx(event)
+                .into_iter()
+                .map(|e| Ok(remap::Event::B0xx(e))),
+        )
+    });
+    let mut sink = SlowSink {
+        written: Vec::new(),
+    };
+    futures::executor::block_on(remap::run(
+        &mut new_main(&profile),
+        events,
+        &mut sink,
+        Default::default(),
+    ))
+    .unwrap();
+    let resync = Main::default().resync();
+    let want: Vec<_> = (0..TOGGLES)
+        .map(|i| DolphinPipeInput::Button(GCButton::A, i % 2 == 0))
+        .collect();
+    assert_eq!(sink.written[..resync.len()], resync);
+    assert_eq!(sink.written[resync.len()..], want);
+    let stats = stats.get();
+    assert_eq!(stats.dropped, 0);
+    assert_eq!(stats.depth, 0);
+    assert!(stats.max_depth <= CAPACITY, "{:?}", stats);
+}
@@ -0,0 +1,216 @@
+//! End-to-end check of the output path without a keyboard or Dolphin.
+//!
+//! `run` writes the result of `SEQUENCES` to a FIFO through the same `Output`
+//! used for Dolphin's pipe and compares what comes out the other end to
+//! `golden`. The unit tests check `SEQUENCES` against `Main` directly, so the
+//! golden output can't drift from the engine.
+
+use super::consts::*;
+use super::*;
+
+/// Buttons changed in order, each with the inputs it must produce, starting
+/// from a default `Main` and leaving every button released.
+pub(crate) type Sequence = &'static [(B0xxRaw, Pressed, &'static [Input])];
+
+const A_PRESS: Input = Input::Button(Button::Pure(ButtonPure::A), PRESSED);
+const A_RELEASE: Input = Input::Button(Button::Pure(ButtonPure::A), RELEASED);
+const A_NEUTRAL: Input = Input::Stick(Stick::A, (P0000, P0000));
+const DPAD_UP_PRESS: Input = Input::Button(Button::DPad(Axis::Y, POSITIVE), PRESSED);
+const DPAD_UP_RELEASE: Input = Input::Button(Button::DPad(Axis::Y, POSITIVE), RELEASED);
+
+pub(crate) const SEQUENCES: &[(&str, Sequence)] = &[
+    (
+        "button",
+        &[
+            (B0xxRaw::A, PRESSED, &[A_PRESS]),
+            (B0xxRaw::A, RELEASED, &[A_RELEASE]),
+        ],
+    ),
+    (
+        "cardinals",
+        &[
+            (
+                B0xxRaw::Right,
+                PRESSED,
+                &[Input::Stick(Stick::A, (Analog::MAX, P0000))],
+            ),
+            (B0xxRaw::Right, RELEASED, &[A_NEUTRAL]),
+            (
+                B0xxRaw::Up,
+                PRESSED,
+                &[Input::Stick(Stick::A, (P0000, Analog::MAX))],
+            ),
+            (B0xxRaw::Up, RELEASED, &[A_NEUTRAL]),
+        ],
+    ),
+    (
+        "mod_x_diagonal",
+        &[
+            (B0xxRaw::MX, PRESSED, &[]),
+            (
+                B0xxRaw::Right,
+                PRESSED,
+                &[Input::Stick(Stick::A, (P6625, P0000))],
+            ),
+            (
+                B0xxRaw::Up,
+                PRESSED,
+                &[Input::Stick(Stick::A, (P7375, P3125))],
+            ),
+            (
+                B0xxRaw::Up,
+                RELEASED,
+                &[Input::Stick(Stick::A, (P6625, P0000))],
+            ),
+            (B0xxRaw::Right, RELEASED, &[A_NEUTRAL]),
+            (B0xxRaw::MX, RELEASED, &[]),
+        ],
+    ),
+    (
+        "mod_y_diagonal",
+        &[
+            (B0xxRaw::MY, PRESSED, &[]),
+            (
+                B0xxRaw::Right,
+                PRESSED,
+                &[Input::Stick(Stick::A, (P3375, P0000))],
+            ),
+            (
+                B0xxRaw::Up,
+                PRESSED,
+                &[Input::Stick(Stick::A, (P3125, P7375))],
+            ),
+            (
+                B0xxRaw::MY,
+                RELEASED,
+                &[Input::Stick(Stick::A, (P7000, P7000))],
+            ),
+            (
+                B0xxRaw::Right,
+                RELEASED,
+                &[Input::Stick(Stick::A, (P0000, Analog::MAX))],
+            ),
+            (B0xxRaw::Up, RELEASED, &[A_NEUTRAL]),
+        ],
+    ),
+    (
+        "shield",
+        &[
+            (B0xxRaw::LS, PRESSED, &[Input::Trigger(LS)]),
+            (B0xxRaw::MS, PRESSED, &[Input::Trigger(MS)]),
+            (B0xxRaw::MS, RELEASED, &[Input::Trigger(LS)]),
+            (B0xxRaw::LS, RELEASED, &[Input::Trigger(Trigger::Z)]),
+        ],
+    ),
+    (
+        "dpad",
+        &[
+            (B0xxRaw::MX, PRESSED, &[]),
+            (B0xxRaw::MY, PRESSED, &[]),
+            (B0xxRaw::CU, PRESSED, &[DPAD_UP_PRESS]),
+            (B0xxRaw::CU, RELEASED, &[DPAD_UP_RELEASE]),
+            (B0xxRaw::MY, RELEASED, &[]),
+            (B0xxRaw::MX, RELEASED, &[]),
+        ],
+    ),
+];
+
+/// Returns what must be written to the pipe for all of `SEQUENCES`.
+#[cfg_attr(not(feature = "linux-input"), allow(dead_code))]
+pub(crate) fn golden() -> String {
+    SEQUENCES
+        .iter()
+        .flat_map(|(_, sequence)| sequence.iter())
+        .flat_map(|(_, _, inputs)| inputs.iter())
+        .map(|input| {
+            input.into_pipe_input().into_input_string(
+                config::Handedness::Standard,
+                Game::Melee,
+                config::PipeCapability::Standard,
+            )
+        })
+        .collect()
+}
+
+/// Writes `SEQUENCES` through a FIFO and checks the bytes read back.
+#[cfg(feature = "linux-input")]
+pub(crate) fn run() -> anyhow::Result<()> {
+    use anyhow::Context as _;
+    use std::io::Read as _;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let path = std::env::temp_dir().join(format!("tuxb0xx-self-test-{}", std::process::id()));
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .context("temporary directory contains a NUL byte")?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("failed to create FIFO {}", path.display()));
+    }
+    // Opening either end of a FIFO blocks until the other is opened, so the
+    // reader runs alongside.
+    let reader = {
+        let path = path.clone();
+        std::thread::spawn(move || -> std::io::Result<String> {
+            let mut read = String::new();
+            let _: usize = std::fs::File::open(path)?.read_to_string(&mut read)?;
+            Ok(read)
+        })
+    };
+    let written = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open FIFO {}", path.display()))
+        .and_then(|file| {
+            let mut output = Output {
+                sink: OutputSink {
+                    file,
+                    reopen: None,
+                    command_log: None,
+                    handedness: config::Handedness::Standard,
+                    game: Game::Melee,
+                    capability: config::PipeCapability::Standard,
+                    command: String::new(),
+                    written: Default::default(),
+                },
+                delay_queue: None,
+                quantizer: None,
+            };
+            let mut main = Main::default();
+            for (name, sequence) in SEQUENCES {
+                for &(btn, pressed, _) in sequence.iter() {
+                    let now = Timestamp::now();
+                    let event = B0xxEvent {
+                        time: now,
+                        btn,
+                        pressed,
+                    };
+                    for input in main.process_b0xx(event, false) {
+                        output
+                            .send(now, input)
+                            .with_context(|| format!("failed to write {:?} in {}", input, name))?;
+                    }
+                }
+            }
+            Ok(())
+        });
+    // The writer is closed by now, so the reader has seen end of file unless
+    // opening failed, in which case it is still blocked.
+    let read = match written {
+        Ok(()) => reader.join().expect("FIFO reader panicked"),
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+    };
+    std::fs::remove_file(&path)
+        .with_context(|| format!("failed to remove FIFO {}", path.display()))?;
+    let read = read.with_context(|| format!("failed to read FIFO {}", path.display()))?;
+    let want = golden();
+    anyhow::ensure!(
+        read == want,
+        "read {:?} from the FIFO but expected {:?}",
+        read,
+        want
+    );
+    Ok(())
+}
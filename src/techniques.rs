@@ -0,0 +1,249 @@
+//! Practice feedback on specific techniques.
+//!
+//! Detectors watch the inputs written to the pipe along with when they were
+//! written, and report how many frames an attempt took against the window the
+//! profile allows. They only observe, so the output is the same with or
+//! without them.
+
+use super::consts::*;
+use super::*;
+
+/// Attempts this many frames outside a window are still reported, as misses.
+const SLACK_FRAMES: u32 = 8;
+
+/// Rounds `elapsed` to frames at 60Hz.
+fn frames(elapsed: std::time::Duration) -> u32 {
+    ((elapsed.as_micros() * 60 + 500_000) / 1_000_000)
+        .try_into()
+        .unwrap_or(u32::MAX)
+}
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Technique {
+    Wavedash,
+    Pivot,
+    DashbackOutOfCrouch,
+}
+
+/// An attempt at a technique.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Detection {
+    pub(crate) technique: Technique,
+    pub(crate) frames: u32,
+    pub(crate) window: config::FrameWindow,
+}
+
+impl Detection {
+    fn new(technique: Technique, frames: u32, window: config::FrameWindow) -> Option<Self> {
+        (frames <= window.max + SLACK_FRAMES).then_some(Self {
+            technique,
+            frames,
+            window,
+        })
+    }
+
+    pub(crate) fn in_window(&self) -> bool {
+        self.window.contains(self.frames)
+    }
+}
+
+impl std::fmt::Display for Detection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            technique,
+            frames,
+            window,
+        } = self;
+        let verdict = if *frames < window.min {
+            "early"
+        } else if *frames > window.max {
+            "late"
+        } else {
+            "in window"
+        };
+        write!(
+            f,
+            "{:?}: {} frames, {} ({}..={})",
+            technique, frames, verdict, window.min, window.max
+        )
+    }
+}
+
+/// What detectors see of each input besides the input itself.
+struct Context {
+    time: Timestamp,
+    /// The A-stick before and after the input.
+    a_stick: (AStickInput, AStickInput),
+}
+
+trait Detector {
+    fn input(&mut self, input: Input, context: &Context) -> Option<Detection>;
+}
+
+fn is_dash((x, y): AStickInput) -> bool {
+    (x == Analog::MAX || x == Analog::MIN) && y == P0000
+}
+
+fn is_down_diagonal((x, y): AStickInput) -> bool {
+    x != P0000 && y < P0000
+}
+
+fn is_crouch((x, y): AStickInput) -> bool {
+    x == P0000 && y < P0000
+}
+
+/// A jump followed by L or R with the stick held in a down diagonal, in
+/// either order.
+struct Wavedash {
+    window: config::FrameWindow,
+    jump: Option<Timestamp>,
+    /// How many of L and R are held.
+    shoulders: u8,
+}
+
+impl Detector for Wavedash {
+    fn input(&mut self, input: Input, context: &Context) -> Option<Detection> {
+        let (before, after) = context.a_stick;
+        let airdodge = match input {
+            Input::Button(Button::Pure(ButtonPure::X | ButtonPure::Y), PRESSED) => {
+                self.jump = Some(context.time);
+                false
+            }
+            Input::Button(Button::Impure(ButtonImpure::L | ButtonImpure::R), pressed) => {
+                if pressed {
+                    self.shoulders += 1;
+                } else {
+                    self.shoulders = self.shoulders.saturating_sub(1);
+                }
+                pressed && is_down_diagonal(after)
+            }
+            Input::Stick(Stick::A, _) => {
+                self.shoulders != 0 && !is_down_diagonal(before) && is_down_diagonal(after)
+            }
+            _ => false,
+        };
+        if !airdodge {
+            return None;
+        }
+        let jump = self.jump.take()?;
+        Detection::new(
+            Technique::Wavedash,
+            frames(context.time - jump),
+            self.window,
+        )
+    }
+}
+
+/// A dash turned around and returned to neutral, measuring how long the
+/// opposite direction was held.
+struct Pivot {
+    window: config::FrameWindow,
+    turned: Option<Timestamp>,
+}
+
+impl Detector for Pivot {
+    fn input(&mut self, input: Input, context: &Context) -> Option<Detection> {
+        let (before, after) = match (input, context.a_stick) {
+            (Input::Stick(Stick::A, _), a_stick) => a_stick,
+            _ => return None,
+        };
+        if is_dash(before) && is_dash(after) && before.0 == -after.0 {
+            self.turned = Some(context.time);
+            return None;
+        }
+        let turned = self.turned.take()?;
+        if after != (P0000, P0000) {
+            return None;
+        }
+        Detection::new(Technique::Pivot, frames(context.time - turned), self.window)
+    }
+}
+
+/// Leaving a crouch for a horizontal dash, possibly by way of neutral or a
+/// down diagonal.
+struct DashbackOutOfCrouch {
+    window: config::FrameWindow,
+    left_crouch: Option<Timestamp>,
+}
+
+impl Detector for DashbackOutOfCrouch {
+    fn input(&mut self, input: Input, context: &Context) -> Option<Detection> {
+        let (before, after) = match (input, context.a_stick) {
+            (Input::Stick(Stick::A, _), a_stick) => a_stick,
+            _ => return None,
+        };
+        if is_crouch(before) && !is_crouch(after) {
+            self.left_crouch = Some(context.time);
+        }
+        let left_crouch = self.left_crouch?;
+        if is_dash(after) {
+            self.left_crouch = None;
+            Detection::new(
+                Technique::DashbackOutOfCrouch,
+                frames(context.time - left_crouch),
+                self.window,
+            )
+        } else {
+            if after != (P0000, P0000) && !is_down_diagonal(after) {
+                self.left_crouch = None;
+            }
+            None
+        }
+    }
+}
+
+/// Runs every detector over the inputs written to the pipe.
+pub(crate) struct Detectors {
+    a_stick: AStickInput,
+    detectors: Vec<Box<dyn Detector>>,
+}
+
+impl Detectors {
+    pub(crate) fn new(techniques: config::Techniques) -> Self {
+        let config::Techniques {
+            wavedash,
+            pivot,
+            dashback_out_of_crouch,
+        } = techniques;
+        Self {
+            a_stick: (P0000, P0000),
+            detectors: vec![
+                Box::new(Wavedash {
+                    window: wavedash,
+                    jump: None,
+                    shoulders: 0,
+                }),
+                Box::new(Pivot {
+                    window: pivot,
+                    turned: None,
+                }),
+                Box::new(DashbackOutOfCrouch {
+                    window: dashback_out_of_crouch,
+                    left_crouch: None,
+                }),
+            ],
+        }
+    }
+
+    /// Returns the attempts that `inputs`, written at `time`, complete.
+    pub(crate) fn process(&mut self, time: Timestamp, inputs: &[Input]) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        for &input in inputs {
+            let before = self.a_stick;
+            if let Input::Stick(Stick::A, a_stick) = input {
+                self.a_stick = a_stick;
+            }
+            let context = Context {
+                time,
+                a_stick: (before, self.a_stick),
+            };
+            detections.extend(
+                self.detectors
+                    .iter_mut()
+                    .filter_map(|detector| detector.input(input, &context)),
+            );
+        }
+        detections
+    }
+}
@@ -0,0 +1,265 @@
+//! A live view of the input and output state in the terminal.
+//!
+//! `Tui` receives the same inputs as the pipe and hands frames to a render
+//! thread, replacing any it hasn't drawn yet, so that a slow terminal can never
+//! hold up the main loop. Rendering to lines of text is kept apart from the
+//! terminal so that it builds and is tested without the `tui` feature.
+
+use super::*;
+
+/// Buttons in the order they are shown, grouped by row.
+const ROWS: [(&str, &[B0xxRaw]); 6] = [
+    (
+        "stick",
+        &[B0xxRaw::Left, B0xxRaw::Down, B0xxRaw::Right, B0xxRaw::Up],
+    ),
+    (
+        "mods",
+        &[B0xxRaw::MX, B0xxRaw::MY, B0xxRaw::MT, B0xxRaw::DP],
+    ),
+    (
+        "face",
+        &[
+            B0xxRaw::A,
+            B0xxRaw::B,
+            B0xxRaw::X,
+            B0xxRaw::Y,
+            B0xxRaw::Z,
+            B0xxRaw::Start,
+        ],
+    ),
+    (
+        "shoulder",
+        &[B0xxRaw::L, B0xxRaw::R, B0xxRaw::LS, B0xxRaw::MS],
+    ),
+    (
+        "c-stick",
+        &[B0xxRaw::CL, B0xxRaw::CD, B0xxRaw::CR, B0xxRaw::CU],
+    ),
+    (
+        "d-pad",
+        &[B0xxRaw::DL, B0xxRaw::DD, B0xxRaw::DR, B0xxRaw::DU],
+    ),
+];
+
+/// Cells across and down each stick plot. Odd, so that neutral is centered.
+const PLOT_SIZE: usize = 11;
+
+/// Everything shown at once.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Frame {
+    held: std::collections::BTreeSet<B0xxRaw>,
+    state: B0xxState,
+    shield: Option<B0xxRaw>,
+    a_stick: StickState,
+    c_stick: CStickState,
+    character: Option<String>,
+    output: ControllerSnapshot,
+}
+
+impl Frame {
+    /// Shows the input state of `main` alongside `output`, which is what has
+    /// been sent to the pipe.
+    pub(crate) fn new(main: &Main, output: &ControllerSnapshot) -> Self {
+        Self {
+            held: main.held.clone(),
+            state: main.state,
+            shield: main.shield_state.active,
+            a_stick: main.a_stick,
+            c_stick: main.c_stick,
+            character: main.character.clone(),
+            output: output.clone(),
+        }
+    }
+
+    pub(crate) fn render(&self) -> Vec<String> {
+        let mut lines = ROWS
+            .iter()
+            .map(|(name, buttons)| {
+                let buttons = buttons
+                    .iter()
+                    .map(|btn| {
+                        if self.held.contains(btn) {
+                            format!("[{:?}]", btn)
+                        } else {
+                            format!(" {:?} ", btn)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("{:>8}: {}", name, buttons)
+            })
+            .collect::<Vec<_>>();
+        lines.push(String::new());
+        let ControllerSnapshot {
+            a_stick, c_stick, ..
+        } = self.output;
+        let a_label = format!("A {}", coordinates(a_stick));
+        lines.push(format!(
+            "{:<w$}   C {}",
+            a_label,
+            coordinates(c_stick),
+            w = PLOT_SIZE * 2
+        ));
+        lines.extend(
+            plot(a_stick)
+                .into_iter()
+                .zip(plot(c_stick))
+                .map(|(a, c)| format!("{:<w$}   {}", a, c, w = PLOT_SIZE * 2)),
+        );
+        lines.push(String::new());
+        lines.push(format!("trigger: {}/140", self.output.trigger.get()));
+        lines.push(format!("state: {:?}", self.state));
+        lines.push(format!("shield: {:?}", self.shield));
+        lines.push(format!(
+            "A axes: x {:?}, y {:?}",
+            self.a_stick.x, self.a_stick.y
+        ));
+        lines.push(format!(
+            "C axes: x {:?}, y {:?}",
+            self.c_stick.x, self.c_stick.y
+        ));
+        lines.push(format!(
+            "character: {}",
+            self.character.as_deref().unwrap_or("none")
+        ));
+        lines
+    }
+}
+
+fn coordinates((x, y): GCStickInput) -> String {
+    let unit = |a: Analog| f64::from(a.get()) / f64::from(Analog::MAX.get());
+    format!("({:.4}, {:.4})", unit(x), unit(y))
+}
+
+/// Plots the stick position on a grid with up at the top.
+pub(crate) fn plot((x, y): GCStickInput) -> Vec<String> {
+    let cell = |a: Analog| {
+        let max = i32::from(Analog::MAX.get());
+        let cells = PLOT_SIZE as i32 - 1;
+        // Rounds to the nearest cell.
+        (((i32::from(a.get()) + max) * cells + max) / (2 * max)) as usize
+    };
+    let (column, row) = (cell(x), PLOT_SIZE - 1 - cell(y));
+    let center = PLOT_SIZE / 2;
+    (0..PLOT_SIZE)
+        .map(|r| {
+            (0..PLOT_SIZE)
+                .map(|c| match (r, c) {
+                    _ if (r, c) == (row, column) => "o ",
+                    _ if (r, c) == (center, center) => "+ ",
+                    _ => ". ",
+                })
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Renders at most this often.
+#[cfg(feature = "tui")]
+const PERIOD: std::time::Duration = std::time::Duration::from_millis(33);
+
+/// The latest frame not yet drawn, and whether the sender is gone.
+#[cfg(feature = "tui")]
+type Latest = std::sync::Arc<(std::sync::Mutex<(Option<Frame>, bool)>, std::sync::Condvar)>;
+
+/// A sink that shows what is sent to the pipe in the terminal.
+#[cfg(feature = "tui")]
+pub(crate) struct Tui {
+    output: ControllerSnapshot,
+    latest: Latest,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "tui")]
+impl Tui {
+    pub(crate) fn new() -> std::io::Result<Self> {
+        use crossterm::{cursor, terminal};
+
+        crossterm::execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        let latest = Latest::default();
+        let thread = {
+            let latest = latest.clone();
+            std::thread::spawn(move || loop {
+                let frame = {
+                    let (lock, condvar) = &*latest;
+                    let mut guard = condvar
+                        .wait_while(
+                            lock.lock().expect("TUI lock poisoned"),
+                            |(frame, closed)| frame.is_none() && !*closed,
+                        )
+                        .expect("TUI lock poisoned");
+                    match guard.0.take() {
+                        Some(frame) => frame,
+                        None => break,
+                    }
+                };
+                if let Err(e) = draw(&frame) {
+                    warn!("failed to draw: {}", e);
+                }
+                std::thread::sleep(PERIOD);
+            })
+        };
+        let mut tui = Self {
+            output: Default::default(),
+            latest,
+            thread: Some(thread),
+        };
+        tui.show(&Main::default(), &[]);
+        Ok(tui)
+    }
+
+    /// Shows the input state of `main` once it has emitted `inputs`, replacing
+    /// any frame the render thread hasn't got to yet.
+    pub(crate) fn show(&mut self, main: &Main, inputs: &[Input]) {
+        for &input in inputs {
+            self.output.apply(input.into_pipe_input());
+        }
+        let (lock, condvar) = &*self.latest;
+        lock.lock().expect("TUI lock poisoned").0 = Some(Frame::new(main, &self.output));
+        condvar.notify_one();
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Drop for Tui {
+    /// Draws the last frame and restores the terminal before returning.
+    fn drop(&mut self) {
+        use crossterm::{cursor, terminal};
+
+        let (lock, condvar) = &*self.latest;
+        lock.lock().expect("TUI lock poisoned").1 = true;
+        condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = crossterm::execute!(
+            std::io::stdout(),
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+    }
+}
+
+#[cfg(feature = "tui")]
+fn draw(frame: &Frame) -> std::io::Result<()> {
+    use crossterm::{cursor, style, terminal};
+    use std::io::Write as _;
+
+    let mut stdout = std::io::stdout().lock();
+    crossterm::queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+    for (row, line) in frame.render().into_iter().enumerate() {
+        crossterm::queue!(stdout, cursor::MoveTo(0, row as u16), style::Print(line))?;
+    }
+    stdout.flush()
+}
@@ -0,0 +1,135 @@
+//! A bridge for the b0xx-viewer overlay, which shows the buttons of a B0XX
+//! as its Arduino reports them over serial. Clients connecting over TCP get
+//! the same frames, built from the buttons held rather than the GameCube
+//! output, so that the viewer runs unmodified, e.g. reading a virtual serial
+//! port bridged to the socket.
+
+use super::*;
+
+/// The buttons of a frame, in the order the viewer reads them, as in the
+/// report the B0XX firmware writes and
+/// https://github.com/agirardeau/b0xx-viewer parses. The rest, e.g. Mod Tilt
+/// or the D-pad, have no place on its layout.
+const BUTTONS: [B0xxRaw; 20] = [
+    B0xxRaw::Start,
+    B0xxRaw::Y,
+    B0xxRaw::X,
+    B0xxRaw::B,
+    B0xxRaw::A,
+    B0xxRaw::L,
+    B0xxRaw::R,
+    B0xxRaw::Z,
+    B0xxRaw::Up,
+    B0xxRaw::Down,
+    B0xxRaw::Right,
+    B0xxRaw::Left,
+    B0xxRaw::MX,
+    B0xxRaw::MY,
+    B0xxRaw::CL,
+    B0xxRaw::CR,
+    B0xxRaw::CU,
+    B0xxRaw::CD,
+    B0xxRaw::LS,
+    B0xxRaw::MS,
+];
+
+/// A frame of the viewer's serial format.
+pub(crate) type Frame = [u8; BUTTONS.len() + 1];
+
+/// Returns the frame of the buttons `held`: an ASCII `1` for each of
+/// `BUTTONS` held, or `0`, in order, then a newline.
+pub(crate) fn frame(held: &std::collections::BTreeSet<B0xxRaw>) -> Frame {
+    let mut frame = [b'\n'; BUTTONS.len() + 1];
+    for (byte, btn) in frame.iter_mut().zip(BUTTONS) {
+        *byte = if held.contains(&btn) { b'1' } else { b'0' };
+    }
+    frame
+}
+
+/// Sends `frame` to `stream` without blocking, returning whether it was sent
+/// whole. A client that stopped reading has a full socket buffer, so it fails
+/// rather than holding up the others.
+pub(crate) fn send(mut stream: &std::net::TcpStream, frame: &Frame) -> bool {
+    use std::io::Write as _;
+
+    match stream.write_all(frame) {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("dropping viewer: {}", e);
+            false
+        }
+    }
+}
+
+/// Listens on `port` of the loopback interface, and until `events` ends
+/// sends every client the frame of the buttons held whenever it changes,
+/// starting with the current one on connecting, then disconnects them.
+/// Clients that fail to receive a frame, e.g. as they disconnected or stopped
+/// reading, are dropped. Returns the address listened on, e.g. to find the
+/// port chosen for 0.
+pub(crate) fn serve(
+    port: u16,
+    mut events: remap::Subscriber,
+) -> anyhow::Result<std::net::SocketAddr> {
+    let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("failed to listen for viewers on port {}", port))?;
+    let address = listener
+        .local_addr()
+        .context("failed to get the viewer address")?;
+    info!("listening for viewers on {}", address);
+    // The frame last sent, and the clients connected.
+    let clients = std::sync::Arc::new(std::sync::Mutex::new((
+        frame(&Default::default()),
+        Vec::<std::net::TcpStream>::new(),
+    )));
+    let _: std::thread::JoinHandle<()> = std::thread::spawn({
+        let clients = std::sync::Arc::clone(&clients);
+        move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("failed to accept a viewer connection: {}", e);
+                        continue;
+                    }
+                };
+                // Frames are small and latency is what matters.
+                let _ = stream.set_nodelay(true);
+                if let Err(e) = stream.set_nonblocking(true) {
+                    warn!("failed to set up a viewer connection: {}", e);
+                    continue;
+                }
+                let mut clients = clients.lock().expect("viewer lock poisoned");
+                if send(&stream, &clients.0) {
+                    clients.1.push(stream);
+                }
+            }
+        }
+    });
+    let _: std::thread::JoinHandle<()> = std::thread::spawn(move || {
+        futures::executor::block_on(async {
+            while let Some(event) = events.next().await {
+                let frame = frame(&event.held);
+                // Closed once the lock is released.
+                let dropped: Vec<_> = {
+                    let mut clients = clients.lock().expect("viewer lock poisoned");
+                    if frame == clients.0 {
+                        continue;
+                    }
+                    clients.0 = frame;
+                    let (sent, dropped) = std::mem::take(&mut clients.1)
+                        .into_iter()
+                        .partition(|stream| send(stream, &frame));
+                    clients.1 = sent;
+                    dropped
+                };
+                drop(dropped);
+            }
+            // Viewers see the bridge go away rather than a frozen frame.
+            drop(std::mem::take(
+                &mut clients.lock().expect("viewer lock poisoned").1,
+            ));
+        })
+    });
+    Ok(address)
+}